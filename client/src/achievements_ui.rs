@@ -0,0 +1,42 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::ClientState;
+
+/// how long an unlocked achievement stays on screen, in seconds
+pub const TOAST_SECONDS: f64 = 4.0;
+
+#[derive(Clone, Debug)]
+pub struct AchievementToast {
+    pub name: String,
+    pub shown_until: f64,
+}
+
+/// a short-lived banner at the top of the screen for each achievement just unlocked
+pub fn render_achievement_toasts(
+    mut contexts: EguiContexts,
+    mut client_state: ResMut<ClientState>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds_f64();
+    client_state
+        .achievement_toasts
+        .retain(|toast| toast.shown_until > now);
+    if client_state.achievement_toasts.is_empty() {
+        return;
+    }
+
+    let ctx = contexts.ctx_mut();
+    egui::Area::new(egui::Id::new("achievement_toasts"))
+        .anchor(egui::Align2::CENTER_TOP, [0.0, 40.0])
+        .order(egui::Order::Foreground)
+        .interactable(false)
+        .show(ctx, |ui| {
+            for toast in &client_state.achievement_toasts {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.label(egui::RichText::new("Achievement unlocked").small());
+                    ui.strong(&toast.name);
+                });
+            }
+        });
+}
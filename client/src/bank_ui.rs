@@ -0,0 +1,95 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use shared::actions::GameAction;
+use shared::transport::ClientTransport;
+
+use crate::network_sim::{NetworkConditions, SimulatedTransport};
+use crate::systems::queue_action;
+use crate::ClientState;
+
+/// bank window, shown while the server has the bank open for us
+pub fn render_bank_ui<T: ClientTransport + Resource>(
+    mut contexts: EguiContexts,
+    mut client: ResMut<T>,
+    mut conditions: ResMut<NetworkConditions>,
+    mut client_state: ResMut<ClientState>,
+    time: Res<Time>,
+) {
+    let bank = match client_state.bank {
+        Some(ref bank) => bank.clone(),
+        None => return,
+    };
+
+    let ctx = contexts.ctx_mut();
+    let mut actions = Vec::new();
+    let mut open = true;
+
+    egui::Window::new("Bank")
+        .open(&mut open)
+        .default_pos([400.0, 10.0])
+        .default_width(300.0)
+        .show(ctx, |ui| {
+            ui.heading("Bank");
+            ui.separator();
+
+            let used = bank.slots.iter().filter(|slot| slot.is_some()).count();
+            ui.label(format!("{} / {} slots", used, bank.max_slots));
+
+            egui::ScrollArea::vertical()
+                .id_source("bank_items")
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    for stack in bank.slots.iter().flatten() {
+                        ui.horizontal(|ui| {
+                            let def = client_state.definitions.item(stack.item_type);
+                            ui.label(format!("{} x{}", def.name, stack.quantity));
+                            if ui.button("1").clicked() {
+                                actions.push(GameAction::Withdraw {
+                                    item_type: stack.item_type,
+                                    quantity: 1,
+                                });
+                            }
+                            if ui.button("All").clicked() {
+                                actions.push(GameAction::Withdraw {
+                                    item_type: stack.item_type,
+                                    quantity: stack.quantity,
+                                });
+                            }
+                        });
+                    }
+                });
+
+            ui.add_space(10.0);
+
+            ui.heading("Backpack");
+            ui.separator();
+
+            for (slot, stack) in client_state.inventory.slots.iter().enumerate() {
+                if let Some(stack) = stack {
+                    ui.horizontal(|ui| {
+                        let def = client_state.definitions.item(stack.item_type);
+                        ui.label(format!("{} x{}", def.name, stack.quantity));
+                        if ui.button("Deposit").clicked() {
+                            actions.push(GameAction::Deposit {
+                                slot,
+                                quantity: stack.quantity,
+                            });
+                        }
+                    });
+                }
+            }
+        });
+
+    let mut client = SimulatedTransport {
+        inner: &mut *client,
+        conditions: &mut conditions,
+        now: time.elapsed_seconds_f64(),
+    };
+    for action in actions {
+        queue_action(action, &mut client, &mut client_state);
+    }
+
+    if !open {
+        client_state.bank = None;
+    }
+}
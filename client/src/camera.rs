@@ -1,9 +1,15 @@
 use bevy::prelude::*;
 
 use shared::*;
-use shared::{tile_system::TilePosition, trees::TreeType};
+use shared::{
+    actions::GameActionKind,
+    mining::{Rock, RockType},
+    tile_map::TileType,
+    tile_system::TilePosition,
+    trees::{Tree, TreeStage, TreeType},
+};
 
-use crate::{ClientState, NetworkedEntity};
+use crate::{ClientState, LocalPlayer, NetworkedEntity};
 
 pub fn update_entity_positions(
     client_state: Res<ClientState>,
@@ -11,19 +17,18 @@ pub fn update_entity_positions(
 ) {
     for (networked, mut transform) in query.iter_mut() {
         if let Some(entity) = client_state.visible_entities.get(&networked.entity_id) {
-            // use interpolated position for remote entities if available
-            let display_position = if Some(networked.entity_id) == client_state.my_entity_id {
-                // for our own entity, use the predicted position
-                entity.tile_position
-            } else if let Some(interp_pos) = entity.interpolated_position {
-                // for remote entities, use interpolated position
-                interp_pos
-            } else {
-                // fallback to actual tile position
-                entity.tile_position
-            };
-
-            let target = display_position.to_world().extend(0.0);
+            let is_local = Some(networked.entity_id) == client_state.my_entity_id;
+            let predicted = is_local && !client_state.interpolates_local_player();
+
+            // remote entities are already smoothed by interpolation, place them exactly,
+            // and so is our own one while it isn't predicted
+            if let Some(world) = entity.interpolated_world.filter(|_| !predicted) {
+                transform.translation = world.extend(0.0);
+                continue;
+            }
+
+            // our own entity uses the predicted position, anything else its tile
+            let target = entity.tile_position.to_world().extend(0.0);
             transform.translation = transform.translation.lerp(target, 0.2);
         }
     }
@@ -31,28 +36,118 @@ pub fn update_entity_positions(
 
 pub fn update_tree_visuals(
     client_state: Res<ClientState>,
+    time: Res<Time>,
     mut query: Query<(&NetworkedEntity, &mut Sprite)>,
 ) {
+    let server_tick = client_state.estimated_server_tick(time.elapsed_seconds_f64());
     for (networked, mut sprite) in query.iter_mut() {
         if let Some(entity) = client_state.visible_entities.get(&networked.entity_id) {
             if let Some(ref tree) = entity.tree {
-                let tree_color = match tree.tree_type {
-                    TreeType::Normal => Color::srgb(0.4, 0.6, 0.3),
-                    TreeType::Oak => Color::srgb(0.5, 0.4, 0.2),
-                    TreeType::Willow => Color::srgb(0.6, 0.7, 0.4),
-                };
-
-                sprite.color = if tree.is_chopped {
-                    Color::srgb(0.3, 0.3, 0.3)
-                } else {
-                    tree_color
-                };
+                sprite.color = tree_color(tree, server_tick);
+            } else if let Some(ref rock) = entity.rock {
+                sprite.color = rock_color(rock);
+            }
+        }
+    }
+}
+
+/// swings and tints the local player while a gathering action is predicted or running
+pub fn animate_predicted_action(
+    client_state: Res<ClientState>,
+    time: Res<Time>,
+    mut query: Query<(&mut Sprite, &mut Transform), With<LocalPlayer>>,
+) {
+    for (mut sprite, mut transform) in query.iter_mut() {
+        match client_state.predicted_action {
+            Some(ref predicted) => {
+                let elapsed = (time.elapsed_seconds_f64() - predicted.started_at) as f32;
+                transform.rotation = Quat::from_rotation_z((elapsed * 8.0).sin() * 0.3);
+                sprite.color = Color::srgb(0.55, 0.75, 0.25);
+            }
+            None => {
+                transform.rotation = Quat::IDENTITY;
+                sprite.color = Color::srgb(0.25, 0.75, 0.25);
+            }
+        }
+    }
+}
+
+/// how long a one-off action (equip, pick up, ...) tints a remote player
+const ACTION_FLASH_SECONDS: f64 = 0.6;
+
+/// swings remote players while they gather or fight, and briefly tints them for
+/// one-off actions, so other players' actions are visible
+pub fn animate_remote_actions(
+    client_state: Res<ClientState>,
+    time: Res<Time>,
+    mut query: Query<(&NetworkedEntity, &mut Sprite, &mut Transform), Without<LocalPlayer>>,
+) {
+    let now = time.elapsed_seconds_f64();
+    for (networked, mut sprite, mut transform) in query.iter_mut() {
+        let entity = match client_state.visible_entities.get(&networked.entity_id) {
+            Some(entity) if entity.player_id.is_some() => entity,
+            _ => continue,
+        };
+        // entities already in the middle of an action when they came into view have
+        // no start time, their swing just starts at an arbitrary phase
+        let elapsed = now - entity.action_started_at.unwrap_or(0.0);
+
+        match entity.current_action {
+            Some(GameActionKind::ChopTree | GameActionKind::Mine | GameActionKind::Attack) => {
+                transform.rotation = Quat::from_rotation_z((elapsed as f32 * 8.0).sin() * 0.3);
+                sprite.color = Color::srgb(0.85, 0.45, 0.25);
             }
+            Some(kind) if kind != GameActionKind::Move && elapsed < ACTION_FLASH_SECONDS => {
+                transform.rotation = Quat::IDENTITY;
+                sprite.color = Color::srgb(0.85, 0.45, 0.25);
+            }
+            _ => {
+                transform.rotation = Quat::IDENTITY;
+                sprite.color = Color::srgb(0.75, 0.25, 0.25);
+            }
+        }
+    }
+}
+
+/// a felled tree shows as a stump, then fades back in over the second half of its
+/// respawn wait
+pub fn tree_color(tree: &Tree, server_tick: f64) -> Color {
+    let standing = match tree.tree_type {
+        TreeType::Normal => Color::srgb(0.4, 0.6, 0.3),
+        TreeType::Oak => Color::srgb(0.5, 0.4, 0.2),
+        TreeType::Willow => Color::srgb(0.6, 0.7, 0.4),
+    };
+    let stump = Color::srgb(0.3, 0.3, 0.3);
+    match tree.stage(server_tick) {
+        TreeStage::Standing => standing,
+        TreeStage::Chopped => stump,
+        TreeStage::Regrowing => {
+            let regrown = (tree.regrowth(server_tick) - 0.5) * 2.0;
+            stump.mix(&standing, regrown)
         }
     }
 }
+
+/// sprite colour for a rock, greyed out while depleted
+pub fn rock_color(rock: &Rock) -> Color {
+    if rock.is_depleted {
+        return Color::srgb(0.25, 0.25, 0.25);
+    }
+    match rock.rock_type {
+        RockType::Copper => Color::srgb(0.72, 0.45, 0.2),
+        RockType::Tin => Color::srgb(0.7, 0.7, 0.65),
+        RockType::Iron => Color::srgb(0.45, 0.3, 0.28),
+        RockType::RichVein => Color::srgb(0.55, 0.35, 0.6),
+    }
+}
 /// draw prediction and interpolation ghosts for debugging
-pub fn draw_netcode_ghosts(mut gizmos: Gizmos, client_state: Res<ClientState>) {
+pub fn draw_netcode_ghosts(
+    mut gizmos: Gizmos,
+    client_state: Res<ClientState>,
+    windows: Query<&Window>,
+    camera_q: Query<(&Transform, &OrthographicProjection), With<Camera>>,
+) {
+    let view = camera_view(&windows, &camera_q);
     if client_state.show_prediction_ghosts {
         if let Some(my_entity_id) = client_state.my_entity_id {
             if let Some(my_entity) = client_state.visible_entities.get(&my_entity_id) {
@@ -89,8 +184,11 @@ pub fn draw_netcode_ghosts(mut gizmos: Gizmos, client_state: Res<ClientState>) {
         let my_entity_id = client_state.my_entity_id;
 
         for (entity_id, entity) in client_state.visible_entities.iter() {
-            // skip local player and trees
-            if Some(*entity_id) == my_entity_id || entity.tree.is_some() {
+            // skip local player, static resources and anything off screen
+            if Some(*entity_id) == my_entity_id
+                || entity.is_static()
+                || !view.contains(&entity.tile_position)
+            {
                 continue;
             }
 
@@ -123,23 +221,222 @@ pub fn draw_netcode_ghosts(mut gizmos: Gizmos, client_state: Res<ClientState>) {
     }
 }
 
-pub fn draw_tile_grid(mut gizmos: Gizmos, client_state: Res<ClientState>) {
-    let grid_size = 20;
+/// bar above anything that has lost health, red for what's missing
+pub fn draw_health_bars(mut gizmos: Gizmos, client_state: Res<ClientState>) {
+    for entity in client_state.visible_entities.values() {
+        let max_health = entity.kind.max_health();
+        let health = match entity.health {
+            Some(health) if health < max_health => health,
+            _ => continue,
+        };
+        let center = entity
+            .interpolated_world
+            .unwrap_or_else(|| entity.tile_position.to_world())
+            + Vec2::new(0.0, TILE_SIZE * 0.6);
+        let width = TILE_SIZE * 0.8;
+        let left = center.x - width / 2.0;
+        let filled = width * health as f32 / max_health as f32;
+
+        gizmos.line_2d(
+            Vec2::new(left, center.y),
+            Vec2::new(left + width, center.y),
+            Color::srgb(0.8, 0.1, 0.1),
+        );
+        gizmos.line_2d(
+            Vec2::new(left, center.y),
+            Vec2::new(left + filled, center.y),
+            Color::srgb(0.1, 0.8, 0.1),
+        );
+    }
+}
+
+/// entities this close to the edge of the view are the next to enter or leave it
+const INTEREST_EDGE_TILES: i32 = 1;
+
+/// the edge of what the server sends us, a square like the server's default view
+/// metric, with the entities about to cross it outlined
+fn draw_interest_radius(gizmos: &mut Gizmos, client_state: &ClientState) {
+    let center = match client_state.spectator_center.or_else(|| {
+        client_state
+            .my_entity_id
+            .and_then(|id| client_state.visible_entities.get(&id))
+            .map(|me| me.tile_position)
+    }) {
+        Some(center) => center,
+        None => return,
+    };
+
+    let view_distance = client_state.view_distance;
+    let side = (2 * view_distance + 1) as f32 * TILE_SIZE;
+    gizmos.rect_2d(
+        center.to_world(),
+        0.0,
+        Vec2::new(side, side),
+        Color::srgba(1.0, 1.0, 0.6, 0.3),
+    );
+
+    for (entity_id, entity) in &client_state.visible_entities {
+        if Some(*entity_id) == client_state.my_entity_id {
+            continue;
+        }
+        let pos = entity.interpolated_position.unwrap_or(entity.tile_position);
+        if center.distance_chebyshev(&pos) >= view_distance - INTEREST_EDGE_TILES {
+            gizmos.rect_2d(
+                pos.to_world(),
+                0.0,
+                Vec2::splat(TILE_SIZE),
+                Color::srgb(1.0, 0.6, 0.1),
+            );
+        }
+    }
+}
+
+/// tiles beyond the window edge still drawn, so nothing pops in as the camera moves
+const VIEW_MARGIN_TILES: i32 = 1;
+
+/// the tiles the camera can see, corners included
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ViewRect {
+    pub min: TilePosition,
+    pub max: TilePosition,
+}
+
+impl ViewRect {
+    /// for when there is no window or camera to measure, nothing is culled
+    pub const ALL: ViewRect = ViewRect {
+        min: TilePosition {
+            x: i32::MIN,
+            y: i32::MIN,
+        },
+        max: TilePosition {
+            x: i32::MAX,
+            y: i32::MAX,
+        },
+    };
+
+    /// what an orthographic camera at `center` with `scale` shows in a window of
+    /// `window_size` logical pixels, plus `VIEW_MARGIN_TILES` all round
+    pub fn from_camera(center: Vec2, window_size: Vec2, scale: f32) -> Self {
+        let half = window_size * scale / 2.0;
+        let min = TilePosition::from_world(center - half);
+        let max = TilePosition::from_world(center + half);
+        ViewRect {
+            min: TilePosition {
+                x: min.x - VIEW_MARGIN_TILES,
+                y: min.y - VIEW_MARGIN_TILES,
+            },
+            max: TilePosition {
+                x: max.x + VIEW_MARGIN_TILES,
+                y: max.y + VIEW_MARGIN_TILES,
+            },
+        }
+    }
+
+    /// the square of tiles up to `radius` away from `center`
+    pub fn around(center: TilePosition, radius: i32) -> Self {
+        ViewRect {
+            min: TilePosition {
+                x: center.x - radius,
+                y: center.y - radius,
+            },
+            max: TilePosition {
+                x: center.x + radius,
+                y: center.y + radius,
+            },
+        }
+    }
+
+    pub fn contains(&self, tile: &TilePosition) -> bool {
+        (self.min.x..=self.max.x).contains(&tile.x) && (self.min.y..=self.max.y).contains(&tile.y)
+    }
+}
+
+/// how far the grid reaches from the origin when there is no camera to follow
+const FALLBACK_GRID_RADIUS: i32 = 20;
+
+/// the view of the one camera, or `ViewRect::ALL` before there is one
+fn camera_view(
+    windows: &Query<&Window>,
+    camera_q: &Query<(&Transform, &OrthographicProjection), With<Camera>>,
+) -> ViewRect {
+    match (windows.get_single(), camera_q.get_single()) {
+        (Ok(window), Ok((transform, projection))) => ViewRect::from_camera(
+            transform.translation.truncate(),
+            Vec2::new(window.width(), window.height()),
+            projection.scale,
+        ),
+        _ => ViewRect::ALL,
+    }
+}
+
+pub fn draw_tile_grid(
+    mut gizmos: Gizmos,
+    client_state: Res<ClientState>,
+    windows: Query<&Window>,
+    camera_q: Query<(&Transform, &OrthographicProjection), With<Camera>>,
+) {
     let color = Color::srgba(1.0, 1.0, 1.0, 0.1);
+    let view = camera_view(&windows, &camera_q);
 
-    for x in -grid_size..=grid_size {
-        let start = Vec2::new(x as f32 * TILE_SIZE, -grid_size as f32 * TILE_SIZE);
-        let end = Vec2::new(x as f32 * TILE_SIZE, grid_size as f32 * TILE_SIZE);
+    // the grid covers whatever the camera sees, so it never runs out however far
+    // from the origin we go
+    let grid = match view {
+        ViewRect::ALL => ViewRect::around(TilePosition { x: 0, y: 0 }, FALLBACK_GRID_RADIUS),
+        view => view,
+    };
+    for x in grid.min.x..=grid.max.x {
+        let start = Vec2::new(x as f32 * TILE_SIZE, grid.min.y as f32 * TILE_SIZE);
+        let end = Vec2::new(x as f32 * TILE_SIZE, grid.max.y as f32 * TILE_SIZE);
         gizmos.line_2d(start, end, color);
     }
 
-    for y in -grid_size..=grid_size {
-        let start = Vec2::new(-grid_size as f32 * TILE_SIZE, y as f32 * TILE_SIZE);
-        let end = Vec2::new(grid_size as f32 * TILE_SIZE, y as f32 * TILE_SIZE);
+    for y in grid.min.y..=grid.max.y {
+        let start = Vec2::new(grid.min.x as f32 * TILE_SIZE, y as f32 * TILE_SIZE);
+        let end = Vec2::new(grid.max.x as f32 * TILE_SIZE, y as f32 * TILE_SIZE);
         gizmos.line_2d(start, end, color);
     }
 
-    for obstacle in &client_state.pathfinder.obstacles {
+    for (pos, tile_type) in client_state
+        .tile_map
+        .tiles
+        .iter()
+        .filter(|(pos, _)| view.contains(pos))
+    {
+        let color = match tile_type {
+            TileType::Grass => continue,
+            TileType::Water => Color::srgb(0.2, 0.4, 0.9),
+            TileType::Wall => Color::srgb(0.4, 0.4, 0.4),
+            TileType::Road => Color::srgb(0.75, 0.65, 0.45),
+            TileType::Mud => Color::srgb(0.4, 0.3, 0.15),
+        };
+        let size = TILE_SIZE * 0.9;
+        gizmos.rect_2d(pos.to_world(), 0.0, Vec2::new(size, size), color);
+    }
+
+    if client_state.show_interest_radius {
+        draw_interest_radius(&mut gizmos, &client_state);
+    }
+
+    for portal in client_state
+        .portals
+        .iter()
+        .filter(|portal| view.contains(portal))
+    {
+        gizmos.circle_2d(
+            portal.to_world(),
+            TILE_SIZE * 0.4,
+            Color::srgb(0.7, 0.3, 0.9),
+        );
+    }
+
+    // terrain is drawn above, this leaves the trees and rocks
+    for obstacle in client_state
+        .pathfinder
+        .obstacles()
+        .iter()
+        .filter(|obstacle| view.contains(obstacle))
+        .filter(|obstacle| client_state.tile_map.get(obstacle).is_walkable())
+    {
         let position = obstacle.to_world();
         let size = TILE_SIZE * 0.9;
         gizmos.rect_2d(
@@ -152,37 +449,49 @@ pub fn draw_tile_grid(mut gizmos: Gizmos, client_state: Res<ClientState>) {
 
     if let Some(hover_entity_id) = client_state.hover_entity {
         if let Some(entity) = client_state.visible_entities.get(&hover_entity_id) {
-            if entity.tree.is_some() {
-                let position = entity.tile_position.to_world();
-                let size = TILE_SIZE * 1.3;
-                gizmos.rect_2d(
-                    position,
-                    0.0,
-                    Vec2::new(size, size),
-                    Color::srgb(1.0, 1.0, 0.0),
-                );
-            }
+            let position = entity
+                .interpolated_world
+                .unwrap_or_else(|| entity.tile_position.to_world());
+            let size = TILE_SIZE * 1.3;
+            gizmos.rect_2d(
+                position,
+                0.0,
+                Vec2::new(size, size),
+                Color::srgb(1.0, 1.0, 0.0),
+            );
         }
     }
 
     if let Some(ref path) = client_state.path_preview {
-        draw_path(&mut gizmos, path, Color::srgba(0.5, 0.5, 1.0, 0.3), false);
+        let color = Color::srgba(0.5, 0.5, 1.0, 0.3);
+        draw_path(&mut gizmos, &view, path, color, false);
     }
 
     if let Some(ref path) = client_state.confirmed_path {
-        draw_path(&mut gizmos, path, Color::srgba(0.2, 1.0, 0.2, 0.6), true);
+        let color = Color::srgba(0.2, 1.0, 0.2, 0.6);
+        draw_path(&mut gizmos, &view, path, color, true);
     }
 }
 
-pub fn draw_path(gizmos: &mut Gizmos, path: &[TilePosition], color: Color, draw_arrows: bool) {
-    for tile in path {
+/// the steps of `path` inside `view`, and arrows for any step with an end in it
+pub fn draw_path(
+    gizmos: &mut Gizmos,
+    view: &ViewRect,
+    path: &[TilePosition],
+    color: Color,
+    draw_arrows: bool,
+) {
+    for tile in path.iter().filter(|tile| view.contains(tile)) {
         let position = tile.to_world();
         let size = TILE_SIZE * 0.6;
         gizmos.rect_2d(position, 0.0, Vec2::new(size, size), color);
     }
 
     if draw_arrows && path.len() > 1 {
-        for window in path.windows(2) {
+        for window in path
+            .windows(2)
+            .filter(|step| view.contains(&step[0]) || view.contains(&step[1]))
+        {
             let from = window[0].to_world();
             let to = window[1].to_world();
 
@@ -204,17 +513,29 @@ pub fn draw_path(gizmos: &mut Gizmos, path: &[TilePosition], color: Color, draw_
 
 pub fn camera_follow_player(
     client_state: Res<ClientState>,
-    mut camera_q: Query<&mut Transform, With<Camera>>,
+    windows: Query<&Window>,
+    mut camera_q: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
 ) {
-    if let Some(my_entity_id) = client_state.my_entity_id {
-        if let Some(my_entity) = client_state.visible_entities.get(&my_entity_id) {
-            if let Ok(mut camera_transform) = camera_q.get_single_mut() {
-                let target = my_entity
-                    .tile_position
-                    .to_world()
-                    .extend(camera_transform.translation.z);
-                camera_transform.translation = camera_transform.translation.lerp(target, 0.1);
-            }
-        }
+    let Ok((mut camera_transform, mut projection)) = camera_q.get_single_mut() else {
+        return;
+    };
+
+    let followed = client_state.spectator_center.or_else(|| {
+        client_state
+            .my_entity_id
+            .and_then(|id| client_state.visible_entities.get(&id))
+            .map(|entity| entity.tile_position)
+    });
+    if let Some(tile) = followed {
+        let target = tile.to_world().extend(camera_transform.translation.z);
+        camera_transform.translation = camera_transform.translation.lerp(target, 0.1);
+    }
+
+    // zoom out far enough that the whole view, plus a tile of border, fits the window
+    if let Ok(window) = windows.get_single() {
+        let view_tiles = (2 * client_state.view_distance + 3) as f32;
+        let shortest_side = window.width().min(window.height()).max(1.0);
+        let target_scale = (view_tiles * TILE_SIZE / shortest_side).max(1.0);
+        projection.scale += (target_scale - projection.scale) * 0.1;
     }
 }
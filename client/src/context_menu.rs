@@ -0,0 +1,243 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use bevy_renet::renet::DefaultChannel;
+use shared::actions::GameAction;
+use shared::messages::ClientMessage;
+use shared::mining::RockDefinition;
+use shared::skills::SkillType;
+use shared::tile_system::TilePosition;
+use shared::transport::ClientTransport;
+
+use crate::network_sim::{NetworkConditions, SimulatedTransport};
+use crate::systems::{queue_action, queue_adjacent_action, queue_pick_up, request_path};
+use crate::ClientState;
+
+/// a right-click menu, anchored where the click happened
+#[derive(Clone, Debug)]
+pub struct ContextMenu {
+    pub tile: TilePosition,
+    pub entity_id: Option<u64>, // what was hovered when the menu opened
+    pub screen_pos: Vec2,
+    pub just_opened: bool, // not drawn yet, so the click may have been meant for a window
+}
+
+/// what picking a menu entry does
+enum MenuChoice {
+    Walk,
+    Adjacent(GameAction, TilePosition),
+    PickUp(u64, TilePosition),
+    Attack(u64),
+    Examine(u64),
+}
+
+struct MenuEntry {
+    label: &'static str,
+    choice: MenuChoice,
+    /// why the entry is greyed out, if it is
+    disabled: Option<String>,
+}
+
+/// the open right-click menu and the examine window. entries that can't be used
+/// right now are shown greyed out and never sent
+pub fn render_context_menu<T: ClientTransport + Resource>(
+    mut contexts: EguiContexts,
+    mut client: ResMut<T>,
+    mut conditions: ResMut<NetworkConditions>,
+    mut client_state: ResMut<ClientState>,
+    time: Res<Time>,
+) {
+    let ctx = contexts.ctx_mut();
+    render_examine_window(ctx, &mut client_state);
+
+    if client_state.disconnect_reason.is_some() {
+        client_state.context_menu = None;
+    }
+    let Some(menu) = client_state.context_menu.as_mut() else {
+        return;
+    };
+
+    let menu_id = egui::Id::new("world_context_menu");
+    let screen_pos = egui::pos2(menu.screen_pos.x, menu.screen_pos.y);
+    if menu.just_opened {
+        menu.just_opened = false;
+        // right-clicks on a window belong to that window
+        let over_window = ctx
+            .layer_id_at(screen_pos)
+            .is_some_and(|layer| layer.id != menu_id);
+        if over_window {
+            client_state.context_menu = None;
+            return;
+        }
+    }
+    let menu = menu.clone();
+
+    let title = match menu
+        .entity_id
+        .and_then(|id| client_state.visible_entities.get(&id))
+    {
+        Some(entity) => entity.describe(&client_state.definitions),
+        None => format!("Tile ({}, {})", menu.tile.x, menu.tile.y),
+    };
+    let entries = menu_entries(&client_state, &menu);
+
+    let mut chosen = None;
+    let response = egui::Area::new(menu_id)
+        .fixed_pos(screen_pos)
+        .order(egui::Order::Foreground)
+        .show(ctx, |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.strong(title);
+                ui.separator();
+                for (index, entry) in entries.iter().enumerate() {
+                    let mut response =
+                        ui.add_enabled(entry.disabled.is_none(), egui::Button::new(entry.label));
+                    if let Some(ref reason) = entry.disabled {
+                        response = response.on_disabled_hover_text(reason);
+                    }
+                    if response.clicked() {
+                        chosen = Some(index);
+                    }
+                }
+            });
+        })
+        .response;
+
+    let clicked_away = ctx.input(|i| {
+        i.pointer.primary_pressed()
+            && i.pointer
+                .interact_pos()
+                .is_some_and(|pos| !response.rect.contains(pos))
+    });
+    let escape = ctx.input(|i| i.key_pressed(egui::Key::Escape));
+    if chosen.is_some() || clicked_away || escape {
+        client_state.context_menu = None;
+    }
+
+    let Some(entry) = chosen.and_then(|index| entries.into_iter().nth(index)) else {
+        return;
+    };
+    let now = time.elapsed_seconds_f64();
+    let mut client = SimulatedTransport {
+        inner: &mut *client,
+        conditions: &mut conditions,
+        now,
+    };
+    match entry.choice {
+        MenuChoice::Walk => request_path(menu.tile, &mut client, &client_state),
+        MenuChoice::Adjacent(action, target_pos) => {
+            queue_adjacent_action(target_pos, action, &mut client, &mut client_state, now)
+        }
+        MenuChoice::PickUp(entity_id, item_pos) => {
+            queue_pick_up(entity_id, item_pos, &mut client, &mut client_state, now)
+        }
+        MenuChoice::Attack(target_entity_id) => queue_action(
+            GameAction::Attack { target_entity_id },
+            &mut client,
+            &mut client_state,
+        ),
+        // the server has the final say on descriptions, it replies with ExamineResult
+        MenuChoice::Examine(entity_id) => {
+            let msg = ClientMessage::Examine { entity_id };
+            if let Ok(msg_bytes) = bincode::serialize(&msg) {
+                client.send_message(DefaultChannel::ReliableOrdered, msg_bytes);
+            }
+        }
+    }
+}
+
+/// everything the menu offers for its tile, usable or not
+fn menu_entries(state: &ClientState, menu: &ContextMenu) -> Vec<MenuEntry> {
+    let mut entries = vec![MenuEntry {
+        label: "Walk here",
+        choice: MenuChoice::Walk,
+        disabled: None,
+    }];
+
+    let Some(entity_id) = menu.entity_id else {
+        return entries;
+    };
+    let Some(entity) = state.visible_entities.get(&entity_id) else {
+        return entries;
+    };
+    let level = |skill| state.skills.get(&skill).map_or(1, |data| data.level);
+
+    if let Some(ref tree) = entity.tree {
+        let def = state.definitions.tree(tree.tree_type);
+        let disabled = if tree.is_chopped {
+            Some("The tree has been cut down".to_string())
+        } else if level(SkillType::Woodcutting) < def.level_required {
+            Some(format!("Needs Woodcutting level {}", def.level_required))
+        } else {
+            None
+        };
+        let chop = GameAction::ChopTree {
+            tree_entity_id: entity_id,
+        };
+        entries.push(MenuEntry {
+            label: "Chop",
+            choice: MenuChoice::Adjacent(chop, entity.tile_position),
+            disabled,
+        });
+    } else if let Some(ref rock) = entity.rock {
+        let def = RockDefinition::get(rock.rock_type);
+        let disabled = if rock.is_depleted {
+            Some("The rock is depleted".to_string())
+        } else if level(SkillType::Mining) < def.level_required {
+            Some(format!("Needs Mining level {}", def.level_required))
+        } else {
+            None
+        };
+        let mine = GameAction::Mine {
+            rock_entity_id: entity_id,
+        };
+        entries.push(MenuEntry {
+            label: "Mine",
+            choice: MenuChoice::Adjacent(mine, entity.tile_position),
+            disabled,
+        });
+    } else if entity.bank.is_some() {
+        let interact = GameAction::Interact { entity_id };
+        entries.push(MenuEntry {
+            label: "Use bank",
+            choice: MenuChoice::Adjacent(interact, entity.tile_position),
+            disabled: None,
+        });
+    } else if entity.ground_item.is_some() {
+        entries.push(MenuEntry {
+            label: "Pick up",
+            choice: MenuChoice::PickUp(entity_id, entity.tile_position),
+            disabled: None,
+        });
+    } else if entity.health.is_some() && state.my_entity_id != Some(entity_id) {
+        entries.push(MenuEntry {
+            label: "Attack",
+            choice: MenuChoice::Attack(entity_id),
+            disabled: None,
+        });
+    }
+
+    entries.push(MenuEntry {
+        label: "Examine",
+        choice: MenuChoice::Examine(entity_id),
+        disabled: None,
+    });
+    entries
+}
+
+fn render_examine_window(ctx: &egui::Context, client_state: &mut ClientState) {
+    let Some(text) = client_state.examine_text.clone() else {
+        return;
+    };
+    let mut open = true;
+    egui::Window::new("Examine")
+        .open(&mut open)
+        .default_pos([300.0, 10.0])
+        .resizable(false)
+        .collapsible(false)
+        .show(ctx, |ui| {
+            ui.label(text);
+        });
+    if !open {
+        client_state.examine_text = None;
+    }
+}
@@ -1,11 +1,17 @@
+use crate::net_stats::NET_STATS_HISTORY;
+use crate::network_sim::NetworkConditions;
+use crate::replay::recording_path;
 use crate::ClientState;
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts};
+use shared::MAX_RUN_ENERGY;
+use std::collections::VecDeque;
 
 /// debug UI system - renders overlay with netcode stats
 pub fn render_debug_ui(
     mut contexts: EguiContexts,
     mut client_state: ResMut<ClientState>,
+    mut conditions: ResMut<NetworkConditions>,
     time: Res<Time>,
 ) {
     if !client_state.show_debug_ui {
@@ -65,12 +71,20 @@ pub fn render_debug_ui(
                 (client_state.interpolation_delay * 1000.0) as u32
             ));
 
+            ui.horizontal(|ui| {
+                ui.label("Extrapolation cap:");
+                ui.add(
+                    egui::Slider::new(&mut client_state.extrapolation_cap_ticks, 0.0..=5.0)
+                        .suffix(" ticks"),
+                );
+            });
+
             let mut total_buffers = 0;
             let mut total_snapshots = 0;
             let my_entity_id = client_state.my_entity_id;
 
             for (entity_id, entity) in client_state.visible_entities.iter() {
-                if Some(*entity_id) != my_entity_id && entity.tree.is_none() {
+                if Some(*entity_id) != my_entity_id && !entity.is_static() {
                     total_buffers += 1;
                     total_snapshots += entity.position_buffer.len();
                 }
@@ -81,6 +95,52 @@ pub fn render_debug_ui(
 
             ui.add_space(10.0);
 
+            ui.heading("Network");
+            ui.separator();
+
+            let stats = &client_state.net_stats;
+            ui.label(format!("Reliable: {} B/s", stats.reliable_bytes_per_sec));
+            ui.label(format!(
+                "Unreliable: {} B/s",
+                stats.unreliable_bytes_per_sec
+            ));
+            ui.label(format!("Messages: {}/s", stats.messages_per_sec));
+            ui.label(format!("Deltas: {:.1}/tick", stats.deltas_per_tick));
+            if stats.undecodable_messages > 0 {
+                ui.colored_label(
+                    egui::Color32::YELLOW,
+                    format!("Undecodable: {}", stats.undecodable_messages),
+                );
+            }
+            draw_bandwidth_graph(ui, &stats.history);
+
+            ui.add_space(10.0);
+
+            ui.heading("Network Conditions");
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                ui.label("Latency:");
+                ui.add(
+                    egui::Slider::new(&mut conditions.added_latency_ms, 0.0..=500.0).suffix(" ms"),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.label("Jitter:");
+                ui.add(egui::Slider::new(&mut conditions.jitter_ms, 0.0..=200.0).suffix(" ms"));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Loss:");
+                ui.add(egui::Slider::new(
+                    &mut conditions.loss_probability,
+                    0.0..=0.5,
+                ));
+            });
+            ui.label("Latency applies each way, loss only hits unreliable messages");
+            ui.label(format!("Held back: {}", conditions.queued()));
+
+            ui.add_space(10.0);
+
             ui.heading("Visualization");
             ui.separator();
 
@@ -96,11 +156,40 @@ pub fn render_debug_ui(
             );
             ui.label("Display interpolation buffer endpoints");
 
+            ui.checkbox(
+                &mut client_state.show_interest_radius,
+                "Show Interest Radius",
+            );
+            ui.label("Outline the view edge and the entities about to cross it");
+
+            ui.horizontal(|ui| {
+                ui.label("Label Density:");
+                ui.add(egui::Slider::new(
+                    &mut client_state.label_density_threshold,
+                    1..=10,
+                ));
+            });
+            ui.label("Overlapping labels beyond this are collapsed into a count");
+
             ui.add_space(10.0);
 
             ui.heading("Performance");
             ui.separator();
 
+            ui.label(format!(
+                "Tick Rate: {}ms",
+                (client_state.tick_rate * 1000.0) as u32
+            ));
+            ui.label(format!("View Distance: {}", client_state.view_distance));
+            ui.label(format!(
+                "Run: {} ({:.0}%), R to toggle",
+                if client_state.running { "on" } else { "off" },
+                client_state.run_energy / MAX_RUN_ENERGY * 100.0
+            ));
+            if let Some(eta) = client_state.path_eta_seconds() {
+                ui.label(format!("Path ETA: {:.1}s", eta));
+            }
+
             ui.label(format!("FPS: {:.0}", 1.0 / time.delta_seconds()));
             ui.label(format!("Time: {:.2}s", time.elapsed_seconds_f64()));
 
@@ -109,9 +198,32 @@ pub fn render_debug_ui(
             // Help text
             ui.label("Press F3 to toggle this window");
             ui.label("Press F4 to toggle ghost visuals");
+            ui.label("Press F1 to toggle the interest radius");
+            ui.label("Press F12 to start or stop recording server messages");
         });
 }
 
+/// simple bar graph of total bytes received per second
+fn draw_bandwidth_graph(ui: &mut egui::Ui, history: &VecDeque<u64>) {
+    let (rect, _) =
+        ui.allocate_exact_size(egui::vec2(ui.available_width(), 40.0), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 2.0, egui::Color32::from_black_alpha(80));
+
+    let peak = history.iter().copied().max().unwrap_or(0).max(1) as f32;
+    let bar_width = rect.width() / NET_STATS_HISTORY as f32;
+    for (i, bytes) in history.iter().enumerate() {
+        let height = rect.height() * (*bytes as f32 / peak);
+        let x = rect.left() + i as f32 * bar_width;
+        let bar = egui::Rect::from_min_max(
+            egui::pos2(x, rect.bottom() - height),
+            egui::pos2(x + bar_width - 1.0, rect.bottom()),
+        );
+        painter.rect_filled(bar, 0.0, egui::Color32::from_rgb(90, 170, 250));
+    }
+    ui.label(format!("Peak: {} B/s", peak as u64));
+}
+
 /// Handle debug keybinds
 pub fn handle_debug_keybinds(
     keyboard: Res<ButtonInput<KeyCode>>,
@@ -129,6 +241,42 @@ pub fn handle_debug_keybinds(
         );
     }
 
+    if keyboard.just_pressed(KeyCode::F8) {
+        client_state.show_skills_ui = !client_state.show_skills_ui;
+        info!(
+            "Skills UI: {}",
+            if client_state.show_skills_ui {
+                "ON"
+            } else {
+                "OFF"
+            }
+        );
+    }
+
+    if keyboard.just_pressed(KeyCode::F9) {
+        client_state.show_inventory_ui = !client_state.show_inventory_ui;
+        info!(
+            "Inventory UI: {}",
+            if client_state.show_inventory_ui {
+                "ON"
+            } else {
+                "OFF"
+            }
+        );
+    }
+
+    if keyboard.just_pressed(KeyCode::F2) {
+        client_state.show_minimap = !client_state.show_minimap;
+        info!(
+            "Minimap: {}",
+            if client_state.show_minimap {
+                "ON"
+            } else {
+                "OFF"
+            }
+        );
+    }
+
     if keyboard.just_pressed(KeyCode::F4) {
         let new_state = !client_state.show_prediction_ghosts;
         client_state.show_prediction_ghosts = new_state;
@@ -136,6 +284,29 @@ pub fn handle_debug_keybinds(
         info!("Ghost Visuals: {}", if new_state { "ON" } else { "OFF" });
     }
 
+    if keyboard.just_pressed(KeyCode::F1) {
+        client_state.show_interest_radius = !client_state.show_interest_radius;
+        info!(
+            "Interest radius: {}",
+            if client_state.show_interest_radius {
+                "ON"
+            } else {
+                "OFF"
+            }
+        );
+    }
+
+    if keyboard.just_pressed(KeyCode::F12) {
+        if client_state.recorder.is_recording() {
+            client_state.recorder.stop();
+        } else {
+            let path = recording_path();
+            if let Err(err) = client_state.recorder.start(&path) {
+                warn!("Could not start recording to {}: {}", path.display(), err);
+            }
+        }
+    }
+
     if keyboard.just_pressed(KeyCode::F5) {
         client_state.client_side_prediction = !client_state.client_side_prediction;
         info!(
@@ -0,0 +1,20 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::ClientState;
+
+/// centred notice with the reason the server gave for dropping us
+pub fn render_disconnect_notice(mut contexts: EguiContexts, client_state: Res<ClientState>) {
+    let Some(reason) = client_state.disconnect_reason.as_deref() else {
+        return;
+    };
+
+    let ctx = contexts.ctx_mut();
+    egui::Window::new("Disconnected")
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.label(reason);
+        });
+}
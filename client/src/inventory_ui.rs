@@ -0,0 +1,101 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use bevy_renet::renet::DefaultChannel;
+use shared::actions::GameAction;
+use shared::messages::ClientMessage;
+use shared::transport::ClientTransport;
+
+use crate::network_sim::{NetworkConditions, SimulatedTransport};
+use crate::systems::queue_action;
+use crate::ClientState;
+
+const INVENTORY_COLUMNS: usize = 4;
+const SLOT_SIZE: [f32; 2] = [64.0, 40.0];
+
+/// backpack grid. hovering a slot shows the item description, right-clicking it
+/// offers to eat, wield, drop or examine the item
+pub fn render_inventory_ui<T: ClientTransport + Resource>(
+    mut contexts: EguiContexts,
+    mut client: ResMut<T>,
+    mut conditions: ResMut<NetworkConditions>,
+    mut client_state: ResMut<ClientState>,
+    time: Res<Time>,
+) {
+    if !client_state.show_inventory_ui {
+        return;
+    }
+
+    let ctx = contexts.ctx_mut();
+    let mut actions = Vec::new();
+    let mut examine = None;
+
+    egui::Window::new("Inventory")
+        .default_pos([700.0, 10.0])
+        .resizable(false)
+        .show(ctx, |ui| {
+            egui::Grid::new("inventory_grid")
+                .spacing([4.0, 4.0])
+                .show(ui, |ui| {
+                    for (slot, stack) in client_state.inventory.slots.iter().enumerate() {
+                        match stack {
+                            Some(stack) => {
+                                let def = client_state.definitions.item(stack.item_type);
+                                let text = if def.stackable {
+                                    format!("{}\nx{}", def.name, stack.quantity)
+                                } else {
+                                    def.name.to_string()
+                                };
+                                let response = ui
+                                    .add_sized(SLOT_SIZE, egui::Button::new(text).wrap())
+                                    .on_hover_text(def.description);
+                                response.context_menu(|ui| {
+                                    if def.on_use.is_some() && ui.button("Eat").clicked() {
+                                        actions.push(GameAction::UseItem { slot });
+                                        ui.close_menu();
+                                    }
+                                    if def.equip_slot.is_some() && ui.button("Wield").clicked() {
+                                        actions.push(GameAction::Equip { slot });
+                                        ui.close_menu();
+                                    }
+                                    if ui.button("Drop").clicked() {
+                                        actions.push(GameAction::DropItem { slot });
+                                        ui.close_menu();
+                                    }
+                                    if ui.button("Examine").clicked() {
+                                        examine = Some(slot);
+                                        ui.close_menu();
+                                    }
+                                });
+                            }
+                            None => {
+                                ui.add_enabled(
+                                    false,
+                                    egui::Button::new("").min_size(SLOT_SIZE.into()),
+                                );
+                            }
+                        }
+
+                        if (slot + 1) % INVENTORY_COLUMNS == 0 {
+                            ui.end_row();
+                        }
+                    }
+                });
+
+            ui.label("Press F9 to toggle this window");
+        });
+
+    let mut client = SimulatedTransport {
+        inner: &mut *client,
+        conditions: &mut conditions,
+        now: time.elapsed_seconds_f64(),
+    };
+    for action in actions {
+        queue_action(action, &mut client, &mut client_state);
+    }
+    if let Some(slot) = examine {
+        let msg = ClientMessage::ExamineItem { slot };
+        if let Ok(msg_bytes) = bincode::serialize(&msg) {
+            client.send_message(DefaultChannel::ReliableOrdered, msg_bytes);
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use bevy_egui::{egui, EguiContexts};
+use shared::actions::GameActionKind;
+
+use crate::ClientState;
+
+/// screen-space size of a label above an entity, used for overlap detection
+pub const LABEL_SIZE: Vec2 = Vec2::new(72.0, 18.0);
+
+/// which entity labels should be drawn this frame
+#[derive(Resource, Default)]
+pub struct LabelLayout {
+    pub visible: HashSet<u64>,
+    pub collapsed: Vec<LabelCluster>,
+}
+
+/// labels hidden from an overlapping group, drawn as a single "+N" badge
+pub struct LabelCluster {
+    pub screen_position: Vec2,
+    pub count: usize,
+}
+
+impl LabelLayout {
+    pub fn is_visible(&self, entity_id: u64) -> bool {
+        self.visible.contains(&entity_id)
+    }
+}
+
+struct LabelCandidate {
+    entity_id: u64,
+    screen_position: Vec2,
+    cursor_distance: f32,
+}
+
+fn labels_overlap(a: Vec2, b: Vec2) -> bool {
+    (a.x - b.x).abs() < LABEL_SIZE.x && (a.y - b.y).abs() < LABEL_SIZE.y
+}
+
+/// groups overlapping labels and keeps only the ones nearest the cursor in each group,
+/// the rest are collapsed into a count
+pub fn layout_entity_labels(
+    client_state: Res<ClientState>,
+    windows: Query<&Window>,
+    camera_q: Query<(&Camera, &GlobalTransform)>,
+    mut layout: ResMut<LabelLayout>,
+) {
+    layout.visible.clear();
+    layout.collapsed.clear();
+
+    let Ok((camera, camera_transform)) = camera_q.get_single() else {
+        return;
+    };
+    let focus = windows.get_single().ok().and_then(|window| {
+        window
+            .cursor_position()
+            .or(Some(Vec2::new(window.width(), window.height()) / 2.0))
+    });
+
+    let mut candidates: Vec<LabelCandidate> = client_state
+        .visible_entities
+        .iter()
+        .filter(|(_, entity)| entity.player_id.is_some())
+        .filter_map(|(entity_id, entity)| {
+            let world = entity
+                .interpolated_world
+                .unwrap_or_else(|| entity.tile_position.to_world())
+                .extend(0.0);
+            camera
+                .world_to_viewport(camera_transform, world)
+                .map(|screen_position| LabelCandidate {
+                    entity_id: *entity_id,
+                    screen_position,
+                    cursor_distance: focus.map(|f| f.distance(screen_position)).unwrap_or(0.0),
+                })
+        })
+        .collect();
+
+    candidates.sort_by(|a, b| a.cursor_distance.total_cmp(&b.cursor_distance));
+
+    // greedy clustering: each label joins the first group whose anchor it overlaps
+    let mut groups: Vec<Vec<LabelCandidate>> = Vec::new();
+    for candidate in candidates {
+        match groups
+            .iter_mut()
+            .find(|group| labels_overlap(group[0].screen_position, candidate.screen_position))
+        {
+            Some(group) => group.push(candidate),
+            None => groups.push(vec![candidate]),
+        }
+    }
+
+    let threshold = client_state.label_density_threshold.max(1);
+    for group in groups {
+        for candidate in group.iter().take(threshold) {
+            layout.visible.insert(candidate.entity_id);
+        }
+        if group.len() > threshold {
+            layout.collapsed.push(LabelCluster {
+                screen_position: group[0].screen_position,
+                count: group.len() - threshold,
+            });
+        }
+    }
+}
+
+fn action_label(kind: GameActionKind) -> Option<&'static str> {
+    match kind {
+        GameActionKind::ChopTree => Some("Chopping"),
+        GameActionKind::Mine => Some("Mining"),
+        GameActionKind::Attack => Some("Fighting"),
+        GameActionKind::Deposit | GameActionKind::Withdraw => Some("Banking"),
+        // moving is already visible, the rest are over within a tick
+        _ => None,
+    }
+}
+
+/// names what each labelled player is busy with, e.g. "Chopping"
+pub fn render_action_labels(
+    mut contexts: EguiContexts,
+    client_state: Res<ClientState>,
+    layout: Res<LabelLayout>,
+    camera_q: Query<(&Camera, &GlobalTransform)>,
+) {
+    let Ok((camera, camera_transform)) = camera_q.get_single() else {
+        return;
+    };
+
+    let ctx = contexts.ctx_mut();
+    for (entity_id, entity) in &client_state.visible_entities {
+        let text = match entity.current_action.and_then(action_label) {
+            Some(text) if layout.is_visible(*entity_id) => text,
+            _ => continue,
+        };
+        let world = entity
+            .interpolated_world
+            .unwrap_or_else(|| entity.tile_position.to_world())
+            .extend(0.0);
+        let Some(screen) = camera.world_to_viewport(camera_transform, world) else {
+            continue;
+        };
+
+        let pos = screen - Vec2::new(LABEL_SIZE.x / 2.0, LABEL_SIZE.y * 2.0);
+        egui::Area::new(egui::Id::new(("action_label", *entity_id)))
+            .fixed_pos(egui::pos2(pos.x, pos.y))
+            .interactable(false)
+            .show(ctx, |ui| {
+                ui.label(
+                    egui::RichText::new(text)
+                        .color(egui::Color32::LIGHT_YELLOW)
+                        .background_color(egui::Color32::from_black_alpha(160)),
+                );
+            });
+    }
+}
+
+/// the name of each labelled remote player, above their action label
+pub fn render_name_labels(
+    mut contexts: EguiContexts,
+    client_state: Res<ClientState>,
+    layout: Res<LabelLayout>,
+    camera_q: Query<(&Camera, &GlobalTransform)>,
+) {
+    let Ok((camera, camera_transform)) = camera_q.get_single() else {
+        return;
+    };
+
+    let ctx = contexts.ctx_mut();
+    for (entity_id, entity) in &client_state.visible_entities {
+        if Some(*entity_id) == client_state.my_entity_id || !layout.is_visible(*entity_id) {
+            continue;
+        }
+        let Some(name) = entity.player_name.as_deref() else {
+            continue;
+        };
+        let world = entity
+            .interpolated_world
+            .unwrap_or_else(|| entity.tile_position.to_world())
+            .extend(0.0);
+        let Some(screen) = camera.world_to_viewport(camera_transform, world) else {
+            continue;
+        };
+
+        let pos = screen - Vec2::new(LABEL_SIZE.x / 2.0, LABEL_SIZE.y * 3.0);
+        egui::Area::new(egui::Id::new(("name_label", *entity_id)))
+            .fixed_pos(egui::pos2(pos.x, pos.y))
+            .interactable(false)
+            .show(ctx, |ui| {
+                ui.label(
+                    egui::RichText::new(name)
+                        .color(egui::Color32::WHITE)
+                        .background_color(egui::Color32::from_black_alpha(160)),
+                );
+            });
+    }
+}
+
+/// what the hovered entity is, just below it
+pub fn render_hover_tooltip(
+    mut contexts: EguiContexts,
+    client_state: Res<ClientState>,
+    camera_q: Query<(&Camera, &GlobalTransform)>,
+) {
+    let Some(entity_id) = client_state.hover_entity else {
+        return;
+    };
+    let Some(entity) = client_state.visible_entities.get(&entity_id) else {
+        return;
+    };
+    let Ok((camera, camera_transform)) = camera_q.get_single() else {
+        return;
+    };
+    let world = entity
+        .interpolated_world
+        .unwrap_or_else(|| entity.tile_position.to_world())
+        .extend(0.0);
+    let Some(screen) = camera.world_to_viewport(camera_transform, world) else {
+        return;
+    };
+
+    let pos = screen + Vec2::new(-LABEL_SIZE.x / 2.0, LABEL_SIZE.y * 1.5);
+    egui::Area::new(egui::Id::new("hover_tooltip"))
+        .fixed_pos(egui::pos2(pos.x, pos.y))
+        .interactable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(
+                egui::RichText::new(entity.describe(&client_state.definitions))
+                    .color(egui::Color32::WHITE)
+                    .background_color(egui::Color32::from_black_alpha(200)),
+            );
+        });
+}
+
+/// draws a "+N" badge for each group of labels that was collapsed
+pub fn render_label_clusters(mut contexts: EguiContexts, layout: Res<LabelLayout>) {
+    if layout.collapsed.is_empty() {
+        return;
+    }
+
+    let ctx = contexts.ctx_mut();
+    for (index, cluster) in layout.collapsed.iter().enumerate() {
+        let pos = cluster.screen_position - Vec2::new(0.0, LABEL_SIZE.y * 2.0);
+        egui::Area::new(egui::Id::new(("label_cluster", index)))
+            .fixed_pos(egui::pos2(pos.x, pos.y))
+            .interactable(false)
+            .show(ctx, |ui| {
+                ui.label(
+                    egui::RichText::new(format!("+{}", cluster.count))
+                        .color(egui::Color32::WHITE)
+                        .background_color(egui::Color32::from_black_alpha(160)),
+                );
+            });
+    }
+}
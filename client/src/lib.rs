@@ -2,21 +2,43 @@ use std::net::{SocketAddr, UdpSocket};
 use std::time::SystemTime;
 
 use bevy::prelude::*;
-use bevy::utils::tracing::info;
+use bevy::utils::tracing::{info, warn};
 use bevy::utils::HashMap;
 use bevy_renet::renet::transport::{ClientAuthentication, NetcodeClientTransport};
 use bevy_renet::renet::*;
 
-use shared::actions::GameAction;
+use shared::actions::{GameAction, GameActionKind};
+use shared::bank::Bank;
+use shared::definitions::Definitions;
+use shared::identity::account_to_user_data;
 use shared::inventory::Inventory;
+use shared::items::{Equipment, ItemStack};
+use shared::mining::{Rock, RockDefinition};
 use shared::pathfinding::Pathfinder;
 use shared::skills::{SkillData, SkillType};
+use shared::tile_map::TileMap;
 use shared::tile_system::TilePosition;
 use shared::trees::Tree;
 use shared::*;
 
+use crate::achievements_ui::AchievementToast;
+use crate::context_menu::ContextMenu;
+use crate::net_stats::NetworkStats;
+use crate::replay::ReplayRecorder;
+
+pub mod achievements_ui;
+pub mod bank_ui;
 pub mod camera;
+pub mod context_menu;
 pub mod debug_ui;
+pub mod disconnect_ui;
+pub mod inventory_ui;
+pub mod labels;
+pub mod minimap;
+pub mod net_stats;
+pub mod network_sim;
+pub mod replay;
+pub mod skills_ui;
 pub mod systems;
 
 #[derive(Component)]
@@ -27,29 +49,99 @@ pub struct NetworkedEntity {
     pub entity_id: u64,
 }
 
+/// file the client's persistent account id is kept in between runs
+pub const ACCOUNT_FILE: &str = "account_id.txt";
+
 #[derive(Resource)]
 pub struct ClientState {
+    pub account_id: String,
+    pub disconnect_reason: Option<String>, // why the server dropped us (kick, shutdown)
     pub my_player_id: Option<PlayerId>,
     pub my_entity_id: Option<u64>,
     pub visible_entities: HashMap<u64, ClientEntity>,
     pub current_position: Option<TilePosition>,
     pub pending_move: Option<TilePosition>,
     pub pathfinder: Pathfinder,
+    pub tile_map: TileMap,
+    /// what the server sent over the built-in definitions, dropped with the connection
+    pub definitions: Definitions,
+    pub portals: Vec<TilePosition>, // tiles that lead to another zone
     pub path_preview: Option<Vec<TilePosition>>,
     pub confirmed_path: Option<Vec<TilePosition>>,
     pub inventory: Inventory,
+    pub equipment: Equipment,
+    pub bank: Option<Inventory>, // contents of the bank while it's open
     pub skills: HashMap<SkillType, SkillData>,
+    /// experience left until each skill's next level, as reported by the server
+    pub xp_to_next: HashMap<SkillType, u32>,
     pub hover_entity: Option<u64>,
+    pub context_menu: Option<ContextMenu>, // open right-click menu, if any
+    pub examine_text: Option<String>,      // last thing examined, shown until closed
+    pub achievement_toasts: Vec<AchievementToast>,
     pub join_sent: bool,
     pub input_sequence_number: u32,
     pub pending_inputs: Vec<PendingInput>,
+    pub predicted_action: Option<PredictedAction>,
+    pub predicted_walk: Option<PredictedWalk>,
+    pub net_stats: NetworkStats,
     pub client_side_prediction: bool,
     pub server_reconciliation: bool,
     pub entity_interpolation: bool,
     pub interpolation_delay: f64, // delay in seconds (render timestamp = now - delay)
+    pub extrapolation_cap_ticks: f64, // how far past the newest snapshot remote entities keep moving
+    pub tick_rate: f32,               // seconds per server tick, resynced by the server
+    pub server_tick: u64,             // newest server tick heard of
+    pub server_tick_at: f64,          // when it arrived
+    pub view_distance: i32,           // tiles, sent by the server on join
+    pub running: bool,                // run toggle, as confirmed by the server
+    pub run_energy: f32,
     pub show_debug_ui: bool,
+    pub show_skills_ui: bool,
+    pub show_inventory_ui: bool,
+    pub show_minimap: bool,
     pub show_prediction_ghosts: bool,
     pub show_interpolation_ghosts: bool,
+    pub show_interest_radius: bool, // edge of what the server sends us
+    pub label_density_threshold: usize, // max labels shown per overlapping group
+    pub recorder: ReplayRecorder,
+    pub desync_frames: u32, // frames in a row our entity has been far from the server's position
+    pub spectating: bool,   // join without an entity, see `SPECTATE`
+    pub spectator_center: Option<TilePosition>, // where a spectator is looking
+}
+
+/// a gathering action shown locally before the server confirms it. dropped again if
+/// the server rejects it or never acknowledges it
+#[derive(Clone, Debug)]
+pub struct PredictedAction {
+    pub input_sequence_number: u32,
+    pub action: GameAction,
+    pub started_at: f64,
+    pub confirmed: bool,
+}
+
+/// a walk we've sent and are stepping through ourselves, as many tiles per tick as the
+/// server moves us, so the prediction keeps pace with the server instead of jumping
+#[derive(Clone, Debug)]
+pub struct PredictedWalk {
+    pub input_sequence_number: u32,
+    pub origin: TilePosition, // where the walk started, not part of `path`
+    pub path: Vec<TilePosition>,
+    pub steps_taken: usize, // tiles of `path` walked so far
+    pub next_step_at: f64,
+}
+
+impl PredictedWalk {
+    /// the tile the walk has got to
+    pub fn position(&self) -> TilePosition {
+        match self.steps_taken {
+            0 => self.origin,
+            steps => self.path[steps - 1],
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.steps_taken >= self.path.len()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -62,61 +154,240 @@ pub struct PendingInput {
 pub struct PositionSnapshot {
     pub timestamp: f64, // time in seconds since startup
     pub position: TilePosition,
+    pub velocity: Option<(i32, i32)>, // tiles per tick, as reported by the server
 }
 
 pub struct ClientEntity {
+    pub kind: EntityKind,
     pub tile_position: TilePosition,
     pub player_id: Option<PlayerId>,
     pub entity: Entity,
     pub tree: Option<Tree>,
+    pub rock: Option<Rock>,
+    pub bank: Option<Bank>,
+    pub ground_item: Option<ItemStack>,
     pub position_buffer: Vec<PositionSnapshot>,
     pub server_position: TilePosition,
-    pub interpolated_position: Option<TilePosition>,
+    pub interpolated_position: Option<TilePosition>, // tile-snapped, for logic
+    pub interpolated_world: Option<Vec2>,            // smooth world position, for rendering
+    pub health: Option<u32>,
+    pub current_action: Option<GameActionKind>, // what a remote entity is busy with, if known
+    pub action_started_at: Option<f64>,         // local time the current action was seen starting
+    pub position_tick: u64,                     // server tick of the newest position applied
+    pub player_name: Option<String>,
+}
+
+impl ClientEntity {
+    /// scenery that never moves (trees, rocks, banks)
+    pub fn is_static(&self) -> bool {
+        self.tree.is_some() || self.rock.is_some() || self.bank.is_some()
+    }
+
+    /// what the entity is, for the hover tooltip
+    pub fn describe(&self, definitions: &Definitions) -> String {
+        if let Some(ref tree) = self.tree {
+            let name = definitions.tree(tree.tree_type).name;
+            if tree.is_chopped {
+                format!("{} (stump)", name)
+            } else {
+                name.to_string()
+            }
+        } else if let Some(ref rock) = self.rock {
+            let name = RockDefinition::get(rock.rock_type).name;
+            if rock.is_depleted {
+                format!("{} (depleted)", name)
+            } else {
+                name.to_string()
+            }
+        } else if self.bank.is_some() {
+            "Bank".to_string()
+        } else if let Some(ref item) = self.ground_item {
+            format!(
+                "{} x{}",
+                definitions.item(item.item_type).name,
+                item.quantity
+            )
+        } else {
+            match self.player_name {
+                Some(ref name) => name.clone(),
+                None => "Player".to_string(),
+            }
+        }
+    }
 }
 
 impl Default for ClientState {
     fn default() -> Self {
         Self {
+            account_id: String::new(),
+            disconnect_reason: None,
             my_player_id: None,
             my_entity_id: None,
             visible_entities: HashMap::new(),
             current_position: None,
             pending_move: None,
             pathfinder: Pathfinder::new(false),
+            tile_map: TileMap::default(),
+            definitions: Definitions::default(),
+            portals: Vec::new(),
             path_preview: None,
             confirmed_path: None,
             inventory: Inventory::new(28),
+            equipment: Equipment::new(),
+            bank: None,
             skills: HashMap::new(),
+            xp_to_next: HashMap::new(),
             hover_entity: None,
+            context_menu: None,
+            examine_text: None,
+            achievement_toasts: Vec::new(),
             join_sent: false,
             input_sequence_number: 0,
             pending_inputs: Vec::new(),
+            predicted_action: None,
+            predicted_walk: None,
+            net_stats: NetworkStats::default(),
             client_side_prediction: true,
             server_reconciliation: true,
             entity_interpolation: true,
             interpolation_delay: 0.1,
+            extrapolation_cap_ticks: 2.0,
+            tick_rate: TICK_RATE,
+            server_tick: 0,
+            server_tick_at: 0.0,
+            view_distance: VIEW_DISTANCE,
+            running: false,
+            run_energy: MAX_RUN_ENERGY,
             show_debug_ui: true,
+            show_skills_ui: true,
+            show_inventory_ui: true,
+            show_minimap: true,
             show_prediction_ghosts: true,
             show_interpolation_ghosts: true,
+            show_interest_radius: true,
+            label_density_threshold: 3,
+            recorder: ReplayRecorder::default(),
+            desync_frames: 0,
+            spectating: false,
+            spectator_center: None,
         }
     }
 }
 
-pub fn setup_client(mut commands: Commands) {
+impl ClientState {
+    /// length of one server tick in seconds
+    pub fn tick_duration(&self) -> f64 {
+        self.tick_rate as f64
+    }
+
+    /// remembers the newest server tick a message was stamped with
+    pub fn note_server_tick(&mut self, tick: u64, now: f64) {
+        if tick > self.server_tick {
+            self.server_tick = tick;
+            self.server_tick_at = now;
+        }
+    }
+
+    /// the server's tick right now, run forward from the newest one heard of
+    pub fn estimated_server_tick(&self, now: f64) -> f64 {
+        self.server_tick as f64 + (now - self.server_tick_at).max(0.0) / self.tick_duration()
+    }
+
+    /// whether our own entity goes through the interpolation buffer like everyone
+    /// else's, which it does when it isn't predicted
+    pub fn interpolates_local_player(&self) -> bool {
+        !self.client_side_prediction && self.entity_interpolation
+    }
+
+    /// how far in the past remote entities are drawn, sent with actions so the server
+    /// can validate them against what we saw
+    pub fn render_delay(&self) -> f32 {
+        if self.entity_interpolation {
+            self.interpolation_delay as f32
+        } else {
+            0.0
+        }
+    }
+
+    /// tiles the server moves the local player per tick
+    pub fn steps_per_tick(&self) -> usize {
+        if self.running && self.run_energy > 0.0 {
+            RUN_STEPS
+        } else {
+            1
+        }
+    }
+
+    /// estimated seconds until the local player reaches the end of the confirmed path
+    pub fn path_eta_seconds(&self) -> Option<f64> {
+        self.confirmed_path
+            .as_ref()
+            .map(|path| path.len().div_ceil(self.steps_per_tick()) as f64 * self.tick_duration())
+    }
+}
+
+/// persistent identity sent with `Join` so the server can restore our profile.
+/// `PLAYER_ACCOUNT` overrides it, which is handy when running several clients
+pub fn load_account_id() -> String {
+    if let Ok(account_id) = std::env::var("PLAYER_ACCOUNT") {
+        return account_id;
+    }
+
+    if let Ok(contents) = std::fs::read_to_string(ACCOUNT_FILE) {
+        let account_id = contents.trim();
+        if !account_id.is_empty() {
+            return account_id.to_string();
+        }
+    }
+
+    let account_id = random_uuid();
+    if let Err(err) = std::fs::write(ACCOUNT_FILE, &account_id) {
+        warn!("Could not save account id to {}: {}", ACCOUNT_FILE, err);
+    }
+    account_id
+}
+
+/// a random (version 4) uuid, e.g. `3f2c8a1e-7b4d-4e0a-9c61-5d8f2b7a4e19`
+fn random_uuid() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+pub fn setup_client(mut commands: Commands, mut client_state: ResMut<ClientState>) {
     commands.spawn(Camera2dBundle::default());
 
+    client_state.account_id = load_account_id();
+    // watches the world from a free camera instead of playing, for observing netcode
+    client_state.spectating = std::env::var("SPECTATE").is_ok();
+
     let server_addr: SocketAddr = format!("127.0.0.1:{}", SERVER_PORT).parse().unwrap();
     let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
     let current_time = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap();
-    let client_id = current_time.as_millis() as u64;
+    // random so clients started at the same moment don't collide, who we are
+    // is the account id in the user data
+    let client_id = rand::random::<u64>();
+    let user_data = account_to_user_data(&client_state.account_id);
+    if user_data.is_none() {
+        warn!("Account id doesn't fit in the connect token, the server won't see it");
+    }
 
     let authentication = ClientAuthentication::Unsecure {
         client_id,
         protocol_id: PROTOCOL_ID,
         server_addr,
-        user_data: None,
+        user_data,
     };
 
     let transport = NetcodeClientTransport::new(current_time, authentication, socket).unwrap();
@@ -128,6 +399,7 @@ pub fn setup_client(mut commands: Commands) {
     info!("Client starting...");
     info!("Connecting to server at {}", server_addr);
     info!("Client ID: {}", client_id);
+    info!("Account: {}", client_state.account_id);
     info!("Protocol ID: {}", PROTOCOL_ID);
     info!("");
     info!("Controls:");
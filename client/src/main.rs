@@ -1,39 +1,89 @@
 use bevy::prelude::*;
 use bevy_egui::EguiPlugin;
+use bevy_renet::renet::RenetClient;
 use bevy_renet::transport::NetcodeClientPlugin;
 use bevy_renet::*;
 use client::{
+    achievements_ui::render_achievement_toasts,
+    bank_ui::render_bank_ui,
     camera::{
-        camera_follow_player, draw_netcode_ghosts, draw_tile_grid, update_entity_positions,
-        update_tree_visuals,
+        animate_predicted_action, animate_remote_actions, camera_follow_player, draw_health_bars,
+        draw_netcode_ghosts, draw_tile_grid, update_entity_positions, update_tree_visuals,
     },
+    context_menu::render_context_menu,
     debug_ui::{handle_debug_keybinds, render_debug_ui},
+    disconnect_ui::render_disconnect_notice,
+    inventory_ui::render_inventory_ui,
+    labels::{
+        layout_entity_labels, render_action_labels, render_hover_tooltip, render_label_clusters,
+        render_name_labels, LabelLayout,
+    },
+    minimap::render_minimap,
+    network_sim::NetworkConditions,
+    replay::{replay_path_from_args, run_replay_file},
     setup_client,
-    systems::{client_update_system, interpolate_entities, update_confirmed_path},
+    skills_ui::render_skills_ui,
+    systems::{
+        advance_predicted_walk, client_update_system, expire_predicted_action,
+        interpolate_entities, update_confirmed_path,
+    },
     ClientState,
 };
 
 fn main() {
+    if let Some(path) = replay_path_from_args() {
+        if let Err(err) = run_replay_file(&path) {
+            eprintln!("Replay failed: {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     App::new()
         .add_plugins(DefaultPlugins)
         .add_plugins(EguiPlugin)
         .add_plugins(RenetClientPlugin)
         .add_plugins(NetcodeClientPlugin)
         .init_resource::<ClientState>()
+        .init_resource::<LabelLayout>()
+        .init_resource::<NetworkConditions>()
         .add_systems(Startup, setup_client)
         .add_systems(
             Update,
             (
                 handle_debug_keybinds,
-                client_update_system,
+                client_update_system::<RenetClient>,
+                advance_predicted_walk.after(client_update_system::<RenetClient>),
                 interpolate_entities,
                 update_entity_positions,
                 update_confirmed_path,
                 update_tree_visuals,
+                expire_predicted_action,
+                animate_predicted_action,
+                animate_remote_actions,
                 draw_netcode_ghosts,
                 draw_tile_grid,
+                draw_health_bars,
                 camera_follow_player,
-                render_debug_ui,
+                (
+                    layout_entity_labels,
+                    render_label_clusters,
+                    render_action_labels,
+                    render_name_labels,
+                    render_hover_tooltip,
+                )
+                    .chain(),
+                (
+                    render_debug_ui,
+                    render_disconnect_notice,
+                    render_minimap,
+                    render_skills_ui,
+                    render_inventory_ui::<RenetClient>,
+                    render_bank_ui::<RenetClient>,
+                    render_achievement_toasts,
+                ),
+                // after the update so a click that dismisses the menu isn't also a walk
+                render_context_menu::<RenetClient>.after(client_update_system::<RenetClient>),
             ),
         )
         .run();
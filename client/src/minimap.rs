@@ -0,0 +1,119 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use shared::tile_system::TilePosition;
+
+use crate::{ClientEntity, ClientState};
+
+/// width and height of the map in points
+const MINIMAP_SIZE: f32 = 160.0;
+
+const BACKGROUND: egui::Color32 = egui::Color32::from_rgb(40, 60, 35);
+const OBSTACLE: egui::Color32 = egui::Color32::from_rgb(25, 25, 25);
+const PORTAL: egui::Color32 = egui::Color32::from_rgb(150, 80, 200);
+const TREE: egui::Color32 = egui::Color32::from_rgb(30, 140, 40);
+const STUMP: egui::Color32 = egui::Color32::from_rgb(110, 80, 50);
+const ROCK: egui::Color32 = egui::Color32::from_rgb(130, 130, 130);
+const BANK: egui::Color32 = egui::Color32::from_rgb(220, 180, 40);
+const GROUND_ITEM: egui::Color32 = egui::Color32::from_rgb(240, 230, 140);
+const PLAYER: egui::Color32 = egui::Color32::from_rgb(90, 170, 250);
+const SELF: egui::Color32 = egui::Color32::WHITE;
+
+/// top-down view of everything within view distance, north up with the local player
+/// in the middle. drawn from what the client already knows, nothing is asked of the
+/// server
+pub fn render_minimap(mut contexts: EguiContexts, client_state: Res<ClientState>) {
+    if !client_state.show_minimap {
+        return;
+    }
+
+    let my_entity = client_state
+        .my_entity_id
+        .and_then(|id| client_state.visible_entities.get(&id));
+    let center = my_entity
+        .map(|entity| entity.tile_position)
+        .or(client_state.spectator_center)
+        .unwrap_or_default();
+    let range = client_state.view_distance.max(1);
+    let tile_size = MINIMAP_SIZE / (2 * range + 1) as f32;
+
+    let ctx = contexts.ctx_mut();
+    egui::Window::new("Minimap")
+        .default_pos([10.0, 400.0])
+        .resizable(false)
+        .show(ctx, |ui| {
+            let (rect, _) = ui
+                .allocate_exact_size(egui::vec2(MINIMAP_SIZE, MINIMAP_SIZE), egui::Sense::hover());
+            let painter = ui.painter_at(rect);
+            painter.rect_filled(rect, 0.0, BACKGROUND);
+
+            // tile y grows northwards, screen y downwards
+            let tile_rect = |pos: TilePosition| {
+                let (dx, dy) = (pos.x - center.x, pos.y - center.y);
+                if dx.abs() > range || dy.abs() > range {
+                    return None;
+                }
+                let min = egui::pos2(
+                    rect.left() + (dx + range) as f32 * tile_size,
+                    rect.top() + (range - dy) as f32 * tile_size,
+                );
+                Some(egui::Rect::from_min_size(
+                    min,
+                    egui::vec2(tile_size, tile_size),
+                ))
+            };
+
+            for x in center.x - range..=center.x + range {
+                for y in center.y - range..=center.y + range {
+                    let pos = TilePosition { x, y };
+                    if client_state.pathfinder.is_walkable(&pos) {
+                        continue;
+                    }
+                    if let Some(tile) = tile_rect(pos) {
+                        painter.rect_filled(tile, 0.0, OBSTACLE);
+                    }
+                }
+            }
+
+            for portal in &client_state.portals {
+                if let Some(tile) = tile_rect(*portal) {
+                    painter.rect_filled(tile, 0.0, PORTAL);
+                }
+            }
+
+            for (entity_id, entity) in &client_state.visible_entities {
+                if Some(*entity_id) == client_state.my_entity_id {
+                    continue;
+                }
+                let pos = entity.interpolated_position.unwrap_or(entity.tile_position);
+                if let Some(tile) = tile_rect(pos) {
+                    painter.rect_filled(tile.shrink(tile_size * 0.15), 0.0, entity_color(entity));
+                }
+            }
+
+            if my_entity.is_some() {
+                if let Some(tile) = tile_rect(center) {
+                    painter.circle_filled(tile.center(), tile_size * 0.45, SELF);
+                }
+            }
+
+            ui.label("Press F2 to toggle this window");
+        });
+}
+
+fn entity_color(entity: &ClientEntity) -> egui::Color32 {
+    if let Some(ref tree) = entity.tree {
+        if tree.is_chopped {
+            STUMP
+        } else {
+            TREE
+        }
+    } else if entity.rock.is_some() {
+        ROCK
+    } else if entity.bank.is_some() {
+        BANK
+    } else if entity.ground_item.is_some() {
+        GROUND_ITEM
+    } else {
+        PLAYER
+    }
+}
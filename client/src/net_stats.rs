@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+
+/// seconds of history kept for the bandwidth graph
+pub const NET_STATS_HISTORY: usize = 30;
+
+/// network counters for the debug UI. counts accumulate over one second and are then
+/// rolled into the per-second figures, so nothing grows for the length of a session
+#[derive(Default)]
+pub struct NetworkStats {
+    window_start: f64,
+    reliable_bytes: u64,
+    unreliable_bytes: u64,
+    messages: u64,
+    deltas_applied: u64,
+    delta_updates: u64,
+
+    pub reliable_bytes_per_sec: u64,
+    pub unreliable_bytes_per_sec: u64,
+    pub messages_per_sec: u64,
+    pub deltas_per_tick: f32,
+    /// messages from the server that failed to decode, over the whole session
+    pub undecodable_messages: u64,
+    /// total bytes received for each of the last `NET_STATS_HISTORY` seconds
+    pub history: VecDeque<u64>,
+}
+
+impl NetworkStats {
+    pub fn record_reliable(&mut self, bytes: usize) {
+        self.reliable_bytes = self.reliable_bytes.saturating_add(bytes as u64);
+        self.messages = self.messages.saturating_add(1);
+    }
+
+    pub fn record_unreliable(&mut self, bytes: usize) {
+        self.unreliable_bytes = self.unreliable_bytes.saturating_add(bytes as u64);
+        self.messages = self.messages.saturating_add(1);
+    }
+
+    pub fn record_undecodable(&mut self) {
+        self.undecodable_messages = self.undecodable_messages.saturating_add(1);
+    }
+
+    /// one `DeltaUpdate` (one server tick) carrying `deltas` entity changes
+    pub fn record_deltas(&mut self, deltas: usize) {
+        self.deltas_applied = self.deltas_applied.saturating_add(deltas as u64);
+        self.delta_updates = self.delta_updates.saturating_add(1);
+    }
+
+    /// rolls the counters over once a full second has passed
+    pub fn update(&mut self, now: f64) {
+        if now - self.window_start < 1.0 {
+            return;
+        }
+
+        self.reliable_bytes_per_sec = self.reliable_bytes;
+        self.unreliable_bytes_per_sec = self.unreliable_bytes;
+        self.messages_per_sec = self.messages;
+        self.deltas_per_tick = if self.delta_updates > 0 {
+            self.deltas_applied as f32 / self.delta_updates as f32
+        } else {
+            0.0
+        };
+
+        self.history
+            .push_back(self.reliable_bytes + self.unreliable_bytes);
+        while self.history.len() > NET_STATS_HISTORY {
+            self.history.pop_front();
+        }
+
+        self.reliable_bytes = 0;
+        self.unreliable_bytes = 0;
+        self.messages = 0;
+        self.deltas_applied = 0;
+        self.delta_updates = 0;
+        self.window_start = now;
+    }
+}
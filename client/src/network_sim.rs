@@ -0,0 +1,152 @@
+use bevy::prelude::*;
+use bevy_renet::renet::DefaultChannel;
+use shared::transport::ClientTransport;
+
+/// artificial latency, jitter and packet loss applied inside the app loop. the real
+/// socket is untouched, messages are only held back or dropped on their way in and out
+#[derive(Resource, Default)]
+pub struct NetworkConditions {
+    /// one-way delay added in each direction
+    pub added_latency_ms: f32,
+    /// random extra delay in either direction, up to this much
+    pub jitter_ms: f32,
+    /// chance that an unreliable message is dropped, reliable ones are only delayed
+    pub loss_probability: f32,
+    outgoing: Vec<DelayedMessage>,
+    incoming: Vec<DelayedMessage>,
+    // reliable messages may not overtake each other, so each one is delivered no
+    // earlier than the previous one on the same direction
+    last_reliable_out: f64,
+    last_reliable_in: f64,
+}
+
+struct DelayedMessage {
+    deliver_at: f64,
+    channel: DefaultChannel,
+    message: Vec<u8>,
+}
+
+impl NetworkConditions {
+    pub fn is_active(&self) -> bool {
+        self.added_latency_ms > 0.0 || self.jitter_ms > 0.0 || self.loss_probability > 0.0
+    }
+
+    /// messages currently held back in either direction
+    pub fn queued(&self) -> usize {
+        self.outgoing.len() + self.incoming.len()
+    }
+
+    fn delay_seconds(&self) -> f64 {
+        let jitter = (rand::random::<f32>() * 2.0 - 1.0) * self.jitter_ms;
+        ((self.added_latency_ms + jitter).max(0.0) / 1000.0) as f64
+    }
+
+    fn drops(&self, channel: &DefaultChannel) -> bool {
+        !is_reliable(channel) && rand::random::<f32>() < self.loss_probability
+    }
+}
+
+fn is_reliable(channel: &DefaultChannel) -> bool {
+    !matches!(channel, DefaultChannel::Unreliable)
+}
+
+fn copy_channel(channel: &DefaultChannel) -> DefaultChannel {
+    match channel {
+        DefaultChannel::Unreliable => DefaultChannel::Unreliable,
+        DefaultChannel::ReliableOrdered => DefaultChannel::ReliableOrdered,
+        DefaultChannel::ReliableUnordered => DefaultChannel::ReliableUnordered,
+    }
+}
+
+fn same_channel(a: &DefaultChannel, b: &DefaultChannel) -> bool {
+    u8::from(copy_channel(a)) == u8::from(copy_channel(b))
+}
+
+/// wraps a client transport and routes everything through `NetworkConditions`
+pub struct SimulatedTransport<'a, T: ClientTransport> {
+    pub inner: &'a mut T,
+    pub conditions: &'a mut NetworkConditions,
+    pub now: f64,
+}
+
+impl<T: ClientTransport> SimulatedTransport<'_, T> {
+    /// hands outgoing messages whose delay has passed to the real transport
+    pub fn flush(&mut self) {
+        let now = self.now;
+        let (due, held): (Vec<_>, Vec<_>) = std::mem::take(&mut self.conditions.outgoing)
+            .into_iter()
+            .partition(|delayed| delayed.deliver_at <= now);
+        self.conditions.outgoing = held;
+        for delayed in due {
+            self.inner.send_message(delayed.channel, delayed.message);
+        }
+    }
+
+    /// pulls everything the real transport has on `channel` into the incoming queue
+    fn buffer_incoming(&mut self, channel: &DefaultChannel) {
+        while let Some(message) = self.inner.receive_message(copy_channel(channel)) {
+            if self.conditions.drops(channel) {
+                continue;
+            }
+
+            let mut deliver_at = self.now + self.conditions.delay_seconds();
+            if is_reliable(channel) {
+                deliver_at = deliver_at.max(self.conditions.last_reliable_in);
+                self.conditions.last_reliable_in = deliver_at;
+            }
+            self.conditions.incoming.push(DelayedMessage {
+                deliver_at,
+                channel: copy_channel(channel),
+                message,
+            });
+        }
+    }
+}
+
+impl<T: ClientTransport> ClientTransport for SimulatedTransport<'_, T> {
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn send_message(&mut self, channel: DefaultChannel, message: Vec<u8>) {
+        if !self.conditions.is_active() && self.conditions.outgoing.is_empty() {
+            self.inner.send_message(channel, message);
+            return;
+        }
+        if self.conditions.drops(&channel) {
+            return;
+        }
+
+        let mut deliver_at = self.now + self.conditions.delay_seconds();
+        if is_reliable(&channel) {
+            deliver_at = deliver_at.max(self.conditions.last_reliable_out);
+            self.conditions.last_reliable_out = deliver_at;
+        }
+        self.conditions.outgoing.push(DelayedMessage {
+            deliver_at,
+            channel,
+            message,
+        });
+    }
+
+    fn receive_message(&mut self, channel: DefaultChannel) -> Option<Vec<u8>> {
+        if !self.conditions.is_active() && self.conditions.incoming.is_empty() {
+            return self.inner.receive_message(channel);
+        }
+        self.buffer_incoming(&channel);
+
+        // earliest due message wins, so jitter can reorder unreliable messages
+        let now = self.now;
+        let index = self
+            .conditions
+            .incoming
+            .iter()
+            .enumerate()
+            .filter(|(_, delayed)| {
+                same_channel(&delayed.channel, &channel) && delayed.deliver_at <= now
+            })
+            .min_by(|(_, a), (_, b)| a.deliver_at.total_cmp(&b.deliver_at))
+            .map(|(index, _)| index)?;
+        Some(self.conditions.incoming.remove(index).message)
+    }
+}
@@ -0,0 +1,296 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use bevy::ecs::world::CommandQueue;
+use bevy::prelude::*;
+use bevy::utils::tracing::{info, warn};
+use serde::{Deserialize, Serialize};
+use shared::messages::ServerMessage;
+
+use crate::systems::{handle_server_message_reliable, handle_server_message_unreliable};
+use crate::ClientState;
+
+/// recordings are written to `replay-<unix seconds>.bin`, one file each
+pub const REPLAY_FILE_PREFIX: &str = "replay-";
+
+/// command line flag that plays a recording back instead of connecting
+pub const REPLAY_ARG: &str = "--replay";
+
+/// one server message as the client received it
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReplayRecord {
+    pub time: f64, // seconds since the client started
+    pub reliable: bool,
+    pub message: ServerMessage,
+}
+
+/// writes every server message to a fresh recording while recording. off by default,
+/// toggled with F12
+#[derive(Default)]
+pub struct ReplayRecorder {
+    writer: Option<BufWriter<File>>,
+}
+
+impl ReplayRecorder {
+    pub fn is_recording(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    /// starts a recording at `path`, replacing anything already there
+    pub fn start(&mut self, path: &Path) -> std::io::Result<()> {
+        let file = File::create(path)?;
+        self.writer = Some(BufWriter::new(file));
+        info!("Recording server messages to {}", path.display());
+        Ok(())
+    }
+
+    /// flushes what is left and closes the file
+    pub fn stop(&mut self) {
+        self.flush();
+        if self.writer.take().is_some() {
+            info!("Stopped recording server messages");
+        }
+    }
+
+    pub fn record(&mut self, time: f64, reliable: bool, message: &ServerMessage) {
+        let writer = match self.writer.as_mut() {
+            Some(writer) => writer,
+            None => return,
+        };
+        let record = ReplayRecord {
+            time,
+            reliable,
+            message: message.clone(),
+        };
+        if let Err(err) = bincode::serialize_into(writer, &record) {
+            warn!("Failed to record server message, stopping: {}", err);
+            self.writer = None;
+        }
+    }
+
+    /// called once a frame so a crash loses at most one frame of messages
+    pub fn flush(&mut self) {
+        if let Some(writer) = self.writer.as_mut() {
+            if let Err(err) = writer.flush() {
+                warn!("Failed to flush the recording, stopping: {}", err);
+                self.writer = None;
+            }
+        }
+    }
+}
+
+/// a new file name for a recording started now
+pub fn recording_path() -> PathBuf {
+    let secs = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0);
+    PathBuf::from(format!("{}{}.bin", REPLAY_FILE_PREFIX, secs))
+}
+
+/// the recording named after `REPLAY_ARG`, if the client was started with one
+pub fn replay_path_from_args() -> Option<PathBuf> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter()
+        .position(|arg| arg == REPLAY_ARG)
+        .and_then(|index| args.get(index + 1))
+        .map(PathBuf::from)
+}
+
+/// plays `path` back without a window or a server and prints where everything ended
+/// up, for comparing against what was seen live
+pub fn run_replay_file(path: &Path) -> Result<(), String> {
+    let records = load_replay(path)?;
+    let state = replay(&records);
+    println!(
+        "Replayed {} messages from {}, last server tick {}",
+        records.len(),
+        path.display(),
+        state.server_tick
+    );
+    let mut entities: Vec<_> = state.visible_entities.iter().collect();
+    entities.sort_by_key(|(entity_id, _)| **entity_id);
+    for (entity_id, entity) in entities {
+        println!(
+            "  entity {} ({:?}) at {:?}",
+            entity_id, entity.kind, entity.tile_position
+        );
+    }
+    Ok(())
+}
+
+/// every record in a recording, in the order they were received
+pub fn load_replay(path: &Path) -> Result<Vec<ReplayRecord>, String> {
+    let file = File::open(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let mut reader = BufReader::new(file);
+    let mut records = Vec::new();
+    loop {
+        match bincode::deserialize_from::<_, ReplayRecord>(&mut reader) {
+            Ok(record) => records.push(record),
+            Err(err) => match *err {
+                bincode::ErrorKind::Io(ref io) if io.kind() == ErrorKind::UnexpectedEof => break,
+                _ => return Err(format!("{}: {}", path.display(), err)),
+            },
+        }
+    }
+    Ok(records)
+}
+
+/// feeds a recording through the message handlers into a fresh `ClientState`, with
+/// the clock set to each message's arrival time. no server needed
+pub fn replay(records: &[ReplayRecord]) -> ClientState {
+    let mut world = World::new();
+    let mut queue = CommandQueue::default();
+    let mut state = ClientState::default();
+    let mut time = Time::<()>::default();
+
+    for record in records {
+        let elapsed = record.time.max(time.elapsed_seconds_f64());
+        time.advance_to(Duration::from_secs_f64(elapsed));
+        let mut commands = Commands::new(&mut queue, &world);
+        if record.reliable {
+            handle_server_message_reliable(
+                record.message.clone(),
+                &mut state,
+                &mut commands,
+                &time,
+            );
+        } else {
+            handle_server_message_unreliable(record.message.clone(), &mut state, &time);
+        }
+        queue.apply(&mut world);
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::messages::{DeltaType, EntityDelta, EntitySnapshot};
+    use shared::tile_system::TilePosition;
+    use shared::{EntityKind, PlayerId};
+
+    fn snapshot(entity_id: u64, kind: EntityKind, tile_position: TilePosition) -> EntitySnapshot {
+        EntitySnapshot {
+            entity_id,
+            kind,
+            tile_position,
+            player_id: (kind == EntityKind::Player).then_some(PlayerId(entity_id)),
+            tree: None,
+            rock: None,
+            bank: None,
+            ground_item: None,
+            last_processed_input: None,
+            health: None,
+            current_action: None,
+            player_name: None,
+        }
+    }
+
+    fn positions(state: &ClientState) -> Vec<(u64, TilePosition)> {
+        let mut positions: Vec<_> = state
+            .visible_entities
+            .iter()
+            .map(|(entity_id, entity)| (*entity_id, entity.server_position))
+            .collect();
+        positions.sort_by_key(|(entity_id, _)| *entity_id);
+        positions
+    }
+
+    #[test]
+    fn recorded_session_replays_to_the_same_positions() {
+        let session = vec![
+            (
+                0.0,
+                true,
+                ServerMessage::EntitiesEntered {
+                    entities: vec![
+                        snapshot(1, EntityKind::Player, TilePosition { x: 0, y: 0 }),
+                        snapshot(2, EntityKind::Npc, TilePosition { x: 3, y: 3 }),
+                    ],
+                },
+            ),
+            (
+                0.6,
+                false,
+                ServerMessage::DeltaUpdate {
+                    tick: 1,
+                    deltas: vec![EntityDelta {
+                        entity_id: 2,
+                        delta_type: DeltaType::PositionOnly {
+                            tile_pos: TilePosition { x: 4, y: 3 },
+                            last_processed_input: None,
+                            velocity: Some((1, 0)),
+                        },
+                    }],
+                },
+            ),
+            (
+                1.2,
+                false,
+                ServerMessage::DeltaUpdate {
+                    tick: 2,
+                    deltas: vec![EntityDelta {
+                        entity_id: 1,
+                        delta_type: DeltaType::PositionOnly {
+                            tile_pos: TilePosition { x: 0, y: 1 },
+                            last_processed_input: None,
+                            velocity: Some((0, 1)),
+                        },
+                    }],
+                },
+            ),
+        ];
+
+        let path = std::env::temp_dir().join(format!("replay-test-{}.bin", std::process::id()));
+        let mut recorder = ReplayRecorder::default();
+        recorder.start(&path).unwrap();
+        for (time, reliable, message) in &session {
+            recorder.record(*time, *reliable, message);
+        }
+        recorder.stop();
+
+        let records = load_replay(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(records.len(), session.len());
+
+        let live: Vec<ReplayRecord> = session
+            .into_iter()
+            .map(|(time, reliable, message)| ReplayRecord {
+                time,
+                reliable,
+                message,
+            })
+            .collect();
+        let replayed = replay(&records);
+        assert_eq!(positions(&replayed), positions(&replay(&live)));
+        assert_eq!(
+            positions(&replayed),
+            vec![
+                (1, TilePosition { x: 0, y: 1 }),
+                (2, TilePosition { x: 4, y: 3 }),
+            ]
+        );
+        assert_eq!(replayed.server_tick, 2);
+    }
+
+    #[test]
+    fn starting_a_recording_replaces_the_old_one() {
+        let path = std::env::temp_dir().join(format!("replay-reuse-{}.bin", std::process::id()));
+        let message = ServerMessage::EntitiesLeft {
+            entity_ids: vec![7],
+        };
+        let mut recorder = ReplayRecorder::default();
+        for _ in 0..2 {
+            recorder.start(&path).unwrap();
+            recorder.record(0.0, true, &message);
+            recorder.stop();
+        }
+
+        let records = load_replay(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(records.len(), 1);
+    }
+}
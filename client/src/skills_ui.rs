@@ -0,0 +1,62 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use shared::skills::{SkillData, SkillType, Skills};
+
+use crate::ClientState;
+
+const SKILL_ORDER: [SkillType; 4] = [
+    SkillType::Woodcutting,
+    SkillType::Mining,
+    SkillType::Fishing,
+    SkillType::Combat,
+];
+
+/// skills window with a progress bar towards each skill's next level. skills the
+/// server hasn't sent yet show as level 1 with no experience
+pub fn render_skills_ui(mut contexts: EguiContexts, client_state: Res<ClientState>) {
+    if !client_state.show_skills_ui {
+        return;
+    }
+
+    let ctx = contexts.ctx_mut();
+    egui::Window::new("Skills")
+        .default_pos([10.0, 600.0])
+        .default_width(250.0)
+        .show(ctx, |ui| {
+            for skill in SKILL_ORDER {
+                let data = client_state
+                    .skills
+                    .get(&skill)
+                    .cloned()
+                    .unwrap_or(SkillData {
+                        level: 1,
+                        experience: 0,
+                    });
+                let to_next = client_state
+                    .xp_to_next
+                    .get(&skill)
+                    .copied()
+                    .unwrap_or_else(|| Skills::xp_for_level(2));
+
+                ui.label(format!(
+                    "{:?}: level {} ({} XP)",
+                    skill, data.level, data.experience
+                ));
+                if to_next == 0 {
+                    ui.label("Max level");
+                } else {
+                    // progress within the current level, not since level 1
+                    let base = Skills::xp_for_level(data.level).min(data.experience);
+                    let gained = data.experience - base;
+                    let span = gained + to_next;
+                    ui.add(
+                        egui::ProgressBar::new(gained as f32 / span as f32)
+                            .text(format!("{} / {} XP to next level", gained, span)),
+                    );
+                }
+                ui.add_space(4.0);
+            }
+
+            ui.label("Press F8 to toggle this window");
+        });
+}
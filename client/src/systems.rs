@@ -1,34 +1,71 @@
 use bevy::prelude::*;
 use bevy::utils::tracing::{debug, info, warn};
 use bevy_renet::renet::*;
-use shared::actions::GameAction;
+use shared::actions::{GameAction, GameActionKind};
+use shared::definitions::Definitions;
 
-use shared::items::ItemDefinition;
-use shared::messages::{ClientMessage, DeltaType, EntitySnapshot, ServerMessage};
+use shared::messages::{ActionOutcome, ClientMessage, DeltaType, EntitySnapshot, ServerMessage};
+use shared::mining::RockDefinition;
+use shared::netcode::decode_delta_update;
+use shared::pathfinding::Pathfinder;
 
 use shared::skills::SkillData;
 use shared::tile_system::TilePosition;
-use shared::trees::{TreeDefinition, TreeType};
+use shared::transport::ClientTransport;
 use shared::*;
 
+use crate::achievements_ui::{AchievementToast, TOAST_SECONDS};
+use crate::camera::{rock_color, tree_color};
+use crate::context_menu::ContextMenu;
+use crate::network_sim::{NetworkConditions, SimulatedTransport};
 use crate::{
     ClientEntity, ClientState, LocalPlayer, NetworkedEntity, PendingInput, PositionSnapshot,
+    PredictedAction, PredictedWalk,
 };
 
-pub fn client_update_system(
-    mut client: ResMut<RenetClient>,
+/// how long an unconfirmed predicted action waits for the server before it's rolled back
+pub const PREDICTION_CONFIRM_TICKS: f64 = 3.0;
+
+/// how far, in tiles, our entity may be from the server's position before it counts
+/// as out of sync. a predicted walk runs a few tiles ahead on its own
+pub const RESYNC_DIVERGENCE_TILES: i32 = 4;
+
+/// frames in a row out of sync before we ask the server for a resync
+pub const RESYNC_AFTER_FRAMES: u32 = 60;
+
+/// how far from a clicked obstacle we look for an open tile to walk to instead
+pub const CLICK_SEARCH_RADIUS: i32 = 5;
+
+/// tiles a spectator's view moves per key press
+pub const SPECTATOR_PAN_TILES: i32 = 3;
+
+pub fn client_update_system<T: ClientTransport + Resource>(
+    mut client: ResMut<T>,
     mut client_state: ResMut<ClientState>,
     keyboard: Res<ButtonInput<KeyCode>>,
     mouse: Res<ButtonInput<MouseButton>>,
     windows: Query<&Window>,
     camera_q: Query<(&Camera, &GlobalTransform)>,
+    mut conditions: ResMut<NetworkConditions>,
     time: Res<Time>,
     mut commands: Commands,
 ) {
+    let mut client = SimulatedTransport {
+        inner: &mut *client,
+        conditions: &mut conditions,
+        now: time.elapsed_seconds_f64(),
+    };
+    client.flush();
+
     if client.is_connected() && !client_state.join_sent && client_state.my_player_id.is_none() {
         info!("Connected to server!");
-        let msg = ClientMessage::Join {
-            name: "Player".to_string(),
+        let msg = if client_state.spectating {
+            ClientMessage::JoinAsSpectator
+        } else {
+            ClientMessage::Join {
+                name: "Player".to_string(),
+                account_id: client_state.account_id.clone(),
+            }
         };
         if let Ok(msg_bytes) = bincode::serialize(&msg) {
             client.send_message(DefaultChannel::ReliableOrdered, msg_bytes);
@@ -37,11 +74,75 @@ pub fn client_update_system(
         }
     }
 
-    if client_state.my_player_id.is_some() {
-        handle_tile_movement_input(&keyboard, &mut client, &mut client_state);
+    // nothing we send matters once the server has said goodbye
+    let accepting_input =
+        client_state.my_player_id.is_some() && client_state.disconnect_reason.is_none();
+
+    let spectating = client_state.spectator_center.is_some();
+
+    if accepting_input {
+        if spectating {
+            handle_spectator_pan(&keyboard, &mut client, &mut client_state);
+        } else {
+            handle_tile_movement_input(
+                &keyboard,
+                &mut client,
+                &mut client_state,
+                time.elapsed_seconds_f64(),
+            );
+        }
+
+        // the server clamps the distance and confirms it with a GameConfig
+        let view_change = if keyboard.just_pressed(KeyCode::BracketRight) {
+            1
+        } else if keyboard.just_pressed(KeyCode::BracketLeft) {
+            -1
+        } else {
+            0
+        };
+        if view_change != 0 {
+            let tiles = (client_state.view_distance + view_change)
+                .clamp(MIN_VIEW_DISTANCE, MAX_VIEW_DISTANCE);
+            let msg = ClientMessage::SetViewDistance { tiles };
+            if let Ok(msg_bytes) = bincode::serialize(&msg) {
+                client.send_message(DefaultChannel::ReliableOrdered, msg_bytes);
+            }
+        }
+
+        // the server confirms the toggle with a RunEnergyUpdate
+        if keyboard.just_pressed(KeyCode::KeyR) {
+            let msg = ClientMessage::SetRunning {
+                enabled: !client_state.running,
+            };
+            if let Ok(msg_bytes) = bincode::serialize(&msg) {
+                client.send_message(DefaultChannel::ReliableOrdered, msg_bytes);
+            }
+        }
+
+        // the server keeps picking the next tree until something else is asked for
+        if keyboard.just_pressed(KeyCode::KeyG) {
+            queue_action(GameAction::ChopNearest, &mut client, &mut client_state);
+        }
+
+        // an open context menu takes Escape for itself
+        if keyboard.just_pressed(KeyCode::Escape) && client_state.context_menu.is_none() {
+            cancel_current_action(&mut client, &mut client_state);
+        }
+
+        // admin only, the server refuses everyone else
+        if keyboard.just_pressed(KeyCode::F10) {
+            if let Ok(msg_bytes) = bincode::serialize(&ClientMessage::RequestWorldSnapshot) {
+                client.send_message(DefaultChannel::ReliableOrdered, msg_bytes);
+                info!("Requested world snapshot");
+            }
+        }
     }
 
-    if let Ok(window) = windows.get_single() {
+    if let Some(window) = windows
+        .get_single()
+        .ok()
+        .filter(|_| accepting_input && !spectating)
+    {
         if let Ok((camera, camera_transform)) = camera_q.get_single() {
             handle_mouse_pathfinding(
                 &mouse,
@@ -50,29 +151,131 @@ pub fn client_update_system(
                 camera_transform,
                 &mut client,
                 &mut client_state,
+                time.elapsed_seconds_f64(),
             );
         }
     }
 
     while let Some(message) = client.receive_message(DefaultChannel::ReliableOrdered) {
         debug!("Received reliable message: {} bytes", message.len());
-        if let Ok(server_msg) = bincode::deserialize::<ServerMessage>(&message) {
-            handle_server_message_reliable(server_msg, &mut client_state, &mut commands);
+        client_state.net_stats.record_reliable(message.len());
+        match bincode::deserialize::<ServerMessage>(&message) {
+            Ok(server_msg) => {
+                let now = time.elapsed_seconds_f64();
+                client_state.recorder.record(now, true, &server_msg);
+                handle_server_message_reliable(server_msg, &mut client_state, &mut commands, &time)
+            }
+            Err(err) => {
+                warn_undecodable(&message, "reliable", &err);
+                client_state.net_stats.record_undecodable();
+            }
         }
     }
 
     while let Some(message) = client.receive_message(DefaultChannel::Unreliable) {
         debug!("Received unreliable message: {} bytes", message.len());
-        if let Ok(server_msg) = bincode::deserialize::<ServerMessage>(&message) {
-            handle_server_message_unreliable(server_msg, &mut client_state, &time);
+        client_state.net_stats.record_unreliable(message.len());
+        // the unreliable channel only carries deltas, in their compact encoding
+        match decode_delta_update(&message) {
+            Ok((tick, deltas)) => {
+                let server_msg = ServerMessage::DeltaUpdate { tick, deltas };
+                let now = time.elapsed_seconds_f64();
+                client_state.recorder.record(now, false, &server_msg);
+                handle_server_message_unreliable(server_msg, &mut client_state, &time)
+            }
+            Err(err) => {
+                warn_undecodable(&message, "unreliable", &err);
+                client_state.net_stats.record_undecodable();
+            }
         }
     }
+
+    if client_state.disconnect_reason.is_none() {
+        check_desync(&mut client, &mut client_state);
+    }
+    client_state.recorder.flush();
+
+    client_state.net_stats.update(time.elapsed_seconds_f64());
+}
+
+/// asks the server for a resync once our entity has been far from where the server
+/// puts it for `RESYNC_AFTER_FRAMES` frames in a row, e.g. after heavy packet loss
+fn check_desync(client: &mut impl ClientTransport, state: &mut ClientState) {
+    let diverged = state
+        .my_entity_id
+        .and_then(|id| state.visible_entities.get(&id))
+        .is_some_and(|entity| {
+            entity
+                .tile_position
+                .distance_chebyshev(&entity.server_position)
+                > RESYNC_DIVERGENCE_TILES
+        });
+    if !diverged {
+        state.desync_frames = 0;
+        return;
+    }
+
+    state.desync_frames += 1;
+    if state.desync_frames < RESYNC_AFTER_FRAMES {
+        return;
+    }
+    state.desync_frames = 0;
+    warn!("Position has diverged from the server's, requesting a resync");
+    if let Ok(msg_bytes) = bincode::serialize(&ClientMessage::RequestResync) {
+        client.send_message(DefaultChannel::ReliableOrdered, msg_bytes);
+    }
+}
+
+/// most likely the server runs a different protocol version
+fn warn_undecodable(message: &[u8], channel: &str, err: &dyn std::fmt::Display) {
+    warn!(
+        "Dropped undecodable {} message ({} bytes, starts {:02x?}): {}",
+        channel,
+        message.len(),
+        &message[..message.len().min(16)],
+        err
+    );
+}
+
+/// moves a spectator's view with WASD. there's no entity to predict, the server just
+/// starts sending whatever is around the new center
+pub fn handle_spectator_pan(
+    keyboard: &ButtonInput<KeyCode>,
+    client: &mut impl ClientTransport,
+    state: &mut ClientState,
+) {
+    let center = match state.spectator_center {
+        Some(center) => center,
+        None => return,
+    };
+    let (dx, dy) = if keyboard.just_pressed(KeyCode::KeyW) {
+        (0, 1)
+    } else if keyboard.just_pressed(KeyCode::KeyS) {
+        (0, -1)
+    } else if keyboard.just_pressed(KeyCode::KeyA) {
+        (-1, 0)
+    } else if keyboard.just_pressed(KeyCode::KeyD) {
+        (1, 0)
+    } else {
+        return;
+    };
+
+    let center = TilePosition {
+        x: center.x + dx * SPECTATOR_PAN_TILES,
+        y: center.y + dy * SPECTATOR_PAN_TILES,
+    };
+    state.spectator_center = Some(center);
+    let msg = ClientMessage::MoveSpectatorCamera { center };
+    if let Ok(msg_bytes) = bincode::serialize(&msg) {
+        client.send_message(DefaultChannel::ReliableOrdered, msg_bytes);
+    }
 }
 
 pub fn handle_tile_movement_input(
     keyboard: &ButtonInput<KeyCode>,
-    client: &mut RenetClient,
+    client: &mut impl ClientTransport,
     state: &mut ClientState,
+    now: f64,
 ) {
     let my_entity_id = match state.my_entity_id {
         Some(id) => id,
@@ -84,61 +287,65 @@ pub fn handle_tile_movement_input(
         None => return,
     };
 
-    let mut target_pos = None;
+    let mut step = None;
     let mut direction = "";
 
     if keyboard.just_pressed(KeyCode::KeyW) {
-        target_pos = Some(TilePosition {
-            x: my_pos.x,
-            y: my_pos.y + 1,
-        });
+        step = Some((0, 1));
         direction = "North";
     } else if keyboard.just_pressed(KeyCode::KeyS) {
-        target_pos = Some(TilePosition {
-            x: my_pos.x,
-            y: my_pos.y - 1,
-        });
+        step = Some((0, -1));
         direction = "South";
     } else if keyboard.just_pressed(KeyCode::KeyA) {
-        target_pos = Some(TilePosition {
-            x: my_pos.x - 1,
-            y: my_pos.y,
-        });
+        step = Some((-1, 0));
         direction = "West";
     } else if keyboard.just_pressed(KeyCode::KeyD) {
-        target_pos = Some(TilePosition {
-            x: my_pos.x + 1,
-            y: my_pos.y,
-        });
+        step = Some((1, 0));
         direction = "East";
     }
 
-    if let Some(pos) = target_pos {
-        if !state.pathfinder.is_walkable(&pos) {
-            warn!(" Cannot walk {} to {:?} - blocked!", direction, pos);
-            return;
+    if let Some((dx, dy)) = step {
+        // a running player covers as many tiles per key press as the server moves
+        // them per tick, up to the first blocked one
+        let mut path = Vec::new();
+        for i in 1..=state.steps_per_tick() as i32 {
+            let pos = TilePosition {
+                x: my_pos.x + dx * i,
+                y: my_pos.y + dy * i,
+            };
+            if !state.pathfinder.is_walkable(&pos) {
+                break;
+            }
+            path.push(pos);
         }
+        let pos = match path.last() {
+            Some(pos) => *pos,
+            None => {
+                warn!(" Cannot walk {} from {:?} - blocked!", direction, my_pos);
+                return;
+            }
+        };
 
         info!("Moving {} from {:?} to {:?}", direction, my_pos, pos);
 
-        // Server will automatically replace any in-progress move action
-        let action = GameAction::Move { path: vec![pos] };
+        // the server walks the step from wherever it has us by then, after any steps
+        // still waiting from earlier in the tick (and moving cancels any gathering we
+        // were predicting)
+        state.predicted_action = None;
+        let action = GameAction::Move { path: path.clone() };
         let input_sequence_number = state.input_sequence_number;
         state.input_sequence_number += 1;
-        let msg = ClientMessage::QueueAction {
-            action: action.clone(),
+        let msg = ClientMessage::StepInput {
+            dx,
+            dy,
             input_sequence_number,
+            pressed_at: now,
         };
         let msg_bytes = bincode::serialize(&msg).unwrap();
         client.send_message(DefaultChannel::ReliableOrdered, msg_bytes);
 
-        // client-side prediction: apply the input immediately
-        if state.client_side_prediction {
-            if let Some(my_entity) = state.visible_entities.get_mut(&my_entity_id) {
-                apply_action_to_position(&action, &mut my_entity.tile_position);
-                debug!("Predicted position: {:?}", my_entity.tile_position);
-            }
-        }
+        // client-side prediction: start walking straight away
+        start_predicted_walk(state, input_sequence_number, path, now);
 
         // store this input for later reconciliation
         state.pending_inputs.push(PendingInput {
@@ -156,8 +363,9 @@ pub fn handle_mouse_pathfinding(
     window: &Window,
     camera: &Camera,
     camera_transform: &GlobalTransform,
-    client: &mut RenetClient,
+    client: &mut impl ClientTransport,
     state: &mut ClientState,
+    now: f64,
 ) {
     let cursor_pos = window
         .cursor_position()
@@ -167,20 +375,45 @@ pub fn handle_mouse_pathfinding(
     if let Some(world_pos) = cursor_pos {
         let target_tile = TilePosition::from_world(world_pos);
 
-        state.hover_entity = None;
-        for (entity_id, entity) in &state.visible_entities {
-            if entity.tile_position == target_tile && entity.tree.is_some() {
-                state.hover_entity = Some(*entity_id);
-                break;
-            }
+        // anything but ourselves. scenery and items win over a player standing on the
+        // same tile, since those are what a click acts on
+        state.hover_entity = state
+            .visible_entities
+            .iter()
+            .filter(|(entity_id, entity)| {
+                Some(**entity_id) != state.my_entity_id
+                    && entity.interpolated_position.unwrap_or(entity.tile_position) == target_tile
+            })
+            .min_by_key(|(entity_id, entity)| (entity.player_id.is_some(), **entity_id))
+            .map(|(entity_id, _)| *entity_id);
+
+        if mouse.just_pressed(MouseButton::Right) {
+            state.context_menu = window.cursor_position().map(|cursor| ContextMenu {
+                tile: target_tile,
+                entity_id: state.hover_entity,
+                screen_pos: cursor,
+                just_opened: true,
+            });
+            state.path_preview = None;
+            return;
+        }
+
+        // while the menu is open a left click either picks an entry or dismisses it,
+        // and the menu handles both
+        if state.context_menu.is_some() {
+            state.path_preview = None;
+            return;
         }
 
         if mouse.just_pressed(MouseButton::Left) {
+            // any new click replaces whatever we were predicting
+            state.predicted_action = None;
+
             if let Some(hover_entity_id) = state.hover_entity {
                 if let Some(entity) = state.visible_entities.get(&hover_entity_id) {
                     if let Some(ref tree) = entity.tree {
                         if !tree.is_chopped {
-                            let tree_def = TreeDefinition::get(tree.tree_type);
+                            let tree_def = state.definitions.tree(tree.tree_type);
                             let tree_pos = entity.tile_position;
 
                             info!(
@@ -197,157 +430,78 @@ pub fn handle_mouse_pathfinding(
                             let cancel_bytes = bincode::serialize(&cancel_msg).unwrap();
                             client.send_message(DefaultChannel::ReliableOrdered, cancel_bytes);
 
-                            // check if we need to move to the tree first
-                            if let Some(my_entity_id) = state.my_entity_id {
-                                if let Some(my_entity) = state.visible_entities.get(&my_entity_id) {
-                                    let my_pos = my_entity.tile_position;
-
-                                    // check if we're adjacent to the tree (within 1 tile, including diagonals)
-                                    let dx = (my_pos.x - tree_pos.x).abs();
-                                    let dy = (my_pos.y - tree_pos.y).abs();
-                                    let is_adjacent = dx <= 1 && dy <= 1 && !(dx == 0 && dy == 0);
-
-                                    let input_sequence_number = state.input_sequence_number;
-                                    state.input_sequence_number += 1;
-
-                                    if is_adjacent {
-                                        info!("Adjacent to tree, chopping directly");
-
-                                        let action = GameAction::ChopTree {
-                                            tree_entity_id: hover_entity_id,
-                                        };
-
-                                        let msg = ClientMessage::QueueAction {
-                                            action: action.clone(),
-                                            input_sequence_number,
-                                        };
-                                        let msg_bytes = bincode::serialize(&msg).unwrap();
-                                        client.send_message(
-                                            DefaultChannel::ReliableOrdered,
-                                            msg_bytes,
-                                        );
-
-                                        state.pending_inputs.push(PendingInput {
-                                            input_sequence_number,
-                                            action,
-                                        });
-                                    } else {
-                                        info!("Not adjacent to tree, will move then chop");
-                                        // find an adjacent walkable tile
-                                        let mut best_adjacent: Option<TilePosition> = None;
-                                        let mut min_distance = i32::MAX;
-
-                                        for dx in -1..=1 {
-                                            for dy in -1..=1 {
-                                                if dx == 0 && dy == 0 {
-                                                    continue;
-                                                }
-
-                                                let adjacent = TilePosition {
-                                                    x: tree_pos.x + dx,
-                                                    y: tree_pos.y + dy,
-                                                };
-
-                                                if state.pathfinder.is_walkable(&adjacent) {
-                                                    let dist = (adjacent.x - my_pos.x).abs()
-                                                        + (adjacent.y - my_pos.y).abs();
-                                                    if dist < min_distance {
-                                                        min_distance = dist;
-                                                        best_adjacent = Some(adjacent);
-                                                    }
-                                                }
-                                            }
-                                        }
-
-                                        if let Some(move_to) = best_adjacent {
-                                            // find path to adjacent tile
-                                            if let Some(path) =
-                                                state.pathfinder.find_path_a_star(my_pos, move_to)
-                                            {
-                                                let move_action =
-                                                    GameAction::Move { path: path.clone() };
-                                                let chop_action = GameAction::ChopTree {
-                                                    tree_entity_id: hover_entity_id,
-                                                };
-
-                                                // send both actions as a chain
-                                                let msg = ClientMessage::QueueActions {
-                                                    actions: vec![move_action.clone(), chop_action],
-                                                    input_sequence_number,
-                                                };
-                                                let msg_bytes = bincode::serialize(&msg).unwrap();
-                                                client.send_message(
-                                                    DefaultChannel::ReliableOrdered,
-                                                    msg_bytes,
-                                                );
-
-                                                // predict the movement
-                                                if state.client_side_prediction {
-                                                    if let Some(my_entity_mut) = state
-                                                        .visible_entities
-                                                        .get_mut(&my_entity_id)
-                                                    {
-                                                        apply_action_to_position(
-                                                            &move_action,
-                                                            &mut my_entity_mut.tile_position,
-                                                        );
-                                                        debug!(
-                                                            "Predicted move to: {:?}",
-                                                            my_entity_mut.tile_position
-                                                        );
-                                                    }
-                                                }
-
-                                                state.pending_inputs.push(PendingInput {
-                                                    input_sequence_number,
-                                                    action: move_action,
-                                                });
-
-                                                state.confirmed_path = Some(path);
-
-                                                info!(
-                                                    "Queued: Move to {:?} then chop tree",
-                                                    move_to
-                                                );
-                                            } else {
-                                                warn!("No path found to tree!");
-                                            }
-                                        } else {
-                                            warn!("No walkable tiles adjacent to tree!");
-                                        }
-                                    }
-                                }
-                            }
-
+                            let chop = GameAction::ChopTree {
+                                tree_entity_id: hover_entity_id,
+                            };
+                            queue_adjacent_action(tree_pos, chop, client, state, now);
                             return;
                         } else {
                             debug!("Tree already chopped, waiting for respawn");
                         }
+                    } else if let Some(ref rock) = entity.rock {
+                        if !rock.is_depleted {
+                            let rock_pos = entity.tile_position;
+                            let rock_def = RockDefinition::get(rock.rock_type);
+                            info!(
+                                "Click: Attempting to mine {:?} at {:?} (required level: {})",
+                                rock.rock_type, rock_pos, rock_def.level_required
+                            );
+                            let mine = GameAction::Mine {
+                                rock_entity_id: hover_entity_id,
+                            };
+                            queue_adjacent_action(rock_pos, mine, client, state, now);
+                            return;
+                        } else {
+                            debug!("Rock depleted, waiting for respawn");
+                        }
+                    } else if entity.bank.is_some() {
+                        info!("Click: Opening bank at {:?}", entity.tile_position);
+                        let bank_pos = entity.tile_position;
+                        let interact = GameAction::Interact {
+                            entity_id: hover_entity_id,
+                        };
+                        queue_adjacent_action(bank_pos, interact, client, state, now);
+                        return;
+                    } else if entity.ground_item.is_some() {
+                        let item_pos = entity.tile_position;
+                        queue_pick_up(hover_entity_id, item_pos, client, state, now);
+                        return;
                     }
                 }
             }
 
-            if let Some(my_entity_id) = state.my_entity_id {
-                if let Some(my_entity) = state.visible_entities.get(&my_entity_id) {
-                    info!(
-                        "Click: Requesting path from {:?} to {:?}",
-                        my_entity.tile_position, target_tile
-                    );
-                    let msg = ClientMessage::RequestPath {
-                        start: my_entity.tile_position,
-                        goal: target_tile,
-                    };
-                    let msg_bytes = bincode::serialize(&msg).unwrap();
-                    client.send_message(DefaultChannel::ReliableOrdered, msg_bytes);
+            // a wall or water has nothing to act on, walk as close to it as we can
+            let goal = match state
+                .my_entity_id
+                .and_then(|id| state.visible_entities.get(&id))
+            {
+                Some(me) if !state.pathfinder.is_walkable(&target_tile) => {
+                    match state.pathfinder.nearest_walkable(
+                        target_tile,
+                        me.tile_position,
+                        CLICK_SEARCH_RADIUS,
+                    ) {
+                        Some(open) => open,
+                        None => {
+                            debug!("No open tile near {:?}", target_tile);
+                            return;
+                        }
+                    }
                 }
-            }
+                _ => target_tile,
+            };
+            request_path(goal, client, state);
         } else {
-            if state.hover_entity.is_none() {
+            let hovering_obstacle = state
+                .hover_entity
+                .and_then(|id| state.visible_entities.get(&id))
+                .is_some_and(|entity| entity.is_static());
+            if !hovering_obstacle {
                 if let Some(my_entity_id) = state.my_entity_id {
                     if let Some(my_entity) = state.visible_entities.get(&my_entity_id) {
                         state.path_preview = state
                             .pathfinder
-                            .find_path_a_star(my_entity.tile_position, target_tile);
+                            .find_path(my_entity.tile_position, target_tile);
                     }
                 }
             } else {
@@ -360,29 +514,375 @@ pub fn handle_mouse_pathfinding(
     }
 }
 
+/// asks the server for a path from where we stand to `goal`
+pub fn request_path(goal: TilePosition, client: &mut impl ClientTransport, state: &ClientState) {
+    if let Some(my_entity_id) = state.my_entity_id {
+        if let Some(my_entity) = state.visible_entities.get(&my_entity_id) {
+            info!(
+                "Click: Requesting path from {:?} to {:?}",
+                my_entity.tile_position, goal
+            );
+            let msg = ClientMessage::RequestPath {
+                start: my_entity.tile_position,
+                goal,
+            };
+            let msg_bytes = bincode::serialize(&msg).unwrap();
+            client.send_message(DefaultChannel::ReliableOrdered, msg_bytes);
+        }
+    }
+}
+
+/// walks onto a ground item's tile (if needed) and picks it up
+pub fn queue_pick_up(
+    item_entity_id: u64,
+    item_pos: TilePosition,
+    client: &mut impl ClientTransport,
+    state: &mut ClientState,
+    now: f64,
+) {
+    let my_pos = match state
+        .my_entity_id
+        .and_then(|id| state.visible_entities.get(&id))
+    {
+        Some(e) => e.tile_position,
+        None => return,
+    };
+
+    let input_sequence_number = state.input_sequence_number;
+    state.input_sequence_number += 1;
+
+    let pick_up = GameAction::PickUp {
+        entity_id: item_entity_id,
+    };
+
+    let dx = (my_pos.x - item_pos.x).abs();
+    let dy = (my_pos.y - item_pos.y).abs();
+    let actions = if dx <= 1 && dy <= 1 {
+        info!("Click: Picking up item at {:?}", item_pos);
+        vec![pick_up]
+    } else {
+        match state.pathfinder.find_path(my_pos, item_pos) {
+            Some(path) => {
+                info!("Click: Walking to item at {:?} to pick it up", item_pos);
+                state.confirmed_path = Some(path.clone());
+                vec![GameAction::Move { path }, pick_up]
+            }
+            None => {
+                warn!("No path found to item!");
+                return;
+            }
+        }
+    };
+
+    send_action_chain(actions, input_sequence_number, client, state, now);
+}
+
+/// walks next to an obstacle such as a tree, rock or bank (if needed) and queues an
+/// action on it
+pub fn queue_adjacent_action(
+    target_pos: TilePosition,
+    action: GameAction,
+    client: &mut impl ClientTransport,
+    state: &mut ClientState,
+    now: f64,
+) {
+    let my_pos = match state
+        .my_entity_id
+        .and_then(|id| state.visible_entities.get(&id))
+    {
+        Some(e) => e.tile_position,
+        None => return,
+    };
+
+    let input_sequence_number = state.input_sequence_number;
+    state.input_sequence_number += 1;
+
+    let dx = (my_pos.x - target_pos.x).abs();
+    let dy = (my_pos.y - target_pos.y).abs();
+    let actions = if dx <= 1 && dy <= 1 {
+        start_predicted_action(state, input_sequence_number, action.clone(), now);
+        vec![action]
+    } else {
+        // the target is an obstacle, so this walks to a free tile next to it
+        match state.pathfinder.find_path_or_adjacent(my_pos, target_pos) {
+            Some(path) => {
+                info!("Walking to {:?} first", target_pos);
+                state.confirmed_path = Some(path.clone());
+                vec![GameAction::Move { path }, action]
+            }
+            None => {
+                warn!("No path found to {:?}!", target_pos);
+                return;
+            }
+        }
+    };
+
+    send_action_chain(actions, input_sequence_number, client, state, now);
+}
+
+/// sends one action, or a walk followed by an action, under a single input number.
+/// every action in the chain becomes a pending input so reconciliation replays the
+/// whole walk and not just what comes after it
+fn send_action_chain(
+    actions: Vec<GameAction>,
+    input_sequence_number: u32,
+    client: &mut impl ClientTransport,
+    state: &mut ClientState,
+    now: f64,
+) {
+    let msg = match actions.as_slice() {
+        [action] => ClientMessage::QueueAction {
+            action: action.clone(),
+            input_sequence_number,
+            render_delay: state.render_delay(),
+        },
+        _ => ClientMessage::QueueActions {
+            actions: actions.clone(),
+            input_sequence_number,
+            render_delay: state.render_delay(),
+        },
+    };
+    let msg_bytes = bincode::serialize(&msg).unwrap();
+    client.send_message(DefaultChannel::ReliableOrdered, msg_bytes);
+
+    for action in actions {
+        if let GameAction::Move { ref path } = action {
+            start_predicted_walk(state, input_sequence_number, path.clone(), now);
+        }
+        state.pending_inputs.push(PendingInput {
+            input_sequence_number,
+            action,
+        });
+    }
+}
+
+/// takes the first step of a walk we just sent, the rest follow a tick at a time in
+/// `advance_predicted_walk`. replaces any walk already under way, as the server does
+fn start_predicted_walk(
+    state: &mut ClientState,
+    input_sequence_number: u32,
+    path: Vec<TilePosition>,
+    now: f64,
+) {
+    if !state.client_side_prediction {
+        return;
+    }
+    let origin = match state
+        .my_entity_id
+        .and_then(|id| state.visible_entities.get(&id))
+    {
+        Some(e) => e.tile_position,
+        None => return,
+    };
+    state.predicted_walk = Some(PredictedWalk {
+        input_sequence_number,
+        origin,
+        path,
+        steps_taken: 0,
+        next_step_at: now,
+    });
+    step_predicted_walk(state, now);
+}
+
+/// walks the predicted walk on by however many ticks have passed, and moves our
+/// entity to wherever it has got to
+fn step_predicted_walk(state: &mut ClientState, now: f64) {
+    let steps = state.steps_per_tick();
+    let tick = state.tick_duration();
+    let walk = match state.predicted_walk.as_mut() {
+        Some(walk) => walk,
+        None => return,
+    };
+    if now < walk.next_step_at {
+        return;
+    }
+    while now >= walk.next_step_at && !walk.is_finished() {
+        walk.steps_taken = (walk.steps_taken + steps).min(walk.path.len());
+        walk.next_step_at += tick;
+    }
+
+    let position = walk.position();
+    let finished = walk.is_finished();
+    if let Some(my_entity) = state
+        .my_entity_id
+        .and_then(|id| state.visible_entities.get_mut(&id))
+    {
+        my_entity.tile_position = position;
+        debug!("Predicted position: {:?}", position);
+    }
+    if finished {
+        state.predicted_walk = None;
+    }
+}
+
+/// steps the local player along its predicted walk in time with the server's ticks
+pub fn advance_predicted_walk(mut client_state: ResMut<ClientState>, time: Res<Time>) {
+    step_predicted_walk(&mut client_state, time.elapsed_seconds_f64());
+}
+
+/// starts showing a gathering action straight away instead of waiting for the server
+fn start_predicted_action(
+    state: &mut ClientState,
+    input_sequence_number: u32,
+    action: GameAction,
+    now: f64,
+) {
+    if !state.client_side_prediction || !action.is_repeating() {
+        return;
+    }
+    debug!("Predicting {:?} (input #{})", action, input_sequence_number);
+    state.predicted_action = Some(PredictedAction {
+        input_sequence_number,
+        action,
+        started_at: now,
+        confirmed: false,
+    });
+}
+
+/// drops the predicted action and its pending input so nothing is left waiting on it
+pub fn roll_back_predicted_action(state: &mut ClientState, reason: &str) {
+    if let Some(predicted) = state.predicted_action.take() {
+        warn!(
+            "Rolling back predicted {:?} (input #{}): {}",
+            predicted.action, predicted.input_sequence_number, reason
+        );
+        state
+            .pending_inputs
+            .retain(|input| input.input_sequence_number != predicted.input_sequence_number);
+    }
+}
+
+/// undoes everything predicted for an input the server turned down, and only that
+/// input, so later ones keep their predictions
+pub fn roll_back_input(state: &mut ClientState, input_sequence_number: u32) {
+    if state
+        .predicted_action
+        .as_ref()
+        .is_some_and(|predicted| predicted.input_sequence_number == input_sequence_number)
+    {
+        roll_back_predicted_action(state, "rejected by server");
+    }
+    if state
+        .predicted_walk
+        .as_ref()
+        .is_some_and(|walk| walk.input_sequence_number == input_sequence_number)
+    {
+        state.predicted_walk = None;
+        state.confirmed_path = None;
+    }
+    state
+        .pending_inputs
+        .retain(|input| input.input_sequence_number != input_sequence_number);
+}
+
+/// stops whatever we're doing or walking to. unconfirmed inputs are dropped along
+/// with the prediction, so reconciliation has nothing left to replay, and we go back
+/// to the last tile the server put us on
+pub fn cancel_current_action(client: &mut impl ClientTransport, state: &mut ClientState) {
+    if let Ok(msg_bytes) = bincode::serialize(&ClientMessage::CancelAction) {
+        client.send_message(DefaultChannel::ReliableOrdered, msg_bytes);
+    }
+    info!(
+        "Cancelled current action, dropping {} pending inputs",
+        state.pending_inputs.len()
+    );
+
+    drop_local_predictions(state);
+
+    if let Some(my_entity_id) = state.my_entity_id {
+        if let Some(entity) = state.visible_entities.get_mut(&my_entity_id) {
+            entity.tile_position = entity.server_position;
+        }
+    }
+}
+
+/// rolls back predictions the server never acknowledged
+pub fn expire_predicted_action(mut client_state: ResMut<ClientState>, time: Res<Time>) {
+    let timeout = client_state.tick_duration() * PREDICTION_CONFIRM_TICKS;
+    let expired = client_state
+        .predicted_action
+        .as_ref()
+        .is_some_and(|predicted| {
+            !predicted.confirmed && time.elapsed_seconds_f64() - predicted.started_at > timeout
+        });
+    if expired {
+        roll_back_predicted_action(&mut client_state, "server never confirmed it");
+    }
+}
+
+/// sends a single action for the local player
+pub fn queue_action(
+    action: GameAction,
+    client: &mut impl ClientTransport,
+    state: &mut ClientState,
+) {
+    let input_sequence_number = state.input_sequence_number;
+    state.input_sequence_number += 1;
+
+    let msg = ClientMessage::QueueAction {
+        action: action.clone(),
+        input_sequence_number,
+        render_delay: state.render_delay(),
+    };
+    let msg_bytes = bincode::serialize(&msg).unwrap();
+    client.send_message(DefaultChannel::ReliableOrdered, msg_bytes);
+
+    state.pending_inputs.push(PendingInput {
+        input_sequence_number,
+        action,
+    });
+}
+
 pub fn handle_server_message_reliable(
     msg: ServerMessage,
     state: &mut ClientState,
     commands: &mut Commands,
+    time: &Time,
 ) {
     match msg {
         ServerMessage::Welcome {
             player_id,
             spawn_position: spawn_pos,
+            name,
         } => {
             state.my_player_id = Some(player_id);
-            info!("Welcome! Assigned player ID: {:?}", player_id);
+            info!("Welcome, {}! Assigned player ID: {:?}", name, player_id);
             info!("Spawn position: {:?}", spawn_pos);
         }
 
+        ServerMessage::SpectatorWelcome { player_id, center } => {
+            state.my_player_id = Some(player_id);
+            state.spectator_center = Some(center);
+            info!("Spectating as {:?} from {:?}", player_id, center);
+        }
+
         ServerMessage::EntitiesEntered { entities } => {
             info!("{} entities entered view", entities.len());
             for snapshot in entities {
+                // the server only tells viewers when a tree falls or grows back, so a
+                // tree coming into view brings its tile up to date
+                if let Some(ref tree) = snapshot.tree {
+                    if tree.is_chopped {
+                        state.pathfinder.remove_obstacle(snapshot.tile_position);
+                    } else {
+                        state.pathfinder.add_obstacle(snapshot.tile_position);
+                    }
+                }
                 if snapshot.tree.is_some() {
                     debug!(
                         "Tree entity {} at {:?}",
                         snapshot.entity_id, snapshot.tile_position
                     );
+                } else if snapshot.rock.is_some() {
+                    debug!(
+                        "Rock entity {} at {:?}",
+                        snapshot.entity_id, snapshot.tile_position
+                    );
+                } else if snapshot.bank.is_some() {
+                    debug!(
+                        "Bank entity {} at {:?}",
+                        snapshot.entity_id, snapshot.tile_position
+                    );
                 } else if snapshot.player_id.is_some() {
                     info!(
                         "Player entity {} at {:?}",
@@ -403,42 +903,137 @@ pub fn handle_server_message_reliable(
             }
         }
 
-        ServerMessage::ActionQueued { action } => {
-            info!("Action queued: {:?}", action);
-        }
+        ServerMessage::ActionResult {
+            input_sequence_number,
+            outcome,
+        } => match outcome {
+            ActionOutcome::Accepted => {
+                info!("Input #{} accepted", input_sequence_number);
+                if let Some(ref mut predicted) = state.predicted_action {
+                    if predicted.input_sequence_number == input_sequence_number {
+                        predicted.confirmed = true;
+                    }
+                }
+            }
+            ActionOutcome::Rejected(reason) => {
+                warn!("Input #{} rejected: {:?}", input_sequence_number, reason);
+                roll_back_input(state, input_sequence_number);
+            }
+            ActionOutcome::Completed => {
+                debug!("Input #{} completed", input_sequence_number);
+            }
+        },
 
         ServerMessage::ActionCompleted { entity_id } => {
             debug!("Action completed for entity {}", entity_id);
+            if Some(entity_id) == state.my_entity_id {
+                state.predicted_action = None;
+            }
+            if let Some(entity) = state.visible_entities.get_mut(&entity_id) {
+                entity.current_action = None;
+            }
         }
 
         ServerMessage::PathFound { path } => {
+            let path = Pathfinder::expand_path(&path);
             info!("Path found with {} tiles", path.len());
             state.confirmed_path = Some(path);
         }
 
-        ServerMessage::PathNotFound => {
-            warn!("No path found to target!");
+        ServerMessage::PathNotFound { waypoint_index } => {
+            warn!("No path found to target (waypoint {})!", waypoint_index);
+            state.confirmed_path = None;
+        }
+
+        ServerMessage::TileMapData { tile_map, portals } => {
+            info!(
+                "Received map with {} tiles and {} portals",
+                tile_map.tiles.len(),
+                portals.len()
+            );
+            // the same costs the server paths with, so previews take the same roads
+            state.pathfinder.set_terrain_costs(tile_map.terrain_costs());
+            state.tile_map = tile_map;
+            state.portals = portals;
+        }
+
+        ServerMessage::ZoneChanged {
+            zone,
+            spawn_position,
+        } => {
+            info!("Entered zone {:?} at {:?}", zone, spawn_position);
+            // nothing from the old zone exists any more, including our own entity. the
+            // new zone's entities arrive as entered entities next tick
+            for (_, client_entity) in state.visible_entities.drain() {
+                commands.entity(client_entity.entity).despawn();
+            }
+            state.current_position = Some(spawn_position);
+            state.pending_move = None;
+            state.path_preview = None;
             state.confirmed_path = None;
+            state.pending_inputs.clear();
+            state.predicted_action = None;
+            state.predicted_walk = None;
+            state.hover_entity = None;
         }
 
         ServerMessage::ObstacleData { obstacles } => {
-            state.pathfinder.obstacles = obstacles.into_iter().collect();
+            state.pathfinder.set_obstacles(obstacles);
             info!(
                 "Received {} obstacles from server",
-                state.pathfinder.obstacles.len()
+                state.pathfinder.obstacles().len()
             );
         }
 
+        ServerMessage::ObstacleAdded { tile } => {
+            debug!("Tile {:?} is now blocked", tile);
+            state.pathfinder.add_obstacle(tile);
+        }
+
+        ServerMessage::ObstacleRemoved { tile } => {
+            debug!("Tile {:?} is now walkable", tile);
+            state.pathfinder.remove_obstacle(tile);
+        }
+
         ServerMessage::InventoryUpdate { inventory } => {
             state.inventory = inventory;
             debug!("Inventory updated");
         }
 
+        ServerMessage::BankUpdate { bank } => {
+            debug!("Bank updated");
+            state.bank = Some(bank);
+        }
+
+        ServerMessage::WithdrawPartial {
+            item_type,
+            withdrawn,
+            remainder,
+        } => {
+            warn!(
+                "Inventory full! Withdrew {} {}, {} left in the bank",
+                withdrawn,
+                state.definitions.item(item_type).name,
+                remainder
+            );
+        }
+
+        ServerMessage::EquipmentUpdate { equipment } => {
+            for (slot, stack) in &equipment {
+                info!(
+                    "{:?}: {}",
+                    slot,
+                    state.definitions.item(stack.item_type).name
+                );
+            }
+            state.equipment = equipment;
+        }
+
         ServerMessage::ItemAdded {
             item_type,
             quantity,
         } => {
-            let def = ItemDefinition::get(item_type);
+            let def = state.definitions.item(item_type);
             let total = state.inventory.count_item(item_type);
             info!("Received {} x{} (total: {})", def.name, quantity, total);
         }
@@ -447,7 +1042,7 @@ pub fn handle_server_message_reliable(
             item_type,
             quantity,
         } => {
-            let def = ItemDefinition::get(item_type);
+            let def = state.definitions.item(item_type);
             info!("Removed {} x{}", def.name, quantity);
         }
 
@@ -455,8 +1050,10 @@ pub fn handle_server_message_reliable(
             skill,
             level,
             experience,
+            xp_to_next,
         } => {
             state.skills.insert(skill, SkillData { level, experience });
+            state.xp_to_next.insert(skill, xp_to_next);
             debug!("{:?}: Level {} (XP: {})", skill, level, experience);
         }
 
@@ -464,6 +1061,14 @@ pub fn handle_server_message_reliable(
             info!("LEVEL UP! {:?} is now level {}!", skill, new_level);
         }
 
+        ServerMessage::AchievementUnlocked { id, name } => {
+            info!("Achievement unlocked: {} ({:?})", name, id);
+            state.achievement_toasts.push(AchievementToast {
+                name,
+                shown_until: time.elapsed_seconds_f64() + TOAST_SECONDS,
+            });
+        }
+
         ServerMessage::ExperienceGained { skill, amount } => {
             if let Some(skill_data) = state.skills.get(&skill) {
                 info!(
@@ -474,10 +1079,30 @@ pub fn handle_server_message_reliable(
         }
 
         ServerMessage::TreeChopped { tree_entity_id } => {
+            let now_tick = state.estimated_server_tick(time.elapsed_seconds_f64());
+            let tick_duration = state.tick_duration();
             if let Some(entity) = state.visible_entities.get_mut(&tree_entity_id) {
                 if let Some(ref mut tree) = entity.tree {
                     tree.is_chopped = true;
+                    tree.respawn_timer = 0.0;
+                    tree.update_respawn_ticks(
+                        now_tick.round() as u64,
+                        tick_duration,
+                        &state.definitions,
+                    );
                     info!("Tree {} chopped!", tree_entity_id);
+                    // the stump can be walked over. the ObstacleRemoved the tree's viewers
+                    // get says the same, whichever arrives first wins and the other changes nothing
+                    let tile = entity.tile_position;
+                    state.pathfinder.remove_obstacle(tile);
+                }
+            }
+            clear_actions_next_to(state, tree_entity_id, GameActionKind::ChopTree);
+            // someone else may have felled the tree we were predicting a chop on
+            if let Some(ref predicted) = state.predicted_action {
+                if matches!(predicted.action, GameAction::ChopTree { tree_entity_id: id } if id == tree_entity_id)
+                {
+                    state.predicted_action = None;
                 }
             }
         }
@@ -486,88 +1111,323 @@ pub fn handle_server_message_reliable(
             if let Some(entity) = state.visible_entities.get_mut(&tree_entity_id) {
                 if let Some(ref mut tree) = entity.tree {
                     tree.is_chopped = false;
+                    tree.chopped_at_tick = None;
+                    tree.respawn_at_tick = None;
                     info!("Tree {} respawned!", tree_entity_id);
+                    let tile = entity.tile_position;
+                    state.pathfinder.add_obstacle(tile);
                 }
             }
         }
 
-        ServerMessage::NotEnoughLevel {
-            skill,
-            required,
-            current,
+        ServerMessage::RockDepleted { rock_entity_id } => {
+            if let Some(entity) = state.visible_entities.get_mut(&rock_entity_id) {
+                if let Some(ref mut rock) = entity.rock {
+                    rock.is_depleted = true;
+                    info!("Rock {} depleted!", rock_entity_id);
+                }
+            }
+            clear_actions_next_to(state, rock_entity_id, GameActionKind::Mine);
+            if let Some(ref predicted) = state.predicted_action {
+                if matches!(predicted.action, GameAction::Mine { rock_entity_id: id } if id == rock_entity_id)
+                {
+                    state.predicted_action = None;
+                }
+            }
+        }
+
+        ServerMessage::RockRespawned { rock_entity_id } => {
+            if let Some(entity) = state.visible_entities.get_mut(&rock_entity_id) {
+                if let Some(ref mut rock) = entity.rock {
+                    rock.is_depleted = false;
+                    info!("Rock {} respawned!", rock_entity_id);
+                }
+            }
+        }
+
+        ServerMessage::ActionRejected { reason } => {
+            warn!("Request refused: {}", reason);
+        }
+
+        ServerMessage::PathBlocked { at } => {
+            warn!("Path blocked at {:?}, stopping", at);
+            state.confirmed_path = None;
+            roll_back_predicted_action(state, "path blocked");
+        }
+
+        ServerMessage::Resync {
+            tick,
+            entity_id,
+            tile_pos,
+            last_processed_input,
+        } => {
+            state.note_server_tick(tick, time.elapsed_seconds_f64());
+            info!(
+                "Resynced at {:?} (input #{:?}), dropping predictions",
+                tile_pos, last_processed_input
+            );
+            if let Some(entity) = state.visible_entities.get_mut(&entity_id) {
+                entity.tile_position = tile_pos;
+                entity.server_position = tile_pos;
+                entity.position_tick = tick;
+                entity.interpolated_world = Some(tile_pos.to_world());
+                entity.position_buffer.clear();
+            }
+            drop_local_predictions(state);
+        }
+
+        ServerMessage::GroundItemChanged { entity_id, item } => {
+            if let Some(entity) = state.visible_entities.get_mut(&entity_id) {
+                debug!(
+                    "Ground item {} is now {:?} x{}",
+                    entity_id, item.item_type, item.quantity
+                );
+                entity.ground_item = Some(item);
+            }
+        }
+
+        ServerMessage::HealthUpdate { entity_id, health } => {
+            if let Some(entity) = state.visible_entities.get_mut(&entity_id) {
+                debug!("Entity {} health is now {}", entity_id, health);
+                entity.health = Some(health);
+            }
+        }
+
+        ServerMessage::InventoryFull => {
+            warn!("Your inventory is full!");
+        }
+
+        ServerMessage::TickRateChanged { tick_rate } => {
+            info!(
+                "Server tick rate changed: {}ms -> {}ms",
+                (state.tick_rate * 1000.0) as u32,
+                (tick_rate * 1000.0) as u32
+            );
+            state.tick_rate = tick_rate;
+        }
+
+        ServerMessage::GameConfig {
+            tick_rate,
+            view_distance,
         } => {
-            warn!("Need level {} {:?} (current: {})", required, skill, current);
+            info!(
+                "Game config: tick rate {}ms, view distance {}",
+                (tick_rate * 1000.0) as u32,
+                view_distance
+            );
+            state.tick_rate = tick_rate;
+            state.view_distance = view_distance;
+        }
+
+        ServerMessage::RunEnergyUpdate { energy, running } => {
+            if running != state.running {
+                info!("{}", if running { "Running" } else { "Walking" });
+            }
+            state.running = running;
+            state.run_energy = energy;
         }
 
-        ServerMessage::NoAxeEquipped => {
-            warn!("You need an axe to chop this tree!");
+        ServerMessage::Definitions { definitions } => {
+            info!(
+                "Using {} item and {} tree definitions from the server",
+                definitions.items.len(),
+                definitions.trees.len()
+            );
+            state.definitions = definitions;
+        }
+
+        ServerMessage::ExamineResult { text } => {
+            info!("Examine: {}", text);
+            state.examine_text = Some(text);
+        }
+
+        ServerMessage::Kicked { reason } => {
+            warn!("Kicked by the server: {}", reason);
+            state.disconnect_reason = Some(reason);
+            state.definitions = Definitions::default();
+        }
+
+        ServerMessage::ServerShutdown { reason } => {
+            warn!("Server shutting down: {}", reason);
+            state.disconnect_reason = Some(reason);
+            state.definitions = Definitions::default();
+            state.pending_inputs.clear();
+            state.predicted_walk = None;
+            state.pending_move = None;
+            state.confirmed_path = None;
         }
 
-        _ => {}
+        ServerMessage::WorldSnapshot { tick, entities } => {
+            info!(
+                "World snapshot at tick {}: {} entities",
+                tick,
+                entities.len()
+            );
+            for snapshot in &entities {
+                debug!(
+                    "  entity {} at {:?} (player {:?})",
+                    snapshot.entity_id, snapshot.tile_position, snapshot.player_id
+                );
+            }
+        }
+
+        // deltas normally come unreliably, they are handled the same either way
+        ServerMessage::DeltaUpdate { .. } => handle_server_message_unreliable(msg, state, time),
+
+        ServerMessage::PositionKeyframe { tick, positions } => {
+            let current_time = time.elapsed_seconds_f64();
+            state.note_server_tick(tick, current_time);
+            let my_entity_id = state.my_entity_id;
+            let interpolation = state.entity_interpolation;
+            let interpolate_local = state.interpolates_local_player();
+            // our own entity is predicted, only snap it while nothing is in flight
+            let idle = state.pending_inputs.is_empty()
+                && state.confirmed_path.is_none()
+                && state.predicted_walk.is_none();
+            for (entity_id, tile_pos) in positions {
+                let entity = match state.visible_entities.get_mut(&entity_id) {
+                    Some(entity) => entity,
+                    None => continue,
+                };
+                // a delta from a later tick already got through
+                if entity.position_tick > tick {
+                    continue;
+                }
+                // nothing was lost, but it is still where the last update put it, so it
+                // has stopped and shouldn't be extrapolated any further
+                if entity.server_position == tile_pos {
+                    let moving = entity
+                        .position_buffer
+                        .last()
+                        .is_some_and(|last| last.velocity != Some((0, 0)));
+                    if interpolation && moving {
+                        entity.position_buffer.push(PositionSnapshot {
+                            timestamp: current_time,
+                            position: tile_pos,
+                            velocity: Some((0, 0)),
+                        });
+                    }
+                    continue;
+                }
+                debug!("Keyframe moved entity {} to {:?}", entity_id, tile_pos);
+                entity.server_position = tile_pos;
+                entity.position_tick = tick;
+                if Some(entity_id) == my_entity_id && !interpolate_local {
+                    if idle {
+                        entity.tile_position = tile_pos;
+                    }
+                } else if interpolation {
+                    entity.position_buffer.push(PositionSnapshot {
+                        timestamp: current_time,
+                        position: tile_pos,
+                        velocity: None,
+                    });
+                } else {
+                    entity.tile_position = tile_pos;
+                }
+            }
+        }
     }
 }
 
 pub fn handle_server_message_unreliable(msg: ServerMessage, state: &mut ClientState, time: &Time) {
-    if let ServerMessage::DeltaUpdate { tick: _, deltas } = msg {
+    if let ServerMessage::DeltaUpdate { tick, deltas } = msg {
+        state.net_stats.record_deltas(deltas.len());
+        state.note_server_tick(tick, time.elapsed_seconds_f64());
         for delta in deltas {
             match delta.delta_type {
                 DeltaType::FullState {
                     tile_pos,
                     player_id,
-                    last_processed_input,
+                    velocity,
+                    ..
                 } => {
                     let is_my_player = player_id == state.my_player_id;
                     let current_time = time.elapsed_seconds_f64();
+                    let interpolate_local = state.interpolates_local_player();
 
                     if let Some(entity) = state.visible_entities.get_mut(&delta.entity_id) {
                         entity.server_position = tile_pos;
+                        entity.position_tick = tick;
                         entity.player_id = player_id;
-
                         if is_my_player {
                             state.my_entity_id = Some(delta.entity_id);
+                        }
+
+                        if is_my_player && !interpolate_local {
                             entity.tile_position = tile_pos;
                         } else {
-                            // other player - add to position buffer for interpolation
+                            // full state is authoritative (first sight or a teleport), so
+                            // snap there and interpolate onward from it
+                            entity.tile_position = tile_pos;
+                            entity.interpolated_world = Some(tile_pos.to_world());
+                            entity.position_buffer.clear();
                             if state.entity_interpolation {
                                 entity.position_buffer.push(PositionSnapshot {
                                     timestamp: current_time,
                                     position: tile_pos,
+                                    velocity,
                                 });
-                            } else {
-                                entity.tile_position = tile_pos;
                             }
                         }
                     }
 
                     if is_my_player {
-                        if state.server_reconciliation {
-                            if let Some(last_input) = last_processed_input {
-                                reconcile_client_state(state, delta.entity_id, last_input);
-                            }
-                        } else {
-                            state.pending_inputs.clear();
-                        }
-                        state.pending_move = None;
+                        // we only get full state for ourselves on join or after a
+                        // teleport, either way nothing we predicted still applies
+                        drop_local_predictions(state);
                     }
                 }
                 DeltaType::PositionOnly {
                     tile_pos,
                     last_processed_input,
+                    velocity,
                 } => {
                     let is_my_entity = Some(delta.entity_id) == state.my_entity_id;
                     let current_time = time.elapsed_seconds_f64();
+                    let interpolate_local = state.interpolates_local_player();
+                    let mut jumped = false;
 
                     if let Some(entity) = state.visible_entities.get_mut(&delta.entity_id) {
+                        jumped = is_my_entity
+                            && is_discontinuity(
+                                entity.server_position,
+                                entity.position_tick,
+                                tile_pos,
+                                tick,
+                            );
+                        // no step since the last update, whatever the hint says
+                        let velocity = if entity.server_position == tile_pos {
+                            Some((0, 0))
+                        } else {
+                            velocity
+                        };
                         entity.server_position = tile_pos;
+                        entity.position_tick = tick;
 
                         if is_my_entity {
+                            // unpredicted, we glide along the server's positions instead
+                            // of snapping to each one
+                            if interpolate_local && !jumped {
+                                entity.position_buffer.push(PositionSnapshot {
+                                    timestamp: current_time,
+                                    position: tile_pos,
+                                    velocity,
+                                });
+                            } else if interpolate_local {
+                                entity.position_buffer.clear();
+                                entity.interpolated_world = Some(tile_pos.to_world());
+                            }
                             entity.tile_position = tile_pos;
                         } else {
+                            // whatever it was doing, walking away ended it
+                            entity.current_action = None;
                             // other entity - add to position buffer for interpolation
                             if state.entity_interpolation {
                                 entity.position_buffer.push(PositionSnapshot {
                                     timestamp: current_time,
                                     position: tile_pos,
+                                    velocity,
                                 });
                             } else {
                                 entity.tile_position = tile_pos;
@@ -575,7 +1435,12 @@ pub fn handle_server_message_unreliable(msg: ServerMessage, state: &mut ClientSt
                         }
                     }
 
-                    if is_my_entity {
+                    if jumped {
+                        // teleported, respawned or sent through a portal. nothing we
+                        // were walking or predicting leads anywhere from here
+                        info!("Server moved us to {:?}, dropping predictions", tile_pos);
+                        drop_local_predictions(state);
+                    } else if is_my_entity {
                         if state.server_reconciliation {
                             if let Some(last_input) = last_processed_input {
                                 reconcile_client_state(state, delta.entity_id, last_input);
@@ -594,7 +1459,12 @@ pub fn handle_server_message_unreliable(msg: ServerMessage, state: &mut ClientSt
                         }
                     }
                 }
-                DeltaType::ActionStarted { action: _ } => {}
+                DeltaType::ActionStarted { action } => {
+                    if let Some(entity) = state.visible_entities.get_mut(&delta.entity_id) {
+                        entity.current_action = Some(action);
+                        entity.action_started_at = Some(time.elapsed_seconds_f64());
+                    }
+                }
                 DeltaType::Removed => {
                     state.visible_entities.remove(&delta.entity_id);
                 }
@@ -603,6 +1473,25 @@ pub fn handle_server_message_unreliable(msg: ServerMessage, state: &mut ClientSt
     }
 }
 
+/// true if getting from `from` (seen at `from_tick`) to `to` (at `tick`) took more
+/// tiles than even running covers in the ticks between them
+fn is_discontinuity(from: TilePosition, from_tick: u64, to: TilePosition, tick: u64) -> bool {
+    let ticks = tick.saturating_sub(from_tick).max(1);
+    let distance = (from.x - to.x).abs().max((from.y - to.y).abs()) as u64;
+    distance > ticks * RUN_STEPS as u64
+}
+
+/// forgets every input, walk, path and action we were predicting, for when the
+/// server has put us somewhere none of them started from
+fn drop_local_predictions(state: &mut ClientState) {
+    state.pending_inputs.clear();
+    state.predicted_action = None;
+    state.predicted_walk = None;
+    state.pending_move = None;
+    state.confirmed_path = None;
+    state.path_preview = None;
+}
+
 /// server reconciliation: re-apply inputs that the server hasn't processed yet
 fn reconcile_client_state(state: &mut ClientState, entity_id: u64, last_processed_input: u32) {
     // remove all inputs that have been processed by the server
@@ -616,9 +1505,19 @@ fn reconcile_client_state(state: &mut ClientState, entity_id: u64, last_processe
         state.pending_inputs.len()
     );
 
-    // re-apply all remaining inputs on top of the server's authoritative state
+    // re-apply all remaining inputs on top of the server's authoritative state. the walk
+    // under way is left to the walk itself, it has only got so far
+    let walking = state
+        .predicted_walk
+        .as_ref()
+        .map(|walk| walk.input_sequence_number);
     if let Some(entity) = state.visible_entities.get_mut(&entity_id) {
         for pending_input in &state.pending_inputs {
+            let is_walk = matches!(pending_input.action, GameAction::Move { .. })
+                && Some(pending_input.input_sequence_number) == walking;
+            if is_walk {
+                continue;
+            }
             apply_action_to_position(&pending_input.action, &mut entity.tile_position);
             info!(
                 "Re-applied input #{}: {:?} -> {:?}",
@@ -626,6 +1525,38 @@ fn reconcile_client_state(state: &mut ClientState, entity_id: u64, last_processe
             );
         }
     }
+
+    sync_predicted_walk(state, entity_id);
+}
+
+/// lines the predicted walk up with the server's position. if the server is further
+/// along the path the walk catches up, and if it's somewhere off the path altogether
+/// (blocked, or the walk was replaced) the walk is dropped and the server wins
+fn sync_predicted_walk(state: &mut ClientState, entity_id: u64) {
+    let (walk, entity) = match (
+        state.predicted_walk.as_mut(),
+        state.visible_entities.get_mut(&entity_id),
+    ) {
+        (Some(walk), Some(entity)) => (walk, entity),
+        _ => return,
+    };
+    let server_pos = entity.server_position;
+
+    if let Some(index) = walk.path.iter().position(|tile| *tile == server_pos) {
+        walk.steps_taken = walk.steps_taken.max(index + 1);
+    } else if server_pos != walk.origin {
+        debug!(
+            "Server at {:?} is off the predicted walk, dropping it",
+            server_pos
+        );
+        state.predicted_walk = None;
+        return;
+    }
+
+    entity.tile_position = walk.position();
+    if walk.is_finished() {
+        state.predicted_walk = None;
+    }
 }
 
 pub fn spawn_client_entity(
@@ -635,28 +1566,39 @@ pub fn spawn_client_entity(
 ) {
     let is_local = snapshot.player_id == state.my_player_id;
 
-    let (color, size) = if let Some(ref tree) = snapshot.tree {
-        let tree_color = match tree.tree_type {
-            TreeType::Normal => Color::srgb(0.4, 0.6, 0.3),
-            TreeType::Oak => Color::srgb(0.5, 0.4, 0.2),
-            TreeType::Willow => Color::srgb(0.6, 0.7, 0.4),
-        };
-        let tree_color = if tree.is_chopped {
-            Color::srgb(0.3, 0.3, 0.3)
-        } else {
-            tree_color
-        };
-        (tree_color, Vec2::new(TILE_SIZE * 1.2, TILE_SIZE * 1.5))
-    } else if is_local {
-        (
+    let (color, size) = match (snapshot.kind, &snapshot.tree, &snapshot.rock) {
+        (EntityKind::Tree, Some(tree), _) => (
+            tree_color(tree, state.server_tick as f64),
+            Vec2::new(TILE_SIZE * 1.2, TILE_SIZE * 1.5),
+        ),
+        (EntityKind::Rock, _, Some(rock)) => (
+            rock_color(rock),
+            Vec2::new(TILE_SIZE * 1.1, TILE_SIZE * 0.9),
+        ),
+        (EntityKind::Bank, ..) => (
+            Color::srgb(0.35, 0.3, 0.55),
+            Vec2::new(TILE_SIZE * 1.2, TILE_SIZE * 1.0),
+        ),
+        (EntityKind::GroundItem, ..) => (
+            Color::srgb(0.9, 0.8, 0.3),
+            Vec2::new(TILE_SIZE * 0.4, TILE_SIZE * 0.4),
+        ),
+        (EntityKind::FishingSpot, ..) => (
+            Color::srgb(0.6, 0.85, 1.0),
+            Vec2::new(TILE_SIZE * 0.6, TILE_SIZE * 0.6),
+        ),
+        (EntityKind::Npc, ..) => (
+            Color::srgb(0.85, 0.6, 0.2),
+            Vec2::new(TILE_SIZE * 0.8, TILE_SIZE * 0.8),
+        ),
+        _ if is_local => (
             Color::srgb(0.25, 0.75, 0.25),
             Vec2::new(TILE_SIZE * 0.8, TILE_SIZE * 0.8),
-        )
-    } else {
-        (
+        ),
+        _ => (
             Color::srgb(0.75, 0.25, 0.25),
             Vec2::new(TILE_SIZE * 0.8, TILE_SIZE * 0.8),
-        )
+        ),
     };
 
     let mut entity_commands = commands.spawn((
@@ -675,23 +1617,27 @@ pub fn spawn_client_entity(
         },
     ));
 
-    if is_local {
-        entity_commands.insert(LocalPlayer);
-        state.my_entity_id = Some(snapshot.entity_id);
-        info!(
-            "Spawned local player entity at {:?}",
-            snapshot.tile_position
-        );
-    } else if snapshot.tree.is_some() {
-        debug!(
-            "Spawned tree entity {} at {:?}",
-            snapshot.entity_id, snapshot.tile_position
-        );
-    } else {
-        info!(
+    match (snapshot.kind, &snapshot.ground_item) {
+        _ if is_local => {
+            entity_commands.insert(LocalPlayer);
+            state.my_entity_id = Some(snapshot.entity_id);
+            info!(
+                "Spawned local player entity at {:?}",
+                snapshot.tile_position
+            );
+        }
+        (EntityKind::Player, _) => info!(
             "Spawned remote player entity {} at {:?}",
             snapshot.entity_id, snapshot.tile_position
-        );
+        ),
+        (EntityKind::GroundItem, Some(item)) => debug!(
+            "Spawned ground item {} ({:?} x{}) at {:?}",
+            snapshot.entity_id, item.item_type, item.quantity, snapshot.tile_position
+        ),
+        (kind, _) => debug!(
+            "Spawned {:?} entity {} at {:?}",
+            kind, snapshot.entity_id, snapshot.tile_position
+        ),
     }
 
     let entity = entity_commands.id();
@@ -699,43 +1645,89 @@ pub fn spawn_client_entity(
     state.visible_entities.insert(
         snapshot.entity_id,
         ClientEntity {
+            kind: snapshot.kind,
             tile_position: snapshot.tile_position,
             player_id: snapshot.player_id,
             entity,
             tree: snapshot.tree,
+            rock: snapshot.rock,
+            bank: snapshot.bank,
+            ground_item: snapshot.ground_item,
             position_buffer: Vec::new(),
             server_position: snapshot.tile_position,
             interpolated_position: None,
+            interpolated_world: None,
+            health: snapshot.health,
+            current_action: snapshot.current_action,
+            action_started_at: None,
+            position_tick: 0,
+            player_name: snapshot.player_name,
         },
     );
 }
 
+/// stops showing `kind` on entities next to a tree or rock that just ran out
+fn clear_actions_next_to(state: &mut ClientState, target_id: u64, kind: GameActionKind) {
+    let target_tile = match state.visible_entities.get(&target_id) {
+        Some(entity) => entity.tile_position,
+        None => return,
+    };
+    for entity in state.visible_entities.values_mut() {
+        if entity.current_action == Some(kind)
+            && entity.tile_position.distance_chebyshev(&target_tile) <= 1
+        {
+            entity.current_action = None;
+        }
+    }
+}
+
 /// Interpolation system - computes smooth positions for remote entities
 pub fn interpolate_entities(mut client_state: ResMut<ClientState>, time: Res<Time>) {
     if !client_state.entity_interpolation {
+        // fall back to raw tile positions rather than a stale smoothed one
+        for entity in client_state.visible_entities.values_mut() {
+            entity.interpolated_world = None;
+        }
         return;
     }
 
     let current_time = time.elapsed_seconds_f64();
     let render_timestamp = current_time - client_state.interpolation_delay;
+    // keep a couple of ticks of history so there is always a pair to interpolate between
+    let buffer_window = client_state.tick_duration() * 2.0;
+    let tick_duration = client_state.tick_duration();
+    let extrapolation_cap = tick_duration * client_state.extrapolation_cap_ticks;
     let my_entity_id = client_state.my_entity_id;
+    let state = &mut *client_state;
 
-    for (entity_id, entity) in client_state.visible_entities.iter_mut() {
-        if Some(*entity_id) == my_entity_id {
+    let interpolate_local = state.interpolates_local_player();
+
+    for (entity_id, entity) in state.visible_entities.iter_mut() {
+        if Some(*entity_id) == my_entity_id && !interpolate_local {
+            // predicted, drawn at its tile instead
+            entity.position_buffer.clear();
+            entity.interpolated_world = None;
             continue;
         }
-        if entity.tree.is_some() {
+        if entity.is_static() {
             continue;
         }
 
         let buffer = &mut entity.position_buffer;
 
-        // drop old positions that are older than we need
-        buffer.retain(|snapshot| snapshot.timestamp >= render_timestamp - 1.0);
+        // drop old positions that are older than we need, but keep the newest two
+        // around so there is a velocity to extrapolate with if updates stop
+        let cutoff = render_timestamp - buffer_window;
+        while buffer.len() > 2 && buffer[0].timestamp < cutoff {
+            buffer.remove(0);
+        }
 
-        // if we don't have enough data, just use the server position
-        if buffer.len() < 2 {
+        // if we don't have enough data, just use the server position. a single
+        // snapshot is enough when the server said which way the entity is heading
+        let has_hint = buffer.last().is_some_and(|last| last.velocity.is_some());
+        if buffer.len() < 2 && !has_hint {
             entity.interpolated_position = Some(entity.server_position);
+            entity.interpolated_world = Some(entity.server_position.to_world());
             continue;
         }
 
@@ -766,25 +1758,77 @@ pub fn interpolate_entities(mut client_state: ResMut<ClientState>, time: Res<Tim
                 0.0
             };
 
-            // for tile-based movement, snap to nearest tile
+            // logic still works on whole tiles, the visual glides between them
             entity.interpolated_position = if interpolation_factor < 0.5 {
                 Some(pos0)
             } else {
                 Some(pos1)
             };
+            entity.interpolated_world = Some(lerp_tiles(pos0, pos1, interpolation_factor as f32));
+        } else if render_timestamp > buffer[buffer.len() - 1].timestamp {
+            // the buffer ran dry, keep the entity moving for a little while. past the
+            // cap it holds where that left it instead of jumping back
+            let prev = buffer.len().checked_sub(2).map(|i| &buffer[i]);
+            let last = &buffer[buffer.len() - 1];
+            let until = render_timestamp.min(last.timestamp + extrapolation_cap);
+            let extrapolated = extrapolate(prev, last, until, tick_duration);
+
+            // never carry an entity into a tile it can't stand on
+            let world = if state
+                .pathfinder
+                .is_walkable(&TilePosition::from_world(extrapolated))
+            {
+                extrapolated
+            } else {
+                last.position.to_world()
+            };
+            entity.interpolated_position = Some(last.position);
+            entity.interpolated_world = Some(world);
         } else {
             // fallback to latest server position
             entity.interpolated_position = Some(entity.server_position);
+            entity.interpolated_world = Some(entity.server_position.to_world());
         }
     }
 }
 
+/// continues the entity's velocity up to `render_timestamp`. the server's velocity
+/// hint wins, otherwise the velocity between the last two snapshots is used
+pub fn extrapolate(
+    prev: Option<&PositionSnapshot>,
+    last: &PositionSnapshot,
+    render_timestamp: f64,
+    tick_duration: f64,
+) -> Vec2 {
+    let velocity = match (last.velocity, prev) {
+        (Some((dx, dy)), _) if tick_duration > 0.0 => {
+            let next = TilePosition {
+                x: last.position.x + dx,
+                y: last.position.y + dy,
+            };
+            (next.to_world() - last.position.to_world()) / tick_duration as f32
+        }
+        (_, Some(prev)) if last.timestamp - prev.timestamp > 0.0001 => {
+            let dt = last.timestamp - prev.timestamp;
+            (last.position.to_world() - prev.position.to_world()) / dt as f32
+        }
+        _ => return last.position.to_world(),
+    };
+    last.position.to_world() + velocity * (render_timestamp - last.timestamp) as f32
+}
+
+/// world-space point `factor` of the way from one tile centre to another
+pub fn lerp_tiles(from: TilePosition, to: TilePosition, factor: f32) -> Vec2 {
+    from.to_world().lerp(to.to_world(), factor)
+}
+
 /// helper function to apply an action to a position for prediction and reconciliation
 fn apply_action_to_position(action: &GameAction, position: &mut TilePosition) {
     match action {
+        // a walk nobody is stepping through any more has got to its end
         GameAction::Move { path } => {
-            if let Some(first_pos) = path.first() {
-                *position = *first_pos;
+            if let Some(last_pos) = path.last() {
+                *position = *last_pos;
             }
         }
         _ => {
@@ -825,3 +1869,328 @@ pub fn update_confirmed_path(mut client_state: ResMut<ClientState>) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use server::admin::{admin_console_system, AdminConsole};
+    use server::interest_manager::InterestManager;
+    use server::metrics::BandwidthStats;
+    use server::rng::GameRng;
+    use server::shutdown::{handle_shutdown, ShutdownSignal, SHUTDOWN_REASON};
+    use server::timing::ServerTiming;
+    use server::{server_update_system, ServerState};
+    use shared::transport::memory::{MemoryClientTransport, MemoryServerTransport};
+    use std::sync::mpsc::{self, Sender};
+    use std::time::Duration;
+
+    /// one frame on both sides
+    const FRAME: Duration = Duration::from_millis(50);
+
+    /// a server and one client in the same process, talking over the memory transport.
+    /// time only moves when `run` says so
+    struct Harness {
+        server: App,
+        client: App,
+        /// lines typed into the server console
+        console: Sender<String>,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            let mut transport = MemoryServerTransport::default();
+            let client_transport = transport.connect(ClientId::from_raw(1));
+            let (console, lines) = mpsc::channel();
+
+            let mut server = App::new();
+            server
+                .add_event::<AppExit>()
+                .init_resource::<Time>()
+                .init_resource::<ServerState>()
+                .init_resource::<InterestManager>()
+                .init_resource::<BandwidthStats>()
+                .init_resource::<ServerTiming>()
+                .init_resource::<ShutdownSignal>()
+                .insert_resource(GameRng::new(1))
+                .insert_resource(AdminConsole::from_receiver(lines))
+                .insert_resource(transport)
+                .add_systems(
+                    Update,
+                    (
+                        admin_console_system::<MemoryServerTransport>,
+                        server_update_system::<MemoryServerTransport>,
+                        handle_shutdown::<MemoryServerTransport>,
+                    )
+                        .chain(),
+                );
+
+            let mut client = App::new();
+            client
+                .init_resource::<Time>()
+                .init_resource::<ClientState>()
+                .init_resource::<NetworkConditions>()
+                .init_resource::<ButtonInput<KeyCode>>()
+                .init_resource::<ButtonInput<MouseButton>>()
+                .insert_resource(client_transport)
+                .add_systems(
+                    Update,
+                    (
+                        client_update_system::<MemoryClientTransport>,
+                        advance_predicted_walk,
+                    )
+                        .chain(),
+                );
+
+            Self {
+                server,
+                client,
+                console,
+            }
+        }
+
+        /// runs `frames` frames of the server and then the client
+        fn run(&mut self, frames: usize) {
+            for _ in 0..frames {
+                for app in [&mut self.server, &mut self.client] {
+                    app.world_mut().resource_mut::<Time>().advance_by(FRAME);
+                    app.update();
+                }
+                // a key press only counts on the frame it happened
+                self.client
+                    .world_mut()
+                    .resource_mut::<ButtonInput<KeyCode>>()
+                    .clear();
+            }
+        }
+
+        /// runs until the server has gone `ticks` ticks further
+        fn run_ticks(&mut self, ticks: u64) {
+            let until = self.server_state().server_tick + ticks;
+            while self.server_state().server_tick < until {
+                self.run(1);
+            }
+        }
+
+        /// joins and waits for the server to say who we are
+        fn join(&mut self) -> u64 {
+            for _ in 0..100 {
+                self.run(1);
+                if let Some(entity_id) = self.client_state().my_entity_id {
+                    if self
+                        .client_state()
+                        .visible_entities
+                        .contains_key(&entity_id)
+                    {
+                        return entity_id;
+                    }
+                }
+            }
+            panic!("never joined");
+        }
+
+        fn press(&mut self, key: KeyCode) {
+            self.client
+                .world_mut()
+                .resource_mut::<ButtonInput<KeyCode>>()
+                .press(key);
+        }
+
+        fn client_state(&self) -> &ClientState {
+            self.client.world().resource::<ClientState>()
+        }
+
+        fn server_state(&self) -> &ServerState {
+            self.server.world().resource::<ServerState>()
+        }
+
+        fn server_tile(&self, entity_id: u64) -> TilePosition {
+            self.server_state().entities[&entity_id].tile_pos
+        }
+
+        fn client_tile(&self, entity_id: u64) -> TilePosition {
+            self.client_state().visible_entities[&entity_id].tile_position
+        }
+
+        /// the first of WASD with an open tile that way from `from`
+        fn open_step(&self, from: TilePosition) -> (KeyCode, TilePosition) {
+            [
+                (KeyCode::KeyW, 0, 1),
+                (KeyCode::KeyD, 1, 0),
+                (KeyCode::KeyS, 0, -1),
+                (KeyCode::KeyA, -1, 0),
+            ]
+            .into_iter()
+            .map(|(key, dx, dy)| {
+                let to = TilePosition {
+                    x: from.x + dx,
+                    y: from.y + dy,
+                };
+                (key, to)
+            })
+            .find(|(_, to)| self.client_state().pathfinder.is_walkable(to))
+            .expect("somewhere to step")
+        }
+    }
+
+    #[test]
+    fn join_move_and_reconcile_in_one_process() {
+        let mut harness = Harness::new();
+        let me = harness.join();
+        let start = harness.server_tile(me);
+        assert_eq!(harness.client_tile(me), start);
+
+        let (key, to) = harness.open_step(start);
+        harness.press(key);
+        harness.run(1);
+        // predicted straight away, before the server has heard of it
+        assert_eq!(harness.client_tile(me), to);
+        assert_eq!(harness.server_tile(me), start);
+
+        harness.run_ticks(3);
+        assert_eq!(harness.server_tile(me), to);
+        assert_eq!(harness.client_tile(me), to);
+        assert!(harness.client_state().pending_inputs.is_empty());
+    }
+
+    #[test]
+    fn shutdown_reaches_the_client_before_it_is_disconnected() {
+        let mut harness = Harness::new();
+        harness.join();
+
+        harness
+            .server
+            .world()
+            .resource::<ShutdownSignal>()
+            .request();
+        harness.run(1);
+        // told why, and still connected until the next frame
+        assert_eq!(
+            harness.client_state().disconnect_reason.as_deref(),
+            Some(SHUTDOWN_REASON)
+        );
+        assert!(harness
+            .client
+            .world()
+            .resource::<MemoryClientTransport>()
+            .is_connected());
+
+        harness.run(1);
+        assert!(!harness
+            .client
+            .world()
+            .resource::<MemoryClientTransport>()
+            .is_connected());
+        let exits = harness.server.world().resource::<Events<AppExit>>();
+        assert!(!exits.is_empty());
+    }
+
+    #[test]
+    fn two_steps_pressed_within_one_tick_are_both_taken() {
+        let mut harness = Harness::new();
+        let me = harness.join();
+        let start = harness.server_tile(me);
+        let open = |pos: &TilePosition| harness.client_state().pathfinder.is_walkable(pos);
+        let ((first, second), corner) = [
+            ((KeyCode::KeyW, KeyCode::KeyD), (1, 1)),
+            ((KeyCode::KeyW, KeyCode::KeyA), (-1, 1)),
+            ((KeyCode::KeyS, KeyCode::KeyD), (1, -1)),
+            ((KeyCode::KeyS, KeyCode::KeyA), (-1, -1)),
+        ]
+        .into_iter()
+        .map(|(keys, (dx, dy))| (keys, (start.x + dx, start.y + dy)))
+        .find(|(_, (x, y))| {
+            open(&TilePosition { x: start.x, y: *y }) && open(&TilePosition { x: *x, y: *y })
+        })
+        .expect("an open corner next to the spawn");
+        let corner = TilePosition {
+            x: corner.0,
+            y: corner.1,
+        };
+
+        let tick = harness.server_state().server_tick;
+        harness.press(first);
+        harness.run(1);
+        harness.press(second);
+        harness.run(1);
+        assert_eq!(harness.server_state().server_tick, tick);
+
+        harness.run_ticks(4);
+        assert_eq!(harness.server_tile(me), corner);
+        assert_eq!(harness.client_tile(me), corner);
+    }
+
+    #[test]
+    fn tick_rate_typed_at_the_console_reaches_the_client_eta() {
+        let mut harness = Harness::new();
+        let me = harness.join();
+        let here = harness.client_tile(me);
+        harness
+            .client
+            .world_mut()
+            .resource_mut::<ClientState>()
+            .confirmed_path = Some(vec![here; 4]);
+        assert_eq!(
+            harness.client_state().path_eta_seconds(),
+            Some(4.0 * TICK_RATE as f64)
+        );
+
+        harness.console.send("tick_rate 300".to_string()).unwrap();
+        harness.run(1);
+        assert_eq!(
+            harness
+                .server
+                .world()
+                .resource::<ServerTiming>()
+                .tick_duration,
+            0.3
+        );
+        let eta = harness.client_state().path_eta_seconds().unwrap();
+        assert!((eta - 1.2).abs() < 1e-6, "eta {}", eta);
+    }
+
+    #[test]
+    fn a_lasting_divergence_is_resolved_by_a_resync() {
+        let mut harness = Harness::new();
+        let me = harness.join();
+        // past our first full state, which would snap us back on its own
+        harness.run_ticks(2);
+        let start = harness.server_tile(me);
+        let far = TilePosition {
+            x: start.x + RESYNC_DIVERGENCE_TILES + 3,
+            y: start.y,
+        };
+        harness
+            .client
+            .world_mut()
+            .resource_mut::<ClientState>()
+            .visible_entities
+            .get_mut(&me)
+            .unwrap()
+            .tile_position = far;
+
+        // a short divergence is left alone
+        harness.run(RESYNC_AFTER_FRAMES as usize - 1);
+        assert_eq!(harness.client_tile(me), far);
+
+        // then the resync request goes out and the server's answer snaps us back
+        harness.run(3);
+        assert_eq!(harness.client_tile(me), start);
+        assert_eq!(harness.server_tile(me), start);
+        assert_eq!(harness.client_state().desync_frames, 0);
+        assert!(harness.client_state().pending_inputs.is_empty());
+    }
+
+    #[test]
+    fn halfway_between_two_tiles_is_the_midpoint_of_their_centres() {
+        for (from, to) in [
+            (TilePosition { x: 0, y: 0 }, TilePosition { x: 1, y: 0 }),
+            (TilePosition { x: 2, y: 3 }, TilePosition { x: 3, y: 4 }),
+            (TilePosition { x: -1, y: 5 }, TilePosition { x: -1, y: 4 }),
+        ] {
+            let midpoint = (from.to_world() + to.to_world()) / 2.0;
+            assert_eq!(lerp_tiles(from, to, 0.5), midpoint);
+            assert_eq!(lerp_tiles(from, to, 0.0), from.to_world());
+            assert_eq!(lerp_tiles(from, to, 1.0), to.to_world());
+        }
+    }
+}
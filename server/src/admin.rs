@@ -0,0 +1,316 @@
+use bevy::prelude::*;
+use bevy::utils::tracing::{info, warn};
+use shared::messages::ServerMessage;
+use shared::tile_system::TilePosition;
+use shared::transport::{MessageSink, ServerTransport};
+use shared::trees::{Tree, TreeType};
+use shared::ZoneId;
+use std::io::BufRead;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+
+use crate::interest_manager::InterestManager;
+use crate::metrics::{BandwidthStats, MeteredTransport};
+use crate::timing::ServerTiming;
+use crate::{
+    despawn_entity, occupies_tile, send_to_viewers, set_tick_rate, spawn_tree,
+    update_interest_for_player, ServerEntity, ServerState, MAIN_ZONE,
+};
+
+/// events `events` shows when no count is given
+pub const DEFAULT_EVENTS_SHOWN: usize = 20;
+
+/// tick lengths `tick_rate` accepts, in milliseconds
+pub const TICK_RATE_MS: std::ops::RangeInclusive<u32> = 50..=5000;
+
+/// a command typed into the server console, for setting up test scenarios without
+/// editing `spawn_trees` and rebuilding
+#[derive(Clone, Debug, PartialEq)]
+pub enum AdminCommand {
+    /// `spawn_tree <normal|oak|willow> <x> <y>`, always in the main zone
+    SpawnTree {
+        tree_type: TreeType,
+        pos: TilePosition,
+    },
+    /// `despawn <id>`, any entity other than a player
+    Despawn { entity_id: u64 },
+    /// `move <id> <x> <y>`, within the entity's own zone
+    Move { entity_id: u64, pos: TilePosition },
+    /// `events [count]`, logs the newest entries of the entity event log
+    Events { count: usize },
+    /// `tick_rate <ms>`, changes the length of a tick and tells every client
+    TickRate { millis: u32 },
+}
+
+impl AdminCommand {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts[..] {
+            ["spawn_tree", tree_type, x, y] => Ok(AdminCommand::SpawnTree {
+                tree_type: parse_tree_type(tree_type)?,
+                pos: parse_tile(x, y)?,
+            }),
+            ["despawn", id] => Ok(AdminCommand::Despawn {
+                entity_id: parse_number(id)?,
+            }),
+            ["move", id, x, y] => Ok(AdminCommand::Move {
+                entity_id: parse_number(id)?,
+                pos: parse_tile(x, y)?,
+            }),
+            ["events"] => Ok(AdminCommand::Events {
+                count: DEFAULT_EVENTS_SHOWN,
+            }),
+            ["events", count] => Ok(AdminCommand::Events {
+                count: parse_number(count)?,
+            }),
+            ["tick_rate", millis] => {
+                let millis = parse_number(millis)?;
+                if !TICK_RATE_MS.contains(&millis) {
+                    return Err(format!(
+                        "tick rate must be {} to {}ms",
+                        TICK_RATE_MS.start(),
+                        TICK_RATE_MS.end()
+                    ));
+                }
+                Ok(AdminCommand::TickRate { millis })
+            }
+            _ => Err(
+                "expected spawn_tree <type> <x> <y>, despawn <id>, move <id> <x> <y>, \
+                 events [count] or tick_rate <ms>"
+                    .to_string(),
+            ),
+        }
+    }
+}
+
+fn parse_tree_type(name: &str) -> Result<TreeType, String> {
+    match name {
+        "normal" => Ok(TreeType::Normal),
+        "oak" => Ok(TreeType::Oak),
+        "willow" => Ok(TreeType::Willow),
+        _ => Err(format!("unknown tree type '{}'", name)),
+    }
+}
+
+fn parse_number<N: std::str::FromStr>(text: &str) -> Result<N, String> {
+    text.parse::<N>()
+        .map_err(|_| format!("'{}' is not a number", text))
+}
+
+fn parse_tile(x: &str, y: &str) -> Result<TilePosition, String> {
+    Ok(TilePosition {
+        x: parse_number(x)?,
+        y: parse_number(y)?,
+    })
+}
+
+/// lines typed into the server console. a thread reads stdin and hands lines over
+/// through a channel so the update system never blocks on input
+#[derive(Resource)]
+pub struct AdminConsole {
+    lines: Mutex<Receiver<String>>,
+}
+
+impl AdminConsole {
+    pub fn from_stdin() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Self::from_receiver(receiver)
+    }
+
+    pub fn from_receiver(receiver: Receiver<String>) -> Self {
+        Self {
+            lines: Mutex::new(receiver),
+        }
+    }
+
+    /// every line typed since the last call
+    fn drain(&self) -> Vec<String> {
+        let receiver = match self.lines.lock() {
+            Ok(receiver) => receiver,
+            Err(_) => return Vec::new(),
+        };
+        let mut lines = Vec::new();
+        while let Ok(line) = receiver.try_recv() {
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+pub fn admin_console_system<T: ServerTransport + Resource>(
+    console: Res<AdminConsole>,
+    mut transport: ResMut<T>,
+    mut bandwidth: ResMut<BandwidthStats>,
+    mut state: ResMut<ServerState>,
+    mut interest_manager: ResMut<InterestManager>,
+    mut timing: ResMut<ServerTiming>,
+    mut commands: Commands,
+) {
+    let lines = console.drain();
+    if lines.is_empty() {
+        return;
+    }
+    let mut server = MeteredTransport {
+        inner: &mut *transport,
+        stats: &mut bandwidth,
+    };
+
+    for line in lines.iter().filter(|line| !line.trim().is_empty()) {
+        let result = AdminCommand::parse(line).and_then(|command| {
+            apply_admin_command(
+                command,
+                &mut state,
+                &mut interest_manager,
+                &mut timing,
+                &mut server,
+                &mut commands,
+            )
+        });
+        match result {
+            Ok(()) => info!("Admin command '{}' done", line.trim()),
+            Err(err) => warn!("Admin command '{}' failed: {}", line.trim(), err),
+        }
+    }
+}
+
+/// whether the pathfinder treats the entity's tile as blocked
+fn blocks_pathing(entity: &ServerEntity) -> bool {
+    entity.tree.as_ref().is_some_and(|tree| !tree.is_chopped)
+        || entity.rock.is_some()
+        || entity.bank.is_some()
+}
+
+/// whether nothing stands on `pos` and the terrain there can be walked on
+fn is_tile_free(state: &ServerState, zone: ZoneId, pos: TilePosition) -> bool {
+    let taken = state
+        .entities
+        .values()
+        .any(|entity| entity.zone == zone && entity.tile_pos == pos && occupies_tile(entity));
+    !taken && state.zones[&zone].pathfinder.is_walkable(&pos)
+}
+
+/// applies one admin command, then refreshes every player's view so the change shows
+/// up straight away instead of on the next tick
+pub fn apply_admin_command(
+    command: AdminCommand,
+    state: &mut ServerState,
+    interest_manager: &mut InterestManager,
+    timing: &mut ServerTiming,
+    server: &mut impl MessageSink,
+    commands: &mut Commands,
+) -> Result<(), String> {
+    match command {
+        // only reads the world, so no view needs refreshing
+        AdminCommand::Events { count } => {
+            state.event_log.dump(count);
+            return Ok(());
+        }
+        // nothing in the world moves, only the clients' timing
+        AdminCommand::TickRate { millis } => {
+            set_tick_rate(state, timing, server, millis as f32 / 1000.0);
+            return Ok(());
+        }
+        AdminCommand::SpawnTree { tree_type, pos } => {
+            if !is_tile_free(state, MAIN_ZONE, pos) {
+                return Err(format!("{:?} is not free", pos));
+            }
+            let entity_id = state.next_entity_id;
+            state.next_entity_id += 1;
+            spawn_tree(
+                state,
+                commands,
+                entity_id,
+                MAIN_ZONE,
+                pos,
+                Tree::new(tree_type),
+            );
+            // nobody sees the tree yet, whoever it comes into view for below is told it blocks
+            info!("Spawned {:?} tree {} at {:?}", tree_type, entity_id, pos);
+        }
+        AdminCommand::Despawn { entity_id } => {
+            let entity = match state.entities.get(&entity_id) {
+                Some(entity) => entity,
+                None => return Err(format!("no entity {}", entity_id)),
+            };
+            if entity.player_id.is_some() {
+                return Err(format!("entity {} is a player", entity_id));
+            }
+            let (zone, tile, blocks) = (entity.zone, entity.tile_pos, blocks_pathing(entity));
+            // its viewers hear the tile clears before the entity leaves their view
+            if blocks {
+                state.zone_mut(zone).pathfinder.remove_obstacle(tile);
+                send_to_viewers(
+                    interest_manager,
+                    server,
+                    entity_id,
+                    &ServerMessage::ObstacleRemoved { tile },
+                );
+            }
+            despawn_entity(entity_id, state, interest_manager, server, commands);
+        }
+        AdminCommand::Move { entity_id, pos } => {
+            let entity = match state.entities.get(&entity_id) {
+                Some(entity) => entity,
+                None => return Err(format!("no entity {}", entity_id)),
+            };
+            let (zone, from, blocks) = (entity.zone, entity.tile_pos, blocks_pathing(entity));
+            if from == pos {
+                return Ok(());
+            }
+            if !is_tile_free(state, zone, pos) {
+                return Err(format!("{:?} is not free", pos));
+            }
+
+            if let Some(entity) = state.entities.get_mut(&entity_id) {
+                entity.tile_pos = pos;
+                // a player being moved loses whatever they were doing, like a teleport
+                entity.action_queue.current_action = None;
+                entity.action_queue.suspended_action = None;
+                entity.action_queue.actions.clear();
+                entity.action_queue.input_buffer.clear();
+                entity.action_queue.gathering_nearest = false;
+            }
+            state.dirty_entities.insert(entity_id);
+            interest_manager.resend_full_state(entity_id);
+            if blocks {
+                let pathfinder = &mut state.zone_mut(zone).pathfinder;
+                pathfinder.remove_obstacle(from);
+                pathfinder.add_obstacle(pos);
+                send_to_viewers(
+                    interest_manager,
+                    server,
+                    entity_id,
+                    &ServerMessage::ObstacleRemoved { tile: from },
+                );
+                send_to_viewers(
+                    interest_manager,
+                    server,
+                    entity_id,
+                    &ServerMessage::ObstacleAdded { tile: pos },
+                );
+            }
+        }
+    }
+
+    let player_ids: Vec<_> = state
+        .players
+        .iter()
+        .filter(|(_, player)| player.pending_removal.is_none())
+        .map(|(player_id, _)| *player_id)
+        .collect();
+    for player_id in player_ids {
+        update_interest_for_player(player_id, state, interest_manager, server);
+    }
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use bevy::prelude::*;
+use bevy::utils::tracing::{debug, info};
+use shared::messages::ServerMessage;
+use shared::skills::SkillType;
+use shared::transport::MessageSink;
+
+use crate::interest_manager::InterestManager;
+use crate::rng::GameRng;
+use crate::{damage_entity, send_message, ServerState};
+
+/// hits land for between 1 and this much
+pub const MAX_HIT: u32 = 3;
+
+/// combat xp for the blow that kills a player or npc
+pub const KILL_EXPERIENCE: u32 = 40;
+
+/// resolves a finished attack. the target has to still be next to the attacker in the
+/// same zone, otherwise the swing misses. a killing blow earns the attacker combat xp
+/// if they have skills to put it in
+pub fn handle_attack_completion(
+    attacker_entity_id: u64,
+    target_entity_id: u64,
+    state: &mut ServerState,
+    rng: &mut GameRng,
+    interest_manager: &mut InterestManager,
+    server: &mut impl MessageSink,
+    commands: &mut Commands,
+) {
+    let (attacker, defender) = match (
+        state.entities.get(&attacker_entity_id),
+        state.entities.get(&target_entity_id),
+    ) {
+        (Some(attacker), Some(defender)) => (attacker, defender),
+        _ => return,
+    };
+    let health = match defender.health {
+        Some(health) if health > 0 => health,
+        _ => return,
+    };
+    if attacker.zone != defender.zone
+        || attacker.tile_pos.distance_chebyshev(&defender.tile_pos) > 1
+    {
+        debug!(
+            "Entity {} swung at {} out of reach",
+            attacker_entity_id, target_entity_id
+        );
+        return;
+    }
+
+    let damage = rng.range(1..MAX_HIT as i32 + 1) as u32;
+    debug!(
+        "Entity {} hit {} for {}",
+        attacker_entity_id, target_entity_id, damage
+    );
+    if damage >= health {
+        info!("Entity {} killed {}", attacker_entity_id, target_entity_id);
+        award_kill_experience(state, server, attacker_entity_id);
+    }
+    damage_entity(
+        state,
+        interest_manager,
+        server,
+        commands,
+        target_entity_id,
+        damage,
+    );
+}
+
+fn award_kill_experience(state: &mut ServerState, server: &mut impl MessageSink, entity_id: u64) {
+    let entity = match state.entities.get_mut(&entity_id) {
+        Some(entity) => entity,
+        None => return,
+    };
+    let (player_id, skills) = match (entity.player_id, entity.skills.as_mut()) {
+        (Some(player_id), Some(skills)) => (player_id, skills),
+        _ => return,
+    };
+
+    let old_level = skills.get_level(SkillType::Combat);
+    let leveled_up = skills.add_experience(SkillType::Combat, KILL_EXPERIENCE);
+    info!(
+        "Player {:?} gained {} Combat XP",
+        player_id, KILL_EXPERIENCE
+    );
+
+    let xp_msg = ServerMessage::ExperienceGained {
+        skill: SkillType::Combat,
+        amount: KILL_EXPERIENCE,
+    };
+    send_message(server, player_id, &xp_msg);
+
+    let skill_data = &skills.skills[&SkillType::Combat];
+    let skill_msg = ServerMessage::SkillUpdate {
+        skill: SkillType::Combat,
+        level: skill_data.level,
+        experience: skill_data.experience,
+        xp_to_next: skills.xp_to_next_level(SkillType::Combat),
+    };
+    send_message(server, player_id, &skill_msg);
+
+    if leveled_up {
+        info!(
+            "LEVEL UP! Player {:?} Combat: {} -> {}",
+            player_id, old_level, skill_data.level
+        );
+        let levelup_msg = ServerMessage::LevelUp {
+            skill: SkillType::Combat,
+            new_level: skill_data.level,
+        };
+        send_message(server, player_id, &levelup_msg);
+    }
+}
@@ -0,0 +1,172 @@
+use bevy::prelude::*;
+use bevy::utils::tracing::info;
+use shared::inventory::Inventory;
+use shared::items::ItemStack;
+use shared::messages::ServerMessage;
+use shared::transport::MessageSink;
+use shared::PLAYER_MAX_HEALTH;
+
+use crate::interest_manager::InterestManager;
+use crate::{broadcast_to_zone, drop_ground_item, find_spawn_tile, send_message, ServerState};
+
+/// environment variable choosing the `DeathRule`: `drop_all`, `keep_all` or `keep:<n>`
+pub const DEATH_RULE_ENV: &str = "SERVER_DEATH_RULE";
+
+/// what a player loses when they die. equipped items are always kept
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DeathRule {
+    /// the whole inventory is left on the ground where they died
+    #[default]
+    DropAll,
+    /// the first `n` inventory slots are kept and the rest dropped
+    KeepSlots(usize),
+    KeepAll,
+}
+
+impl DeathRule {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text.trim() {
+            "drop_all" => Ok(DeathRule::DropAll),
+            "keep_all" => Ok(DeathRule::KeepAll),
+            other => match other.strip_prefix("keep:").map(str::parse::<usize>) {
+                Some(Ok(slots)) => Ok(DeathRule::KeepSlots(slots)),
+                _ => Err(format!(
+                    "unknown death rule '{}', expected drop_all, keep_all or keep:<n>",
+                    other
+                )),
+            },
+        }
+    }
+
+    /// takes the stacks this rule loses out of the inventory
+    pub fn take_lost_items(&self, inventory: &mut Inventory) -> Vec<ItemStack> {
+        let kept = match self {
+            DeathRule::DropAll => 0,
+            DeathRule::KeepSlots(slots) => *slots,
+            DeathRule::KeepAll => return Vec::new(),
+        };
+        (kept..inventory.slots.len())
+            .filter_map(|slot| inventory.take_slot(slot))
+            .collect()
+    }
+}
+
+/// drops what the death rule takes where the player fell, stops whatever they were
+/// doing and puts them back on a spawn point of the same zone at full health. npcs
+/// just go home
+pub fn handle_death(
+    state: &mut ServerState,
+    interest_manager: &mut InterestManager,
+    server: &mut impl MessageSink,
+    commands: &mut Commands,
+    entity_id: u64,
+) {
+    let rule = state.death_rule;
+    let entity = match state.entities.get_mut(&entity_id) {
+        Some(entity) => entity,
+        None => return,
+    };
+    if entity.npc.is_some() {
+        handle_npc_death(state, interest_manager, server, entity_id);
+        return;
+    }
+    let player_id = match entity.player_id {
+        Some(player_id) => player_id,
+        None => return,
+    };
+
+    let queue = &mut entity.action_queue;
+    queue.current_action = None;
+    queue.suspended_action = None;
+    queue.actions.clear();
+    queue.input_buffer.clear();
+    queue.gathering_nearest = false;
+    entity.health = Some(PLAYER_MAX_HEALTH);
+    entity.last_damaged_tick = None;
+
+    let (zone, death_tile) = (entity.zone, entity.tile_pos);
+    let lost = match entity.inventory.as_mut() {
+        Some(inventory) => {
+            let lost = rule.take_lost_items(inventory);
+            let msg = ServerMessage::InventoryUpdate {
+                inventory: inventory.clone(),
+            };
+            send_message(server, player_id, &msg);
+            lost
+        }
+        None => Vec::new(),
+    };
+    info!(
+        "Player {:?} died at {:?}, dropping {} stacks",
+        player_id,
+        death_tile,
+        lost.len()
+    );
+    for stack in lost {
+        drop_ground_item(
+            zone,
+            death_tile,
+            stack,
+            state,
+            interest_manager,
+            server,
+            commands,
+        );
+    }
+
+    let spawn = find_spawn_tile(state, zone);
+    if let Some(entity) = state.entities.get_mut(&entity_id) {
+        entity.tile_pos = spawn;
+    }
+    state.dirty_entities.insert(entity_id);
+    interest_manager.resend_full_state(entity_id);
+    info!("Player {:?} respawned at {:?}", player_id, spawn);
+
+    let health_msg = ServerMessage::HealthUpdate {
+        entity_id,
+        health: PLAYER_MAX_HEALTH,
+    };
+    broadcast_to_zone(state, server, zone, &health_msg);
+    send_message(
+        server,
+        player_id,
+        &ServerMessage::ActionCompleted { entity_id },
+    );
+}
+
+/// a dead npc turns up back at home at full health, having forgotten who it was after
+fn handle_npc_death(
+    state: &mut ServerState,
+    interest_manager: &mut InterestManager,
+    server: &mut impl MessageSink,
+    entity_id: u64,
+) {
+    let tick = state.server_tick;
+    let entity = match state.entities.get_mut(&entity_id) {
+        Some(entity) => entity,
+        None => return,
+    };
+    let npc = match entity.npc.as_mut() {
+        Some(npc) => npc,
+        None => return,
+    };
+    npc.target = None;
+    npc.next_wander_tick = tick;
+    let home = npc.home;
+
+    let queue = &mut entity.action_queue;
+    queue.current_action = None;
+    queue.actions.clear();
+    let health = entity.kind.max_health();
+    entity.health = Some(health);
+    entity.last_damaged_tick = None;
+    entity.tile_pos = home;
+    let zone = entity.zone;
+
+    state.dirty_entities.insert(entity_id);
+    interest_manager.resend_full_state(entity_id);
+    info!("Npc {} died and respawned at {:?}", entity_id, home);
+
+    let health_msg = ServerMessage::HealthUpdate { entity_id, health };
+    broadcast_to_zone(state, server, zone, &health_msg);
+}
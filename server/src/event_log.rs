@@ -0,0 +1,79 @@
+use bevy::prelude::*;
+use bevy::utils::tracing::info;
+use std::collections::VecDeque;
+
+use crate::ServerState;
+
+/// how many events the log keeps, older ones are dropped as new ones come in
+pub const EVENT_LOG_CAPACITY: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntityEventKind {
+    Spawned,
+    Despawned,
+    TreeChopped,
+    TreeRespawned,
+    RockDepleted,
+    RockRespawned,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntityEvent {
+    pub tick: u64,
+    pub entity_id: u64,
+    pub kind: EntityEventKind,
+}
+
+/// the last `EVENT_LOG_CAPACITY` times an entity appeared, went away or changed state,
+/// for working out why a client saw something come and go. shown with the `events`
+/// admin command and dumped when the server exits
+#[derive(Default)]
+pub struct EventLog {
+    events: VecDeque<EntityEvent>,
+}
+
+impl EventLog {
+    pub fn record(&mut self, tick: u64, entity_id: u64, kind: EntityEventKind) {
+        if self.events.len() == EVENT_LOG_CAPACITY {
+            self.events.pop_front();
+        }
+        self.events.push_back(EntityEvent {
+            tick,
+            entity_id,
+            kind,
+        });
+    }
+
+    /// every event still kept, oldest first
+    pub fn events(&self) -> impl Iterator<Item = &EntityEvent> {
+        self.events.iter()
+    }
+
+    /// the newest `count` events, oldest first
+    pub fn recent(&self, count: usize) -> impl Iterator<Item = &EntityEvent> {
+        self.events
+            .iter()
+            .skip(self.events.len().saturating_sub(count))
+    }
+
+    /// writes the newest `count` events to the log
+    pub fn dump(&self, count: usize) {
+        info!(
+            "Last {} of {} entity events:",
+            count.min(self.events.len()),
+            self.events.len()
+        );
+        for event in self.recent(count) {
+            info!(
+                "  tick {}: entity {} {:?}",
+                event.tick, event.entity_id, event.kind
+            );
+        }
+    }
+}
+
+pub fn dump_event_log_on_exit(mut exit_events: EventReader<AppExit>, state: Res<ServerState>) {
+    if exit_events.read().next().is_some() {
+        state.event_log.dump(EVENT_LOG_CAPACITY);
+    }
+}
@@ -3,9 +3,51 @@ use shared::tile_system::TilePosition;
 use shared::*;
 use std::collections::{HashMap, HashSet};
 
-#[derive(Resource, Default)]
+/// how distance from the player is measured when deciding what is in view
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DistanceMetric {
+    /// diamond-shaped view
+    Manhattan,
+    /// square view of `2 * VIEW_DISTANCE + 1` tiles, matching the client grid
+    #[default]
+    Chebyshev,
+}
+
+impl DistanceMetric {
+    pub fn distance(&self, a: &TilePosition, b: &TilePosition) -> i32 {
+        match self {
+            DistanceMetric::Manhattan => a.distance_to(b),
+            DistanceMetric::Chebyshev => a.distance_chebyshev(b),
+        }
+    }
+}
+
+/// extra tiles an entity already in view may move past `VIEW_DISTANCE` before it leaves
+pub const DEFAULT_LEAVE_MARGIN: i32 = 1;
+
+#[derive(Resource)]
 pub struct InterestManager {
+    pub metric: DistanceMetric,
+    /// entities enter view at `VIEW_DISTANCE` but only leave past `VIEW_DISTANCE +
+    /// leave_margin`, so one pacing along the edge doesn't enter and leave every tick
+    pub leave_margin: i32,
     pub client_views: HashMap<PlayerId, HashSet<u64>>,
+    /// entities each player has already been sent a FullState delta for
+    pub full_state_sent: HashMap<PlayerId, HashSet<u64>>,
+    /// players who asked for something other than `VIEW_DISTANCE`
+    pub view_distances: HashMap<PlayerId, i32>,
+}
+
+impl Default for InterestManager {
+    fn default() -> Self {
+        Self {
+            metric: DistanceMetric::default(),
+            leave_margin: DEFAULT_LEAVE_MARGIN,
+            client_views: HashMap::new(),
+            full_state_sent: HashMap::new(),
+            view_distances: HashMap::new(),
+        }
+    }
 }
 
 impl InterestManager {
@@ -15,6 +57,9 @@ impl InterestManager {
         center: TilePosition,
         entities: &HashMap<u64, TilePosition>,
     ) -> (Vec<u64>, Vec<u64>) {
+        let metric = self.metric;
+        let view_distance = self.view_distance(player_id);
+        let leave_distance = view_distance + self.leave_margin.max(0);
         let view = self
             .client_views
             .entry(player_id)
@@ -22,7 +67,9 @@ impl InterestManager {
         let mut now_visible = HashSet::new();
 
         for (entity_id, pos) in entities {
-            if center.distance_to(pos) <= VIEW_DISTANCE {
+            let distance = metric.distance(&center, pos);
+            if distance <= view_distance || (distance <= leave_distance && view.contains(entity_id))
+            {
                 now_visible.insert(*entity_id);
             }
         }
@@ -31,6 +78,74 @@ impl InterestManager {
         let left: Vec<u64> = view.difference(&now_visible).copied().collect();
 
         *view = now_visible;
+
+        // an entity that re-enters view must start again with a FullState
+        if let Some(sent) = self.full_state_sent.get_mut(&player_id) {
+            for entity_id in &left {
+                sent.remove(entity_id);
+            }
+        }
+
         (entered, left)
     }
+
+    pub fn view_distance(&self, player_id: PlayerId) -> i32 {
+        self.view_distances
+            .get(&player_id)
+            .copied()
+            .unwrap_or(VIEW_DISTANCE)
+    }
+
+    /// clamps `tiles` to what the server allows and returns the distance that was set
+    pub fn set_view_distance(&mut self, player_id: PlayerId, tiles: i32) -> i32 {
+        let tiles = tiles.clamp(MIN_VIEW_DISTANCE, MAX_VIEW_DISTANCE);
+        self.view_distances.insert(player_id, tiles);
+        tiles
+    }
+
+    pub fn can_see(&self, player_id: PlayerId, entity_id: u64) -> bool {
+        self.client_views
+            .get(&player_id)
+            .is_some_and(|view| view.contains(&entity_id))
+    }
+
+    /// records that the player has been sent full state for the entity,
+    /// returns true if this is the first time
+    pub fn mark_full_state_sent(&mut self, player_id: PlayerId, entity_id: u64) -> bool {
+        self.full_state_sent
+            .entry(player_id)
+            .or_default()
+            .insert(entity_id)
+    }
+
+    /// makes the next delta for the entity a FullState for everyone, so clients snap to
+    /// its position instead of interpolating (e.g. after a teleport)
+    pub fn resend_full_state(&mut self, entity_id: u64) {
+        for sent in self.full_state_sent.values_mut() {
+            sent.remove(&entity_id);
+        }
+    }
+
+    /// drops a despawned entity from every view so it isn't reported as leaving again
+    pub fn forget_entity(&mut self, entity_id: u64) {
+        for view in self.client_views.values_mut() {
+            view.remove(&entity_id);
+        }
+        for sent in self.full_state_sent.values_mut() {
+            sent.remove(&entity_id);
+        }
+    }
+
+    /// forgets everything the player has been sent, e.g. when they change zone, so
+    /// their next update sends their surroundings from scratch
+    pub fn reset_view(&mut self, player_id: PlayerId) {
+        self.client_views.insert(player_id, HashSet::new());
+        self.full_state_sent.remove(&player_id);
+    }
+
+    pub fn remove_client(&mut self, player_id: PlayerId) {
+        self.client_views.remove(&player_id);
+        self.full_state_sent.remove(&player_id);
+        self.view_distances.remove(&player_id);
+    }
 }
@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+
+/// most ticks an action's validation is rewound by, however far behind the client is
+pub const MAX_REWIND_TICKS: u64 = 3;
+
+/// ticks at which trees were felled and rocks depleted, kept just long enough to
+/// validate actions against what a client drawing the world a little in the past
+/// could still see
+#[derive(Default)]
+pub struct ResourceHistory {
+    depleted_at: HashMap<u64, u64>,
+}
+
+impl ResourceHistory {
+    pub fn record_depleted(&mut self, entity_id: u64, tick: u64) {
+        self.depleted_at.insert(entity_id, tick);
+    }
+
+    pub fn record_restored(&mut self, entity_id: u64) {
+        self.depleted_at.remove(&entity_id);
+    }
+
+    /// forgets anything older than the furthest a validation can rewind to
+    pub fn prune(&mut self, tick: u64) {
+        self.depleted_at
+            .retain(|_, depleted_at| tick.saturating_sub(*depleted_at) <= MAX_REWIND_TICKS);
+    }
+
+    /// whether a resource that is depleted now was still there at `tick`
+    pub fn was_available_at(&self, entity_id: u64, tick: u64) -> bool {
+        self.depleted_at
+            .get(&entity_id)
+            .is_some_and(|depleted_at| *depleted_at > tick)
+    }
+}
+
+/// how many ticks behind the server a client with `render_delay` seconds of
+/// interpolation delay sees the world, capped at `MAX_REWIND_TICKS`
+pub fn rewind_ticks(render_delay: f32, tick_duration: f32) -> u64 {
+    if render_delay <= 0.0 || tick_duration <= 0.0 {
+        return 0;
+    }
+    ((render_delay / tick_duration).ceil() as u64).min(MAX_REWIND_TICKS)
+}
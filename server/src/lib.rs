@@ -1,22 +1,137 @@
+use crate::combat::handle_attack_completion;
+use crate::death::{handle_death, DeathRule, DEATH_RULE_ENV};
+use crate::event_log::{EntityEventKind, EventLog};
 use crate::interest_manager::InterestManager;
+use crate::lag_compensation::{rewind_ticks, ResourceHistory};
+use crate::metrics::{BandwidthStats, MeteredTransport};
+use crate::npc::{chase_players, spawn_npcs, wander_npcs, Npc};
+use crate::persistence::{
+    load_definitions, load_tile_map, load_world, save_world, SAVE_INTERVAL_TICKS,
+};
+use crate::profiles::{PlayerProfile, PlayerProfileStore};
+use crate::rng::GameRng;
+use crate::timing::ServerTiming;
 use bevy::prelude::*;
 use bevy::utils::tracing::{debug, info, warn};
 use bevy_renet::renet::transport::{NetcodeServerTransport, ServerAuthentication, ServerConfig};
 use bevy_renet::renet::*;
-use shared::actions::GameAction;
+use shared::achievements::{AchievementId, Achievements};
+use shared::actions::{ActionPriority, GameAction};
+use shared::bank::{Bank, BANK_SLOTS};
+use shared::definitions::Definitions;
+use shared::identity::account_from_user_data;
 use shared::inventory::Inventory;
-use shared::items::{ItemDefinition, ItemType};
-use shared::messages::{ClientMessage, DeltaType, EntityDelta, EntitySnapshot, ServerMessage};
-use shared::pathfinding::Pathfinder;
+use shared::items::{EquipSlot, Equipment, ItemEffect, ItemStack, ItemType};
+use shared::messages::{
+    ActionOutcome, ClientMessage, DeltaType, EntityDelta, EntitySnapshot, RejectReason,
+    ServerMessage,
+};
+use shared::mining::{Rock, RockDefinition, RockType};
+use shared::netcode::encode_delta_update;
+use shared::pathfinding::{PathMode, Pathfinder};
 use shared::skills::{SkillType, Skills};
+use shared::tile_map::TileMap;
 use shared::tile_system::TilePosition;
-use shared::trees::{Tree, TreeDefinition, TreeType};
+use shared::transport::{MessageSink, ServerTransport};
+use shared::trees::{Tree, TreeType};
 use shared::*;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::net::{SocketAddr, UdpSocket};
 use std::time::SystemTime;
 
+pub mod admin;
+pub mod combat;
+pub mod death;
+pub mod event_log;
 pub mod interest_manager;
+pub mod lag_compensation;
+pub mod metrics;
+pub mod npc;
+pub mod persistence;
+pub mod profiles;
+pub mod rng;
+pub mod shutdown;
+pub mod timing;
+
+/// most actions a player can have waiting behind the one in progress
+pub const MAX_QUEUED_ACTIONS: usize = 16;
+
+/// most WASD steps a player can have waiting for a tick, the oldest is dropped past it
+pub const MAX_BUFFERED_STEPS: usize = 4;
+
+/// how many messages from one player are handled per tick, the rest wait for the next
+pub const MAX_MESSAGES_PER_TICK: u32 = 32;
+
+/// undecodable messages a client may send before it is treated as malicious
+pub const MAX_UNDECODABLE_MESSAGES: u32 = 10;
+
+/// longest name a player can have, in characters
+pub const MAX_NAME_LENGTH: usize = 20;
+
+/// environment variable with a comma separated list of admin account ids
+pub const ADMINS_ENV: &str = "SERVER_ADMINS";
+
+/// environment variable overriding how many nodes a path search may expand
+pub const PATH_NODES_ENV: &str = "SERVER_PATH_NODES";
+/// environment variable choosing the path search, `astar` or `jps`
+pub const PATH_MODE_ENV: &str = "SERVER_PATH_MODE";
+
+/// how many clients can be in the game at once unless configured otherwise
+pub const DEFAULT_MAX_CLIENTS: usize = 64;
+/// environment variable overriding how many clients can be in the game at once
+pub const MAX_CLIENTS_ENV: &str = "SERVER_MAX_CLIENTS";
+/// command line flag for the same, takes priority over the env var
+pub const MAX_CLIENTS_ARG: &str = "--max-clients";
+/// connections accepted beyond `max_clients`, so a client joining a full server gets
+/// far enough to be told why instead of its connection silently timing out
+pub const OVERFLOW_CONNECTIONS: usize = 4;
+/// what a client joining a full server is kicked with
+pub const SERVER_FULL_REASON: &str = "server full";
+
+/// ticks between telling a client it is kicked and disconnecting it
+pub const KICK_DELAY_TICKS: u64 = 2;
+
+/// ticks between each hitpoint regenerated while below full health
+pub const REGEN_INTERVAL_TICKS: u64 = 10;
+
+/// ticks after taking damage before regeneration starts counting again
+pub const REGEN_PAUSE_TICKS: u64 = 5;
+
+/// how often an entity that moved has its position resent on the reliable channel
+pub const KEYFRAME_INTERVAL_TICKS: u64 = 10;
+
+/// per-player message counts since the last tick
+#[derive(Default)]
+pub struct MessageBudget {
+    counts: HashMap<PlayerId, u32>,
+    warned: HashSet<PlayerId>,
+}
+
+impl MessageBudget {
+    /// false once the player has used up this tick's budget
+    pub fn has_room(&mut self, player_id: PlayerId) -> bool {
+        let count = self.counts.get(&player_id).copied().unwrap_or(0);
+        if count < MAX_MESSAGES_PER_TICK {
+            return true;
+        }
+        if self.warned.insert(player_id) {
+            warn!(
+                "Player {:?} sent more than {} messages this tick, holding the rest",
+                player_id, MAX_MESSAGES_PER_TICK
+            );
+        }
+        false
+    }
+
+    pub fn spend(&mut self, player_id: PlayerId) {
+        *self.counts.entry(player_id).or_default() += 1;
+    }
+
+    pub fn reset(&mut self) {
+        self.counts.clear();
+        self.warned.clear();
+    }
+}
 
 #[derive(Component)]
 pub struct ActionQueue {
@@ -24,6 +139,25 @@ pub struct ActionQueue {
     pub current_action: Option<ActionInProgress>,
     /// Suspended action (Strong actions can suspend Normal actions)
     pub suspended_action: Option<ActionInProgress>,
+    /// tile a move was stopped at because it was occupied, reported to the player
+    pub blocked_at: Option<TilePosition>,
+    /// WASD steps waiting for a tick of their own, oldest first
+    pub input_buffer: VecDeque<BufferedStep>,
+    /// tick the last WASD step started in, only one starts per tick
+    pub last_step_tick: Option<u64>,
+    /// running a `ChopNearest` loop, another tree is picked each time one is felled
+    pub gathering_nearest: bool,
+    /// the accepted input still running, reported `Completed` once the queue empties
+    pub awaiting_completion: Option<u32>,
+}
+
+/// a `StepInput` that arrived in a tick that had already taken a step
+#[derive(Clone, Copy, Debug)]
+pub struct BufferedStep {
+    pub dx: i32,
+    pub dy: i32,
+    pub input_sequence_number: u32,
+    pub pressed_at: f64,
 }
 
 #[derive(Clone, Debug)]
@@ -41,10 +175,25 @@ impl Default for ActionQueue {
             actions: VecDeque::new(),
             current_action: None,
             suspended_action: None,
+            blocked_at: None,
+            input_buffer: VecDeque::new(),
+            last_step_tick: None,
+            gathering_nearest: false,
+            awaiting_completion: None,
         }
     }
 }
 
+impl ActionQueue {
+    /// stops the current move and anything queued behind it, including a gather loop
+    fn block_move(&mut self, at: TilePosition) {
+        self.current_action = None;
+        self.actions.clear();
+        self.blocked_at = Some(at);
+        self.gathering_nearest = false;
+    }
+}
+
 impl ActionInProgress {
     pub fn should_repeat(&self) -> bool {
         self.action.is_repeating()
@@ -59,46 +208,214 @@ pub struct ServerState {
     pub next_entity_id: u64,
     pub server_tick: u64,
     pub tick_accumulator: f32,
+    /// game clock in seconds, advanced by one tick duration every tick so it stays
+    /// continuous when the tick rate changes
+    pub game_time: f64,
     pub last_states: HashMap<u64, EntityLastState>,
-    pub pathfinder: Pathfinder,
+    /// entities that may have moved or started an action since the last delta update,
+    /// the only ones `send_delta_updates` looks at
+    pub dirty_entities: HashSet<u64>,
+    pub zones: HashMap<ZoneId, Zone>,
+    pub profiles: PlayerProfileStore,
+    /// how long dropped items stay on the ground
+    pub ground_item_despawn_ticks: u64,
+    pub message_budget: MessageBudget,
+    /// players who send nothing for this many ticks are disconnected
+    pub idle_timeout_ticks: u64,
+    /// how long a disconnected player's entity waits for them to reconnect
+    pub reconnect_grace_ticks: u64,
+    /// messages that failed to decode, per client
+    pub undecodable_messages: HashMap<PlayerId, u32>,
+    /// accounts allowed to use debugging requests like world snapshots
+    pub admin_accounts: HashSet<String>,
+    /// item and tree definitions loaded over the built-in ones, sent to every client
+    pub definitions: Definitions,
+    /// account ids clients sent in the connection handshake, these win over the one
+    /// in `Join`
+    pub client_accounts: HashMap<PlayerId, String>,
+    /// kicked clients and the tick they get disconnected at, a little after the
+    /// `Kicked` message so it has a chance to arrive
+    pub kicked: HashMap<PlayerId, u64>,
+    /// clients watching without an entity of their own
+    pub spectators: HashMap<PlayerId, Spectator>,
+    /// when resources ran out, so actions from clients a few ticks behind validate
+    /// against what those clients could see
+    pub resource_history: ResourceHistory,
+    /// recent spawns, despawns and resource changes, for debugging interest churn
+    pub event_log: EventLog,
+    /// most players and spectators in the game at once, joins past it are kicked
+    pub max_clients: usize,
+    /// what players lose when they die
+    pub death_rule: DeathRule,
+}
+
+/// a client that joined with `JoinAsSpectator`. it sees what is around `center`,
+/// which it moves itself
+pub struct Spectator {
+    pub zone: ZoneId,
+    pub center: TilePosition,
 }
 
 pub struct ServerPlayer {
     pub entity_id: u64,
     pub name: String,
+    pub account_id: String,
+    pub bank: Inventory,
+    /// server tick of the last message we got from this player
+    pub last_message_tick: u64,
+    /// tick at which a disconnected player's entity is removed, `None` while connected
+    pub pending_removal: Option<u64>,
 }
 
 pub struct ServerEntity {
+    pub kind: EntityKind,
     pub tile_pos: TilePosition,
+    pub zone: ZoneId,
     pub player_id: Option<PlayerId>,
     pub action_queue: ActionQueue,
     pub entity: Entity,
     pub is_obstacle: bool,
     pub inventory: Option<Inventory>,
     pub skills: Option<Skills>,
+    /// only players have them
+    pub achievements: Option<Achievements>,
+    pub equipment: Equipment,
     pub tree: Option<Tree>,
+    pub rock: Option<Rock>,
+    pub bank: Option<Bank>,
+    pub ground_item: Option<ItemStack>,
+    /// tick at which this entity is removed from the world (ground items)
+    pub despawn_tick: Option<u64>,
     pub last_processed_input: Option<u32>,
+    /// hitpoints, only players and npcs have them
+    pub health: Option<u32>,
+    /// tick this entity last lost health at, regeneration waits `REGEN_PAUSE_TICKS`
+    /// after it
+    pub last_damaged_tick: Option<u64>,
+    /// run toggle and energy, only players have them
+    pub run: Option<RunState>,
+    pub npc: Option<Npc>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RunState {
+    pub enabled: bool,
+    pub energy: f32,
+}
+
+impl Default for RunState {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            energy: MAX_RUN_ENERGY,
+        }
+    }
+}
+
+impl RunState {
+    /// whether moves this tick cover `RUN_STEPS` tiles
+    pub fn is_running(&self) -> bool {
+        self.enabled && self.energy > 0.0
+    }
+
+    /// drains energy for a tick spent running and refills it for one spent standing
+    /// still. running switches itself off once the energy is gone. true if anything
+    /// changed
+    pub fn update(&mut self, ran: bool, idle: bool) -> bool {
+        let before = (self.enabled, self.energy);
+        if ran {
+            self.energy = (self.energy - RUN_ENERGY_DRAIN).max(0.0);
+            if self.energy == 0.0 {
+                self.enabled = false;
+            }
+        } else if idle {
+            self.energy = (self.energy + RUN_ENERGY_REGEN).min(MAX_RUN_ENERGY);
+        }
+        (self.enabled, self.energy) != before
+    }
 }
 
 #[derive(Default)]
 pub struct EntityLastState {
     pub tile_pos: TilePosition,
     pub last_sent_tick: u64,
+    /// start time of the last action viewers were told about
+    pub action_started_at: Option<f64>,
+    /// position in the last keyframe, clients got it reliably
+    pub keyframe_pos: TilePosition,
+    pub last_keyframe_tick: u64,
 }
 
-impl Default for ServerState {
-    fn default() -> Self {
-        let mut pathfinder = Pathfinder::new(false);
+/// a tile that takes whoever steps on it to another zone
+pub struct Portal {
+    pub portal_id: u32,
+    pub tile: TilePosition,
+    pub target_zone: ZoneId,
+}
 
-        // add boundary walls
-        for x in -5..=5 {
-            pathfinder.add_obstacle(TilePosition { x, y: 5 });
-            pathfinder.add_obstacle(TilePosition { x, y: -5 });
-        }
-        for y in -5..=5 {
-            pathfinder.add_obstacle(TilePosition { x: 5, y });
-            pathfinder.add_obstacle(TilePosition { x: -5, y });
+pub struct Zone {
+    pub tile_map: TileMap,
+    pub pathfinder: Pathfinder,
+    /// tiles new arrivals are placed on, in order of preference
+    pub spawn_points: Vec<TilePosition>,
+    pub portals: Vec<Portal>,
+}
+
+impl Zone {
+    pub fn new(tile_map: TileMap, spawn_points: Vec<TilePosition>, portals: Vec<Portal>) -> Self {
+        let mut pathfinder = Pathfinder::new(false);
+        pathfinder.set_obstacles(tile_map.blocked_tiles());
+        pathfinder.set_terrain_costs(tile_map.terrain_costs());
+        Self {
+            tile_map,
+            pathfinder,
+            spawn_points,
+            portals,
         }
+    }
+
+    pub fn portal_at(&self, tile: &TilePosition) -> Option<&Portal> {
+        self.portals.iter().find(|portal| portal.tile == *tile)
+    }
+}
+
+/// where players join and where the persisted trees and rocks live
+pub const MAIN_ZONE: ZoneId = ZoneId(0);
+pub const CAVE_ZONE: ZoneId = ZoneId(1);
+
+impl Default for ServerState {
+    fn default() -> Self {
+        let mut zones = HashMap::new();
+        zones.insert(
+            MAIN_ZONE,
+            Zone::new(
+                TileMap::parse(DEFAULT_MAP).expect("bundled map is valid"),
+                vec![
+                    TilePosition { x: 0, y: 0 },
+                    TilePosition { x: 1, y: 0 },
+                    TilePosition { x: -1, y: 0 },
+                    TilePosition { x: 0, y: 1 },
+                    TilePosition { x: 0, y: -1 },
+                ],
+                vec![Portal {
+                    portal_id: 1,
+                    tile: TilePosition { x: 4, y: 4 },
+                    target_zone: CAVE_ZONE,
+                }],
+            ),
+        );
+        zones.insert(
+            CAVE_ZONE,
+            Zone::new(
+                TileMap::parse(CAVE_MAP).expect("bundled map is valid"),
+                vec![TilePosition { x: 1, y: 0 }, TilePosition { x: 0, y: -1 }],
+                vec![Portal {
+                    portal_id: 2,
+                    tile: TilePosition { x: 2, y: -2 },
+                    target_zone: MAIN_ZONE,
+                }],
+            ),
+        );
 
         Self {
             players: HashMap::new(),
@@ -107,293 +424,1432 @@ impl Default for ServerState {
             next_entity_id: 1,
             server_tick: 0,
             tick_accumulator: 0.0,
+            game_time: 0.0,
             last_states: HashMap::new(),
-            pathfinder,
+            dirty_entities: HashSet::new(),
+            zones,
+            profiles: PlayerProfileStore::default(),
+            ground_item_despawn_ticks: 100,
+            message_budget: MessageBudget::default(),
+            idle_timeout_ticks: 500,
+            reconnect_grace_ticks: 50,
+            undecodable_messages: HashMap::new(),
+            admin_accounts: HashSet::new(),
+            definitions: Definitions::default(),
+            client_accounts: HashMap::new(),
+            kicked: HashMap::new(),
+            spectators: HashMap::new(),
+            resource_history: ResourceHistory::default(),
+            event_log: EventLog::default(),
+            max_clients: DEFAULT_MAX_CLIENTS,
+            death_rule: DeathRule::default(),
         }
     }
 }
 
-pub fn setup_server(mut commands: Commands, mut state: ResMut<ServerState>) {
-    let server_addr: SocketAddr = format!("127.0.0.1:{}", SERVER_PORT).parse().unwrap();
-    let socket = UdpSocket::bind(server_addr).unwrap();
-    let current_time = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap();
-
-    let server_config = ServerConfig {
-        current_time,
-        max_clients: 64,
-        protocol_id: PROTOCOL_ID,
-        public_addresses: vec![server_addr],
-        authentication: ServerAuthentication::Unsecure,
-    };
+/// map of the main zone when there is no `world.map` next to the server
+const DEFAULT_MAP: &str = include_str!("../world.map");
+const CAVE_MAP: &str = include_str!("../cave.map");
 
-    let transport = NetcodeServerTransport::new(server_config, socket).unwrap();
-    let server = RenetServer::new(ConnectionConfig::default());
+impl ServerState {
+    /// zones are created at startup and never removed, so every entity's zone exists
+    pub fn zone(&self, zone: ZoneId) -> &Zone {
+        &self.zones[&zone]
+    }
 
-    commands.insert_resource(server);
-    commands.insert_resource(transport);
+    pub fn zone_mut(&mut self, zone: ZoneId) -> &mut Zone {
+        self.zones.get_mut(&zone).expect("zones are never removed")
+    }
 
-    spawn_trees(&mut state, &mut commands);
+    /// swaps a zone's terrain, call before spawning trees and rocks since their
+    /// obstacles are cleared along with the old map's
+    pub fn set_tile_map(&mut self, zone: ZoneId, tile_map: TileMap) {
+        let zone = self.zone_mut(zone);
+        zone.pathfinder.set_obstacles(tile_map.blocked_tiles());
+        zone.pathfinder.set_terrain_costs(tile_map.terrain_costs());
+        zone.tile_map = tile_map;
+    }
 
-    info!("Server started on {}", server_addr);
-    info!("Server configuration:");
-    info!("Max clients: 64");
-    info!("Protocol ID: {}", PROTOCOL_ID);
-    info!("Tick rate: {}ms", (TICK_RATE * 1000.0) as u32);
-    info!("View distance: {} tiles", VIEW_DISTANCE);
-    info!(
-        "Spawned {} entities (including {} trees)",
-        state.entities.len(),
-        state.entities.len()
-    );
+    /// zone of the player's entity, the main zone if they haven't spawned
+    pub fn player_zone(&self, player_id: PlayerId) -> ZoneId {
+        self.players
+            .get(&player_id)
+            .and_then(|player| self.entities.get(&player.entity_id))
+            .map(|entity| entity.zone)
+            .unwrap_or(MAIN_ZONE)
+    }
 }
 
-pub fn spawn_trees(state: &mut ServerState, commands: &mut Commands) {
-    let tree_positions = vec![
-        (TilePosition { x: -3, y: -3 }, TreeType::Normal),
-        (TilePosition { x: -2, y: -3 }, TreeType::Normal),
-        (TilePosition { x: 3, y: 3 }, TreeType::Oak),
-        (TilePosition { x: 2, y: 3 }, TreeType::Oak),
-        (TilePosition { x: -3, y: 3 }, TreeType::Willow),
-        (TilePosition { x: 0, y: -4 }, TreeType::Normal),
-        (TilePosition { x: 1, y: -4 }, TreeType::Oak),
-    ];
+/// how far from the origin to look for a free tile once every spawn point is taken
+const SPAWN_SEARCH_RADIUS: i32 = 10;
 
-    for (pos, tree_type) in tree_positions {
-        let entity_id = state.next_entity_id;
-        state.next_entity_id += 1;
+/// first free walkable spawn point of the zone, or the nearest free tile around the
+/// origin if they're all taken. ground items don't count as occupying a tile
+pub fn find_spawn_tile(state: &ServerState, zone: ZoneId) -> TilePosition {
+    let occupied: HashSet<TilePosition> = state
+        .entities
+        .values()
+        .filter(|entity| entity.zone == zone && entity.ground_item.is_none())
+        .map(|entity| entity.tile_pos)
+        .collect();
+    let zone = state.zone(zone);
+    let is_free = |pos: &TilePosition| zone.pathfinder.is_walkable(pos) && !occupied.contains(pos);
 
-        let entity = commands
-            .spawn((pos, Transform::from_translation(pos.to_world().extend(0.0))))
-            .id();
-
-        let server_entity = ServerEntity {
-            tile_pos: pos,
-            player_id: None,
-            action_queue: ActionQueue::default(),
-            entity,
-            is_obstacle: false,
-            inventory: None,
-            skills: None,
-            tree: Some(Tree::new(tree_type)),
-            last_processed_input: None,
-        };
+    if let Some(pos) = zone.spawn_points.iter().find(|pos| is_free(pos)) {
+        return *pos;
+    }
 
-        state.entities.insert(entity_id, server_entity);
-        state.pathfinder.add_obstacle(pos);
+    let origin = TilePosition { x: 0, y: 0 };
+    for radius in 1..=SPAWN_SEARCH_RADIUS {
+        for x in -radius..=radius {
+            for y in -radius..=radius {
+                let pos = TilePosition { x, y };
+                if origin.distance_chebyshev(&pos) == radius && is_free(&pos) {
+                    return pos;
+                }
+            }
+        }
     }
+
+    warn!("No free spawn tile found, spawning at the origin");
+    origin
 }
 
-pub fn server_update_system(
-    mut server: ResMut<RenetServer>,
-    mut server_state: ResMut<ServerState>,
-    mut interest_manager: ResMut<InterestManager>,
-    time: Res<Time>,
-    mut commands: Commands,
-) {
-    server_state.tick_accumulator += time.delta_seconds();
+/// spawns a fresh player entity, restoring the account's saved profile if it has one
+fn spawn_player(
+    state: &mut ServerState,
+    interest_manager: &mut InterestManager,
+    commands: &mut Commands,
+    player_id: PlayerId,
+    name: String,
+    account_id: String,
+) -> u64 {
+    let spawn_pos = find_spawn_tile(state, MAIN_ZONE);
+    let entity_id = state.next_entity_id;
+    state.next_entity_id += 1;
 
-    for client_id in server.clients_id() {
-        while let Some(message) = server.receive_message(client_id, DefaultChannel::ReliableOrdered)
-        {
-            debug!(
-                "Received message from ClientId({}), {} bytes",
-                client_id.raw(),
-                message.len()
+    let (inventory, skills, equipment, bank, achievements) = match state.profiles.get(&account_id) {
+        Some(profile) => {
+            info!("Restoring saved profile for account '{}'", account_id);
+            (
+                profile.inventory.clone(),
+                profile.skills.clone(),
+                profile.equipment.clone(),
+                profile.bank.clone(),
+                profile.achievements.clone(),
+            )
+        }
+        None => {
+            let mut inventory = Inventory::new(28);
+            inventory.add_item(ItemType::BronzePickaxe, 1, &state.definitions);
+            let mut equipment = Equipment::new();
+            equipment.insert(
+                EquipSlot::Weapon,
+                ItemStack {
+                    item_type: ItemType::BronzeAxe,
+                    quantity: 1,
+                },
             );
-            if let Ok(client_msg) = bincode::deserialize::<ClientMessage>(&message) {
-                info!(
-                    "Processing message from PlayerId({}): {:?}",
-                    client_id.raw(),
-                    match &client_msg {
-                        ClientMessage::Join { name } => format!("Join(name={})", name),
-                        ClientMessage::QueueAction {
-                            action,
-                            input_sequence_number,
-                        } => format!("QueueAction({:?}, seq={})", action, input_sequence_number),
-                        ClientMessage::QueueActions {
-                            actions,
-                            input_sequence_number,
-                        } => format!(
-                            "QueueActions([{} actions], seq={})",
-                            actions.len(),
-                            input_sequence_number
-                        ),
-                        ClientMessage::CancelAction => "CancelAction".to_string(),
-                        ClientMessage::RequestPath { start, goal } =>
-                            format!("RequestPath({:?} -> {:?})", start, goal),
-                    }
-                );
-                handle_client_message(
-                    client_msg,
-                    PlayerId(client_id.raw()),
-                    &mut server_state,
-                    &mut interest_manager,
-                    &mut server,
-                    &mut commands,
-                    time.elapsed_seconds_f64(),
-                );
-            }
+            info!("Starting inventory: Bronze pickaxe, wielding Bronze axe");
+            (
+                inventory,
+                Skills::new(),
+                equipment,
+                Inventory::new(BANK_SLOTS),
+                Achievements::default(),
+            )
         }
-    }
+    };
+
+    let entity = commands
+        .spawn((
+            spawn_pos,
+            Transform::from_translation(spawn_pos.to_world().extend(0.0)),
+            ActionQueue::default(),
+        ))
+        .id();
 
-    handle_disconnections(
-        &mut server,
-        &mut server_state,
-        &mut interest_manager,
-        &mut commands,
+    let server_entity = ServerEntity {
+        kind: EntityKind::Player,
+        tile_pos: spawn_pos,
+        zone: MAIN_ZONE,
+        player_id: Some(player_id),
+        action_queue: ActionQueue::default(),
+        entity,
+        is_obstacle: false,
+        inventory: Some(inventory),
+        skills: Some(skills),
+        achievements: Some(achievements),
+        equipment,
+        tree: None,
+        rock: None,
+        bank: None,
+        ground_item: None,
+        despawn_tick: None,
+        last_processed_input: None,
+        health: Some(PLAYER_MAX_HEALTH),
+        last_damaged_tick: None,
+        run: Some(RunState::default()),
+        npc: None,
+    };
+
+    state.entities.insert(entity_id, server_entity);
+    state.dirty_entities.insert(entity_id);
+    state
+        .event_log
+        .record(state.server_tick, entity_id, EntityEventKind::Spawned);
+    state.players.insert(
+        player_id,
+        ServerPlayer {
+            entity_id,
+            name: name.clone(),
+            account_id,
+            bank,
+            last_message_tick: state.server_tick,
+            pending_removal: None,
+        },
     );
+    interest_manager
+        .client_views
+        .insert(player_id, HashSet::new());
 
-    while server_state.tick_accumulator >= TICK_RATE {
-        server_state.tick_accumulator -= TICK_RATE;
-        server_state.server_tick += 1;
-        debug!("Server tick #{}", server_state.server_tick);
-        process_server_tick(&mut server_state, &mut server, &mut interest_manager);
-    }
+    info!(
+        "Player {:?} '{}' spawned at {:?} with entity_id={}",
+        player_id, name, spawn_pos, entity_id
+    );
+
+    entity_id
 }
 
-pub fn handle_client_message(
-    message: ClientMessage,
-    player_id: PlayerId,
+/// hands a player parked after a disconnect over to their new connection. the entity
+/// keeps its tile, items and queued actions
+fn reattach_player(
     state: &mut ServerState,
     interest_manager: &mut InterestManager,
-    server: &mut RenetServer,
-    commands: &mut Commands,
-    current_time: f64,
-) {
-    match message {
-        ClientMessage::Join { name } => {
-            info!("Player {:?} joining with name '{}'", player_id, name);
+    parked_id: PlayerId,
+    player_id: PlayerId,
+) -> Option<u64> {
+    let mut player = state.players.remove(&parked_id)?;
+    player.pending_removal = None;
+    player.last_message_tick = state.server_tick;
+    let entity_id = player.entity_id;
+    info!(
+        "Player {:?} '{}' reconnected as {:?}, reattaching entity {}",
+        parked_id, player.name, player_id, entity_id
+    );
+    state.players.insert(player_id, player);
 
-            let spawn_pos = TilePosition { x: 0, y: 0 };
-            let entity_id = state.next_entity_id;
-            state.next_entity_id += 1;
+    if let Some(entity) = state.entities.get_mut(&entity_id) {
+        entity.player_id = Some(player_id);
+        // the new connection numbers its inputs from scratch
+        entity.last_processed_input = None;
+    }
+    interest_manager.remove_client(parked_id);
+    interest_manager
+        .client_views
+        .insert(player_id, HashSet::new());
+    // everyone else still has the old player id on this entity
+    interest_manager.resend_full_state(entity_id);
+    Some(entity_id)
+}
 
-            let mut inventory = Inventory::new(28);
-            inventory.add_item(ItemType::BronzeAxe, 1);
-            let skills = Skills::new();
-
-            let entity = commands
-                .spawn((
-                    spawn_pos,
-                    Transform::from_translation(spawn_pos.to_world().extend(0.0)),
-                    ActionQueue::default(),
-                ))
-                .id();
-
-            let server_entity = ServerEntity {
-                tile_pos: spawn_pos,
-                player_id: Some(player_id),
-                action_queue: ActionQueue::default(),
-                entity,
-                is_obstacle: false,
-                inventory: Some(inventory.clone()),
-                skills: Some(skills.clone()),
-                tree: None,
-                last_processed_input: None,
-            };
+/// the name as others will see it: trimmed, 1 to `MAX_NAME_LENGTH` characters, and only
+/// letters, digits, spaces, `_` and `-`. anything else gets `Player<id>` instead, since
+/// names end up in other players' name tags
+pub fn sanitize_name(name: &str, player_id: PlayerId) -> String {
+    let trimmed = name.trim();
+    let length = trimmed.chars().count();
+    let allowed = trimmed
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == ' ' || c == '_' || c == '-');
+    if (1..=MAX_NAME_LENGTH).contains(&length) && allowed {
+        trimmed.to_string()
+    } else {
+        format!("Player{}", player_id.0)
+    }
+}
 
-            state.entities.insert(entity_id, server_entity);
-            state.players.insert(
-                player_id,
-                ServerPlayer {
-                    entity_id,
-                    name: name.clone(),
-                },
-            );
-            interest_manager
-                .client_views
-                .insert(player_id, HashSet::new());
+/// everything a client needs after joining: its spawn tile, settings, loaded
+/// definitions, items, skills and the obstacle map
+fn send_join_state(
+    state: &ServerState,
+    server: &mut impl MessageSink,
+    timing: &ServerTiming,
+    player_id: PlayerId,
+    entity_id: u64,
+) {
+    let entity = match state.entities.get(&entity_id) {
+        Some(entity) => entity,
+        None => return,
+    };
 
-            info!(
-                "Player {:?} '{}' spawned at {:?} with entity_id={}",
-                player_id, name, spawn_pos, entity_id
-            );
-            info!("Starting inventory: Bronze axe");
-            info!("Active players: {}", state.players.len());
+    let name = match state.players.get(&player_id) {
+        Some(player) => player.name.clone(),
+        None => return,
+    };
+    let msg = ServerMessage::Welcome {
+        player_id,
+        spawn_position: entity.tile_pos,
+        name,
+    };
+    send_message(server, player_id, &msg);
+    send_game_config(state, server, timing, player_id);
+    send_player_stats(server, player_id, entity);
+    send_zone_data(state, server, player_id, entity.zone);
+}
 
-            let msg = ServerMessage::Welcome {
-                player_id,
-                spawn_position: spawn_pos,
-            };
-            send_message(server, player_id, &msg);
+/// the player's inventory, equipment and skills, in full
+fn send_player_stats(server: &mut impl MessageSink, player_id: PlayerId, entity: &ServerEntity) {
+    let inventory = entity
+        .inventory
+        .clone()
+        .unwrap_or_else(|| Inventory::new(28));
+    let skills = entity.skills.clone().unwrap_or_else(Skills::new);
+    let equipment = entity.equipment.clone();
 
-            let inv_msg = ServerMessage::InventoryUpdate { inventory };
-            send_message(server, player_id, &inv_msg);
+    let inv_msg = ServerMessage::InventoryUpdate { inventory };
+    send_message(server, player_id, &inv_msg);
 
-            for (skill_type, skill_data) in skills.skills {
-                let skill_msg = ServerMessage::SkillUpdate {
-                    skill: skill_type,
-                    level: skill_data.level,
-                    experience: skill_data.experience,
-                };
-                send_message(server, player_id, &skill_msg);
-            }
+    let equipment_msg = ServerMessage::EquipmentUpdate { equipment };
+    send_message(server, player_id, &equipment_msg);
 
-            let obstacles: Vec<TilePosition> = state.pathfinder.obstacles.iter().copied().collect();
-            info!(
-                "Sending {} obstacles to player {:?}",
-                obstacles.len(),
-                player_id
-            );
-            let obstacle_msg = ServerMessage::ObstacleData { obstacles };
-            send_message(server, player_id, &obstacle_msg);
+    for (skill_type, skill_data) in &skills.skills {
+        let skill_msg = ServerMessage::SkillUpdate {
+            skill: *skill_type,
+            level: skill_data.level,
+            experience: skill_data.experience,
+            xp_to_next: skills.xp_to_next_level(*skill_type),
+        };
+        send_message(server, player_id, &skill_msg);
+    }
+}
 
-            update_interest_for_player(player_id, state, interest_manager, server);
-        }
+/// the player's own position, marked as a resync, followed by their stats, for a
+/// client that has lost track of where it is
+fn send_resync(state: &ServerState, server: &mut impl MessageSink, player_id: PlayerId) {
+    let entity_id = match state.players.get(&player_id) {
+        Some(player) => player.entity_id,
+        None => return,
+    };
+    let entity = match state.entities.get(&entity_id) {
+        Some(entity) => entity,
+        None => return,
+    };
+    info!(
+        "Resyncing player {:?} at {:?} (input #{:?})",
+        player_id, entity.tile_pos, entity.last_processed_input
+    );
 
-        ClientMessage::QueueAction {
-            action,
-            input_sequence_number,
-        } => {
-            if let Some(player) = state.players.get(&player_id) {
-                info!(
-                    "Player {:?} '{}' queuing action: {:?} (priority: {:?}, input #{})",
-                    player_id,
-                    player.name,
-                    action,
-                    action.priority(),
-                    input_sequence_number
-                );
-                if let GameAction::ChopTree { tree_entity_id } = action {
-                    let validation_result = {
-                        let player_entity = state.entities.get(&player.entity_id);
-                        let tree_entity = state.entities.get(&tree_entity_id);
-
-                        match (player_entity, tree_entity) {
-                            (Some(p_entity), Some(t_entity)) => {
-                                validate_woodcutting_action(p_entity, t_entity, server, player_id)
-                            }
-                            _ => {
-                                warn!(
-                                    "Invalid woodcutting: entity not found (player={}, tree={})",
-                                    player.entity_id, tree_entity_id
-                                );
-                                false
-                            }
-                        }
-                    };
+    let msg = ServerMessage::Resync {
+        tick: state.server_tick,
+        entity_id,
+        tile_pos: entity.tile_pos,
+        last_processed_input: entity.last_processed_input,
+    };
+    send_message(server, player_id, &msg);
+    send_player_stats(server, player_id, entity);
+}
 
-                    if !validation_result {
-                        return;
-                    }
-                }
+/// loaded definitions and the tick rate and view distance, what every client needs
+/// before it can make sense of the world
+fn send_game_config(
+    state: &ServerState,
+    server: &mut impl MessageSink,
+    timing: &ServerTiming,
+    player_id: PlayerId,
+) {
+    // before anything that names an item, so the client shows the loaded names
+    if !state.definitions.is_empty() {
+        let definitions_msg = ServerMessage::Definitions {
+            definitions: state.definitions.clone(),
+        };
+        send_message(server, player_id, &definitions_msg);
+    }
 
-                if let Some(entity) = state.entities.get_mut(&player.entity_id) {
-                    let result = queue_action_with_priority(
-                        &mut entity.action_queue,
-                        &mut entity.tile_pos,
-                        action.clone(),
-                        current_time,
-                    );
+    let config_msg = ServerMessage::GameConfig {
+        tick_rate: timing.tick_duration,
+        view_distance: VIEW_DISTANCE,
+    };
+    send_message(server, player_id, &config_msg);
+}
 
-                    entity.last_processed_input = Some(input_sequence_number);
+/// terrain, portals and obstacles of a zone, so the client's pathfinder matches ours
+fn send_zone_data(
+    state: &ServerState,
+    server: &mut impl MessageSink,
+    player_id: PlayerId,
+    zone: ZoneId,
+) {
+    let zone = state.zone(zone);
+    let map_msg = ServerMessage::TileMapData {
+        tile_map: zone.tile_map.clone(),
+        portals: zone.portals.iter().map(|portal| portal.tile).collect(),
+    };
+    send_message(server, player_id, &map_msg);
 
-                    match result {
-                        QueueResult::Started => {
-                            info!("  → Action started immediately");
-                        }
+    let obstacles: Vec<TilePosition> = zone.pathfinder.obstacles().iter().copied().collect();
+    info!(
+        "Sending {} obstacles to player {:?}",
+        obstacles.len(),
+        player_id
+    );
+    let obstacle_msg = ServerMessage::ObstacleData { obstacles };
+    send_message(server, player_id, &obstacle_msg);
+}
+
+/// whether an entity stops others from stepping onto its tile
+fn occupies_tile(entity: &ServerEntity) -> bool {
+    entity.player_id.is_some() || entity.is_obstacle
+}
+
+/// tiles taken by players and obstacle entities in the same zone as `except`, other
+/// than `except` itself
+pub fn occupied_tiles(state: &ServerState, except: u64) -> HashSet<TilePosition> {
+    let zone = match state.entities.get(&except) {
+        Some(entity) => entity.zone,
+        None => MAIN_ZONE,
+    };
+    state
+        .entities
+        .iter()
+        .filter(|(entity_id, entity)| {
+            **entity_id != except && entity.zone == zone && occupies_tile(entity)
+        })
+        .map(|(_, entity)| entity.tile_pos)
+        .collect()
+}
+
+fn reject_action(
+    server: &mut impl MessageSink,
+    player_id: PlayerId,
+    input_sequence_number: u32,
+    reason: RejectReason,
+) {
+    let msg = ServerMessage::ActionResult {
+        input_sequence_number,
+        outcome: ActionOutcome::Rejected(reason),
+    };
+    send_message(server, player_id, &msg);
+}
+
+/// acknowledges an input whose actions were queued, its `Completed` follows once
+/// they have all finished
+fn accept_action(
+    server: &mut impl MessageSink,
+    player_id: PlayerId,
+    queue: &mut ActionQueue,
+    input_sequence_number: u32,
+) {
+    queue.awaiting_completion = Some(input_sequence_number);
+    let msg = ServerMessage::ActionResult {
+        input_sequence_number,
+        outcome: ActionOutcome::Accepted,
+    };
+    send_message(server, player_id, &msg);
+}
+
+/// turns down a request that isn't an action, so has no input number to answer to
+fn refuse_request(server: &mut impl MessageSink, player_id: PlayerId, reason: &str) {
+    let msg = ServerMessage::ActionRejected {
+        reason: reason.to_string(),
+    };
+    send_message(server, player_id, &msg);
+}
+
+/// queues a move along a path the server found for the player. goes through the same
+/// priority rules as client actions, so it replaces a move in progress instead of stacking.
+/// `input_sequence_number` is the step input it walks, if any
+fn queue_requested_path(
+    state: &mut ServerState,
+    server: &mut impl MessageSink,
+    player_id: PlayerId,
+    path: Vec<TilePosition>,
+    input_sequence_number: Option<u32>,
+    timing: &ServerTiming,
+) {
+    let entity_id = match state.players.get(&player_id) {
+        Some(player) => player.entity_id,
+        None => return,
+    };
+    let occupied = occupied_tiles(state, entity_id);
+    let current_time = state.game_time;
+    let entity = match state.entities.get_mut(&entity_id) {
+        Some(entity) => entity,
+        None => return,
+    };
+
+    entity.action_queue.gathering_nearest = false;
+    let result = queue_action_with_priority(
+        &mut entity.action_queue,
+        &mut entity.tile_pos,
+        GameAction::Move { path },
+        current_time,
+        timing.tick_duration as f64,
+        equipped_tool(&entity.equipment),
+        &occupied,
+    );
+    state.dirty_entities.insert(entity_id);
+    if let Some(at) = entity.action_queue.blocked_at.take() {
+        send_path_blocked(server, player_id, at);
+    } else if matches!(result, QueueResult::QueueFull) {
+        match input_sequence_number {
+            Some(input_sequence_number) => reject_action(
+                server,
+                player_id,
+                input_sequence_number,
+                RejectReason::QueueFull,
+            ),
+            None => refuse_request(server, player_id, "action queue is full"),
+        }
+    }
+}
+
+fn send_path_blocked(server: &mut impl MessageSink, player_id: PlayerId, at: TilePosition) {
+    info!("Player {:?} blocked at {:?}", player_id, at);
+    let msg = ServerMessage::PathBlocked { at };
+    send_message(server, player_id, &msg);
+}
+
+/// starts a `ChopNearest` loop, or tells the player there is nothing to chop
+fn start_gather_loop(
+    state: &mut ServerState,
+    interest_manager: &InterestManager,
+    server: &mut impl MessageSink,
+    player_id: PlayerId,
+    input_sequence_number: u32,
+    timing: &ServerTiming,
+) {
+    if let Some(entity) = state
+        .players
+        .get(&player_id)
+        .and_then(|player| state.entities.get_mut(&player.entity_id))
+    {
+        entity.last_processed_input = Some(input_sequence_number);
+        entity.action_queue.input_buffer.clear();
+    }
+    if !chop_nearest_tree(state, interest_manager, server, player_id, timing) {
+        reject_action(
+            server,
+            player_id,
+            input_sequence_number,
+            RejectReason::NoTreesInView,
+        );
+        return;
+    }
+    info!("Player {:?} started chopping the nearest trees", player_id);
+    if let Some(entity) = state
+        .players
+        .get(&player_id)
+        .and_then(|player| state.entities.get_mut(&player.entity_id))
+    {
+        accept_action(
+            server,
+            player_id,
+            &mut entity.action_queue,
+            input_sequence_number,
+        );
+    }
+}
+
+/// walks the player up to the nearest standing tree they can see and chops it. false,
+/// and the gather loop ends, if there isn't one they can reach
+fn chop_nearest_tree(
+    state: &mut ServerState,
+    interest_manager: &InterestManager,
+    server: &mut impl MessageSink,
+    player_id: PlayerId,
+    timing: &ServerTiming,
+) -> bool {
+    let entity_id = match state.players.get(&player_id) {
+        Some(player) => player.entity_id,
+        None => return false,
+    };
+    let (from, zone) = match state.entities.get(&entity_id) {
+        Some(entity) => (entity.tile_pos, entity.zone),
+        None => return false,
+    };
+
+    let mut trees: Vec<(u64, TilePosition)> = state
+        .entities
+        .iter()
+        .filter(|(tree_id, entity)| {
+            entity.zone == zone
+                && entity.tree.as_ref().is_some_and(|tree| !tree.is_chopped)
+                && interest_manager.can_see(player_id, **tree_id)
+        })
+        .map(|(tree_id, entity)| (*tree_id, entity.tile_pos))
+        .collect();
+    trees.sort_by_key(|(tree_id, tile)| (tile.distance_to(&from), *tree_id));
+    let pathfinder = &mut state.zone_mut(zone).pathfinder;
+    let nearest = trees.into_iter().find_map(|(tree_id, tile)| {
+        pathfinder
+            .find_path_or_adjacent(from, tile)
+            .map(|path| (tree_id, path))
+    });
+
+    let occupied = occupied_tiles(state, entity_id);
+    let current_time = state.game_time;
+    let entity = match state.entities.get_mut(&entity_id) {
+        Some(entity) => entity,
+        None => return false,
+    };
+    let (tree_entity_id, path) = match nearest {
+        Some(nearest) => nearest,
+        None => {
+            entity.action_queue.gathering_nearest = false;
+            return false;
+        }
+    };
+    debug!(
+        "Player {:?} heading for tree {} ({} steps)",
+        player_id,
+        tree_entity_id,
+        path.len() - 1
+    );
+
+    let chop = GameAction::ChopTree { tree_entity_id };
+    let walk = path.len() > 1;
+    let first = if walk {
+        GameAction::Move { path }
+    } else {
+        chop.clone()
+    };
+    let result = queue_action_with_priority(
+        &mut entity.action_queue,
+        &mut entity.tile_pos,
+        first,
+        current_time,
+        timing.tick_duration as f64,
+        equipped_tool(&entity.equipment),
+        &occupied,
+    );
+    if walk && !matches!(result, QueueResult::QueueFull) {
+        entity.action_queue.actions.push_back(chop);
+    }
+    entity.action_queue.gathering_nearest = true;
+    state.dirty_entities.insert(entity_id);
+    if let Some(at) = entity.action_queue.blocked_at.take() {
+        entity.action_queue.gathering_nearest = false;
+        send_path_blocked(server, player_id, at);
+    }
+    true
+}
+
+/// keeps a step for a later tick, in the order the keys were pressed
+fn buffer_step(queue: &mut ActionQueue, step: BufferedStep) {
+    let index = queue
+        .input_buffer
+        .iter()
+        .position(|waiting| waiting.pressed_at > step.pressed_at)
+        .unwrap_or(queue.input_buffer.len());
+    queue.input_buffer.insert(index, step);
+    if queue.input_buffer.len() > MAX_BUFFERED_STEPS {
+        if let Some(dropped) = queue.input_buffer.pop_front() {
+            debug!(
+                "Step buffer full, dropped input #{}",
+                dropped.input_sequence_number
+            );
+        }
+    }
+}
+
+/// walks a WASD step from wherever the player is now, as many tiles as they cover in
+/// a tick, and counts it as the tick's step
+fn start_step(
+    state: &mut ServerState,
+    server: &mut impl MessageSink,
+    player_id: PlayerId,
+    step: BufferedStep,
+    timing: &ServerTiming,
+) {
+    let tick = state.server_tick;
+    let entity = match state
+        .players
+        .get(&player_id)
+        .and_then(|player| state.entities.get_mut(&player.entity_id))
+    {
+        Some(entity) => entity,
+        None => return,
+    };
+    entity.action_queue.last_step_tick = Some(tick);
+    entity.last_processed_input = Some(step.input_sequence_number);
+    let (from, zone) = (entity.tile_pos, entity.zone);
+    let running = entity.run.is_some_and(|run| run.is_running());
+
+    let steps = if running { RUN_STEPS } else { 1 } as i32;
+    let pathfinder = &state.zone(zone).pathfinder;
+    let path: Vec<TilePosition> = (1..=steps)
+        .map(|i| TilePosition {
+            x: from.x + step.dx * i,
+            y: from.y + step.dy * i,
+        })
+        .take_while(|tile| pathfinder.is_walkable(tile))
+        .collect();
+    if let Err(reason) = pathfinder.check_walk(from, &path) {
+        warn!("Invalid step from player {:?}: {}", player_id, reason);
+        reject_action(
+            server,
+            player_id,
+            step.input_sequence_number,
+            RejectReason::InvalidPath,
+        );
+        return;
+    }
+    let input_sequence_number = Some(step.input_sequence_number);
+    queue_requested_path(
+        state,
+        server,
+        player_id,
+        path,
+        input_sequence_number,
+        timing,
+    );
+}
+
+/// takes the oldest buffered step of every player who has one, so steps pressed
+/// within one tick are walked one tick after another instead of replacing each other
+fn start_buffered_steps(
+    state: &mut ServerState,
+    server: &mut impl MessageSink,
+    timing: &ServerTiming,
+) {
+    let stepping: Vec<PlayerId> = state
+        .players
+        .iter()
+        .filter(|(_, player)| player.pending_removal.is_none())
+        .filter(|(_, player)| {
+            state
+                .entities
+                .get(&player.entity_id)
+                .is_some_and(|entity| !entity.action_queue.input_buffer.is_empty())
+        })
+        .map(|(player_id, _)| *player_id)
+        .collect();
+    for player_id in stepping {
+        let step = state
+            .players
+            .get(&player_id)
+            .and_then(|player| state.entities.get_mut(&player.entity_id))
+            .and_then(|entity| entity.action_queue.input_buffer.pop_front());
+        if let Some(step) = step {
+            start_step(state, server, player_id, step, timing);
+        }
+    }
+}
+
+/// reads `--max-clients <n>` or `SERVER_MAX_CLIENTS`, falling back to
+/// `DEFAULT_MAX_CLIENTS`
+fn max_clients_from_args_or_env() -> usize {
+    let args: Vec<String> = std::env::args().collect();
+    let from_args = args
+        .iter()
+        .position(|arg| arg == MAX_CLIENTS_ARG)
+        .and_then(|i| args.get(i + 1).cloned());
+    let value = match from_args.or_else(|| std::env::var(MAX_CLIENTS_ENV).ok()) {
+        Some(value) => value,
+        None => return DEFAULT_MAX_CLIENTS,
+    };
+    match value.parse::<usize>() {
+        Ok(max_clients) if max_clients > 0 => max_clients,
+        _ => {
+            warn!("Invalid client limit '{}', using the default", value);
+            DEFAULT_MAX_CLIENTS
+        }
+    }
+}
+
+/// whether every slot is taken. parked players still hold theirs until they expire
+pub fn is_full(state: &ServerState) -> bool {
+    state.players.len() + state.spectators.len() >= state.max_clients
+}
+
+pub fn setup_server(
+    mut commands: Commands,
+    mut state: ResMut<ServerState>,
+    timing: Res<ServerTiming>,
+) {
+    let server_addr: SocketAddr = format!("127.0.0.1:{}", SERVER_PORT).parse().unwrap();
+    let socket = UdpSocket::bind(server_addr).unwrap();
+    let current_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+
+    state.max_clients = max_clients_from_args_or_env();
+    let server_config = ServerConfig {
+        current_time,
+        max_clients: state.max_clients + OVERFLOW_CONNECTIONS,
+        protocol_id: PROTOCOL_ID,
+        public_addresses: vec![server_addr],
+        authentication: ServerAuthentication::Unsecure,
+    };
+
+    let transport = NetcodeServerTransport::new(server_config, socket).unwrap();
+    let server = RenetServer::new(ConnectionConfig::default());
+
+    commands.insert_resource(server);
+    commands.insert_resource(transport);
+
+    if let Ok(admins) = std::env::var(ADMINS_ENV) {
+        state.admin_accounts = admins
+            .split(',')
+            .map(|account| account.trim().to_string())
+            .filter(|account| !account.is_empty())
+            .collect();
+        info!("{} admin account(s)", state.admin_accounts.len());
+    }
+
+    if let Ok(value) = std::env::var(DEATH_RULE_ENV) {
+        match DeathRule::parse(&value) {
+            Ok(rule) => {
+                state.death_rule = rule;
+                info!("Death rule: {:?}", rule);
+            }
+            Err(err) => warn!("{}, using the default", err),
+        }
+    }
+
+    load_tile_map(&mut state);
+    load_definitions(&mut state);
+    if let Ok(value) = std::env::var(PATH_NODES_ENV) {
+        match value.parse::<usize>() {
+            Ok(max_nodes) if max_nodes > 0 => {
+                for zone in state.zones.values_mut() {
+                    zone.pathfinder.max_nodes = max_nodes;
+                }
+                info!("Path searches give up after {} nodes", max_nodes);
+            }
+            _ => warn!("Invalid path node budget '{}', using the default", value),
+        }
+    }
+    if let Ok(value) = std::env::var(PATH_MODE_ENV) {
+        match PathMode::parse(&value) {
+            Ok(mode) => {
+                for (zone_id, zone) in state.zones.iter_mut() {
+                    if let Err(err) = zone.pathfinder.set_mode(mode) {
+                        warn!("Zone {:?}: {}, using A*", zone_id, err);
+                    }
+                }
+                info!("Path mode: {:?}", mode);
+            }
+            Err(err) => warn!("{}, using A*", err),
+        }
+    }
+    if !load_world(&mut state, &mut commands) {
+        spawn_trees(&mut state, &mut commands);
+        spawn_rocks(&mut state, &mut commands);
+    }
+    // banks are fixed scenery and npcs start over at home, neither is part of the save
+    spawn_bank(&mut state, &mut commands, BANK_POSITION);
+    spawn_npcs(&mut state, &mut commands);
+
+    info!("Server started on {}", server_addr);
+    info!("Server configuration:");
+    info!("Max clients: {}", state.max_clients);
+    info!("Protocol ID: {}", PROTOCOL_ID);
+    info!("Tick rate: {}ms", (timing.tick_duration * 1000.0) as u32);
+    info!(
+        "View distance: {} tiles (players may pick {} to {})",
+        VIEW_DISTANCE, MIN_VIEW_DISTANCE, MAX_VIEW_DISTANCE
+    );
+    info!(
+        "Spawned {} entities (including {} trees, {} rocks)",
+        state.entities.len(),
+        state.entities.values().filter(|e| e.tree.is_some()).count(),
+        state.entities.values().filter(|e| e.rock.is_some()).count()
+    );
+}
+
+pub fn spawn_trees(state: &mut ServerState, commands: &mut Commands) {
+    let tree_positions = vec![
+        (TilePosition { x: -3, y: -3 }, TreeType::Normal),
+        (TilePosition { x: -2, y: -3 }, TreeType::Normal),
+        (TilePosition { x: 3, y: 3 }, TreeType::Oak),
+        (TilePosition { x: 2, y: 3 }, TreeType::Oak),
+        (TilePosition { x: -3, y: 3 }, TreeType::Willow),
+        (TilePosition { x: 0, y: -4 }, TreeType::Normal),
+        (TilePosition { x: 1, y: -4 }, TreeType::Oak),
+    ];
+
+    for (pos, tree_type) in tree_positions {
+        let entity_id = state.next_entity_id;
+        state.next_entity_id += 1;
+        spawn_tree(
+            state,
+            commands,
+            entity_id,
+            MAIN_ZONE,
+            pos,
+            Tree::new(tree_type),
+        );
+    }
+}
+
+pub fn spawn_tree(
+    state: &mut ServerState,
+    commands: &mut Commands,
+    entity_id: u64,
+    zone: ZoneId,
+    pos: TilePosition,
+    tree: Tree,
+) {
+    let blocks = !tree.is_chopped;
+    let entity = commands
+        .spawn((pos, Transform::from_translation(pos.to_world().extend(0.0))))
+        .id();
+
+    let server_entity = ServerEntity {
+        kind: EntityKind::Tree,
+        tile_pos: pos,
+        zone,
+        player_id: None,
+        action_queue: ActionQueue::default(),
+        entity,
+        is_obstacle: false,
+        inventory: None,
+        skills: None,
+        achievements: None,
+        equipment: Equipment::new(),
+        tree: Some(tree),
+        rock: None,
+        bank: None,
+        ground_item: None,
+        despawn_tick: None,
+        last_processed_input: None,
+        health: None,
+        last_damaged_tick: None,
+        run: None,
+        npc: None,
+    };
+
+    state.entities.insert(entity_id, server_entity);
+    state
+        .event_log
+        .record(state.server_tick, entity_id, EntityEventKind::Spawned);
+    // chopped trees can be walked through until they grow back
+    if blocks {
+        state.zone_mut(zone).pathfinder.add_obstacle(pos);
+    }
+}
+
+pub const BANK_POSITION: TilePosition = TilePosition { x: 0, y: 3 };
+
+pub fn spawn_bank(state: &mut ServerState, commands: &mut Commands, pos: TilePosition) {
+    let entity_id = state.next_entity_id;
+    state.next_entity_id += 1;
+
+    let entity = commands
+        .spawn((pos, Transform::from_translation(pos.to_world().extend(0.0))))
+        .id();
+
+    let server_entity = ServerEntity {
+        kind: EntityKind::Bank,
+        tile_pos: pos,
+        zone: MAIN_ZONE,
+        player_id: None,
+        action_queue: ActionQueue::default(),
+        entity,
+        is_obstacle: false,
+        inventory: None,
+        skills: None,
+        achievements: None,
+        equipment: Equipment::new(),
+        tree: None,
+        rock: None,
+        bank: Some(Bank),
+        ground_item: None,
+        despawn_tick: None,
+        last_processed_input: None,
+        health: None,
+        last_damaged_tick: None,
+        run: None,
+        npc: None,
+    };
+
+    state.entities.insert(entity_id, server_entity);
+    state
+        .event_log
+        .record(state.server_tick, entity_id, EntityEventKind::Spawned);
+    state.zone_mut(MAIN_ZONE).pathfinder.add_obstacle(pos);
+    info!("Spawned bank {} at {:?}", entity_id, pos);
+}
+
+pub fn spawn_rocks(state: &mut ServerState, commands: &mut Commands) {
+    let rock_positions = vec![
+        (TilePosition { x: 3, y: -3 }, RockType::Copper),
+        (TilePosition { x: 3, y: -2 }, RockType::Tin),
+        (TilePosition { x: 2, y: -3 }, RockType::Copper),
+        (TilePosition { x: 4, y: 0 }, RockType::Iron),
+        (TilePosition { x: -4, y: 0 }, RockType::RichVein),
+    ];
+
+    for (pos, rock_type) in rock_positions {
+        let entity_id = state.next_entity_id;
+        state.next_entity_id += 1;
+        spawn_rock(
+            state,
+            commands,
+            entity_id,
+            MAIN_ZONE,
+            pos,
+            Rock::new(rock_type),
+        );
+    }
+}
+
+pub fn spawn_rock(
+    state: &mut ServerState,
+    commands: &mut Commands,
+    entity_id: u64,
+    zone: ZoneId,
+    pos: TilePosition,
+    rock: Rock,
+) {
+    let entity = commands
+        .spawn((pos, Transform::from_translation(pos.to_world().extend(0.0))))
+        .id();
+
+    let server_entity = ServerEntity {
+        kind: EntityKind::Rock,
+        tile_pos: pos,
+        zone,
+        player_id: None,
+        action_queue: ActionQueue::default(),
+        entity,
+        is_obstacle: false,
+        inventory: None,
+        skills: None,
+        achievements: None,
+        equipment: Equipment::new(),
+        tree: None,
+        rock: Some(rock),
+        bank: None,
+        ground_item: None,
+        despawn_tick: None,
+        last_processed_input: None,
+        health: None,
+        last_damaged_tick: None,
+        run: None,
+        npc: None,
+    };
+
+    state.entities.insert(entity_id, server_entity);
+    state
+        .event_log
+        .record(state.server_tick, entity_id, EntityEventKind::Spawned);
+    state.zone_mut(zone).pathfinder.add_obstacle(pos);
+}
+
+pub fn server_update_system<T: ServerTransport + Resource>(
+    mut transport: ResMut<T>,
+    mut bandwidth: ResMut<BandwidthStats>,
+    mut server_state: ResMut<ServerState>,
+    mut interest_manager: ResMut<InterestManager>,
+    timing: Res<ServerTiming>,
+    mut rng: ResMut<GameRng>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    let mut server = MeteredTransport {
+        inner: &mut *transport,
+        stats: &mut bandwidth,
+    };
+    server_state.tick_accumulator += time.delta_seconds();
+
+    for client_id in server.clients_id() {
+        let player_id = PlayerId(client_id.raw());
+        // a kicked client is only waiting to be disconnected
+        if server_state.kicked.contains_key(&player_id) {
+            continue;
+        }
+        // anything over budget stays in the transport until the next tick
+        while server_state.message_budget.has_room(player_id) {
+            let message = match server.receive_message(client_id, DefaultChannel::ReliableOrdered) {
+                Some(message) => message,
+                None => break,
+            };
+            server_state.message_budget.spend(player_id);
+            let tick = server_state.server_tick;
+            if let Some(player) = server_state.players.get_mut(&player_id) {
+                player.last_message_tick = tick;
+            }
+            debug!(
+                "Received message from ClientId({}), {} bytes",
+                client_id.raw(),
+                message.len()
+            );
+            let client_msg = match bincode::deserialize::<ClientMessage>(&message) {
+                Ok(client_msg) => client_msg,
+                Err(err) => {
+                    if record_undecodable(&mut server_state, player_id, &message, &err) {
+                        server.disconnect(client_id);
+                        remove_player(
+                            &mut server,
+                            &mut server_state,
+                            &mut interest_manager,
+                            &mut commands,
+                            player_id,
+                        );
+                        break;
+                    }
+                    continue;
+                }
+            };
+            info!(
+                "Processing message from PlayerId({}): {:?}",
+                client_id.raw(),
+                match &client_msg {
+                    ClientMessage::Join { name, account_id } =>
+                        format!("Join(name={:?}, account={:?})", name, account_id),
+                    ClientMessage::QueueAction {
+                        action,
+                        input_sequence_number,
+                        ..
+                    } => format!("QueueAction({:?}, seq={})", action, input_sequence_number),
+                    ClientMessage::QueueActions {
+                        actions,
+                        input_sequence_number,
+                        ..
+                    } => format!(
+                        "QueueActions([{} actions], seq={})",
+                        actions.len(),
+                        input_sequence_number
+                    ),
+                    ClientMessage::StepInput {
+                        dx,
+                        dy,
+                        input_sequence_number,
+                        ..
+                    } => format!("StepInput({}, {}, seq={})", dx, dy, input_sequence_number),
+                    ClientMessage::CancelAction => "CancelAction".to_string(),
+                    ClientMessage::RequestPath { start, goal } =>
+                        format!("RequestPath({:?} -> {:?})", start, goal),
+                    ClientMessage::RequestMultiPath { waypoints } =>
+                        format!("RequestMultiPath([{} waypoints])", waypoints.len()),
+                    ClientMessage::MoveItem { from, to } => format!("MoveItem({} -> {})", from, to),
+                    ClientMessage::RequestWorldSnapshot => "RequestWorldSnapshot".to_string(),
+                    ClientMessage::SetViewDistance { tiles } =>
+                        format!("SetViewDistance({})", tiles),
+                    ClientMessage::SetRunning { enabled } => format!("SetRunning({})", enabled),
+                    ClientMessage::Examine { entity_id } => format!("Examine({})", entity_id),
+                    ClientMessage::ExamineItem { slot } => format!("ExamineItem({})", slot),
+                    ClientMessage::JoinAsSpectator => "JoinAsSpectator".to_string(),
+                    ClientMessage::RequestResync => "RequestResync".to_string(),
+                    ClientMessage::MoveSpectatorCamera { center } =>
+                        format!("MoveSpectatorCamera({:?})", center),
+                }
+            );
+            handle_client_message(
+                client_msg,
+                player_id,
+                &mut server_state,
+                &mut interest_manager,
+                &mut server,
+                &mut commands,
+                &timing,
+            );
+        }
+    }
+
+    handle_disconnections(&mut server, &mut server_state, &mut interest_manager);
+
+    while server_state.tick_accumulator >= timing.tick_duration {
+        server_state.tick_accumulator -= timing.tick_duration;
+        server_state.server_tick += 1;
+        server_state.game_time += timing.tick_duration as f64;
+        server_state.message_budget.reset();
+        debug!("Server tick #{}", server_state.server_tick);
+        let deltas_sent = process_server_tick(
+            &mut server_state,
+            &timing,
+            &mut rng,
+            &mut server,
+            &mut interest_manager,
+            &mut commands,
+        );
+        server.stats.record_deltas(deltas_sent);
+        let connected = server_state
+            .players
+            .values()
+            .filter(|player| player.pending_removal.is_none())
+            .count();
+        server.stats.end_tick(
+            server_state.server_tick,
+            connected,
+            server_state.entities.len(),
+        );
+        disconnect_idle_players(
+            &mut server,
+            &mut server_state,
+            &mut interest_manager,
+            &mut commands,
+        );
+
+        if server_state.server_tick.is_multiple_of(SAVE_INTERVAL_TICKS) {
+            save_world(&server_state);
+        }
+    }
+}
+
+/// logs a message that failed to decode and counts it against the client. true once
+/// the client has sent too many, at which point it should be disconnected
+pub fn record_undecodable(
+    state: &mut ServerState,
+    player_id: PlayerId,
+    message: &[u8],
+    err: &bincode::Error,
+) -> bool {
+    let count = state.undecodable_messages.entry(player_id).or_default();
+    *count += 1;
+    warn!(
+        "Dropped undecodable message from {:?} on reliable channel ({} bytes, starts {:02x?}): {} ({} so far)",
+        player_id,
+        message.len(),
+        &message[..message.len().min(16)],
+        err,
+        count
+    );
+    if *count < MAX_UNDECODABLE_MESSAGES {
+        return false;
+    }
+    warn!(
+        "Player {:?} sent {} undecodable messages, disconnecting",
+        player_id, count
+    );
+    state.undecodable_messages.remove(&player_id);
+    true
+}
+
+/// changes how often the server ticks and tells every client so their timing stays in sync.
+/// game logic is measured in ticks, so only the real-time length of a tick changes
+pub fn set_tick_rate(
+    state: &mut ServerState,
+    timing: &mut ServerTiming,
+    server: &mut impl MessageSink,
+    tick_rate: f32,
+) {
+    info!(
+        "Tick rate changed: {}ms -> {}ms",
+        (timing.tick_duration * 1000.0) as u32,
+        (tick_rate * 1000.0) as u32
+    );
+    timing.tick_duration = tick_rate;
+    state.tick_accumulator = 0.0;
+
+    let msg = ServerMessage::TickRateChanged { tick_rate };
+    broadcast_message(server, &msg);
+}
+
+pub fn handle_client_message(
+    message: ClientMessage,
+    player_id: PlayerId,
+    state: &mut ServerState,
+    interest_manager: &mut InterestManager,
+    server: &mut impl MessageSink,
+    commands: &mut Commands,
+    timing: &ServerTiming,
+) {
+    let current_time = state.game_time;
+    let tick_duration = timing.tick_duration as f64;
+    match message {
+        ClientMessage::Join { name, account_id } => {
+            if state.players.contains_key(&player_id) || state.spectators.contains_key(&player_id) {
+                warn!("Player {:?} sent Join twice, ignoring it", player_id);
+                return;
+            }
+            let account_id = match state.client_accounts.get(&player_id) {
+                Some(handshake_account) => {
+                    if *handshake_account != account_id {
+                        warn!(
+                            "Player {:?} joined as account {:?} but connected as {:?}",
+                            player_id, account_id, handshake_account
+                        );
+                    }
+                    handshake_account.clone()
+                }
+                None => account_id,
+            };
+            let already_active = state
+                .players
+                .values()
+                .any(|player| player.pending_removal.is_none() && player.account_id == account_id);
+            if already_active {
+                kick(
+                    state,
+                    server,
+                    player_id,
+                    "this account is already logged in",
+                );
+                return;
+            }
+            // a player coming back within the grace period gets their old slot back
+            let returning = state
+                .players
+                .values()
+                .any(|player| player.pending_removal.is_some() && player.account_id == account_id);
+            if is_full(state) && !returning {
+                kick(state, server, player_id, SERVER_FULL_REASON);
+                return;
+            }
+
+            let sanitized = sanitize_name(&name, player_id);
+            if sanitized != name {
+                info!(
+                    "Player {:?} asked for name {:?}, using '{}'",
+                    player_id, name, sanitized
+                );
+            }
+            let name = sanitized;
+            info!(
+                "Player {:?} joining with name '{}' (account {:?})",
+                player_id, name, account_id
+            );
+
+            let parked = state
+                .players
+                .iter()
+                .find(|(_, player)| {
+                    player.pending_removal.is_some() && player.account_id == account_id
+                })
+                .map(|(parked_id, _)| *parked_id);
+            let reattached = parked.and_then(|parked_id| {
+                reattach_player(state, interest_manager, parked_id, player_id)
+            });
+            let entity_id = match reattached {
+                Some(entity_id) => entity_id,
+                None => spawn_player(
+                    state,
+                    interest_manager,
+                    commands,
+                    player_id,
+                    name,
+                    account_id,
+                ),
+            };
+            info!("Active players: {}", state.players.len());
+
+            send_join_state(state, server, timing, player_id, entity_id);
+            update_interest_for_player(player_id, state, interest_manager, server);
+        }
+
+        ClientMessage::JoinAsSpectator => {
+            if state.players.contains_key(&player_id) || state.spectators.contains_key(&player_id) {
+                warn!("Player {:?} sent Join twice, ignoring it", player_id);
+                return;
+            }
+            if is_full(state) {
+                kick(state, server, player_id, SERVER_FULL_REASON);
+                return;
+            }
+            let center = state
+                .zone(MAIN_ZONE)
+                .spawn_points
+                .first()
+                .copied()
+                .unwrap_or_default();
+            info!("Player {:?} spectating from {:?}", player_id, center);
+            state.spectators.insert(
+                player_id,
+                Spectator {
+                    zone: MAIN_ZONE,
+                    center,
+                },
+            );
+
+            let msg = ServerMessage::SpectatorWelcome { player_id, center };
+            send_message(server, player_id, &msg);
+            send_game_config(state, server, timing, player_id);
+            send_zone_data(state, server, player_id, MAIN_ZONE);
+            update_interest_for_player(player_id, state, interest_manager, server);
+        }
+
+        ClientMessage::MoveSpectatorCamera { center } => {
+            let spectator = match state.spectators.get_mut(&player_id) {
+                Some(spectator) => spectator,
+                None => return,
+            };
+            spectator.center = center;
+            update_interest_for_player(player_id, state, interest_manager, server);
+        }
+
+        ClientMessage::QueueAction {
+            action,
+            input_sequence_number,
+            render_delay,
+        } => {
+            if matches!(action, GameAction::ChopNearest) {
+                start_gather_loop(
+                    state,
+                    interest_manager,
+                    server,
+                    player_id,
+                    input_sequence_number,
+                    timing,
+                );
+                return;
+            }
+            let view_tick = state
+                .server_tick
+                .saturating_sub(rewind_ticks(render_delay, timing.tick_duration));
+            if let Some(player) = state.players.get(&player_id) {
+                info!(
+                    "Player {:?} '{}' queuing action: {:?} (priority: {:?}, input #{})",
+                    player_id,
+                    player.name,
+                    action,
+                    action.priority(),
+                    input_sequence_number
+                );
+                // anything but a walk starts once the walk in progress is over
+                let from = match state.entities.get(&player.entity_id) {
+                    Some(entity) => planned_tile(entity),
+                    None => return,
+                };
+                let valid =
+                    validate_action_target(interest_manager, &action, player_id).and_then(|_| {
+                        validate_gathering_action(
+                            state,
+                            player.entity_id,
+                            &action,
+                            from,
+                            view_tick,
+                            player_id,
+                        )
+                    });
+                if let Err(reason) = valid {
+                    reject_action(server, player_id, input_sequence_number, reason);
+                    return;
+                }
+
+                let occupied = occupied_tiles(state, player.entity_id);
+                if let Some(entity) = state.entities.get_mut(&player.entity_id) {
+                    // anything else the player asks for replaces the steps still waiting
+                    // and ends a gather loop
+                    entity.action_queue.input_buffer.clear();
+                    entity.action_queue.gathering_nearest = false;
+                    let result = queue_action_with_priority(
+                        &mut entity.action_queue,
+                        &mut entity.tile_pos,
+                        action.clone(),
+                        current_time,
+                        tick_duration,
+                        equipped_tool(&entity.equipment),
+                        &occupied,
+                    );
+                    state.dirty_entities.insert(player.entity_id);
+
+                    entity.last_processed_input = Some(input_sequence_number);
+
+                    if let Some(at) = entity.action_queue.blocked_at.take() {
+                        send_path_blocked(server, player_id, at);
+                        let reason = RejectReason::PathBlocked;
+                        reject_action(server, player_id, input_sequence_number, reason);
+                        return;
+                    }
+
+                    match result {
+                        QueueResult::Started => {
+                            info!("  → Action started immediately");
+                        }
                         QueueResult::Queued => {
                             info!(
                                 "  → Action queued (queue size: {})",
@@ -408,736 +1864,5323 @@ pub fn handle_client_message(
                                 "  → Cancelled weak action and started (priority: Normal > Weak)"
                             );
                         }
-                        QueueResult::Suspended => {
-                            info!("  → Suspended normal action (priority: Strong)");
+                        QueueResult::Suspended => {
+                            info!("  → Suspended normal action (priority: Strong)");
+                        }
+                        QueueResult::QueueFull => {
+                            warn!(
+                                "  → Queue full (max {} queued), action rejected",
+                                MAX_QUEUED_ACTIONS
+                            );
+                            let reason = RejectReason::QueueFull;
+                            reject_action(server, player_id, input_sequence_number, reason);
+                            return;
+                        }
+                    }
+
+                    accept_action(
+                        server,
+                        player_id,
+                        &mut entity.action_queue,
+                        input_sequence_number,
+                    );
+                }
+            }
+        }
+        ClientMessage::QueueActions {
+            actions,
+            input_sequence_number,
+            render_delay,
+        } => {
+            let view_tick = state
+                .server_tick
+                .saturating_sub(rewind_ticks(render_delay, timing.tick_duration));
+            if let Some(player) = state.players.get(&player_id) {
+                info!(
+                    "Player {:?} '{}' queuing {} actions (input #{})",
+                    player_id,
+                    player.name,
+                    actions.len(),
+                    input_sequence_number
+                );
+
+                // each action starts where the walks before it in the chain end, the
+                // first once the walk in progress is over
+                let mut from = match state.entities.get(&player.entity_id) {
+                    Some(entity) => planned_tile(entity),
+                    None => return,
+                };
+                let valid = actions.iter().try_for_each(|action| {
+                    validate_action_target(interest_manager, action, player_id)?;
+                    validate_gathering_action(
+                        state,
+                        player.entity_id,
+                        action,
+                        from,
+                        view_tick,
+                        player_id,
+                    )?;
+                    if let GameAction::Move { path } = action {
+                        from = path.last().copied().unwrap_or(from);
+                    }
+                    Ok(())
+                });
+                if let Err(reason) = valid {
+                    reject_action(server, player_id, input_sequence_number, reason);
+                    return;
+                }
+
+                if actions.is_empty() {
+                    reject_action(
+                        server,
+                        player_id,
+                        input_sequence_number,
+                        RejectReason::InvalidPath,
+                    );
+                } else {
+                    let occupied = occupied_tiles(state, player.entity_id);
+                    if let Some(entity) = state.entities.get_mut(&player.entity_id) {
+                        entity.action_queue.input_buffer.clear();
+                        entity.action_queue.gathering_nearest = false;
+                        let first_action = actions[0].clone();
+                        let result = queue_action_with_priority(
+                            &mut entity.action_queue,
+                            &mut entity.tile_pos,
+                            first_action.clone(),
+                            current_time,
+                            tick_duration,
+                            equipped_tool(&entity.equipment),
+                            &occupied,
+                        );
+                        state.dirty_entities.insert(player.entity_id);
+                        info!(
+                            "  First action ({:?}): {:?}",
+                            first_action.priority(),
+                            result
+                        );
+                        if matches!(result, QueueResult::QueueFull) {
+                            let reason = RejectReason::QueueFull;
+                            reject_action(server, player_id, input_sequence_number, reason);
+                            return;
+                        }
+
+                        entity.last_processed_input = Some(input_sequence_number);
+                        if let Some(at) = entity.action_queue.blocked_at.take() {
+                            send_path_blocked(server, player_id, at);
+                            let reason = RejectReason::PathBlocked;
+                            reject_action(server, player_id, input_sequence_number, reason);
+                            return;
+                        }
+
+                        // the first action has already started, so the chain is accepted
+                        // with whatever fits and the rest is dropped
+                        let room =
+                            MAX_QUEUED_ACTIONS.saturating_sub(entity.action_queue.actions.len());
+                        let rest = &actions[1..];
+                        for action in rest.iter().take(room) {
+                            entity.action_queue.actions.push_back(action.clone());
+                            info!("  Queued: {:?}", action);
+                        }
+                        for action in rest.iter().skip(room) {
+                            warn!("  Queue full, couldn't add: {:?}", action);
+                        }
+                        accept_action(
+                            server,
+                            player_id,
+                            &mut entity.action_queue,
+                            input_sequence_number,
+                        );
+
+                        info!(
+                            "Action chain processed for player {:?}. Queue size: {}",
+                            player_id,
+                            entity.action_queue.actions.len()
+                        );
+                    }
+                }
+            }
+        }
+        ClientMessage::StepInput {
+            dx,
+            dy,
+            input_sequence_number,
+            pressed_at,
+        } => {
+            let step = BufferedStep {
+                dx,
+                dy,
+                input_sequence_number,
+                pressed_at,
+            };
+            let tick = state.server_tick;
+            let entity = match state
+                .players
+                .get(&player_id)
+                .and_then(|player| state.entities.get_mut(&player.entity_id))
+            {
+                Some(entity) => entity,
+                None => return,
+            };
+            let queue = &mut entity.action_queue;
+            if queue.last_step_tick == Some(tick) || !queue.input_buffer.is_empty() {
+                buffer_step(queue, step);
+                return;
+            }
+            start_step(state, server, player_id, step, timing);
+        }
+        ClientMessage::CancelAction => {
+            if let Some(player) = state.players.get(&player_id) {
+                if let Some(entity) = state.entities.get_mut(&player.entity_id) {
+                    let queue_size = entity.action_queue.actions.len();
+                    let has_current = entity.action_queue.current_action.is_some();
+                    entity.action_queue.current_action = None;
+                    entity.action_queue.suspended_action = None;
+                    entity.action_queue.actions.clear();
+                    entity.action_queue.input_buffer.clear();
+                    entity.action_queue.gathering_nearest = false;
+                    entity.action_queue.awaiting_completion = None;
+                    info!(
+                        "Player {:?} '{}' cancelled action. Cleared {} queued actions{}",
+                        player_id,
+                        player.name,
+                        queue_size,
+                        if has_current {
+                            " and current action"
+                        } else {
+                            ""
+                        }
+                    );
+                }
+            }
+        }
+        ClientMessage::RequestPath { start, goal } => {
+            info!(
+                "Player {:?} requesting path from {:?} to {:?}",
+                player_id, start, goal
+            );
+
+            // paths start where the server has the player, whatever the client says
+            let start = match state
+                .players
+                .get(&player_id)
+                .and_then(|player| state.entities.get(&player.entity_id))
+            {
+                Some(entity) => entity.tile_pos,
+                None => return,
+            };
+
+            // asking for a path to a tree or rock walks up to it
+            let pathfinder = &mut state.zone_mut(state.player_zone(player_id)).pathfinder;
+            if let Some(path) = pathfinder.find_path_or_adjacent(start, goal) {
+                let path = pathfinder.smooth_path(&path);
+                info!("Path found: {} waypoints", path.len());
+                let msg = ServerMessage::PathFound { path: path.clone() };
+                send_message(server, player_id, &msg);
+                queue_requested_path(state, server, player_id, path, None, timing);
+            } else {
+                warn!("No path found from {:?} to {:?}", start, goal);
+                let msg = ServerMessage::PathNotFound { waypoint_index: 0 };
+                send_message(server, player_id, &msg);
+            }
+        }
+        ClientMessage::RequestMultiPath { waypoints } => {
+            let (start, zone) = match state
+                .players
+                .get(&player_id)
+                .and_then(|player| state.entities.get(&player.entity_id))
+            {
+                Some(entity) => (entity.tile_pos, entity.zone),
+                None => return,
+            };
+            info!(
+                "Player {:?} requesting path from {:?} through {} waypoints",
+                player_id,
+                start,
+                waypoints.len()
+            );
+
+            let pathfinder = &mut state.zone_mut(zone).pathfinder;
+            match pathfinder.find_path_multi(start, &waypoints) {
+                Ok(path) => {
+                    let path = pathfinder.smooth_path(&path);
+                    info!("Multi-waypoint path found: {} waypoints", path.len());
+                    let msg = ServerMessage::PathFound { path: path.clone() };
+                    send_message(server, player_id, &msg);
+                    queue_requested_path(state, server, player_id, path, None, timing);
+                }
+                Err(waypoint_index) => {
+                    warn!(
+                        "No path to waypoint {} ({:?})",
+                        waypoint_index, waypoints[waypoint_index]
+                    );
+                    let msg = ServerMessage::PathNotFound { waypoint_index };
+                    send_message(server, player_id, &msg);
+                }
+            }
+        }
+        ClientMessage::MoveItem { from, to } => {
+            if let Some(player) = state.players.get(&player_id) {
+                if let Some(entity) = state.entities.get_mut(&player.entity_id) {
+                    if let Some(ref mut inventory) = entity.inventory {
+                        if !inventory.move_item(from, to, &state.definitions) {
+                            warn!(
+                                "Player {:?} invalid item move {} -> {}",
+                                player_id, from, to
+                            );
+                        }
+
+                        // always reply so the client re-syncs even if the move was rejected
+                        let msg = ServerMessage::InventoryUpdate {
+                            inventory: inventory.clone(),
+                        };
+                        send_message(server, player_id, &msg);
+                    }
+                }
+            }
+        }
+
+        ClientMessage::SetViewDistance { tiles } => {
+            let view_distance = interest_manager.set_view_distance(player_id, tiles);
+            info!(
+                "Player {:?} view distance set to {} tiles (asked for {})",
+                player_id, view_distance, tiles
+            );
+            let msg = ServerMessage::GameConfig {
+                tick_rate: timing.tick_duration,
+                view_distance,
+            };
+            send_message(server, player_id, &msg);
+            update_interest_for_player(player_id, state, interest_manager, server);
+        }
+
+        ClientMessage::RequestResync => send_resync(state, server, player_id),
+
+        ClientMessage::SetRunning { enabled } => {
+            let entity_id = match state.players.get(&player_id) {
+                Some(player) => player.entity_id,
+                None => return,
+            };
+            let run = match state
+                .entities
+                .get_mut(&entity_id)
+                .and_then(|e| e.run.as_mut())
+            {
+                Some(run) => run,
+                None => return,
+            };
+            // there's nothing to run on until some energy has come back
+            run.enabled = enabled && run.energy > 0.0;
+            info!(
+                "Player {:?} is {} ({:.0} energy)",
+                player_id,
+                if run.enabled { "running" } else { "walking" },
+                run.energy
+            );
+            let run = *run;
+            send_run_energy(server, player_id, run);
+        }
+
+        ClientMessage::RequestWorldSnapshot => {
+            let is_admin = state
+                .players
+                .get(&player_id)
+                .is_some_and(|player| state.admin_accounts.contains(&player.account_id));
+            if !is_admin {
+                warn!("Player {:?} asked for a world snapshot", player_id);
+                refuse_request(server, player_id, "world snapshots are for admins only");
+                return;
+            }
+            let zone = state.player_zone(player_id);
+            let entities: Vec<EntitySnapshot> = state
+                .entities
+                .iter()
+                .filter(|(_, entity)| entity.zone == zone)
+                .map(|(entity_id, entity)| entity_snapshot(state, *entity_id, entity))
+                .collect();
+            info!(
+                "World snapshot for {:?}: {} entities at tick {}",
+                player_id,
+                entities.len(),
+                state.server_tick
+            );
+            let msg = ServerMessage::WorldSnapshot {
+                tick: state.server_tick,
+                entities,
+            };
+            send_message(server, player_id, &msg);
+        }
+
+        ClientMessage::Examine { entity_id } => {
+            if !interest_manager.can_see(player_id, entity_id) {
+                warn!(
+                    "Player {:?} examined entity {} outside their view",
+                    player_id, entity_id
+                );
+                refuse_request(server, player_id, "target is not in view");
+                return;
+            }
+            if let Some(entity) = state.entities.get(&entity_id) {
+                let msg = ServerMessage::ExamineResult {
+                    text: examine_text(state, entity),
+                };
+                send_message(server, player_id, &msg);
+            }
+        }
+
+        ClientMessage::ExamineItem { slot } => {
+            let item_type = state
+                .players
+                .get(&player_id)
+                .and_then(|player| state.entities.get(&player.entity_id))
+                .and_then(|entity| entity.inventory.as_ref())
+                .and_then(|inventory| inventory.slots.get(slot))
+                .and_then(|stack| stack.as_ref())
+                .map(|stack| stack.item_type);
+            match item_type {
+                Some(item_type) => {
+                    let def = state.definitions.item(item_type);
+                    let msg = ServerMessage::ExamineResult {
+                        text: format!("{}: {}", def.name, def.description),
+                    };
+                    send_message(server, player_id, &msg);
+                }
+                None => warn!("Player {:?} examined empty slot {}", player_id, slot),
+            }
+        }
+    }
+}
+
+/// name and description of an entity, as the examine option shows them
+pub fn examine_text(state: &ServerState, entity: &ServerEntity) -> String {
+    if let Some(ref tree) = entity.tree {
+        let def = state.definitions.tree(tree.tree_type);
+        format!("{}: {}", def.name, def.description)
+    } else if let Some(ref rock) = entity.rock {
+        let def = RockDefinition::get(rock.rock_type);
+        format!("{}: {}", def.name, def.description)
+    } else if entity.bank.is_some() {
+        "Bank: Keeps your items safe while you're away.".to_string()
+    } else if let Some(ref item) = entity.ground_item {
+        let def = state.definitions.item(item.item_type);
+        format!("{}: {}", def.name, def.description)
+    } else {
+        let name = entity
+            .player_id
+            .and_then(|id| state.players.get(&id))
+            .map(|player| player.name.as_str())
+            .unwrap_or("Player");
+        format!("{}: A fellow adventurer.", name)
+    }
+}
+
+/// actions may only target entities the player has been told about
+pub fn validate_action_target(
+    interest_manager: &InterestManager,
+    action: &GameAction,
+    player_id: PlayerId,
+) -> Result<(), RejectReason> {
+    match action.target_entity() {
+        Some(entity_id) if !interest_manager.can_see(player_id, entity_id) => {
+            warn!(
+                "Player {:?} targeted entity {} outside their view",
+                player_id, entity_id
+            );
+            Err(RejectReason::NotInView)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// where the player will be once the walk they're on, or have queued, is over
+fn planned_tile(entity: &ServerEntity) -> TilePosition {
+    let queue = &entity.action_queue;
+    queue
+        .actions
+        .iter()
+        .rev()
+        .chain(queue.current_action.iter().map(|current| &current.action))
+        .find_map(|action| match action {
+            GameAction::Move { path } => path.last().copied(),
+            _ => None,
+        })
+        .unwrap_or(entity.tile_pos)
+}
+
+/// checks the action against the world as the client saw it at `view_tick`, so a tree
+/// felled a moment ago still counts as standing for a client that hasn't drawn it yet.
+/// `from` is where the player will be standing when the action starts
+pub fn validate_gathering_action(
+    state: &ServerState,
+    player_entity_id: u64,
+    action: &GameAction,
+    from: TilePosition,
+    view_tick: u64,
+    player_id: PlayerId,
+) -> Result<(), RejectReason> {
+    let player_entity = state.entities.get(&player_entity_id);
+    match action {
+        GameAction::EnterZone { portal_id } => {
+            let on_portal = player_entity.is_some_and(|entity| {
+                state
+                    .zone(entity.zone)
+                    .portal_at(&entity.tile_pos)
+                    .is_some_and(|portal| portal.portal_id == *portal_id)
+            });
+            if !on_portal {
+                warn!("Invalid zone change: not standing on portal {}", portal_id);
+                return Err(RejectReason::NotOnPortal);
+            }
+            Ok(())
+        }
+        // teleports are for admin commands and game mechanics, a client can't ask for one
+        GameAction::Teleport { destination } => {
+            warn!(
+                "Player {:?} tried to teleport themselves to {:?}",
+                player_id, destination
+            );
+            Err(RejectReason::ServerOnly)
+        }
+        GameAction::Attack { target_entity_id } => {
+            let attackable = *target_entity_id != player_entity_id
+                && state
+                    .entities
+                    .get(target_entity_id)
+                    .is_some_and(|target| target.health.is_some());
+            if !attackable {
+                warn!(
+                    "Player {:?} tried to attack entity {}, which can't be hit",
+                    player_id, target_entity_id
+                );
+                return Err(RejectReason::InvalidTarget);
+            }
+            Ok(())
+        }
+        GameAction::ChopTree { tree_entity_id } => {
+            match (player_entity, state.entities.get(tree_entity_id)) {
+                (Some(p_entity), Some(t_entity)) => {
+                    let seen_standing = state
+                        .resource_history
+                        .was_available_at(*tree_entity_id, view_tick);
+                    validate_woodcutting_action(
+                        p_entity,
+                        t_entity,
+                        from,
+                        seen_standing,
+                        player_id,
+                        &state.definitions,
+                    )
+                }
+                _ => {
+                    warn!(
+                        "Invalid woodcutting: entity not found (player={}, tree={})",
+                        player_entity_id, tree_entity_id
+                    );
+                    Err(RejectReason::InvalidTarget)
+                }
+            }
+        }
+        GameAction::Mine { rock_entity_id } => {
+            match (player_entity, state.entities.get(rock_entity_id)) {
+                (Some(p_entity), Some(r_entity)) => {
+                    let seen_unmined = state
+                        .resource_history
+                        .was_available_at(*rock_entity_id, view_tick);
+                    validate_mining_action(p_entity, r_entity, from, seen_unmined, player_id)
+                }
+                _ => {
+                    warn!(
+                        "Invalid mining: entity not found (player={}, rock={})",
+                        player_entity_id, rock_entity_id
+                    );
+                    Err(RejectReason::InvalidTarget)
+                }
+            }
+        }
+        GameAction::Move { path } => {
+            // a walk replaces the one in progress, so it starts from where they are now
+            let from = match player_entity {
+                Some(entity) => entity.tile_pos,
+                None => return Err(RejectReason::InvalidPath),
+            };
+            let zone = state.zone(state.player_zone(player_id));
+            if let Err(reason) = zone.pathfinder.check_walk(from, path) {
+                warn!("Invalid move from player {:?}: {}", player_id, reason);
+                return Err(RejectReason::InvalidPath);
+            }
+            Ok(())
+        }
+        GameAction::UseItem { slot } => {
+            let usable = player_entity
+                .and_then(|entity| entity.inventory.as_ref())
+                .and_then(|inventory| inventory.slots.get(*slot))
+                .and_then(|stack| stack.as_ref())
+                .is_some_and(|stack| state.definitions.item(stack.item_type).on_use.is_some());
+            if !usable {
+                warn!(
+                    "Player {:?} tried to use slot {}, which holds nothing usable",
+                    player_id, slot
+                );
+                return Err(RejectReason::NothingUsable);
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// gathering needs the player next to the target, diagonals included
+fn check_reach(
+    from: TilePosition,
+    target: &ServerEntity,
+    player_id: PlayerId,
+) -> Result<(), RejectReason> {
+    if from.distance_chebyshev(&target.tile_pos) > 1 {
+        warn!(
+            "Player {:?} would be at {:?}, too far from {:?}",
+            player_id, from, target.tile_pos
+        );
+        return Err(RejectReason::TooFar);
+    }
+    Ok(())
+}
+
+/// `from` is where the player will chop from, which has to be next to the tree.
+/// `seen_standing` lets through a tree the client still saw standing, see
+/// `validate_gathering_action`
+pub fn validate_woodcutting_action(
+    player_entity: &ServerEntity,
+    tree_entity: &ServerEntity,
+    from: TilePosition,
+    seen_standing: bool,
+    player_id: PlayerId,
+    definitions: &Definitions,
+) -> Result<(), RejectReason> {
+    check_reach(from, tree_entity, player_id)?;
+    let tree = match &tree_entity.tree {
+        Some(t) if !t.is_chopped || seen_standing => t,
+        Some(t) if t.is_chopped => {
+            warn!("Player {:?} tried to chop already chopped tree", player_id);
+            return Err(RejectReason::Depleted);
+        }
+        _ => {
+            warn!("Player {:?} tried to chop invalid tree", player_id);
+            return Err(RejectReason::InvalidTarget);
+        }
+    };
+
+    let tree_def = definitions.tree(tree.tree_type);
+    info!(
+        "Validating woodcutting for player {:?}: tree={:?}, required_level={}",
+        player_id, tree.tree_type, tree_def.level_required
+    );
+
+    if let Some(ref skills) = player_entity.skills {
+        let wc_level = skills.get_level(SkillType::Woodcutting);
+        if wc_level < tree_def.level_required {
+            warn!(
+                "Player {:?} insufficient level: has {}, needs {}",
+                player_id, wc_level, tree_def.level_required
+            );
+            return Err(RejectReason::NotEnoughLevel {
+                skill: SkillType::Woodcutting,
+                required: tree_def.level_required,
+                current: wc_level,
+            });
+        }
+        info!("Level check passed: player has level {}", wc_level);
+    }
+
+    match equipped_tool(&player_entity.equipment) {
+        Some(axe) if axe.is_axe() => {
+            info!("Axe check passed: player is wielding {:?}", axe);
+        }
+        _ => {
+            warn!("Player {:?} has no axe equipped", player_id);
+            return Err(RejectReason::NoAxeEquipped);
+        }
+    }
+
+    info!("Woodcutting validation passed for player {:?}", player_id);
+    Ok(())
+}
+
+/// `from` is where the player will mine from, which has to be next to the rock.
+/// `seen_unmined` lets through a rock the client still saw unmined, see
+/// `validate_gathering_action`
+pub fn validate_mining_action(
+    player_entity: &ServerEntity,
+    rock_entity: &ServerEntity,
+    from: TilePosition,
+    seen_unmined: bool,
+    player_id: PlayerId,
+) -> Result<(), RejectReason> {
+    check_reach(from, rock_entity, player_id)?;
+    let rock = match &rock_entity.rock {
+        Some(r) if !r.is_depleted || seen_unmined => r,
+        Some(r) if r.is_depleted => {
+            warn!("Player {:?} tried to mine depleted rock", player_id);
+            return Err(RejectReason::Depleted);
+        }
+        _ => {
+            warn!("Player {:?} tried to mine invalid rock", player_id);
+            return Err(RejectReason::InvalidTarget);
+        }
+    };
+
+    let rock_def = RockDefinition::get(rock.rock_type);
+    info!(
+        "Validating mining for player {:?}: rock={:?}, required_level={}",
+        player_id, rock.rock_type, rock_def.level_required
+    );
+
+    if let Some(ref skills) = player_entity.skills {
+        let mining_level = skills.get_level(SkillType::Mining);
+        if mining_level < rock_def.level_required {
+            warn!(
+                "Player {:?} insufficient level: has {}, needs {}",
+                player_id, mining_level, rock_def.level_required
+            );
+            return Err(RejectReason::NotEnoughLevel {
+                skill: SkillType::Mining,
+                required: rock_def.level_required,
+                current: mining_level,
+            });
+        }
+        info!("Level check passed: player has level {}", mining_level);
+    }
+
+    match equipped_tool(&player_entity.equipment) {
+        Some(pickaxe) if pickaxe.is_pickaxe() => {
+            info!("Pickaxe check passed: player is wielding {:?}", pickaxe);
+        }
+        _ => {
+            warn!("Player {:?} has no pickaxe equipped", player_id);
+            return Err(RejectReason::NoPickaxeEquipped);
+        }
+    }
+
+    info!("Mining validation passed for player {:?}", player_id);
+    Ok(())
+}
+
+/// runs one game tick, returns how many entity deltas it sent
+pub fn process_server_tick(
+    state: &mut ServerState,
+    timing: &ServerTiming,
+    rng: &mut GameRng,
+    server: &mut impl MessageSink,
+    interest_manager: &mut InterestManager,
+    commands: &mut Commands,
+) -> usize {
+    let tick = state.server_tick;
+    let current_time = state.game_time;
+    let tick_duration = timing.tick_duration as f64;
+
+    let mut completed_actions = Vec::new();
+    let mut woodcutting_completions = Vec::new();
+    let mut mining_completions = Vec::new();
+    let mut item_completions = Vec::new();
+    let mut interact_completions = Vec::new();
+    let mut attack_completions = Vec::new();
+    let mut blocked_moves = Vec::new();
+    let mut teleports = Vec::new();
+    let mut zone_entries = Vec::new();
+    let mut run_updates = Vec::new();
+
+    remove_expired_players(server, state, interest_manager, commands);
+    state.resource_history.prune(tick);
+    start_buffered_steps(state, server, timing);
+    chase_players(state, tick_duration);
+    wander_npcs(state, rng, tick_duration);
+    // a disconnected player's entity holds its tile but does nothing until they return
+    let parked: HashSet<u64> = state
+        .players
+        .values()
+        .filter(|player| player.pending_removal.is_some())
+        .map(|player| player.entity_id)
+        .collect();
+
+    // updated as entities move so two of them can't step onto the same tile, or
+    // swap tiles, in the same tick
+    let mut occupied: HashMap<ZoneId, HashSet<TilePosition>> = HashMap::new();
+    for entity in state
+        .entities
+        .values()
+        .filter(|entity| occupies_tile(entity))
+    {
+        occupied
+            .entry(entity.zone)
+            .or_default()
+            .insert(entity.tile_pos);
+    }
+
+    // lowest id first, so when two entities step onto the same tile the same one
+    // gets there every time instead of whichever the hash map yields first
+    let mut entity_ids: Vec<u64> = state.entities.keys().copied().collect();
+    entity_ids.sort_unstable();
+    for entity_id in &entity_ids {
+        if parked.contains(entity_id) {
+            continue;
+        }
+        let entity = match state.entities.get_mut(entity_id) {
+            Some(entity) => entity,
+            None => continue,
+        };
+        let before = (entity.tile_pos, action_started_at(entity));
+        if let Some(ref current_action) = entity.action_queue.current_action {
+            if current_time >= current_action.completion_time {
+                match current_action.action {
+                    GameAction::ChopTree { tree_entity_id } => {
+                        woodcutting_completions.push((*entity_id, tree_entity_id));
+                    }
+                    GameAction::Mine { rock_entity_id } => {
+                        mining_completions.push((*entity_id, rock_entity_id));
+                    }
+                    GameAction::DropItem { .. }
+                    | GameAction::PickUp { .. }
+                    | GameAction::Equip { .. }
+                    | GameAction::Unequip { .. }
+                    | GameAction::UseItem { .. }
+                    | GameAction::Deposit { .. }
+                    | GameAction::Withdraw { .. } => {
+                        item_completions.push((*entity_id, current_action.action.clone()));
+                    }
+                    GameAction::Interact {
+                        entity_id: target_id,
+                    } => {
+                        interact_completions.push((*entity_id, target_id));
+                    }
+                    GameAction::Attack { target_entity_id } => {
+                        attack_completions.push((*entity_id, target_entity_id));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let tool = equipped_tool(&entity.equipment);
+        let occupies = occupies_tile(entity);
+        let occupied = occupied.entry(entity.zone).or_default();
+        if occupies {
+            occupied.remove(&entity.tile_pos);
+        }
+        let instant = entity
+            .action_queue
+            .current_action
+            .as_ref()
+            .map(|current| current.action.clone())
+            .filter(|action| {
+                matches!(
+                    action,
+                    GameAction::Teleport { .. } | GameAction::EnterZone { .. }
+                )
+            });
+        if let Some(action) = instant {
+            // teleports and zone changes cancel everything, including the action
+            // they suspended
+            entity.action_queue.current_action = None;
+            entity.action_queue.suspended_action = None;
+            entity.action_queue.actions.clear();
+            entity.action_queue.input_buffer.clear();
+            entity.action_queue.gathering_nearest = false;
+            match action {
+                GameAction::Teleport { destination } if occupied.contains(&destination) => {
+                    entity.action_queue.blocked_at = Some(destination);
+                }
+                GameAction::Teleport { destination } => {
+                    info!("Entity {} teleported to {:?}", entity_id, destination);
+                    entity.tile_pos = destination;
+                    teleports.push((*entity_id, entity.player_id));
+                }
+                GameAction::EnterZone { portal_id } => {
+                    zone_entries.push((*entity_id, portal_id));
+                }
+                _ => {}
+            }
+        }
+        let previous_tile = entity.tile_pos;
+        let running = entity.run.is_some_and(|run| run.is_running());
+        let moved = process_action_queue(
+            &mut entity.action_queue,
+            &mut entity.tile_pos,
+            current_time,
+            tick_duration,
+            tool,
+            if running { RUN_STEPS } else { 1 },
+            occupied,
+        );
+        if occupies {
+            occupied.insert(entity.tile_pos);
+        }
+        if let Some(ref mut run) = entity.run {
+            let idle = entity.action_queue.current_action.is_none();
+            if run.update(running && moved > 0, idle) {
+                if let Some(player_id) = entity.player_id {
+                    run_updates.push((player_id, *run));
+                }
+            }
+        }
+        if entity.player_id.is_some() && entity.tile_pos != previous_tile {
+            if let Some(portal) = state.zones[&entity.zone].portal_at(&entity.tile_pos) {
+                // stepping onto a portal ends the walk, the zone change happens next tick
+                let action = GameAction::EnterZone {
+                    portal_id: portal.portal_id,
+                };
+                entity.action_queue.actions.clear();
+                entity.action_queue.current_action = Some(ActionInProgress {
+                    completion_time: current_time + action.duration_seconds(tick_duration),
+                    action,
+                    started_at: current_time,
+                    current_path_index: 0,
+                    repeat_count: 0,
+                });
+            }
+        }
+        if (entity.tile_pos, action_started_at(entity)) != before {
+            state.dirty_entities.insert(*entity_id);
+        }
+        if let Some(at) = entity.action_queue.blocked_at.take() {
+            if let Some(player_id) = entity.player_id {
+                blocked_moves.push((player_id, at));
+            }
+        }
+
+        if let Some(ref action_in_progress) = entity.action_queue.current_action {
+            if current_time >= action_in_progress.completion_time {
+                if !matches!(
+                    action_in_progress.action,
+                    GameAction::ChopTree { .. } | GameAction::Mine { .. }
+                ) {
+                    completed_actions.push(*entity_id);
+                }
+            }
+        }
+    }
+
+    for (player_id, at) in blocked_moves {
+        send_path_blocked(server, player_id, at);
+    }
+
+    for (player_id, run) in run_updates {
+        send_run_energy(server, player_id, run);
+    }
+
+    for (entity_id, portal_id) in zone_entries {
+        enter_zone(state, server, interest_manager, entity_id, portal_id);
+    }
+
+    for (entity_id, player_id) in teleports {
+        interest_manager.resend_full_state(entity_id);
+        if let Some(player_id) = player_id {
+            let msg = ServerMessage::ActionCompleted { entity_id };
+            send_message(server, player_id, &msg);
+        }
+    }
+
+    if !woodcutting_completions.is_empty() {
+        info!(
+            "Processing {} woodcutting completions",
+            woodcutting_completions.len()
+        );
+    }
+
+    let choppers: Vec<u64> = woodcutting_completions
+        .iter()
+        .map(|(player_entity_id, _)| *player_entity_id)
+        .collect();
+    for (player_entity_id, tree_entity_id) in woodcutting_completions {
+        handle_woodcutting_completion(
+            player_entity_id,
+            tree_entity_id,
+            state,
+            rng,
+            interest_manager,
+            server,
+        );
+    }
+    // players in a gather loop who have nothing left to do move on to the next tree
+    let gatherers: Vec<PlayerId> = choppers
+        .iter()
+        .filter_map(|entity_id| state.entities.get(entity_id))
+        .filter(|entity| {
+            let queue = &entity.action_queue;
+            queue.gathering_nearest && queue.current_action.is_none() && queue.actions.is_empty()
+        })
+        .filter_map(|entity| entity.player_id)
+        .collect();
+    for player_id in gatherers {
+        if !chop_nearest_tree(state, interest_manager, server, player_id, timing) {
+            info!("Player {:?} has no trees left in view", player_id);
+        }
+    }
+
+    if !mining_completions.is_empty() {
+        info!("Processing {} mining completions", mining_completions.len());
+    }
+
+    for (player_entity_id, rock_entity_id) in mining_completions {
+        handle_mining_completion(
+            player_entity_id,
+            rock_entity_id,
+            state,
+            interest_manager,
+            server,
+        );
+    }
+
+    for (player_entity_id, action) in item_completions {
+        match action {
+            GameAction::DropItem { slot } => {
+                handle_drop_completion(
+                    player_entity_id,
+                    slot,
+                    state,
+                    interest_manager,
+                    server,
+                    commands,
+                );
+            }
+            GameAction::PickUp { entity_id } => handle_pickup_completion(
+                player_entity_id,
+                entity_id,
+                state,
+                interest_manager,
+                server,
+                commands,
+            ),
+            GameAction::Equip { slot } => {
+                handle_equip_completion(player_entity_id, slot, state, server);
+            }
+            GameAction::Unequip { slot } => {
+                handle_unequip_completion(player_entity_id, slot, state, server);
+            }
+            GameAction::UseItem { slot } => {
+                handle_use_item_completion(player_entity_id, slot, state, server);
+            }
+            GameAction::Deposit { slot, quantity } => {
+                handle_deposit_completion(player_entity_id, slot, quantity, state, server);
+            }
+            GameAction::Withdraw {
+                item_type,
+                quantity,
+            } => {
+                handle_withdraw_completion(player_entity_id, item_type, quantity, state, server);
+            }
+            _ => {}
+        }
+    }
+
+    for (player_entity_id, target_id) in interact_completions {
+        handle_interact_completion(player_entity_id, target_id, state, server);
+    }
+
+    for (attacker_entity_id, target_entity_id) in attack_completions {
+        handle_attack_completion(
+            attacker_entity_id,
+            target_entity_id,
+            state,
+            rng,
+            interest_manager,
+            server,
+            commands,
+        );
+    }
+
+    for entity_id in completed_actions {
+        if let Some(entity) = state.entities.get_mut(&entity_id) {
+            entity.action_queue.current_action = None;
+
+            if let Some(player_id) = entity.player_id {
+                debug!("Action completed for player {:?}", player_id);
+                let msg = ServerMessage::ActionCompleted { entity_id };
+                send_message(server, player_id, &msg);
+            }
+        }
+    }
+
+    report_completed_inputs(state, server);
+    regenerate_health(state, server, tick, &parked);
+
+    // update tree respawn timers. a tree that would grow back under someone waits
+    // until they step off, like spawning does
+    let standing: HashSet<(ZoneId, TilePosition)> = state
+        .entities
+        .values()
+        .filter(|entity| entity.player_id.is_some() || entity.npc.is_some())
+        .map(|entity| (entity.zone, entity.tile_pos))
+        .collect();
+    let mut respawned_trees = Vec::new();
+    for (tree_entity_id, tree_entity) in state.entities.iter_mut() {
+        if let Some(ref mut tree) = tree_entity.tree {
+            if tree.is_chopped {
+                tree.respawn_timer += tick_duration;
+
+                let tree_def = state.definitions.tree(tree.tree_type);
+                let blocked = standing.contains(&(tree_entity.zone, tree_entity.tile_pos));
+                if tree.respawn_timer >= tree_def.respawn_time && blocked {
+                    debug!(
+                        "Tree {} waiting for its tile to clear before respawning",
+                        tree_entity_id
+                    );
+                    tree.update_respawn_ticks(tick, tick_duration, &state.definitions);
+                } else if tree.respawn_timer >= tree_def.respawn_time {
+                    tree.is_chopped = false;
+                    tree.respawn_timer = 0.0;
+                    tree.update_respawn_ticks(tick, tick_duration, &state.definitions);
+                    respawned_trees.push((
+                        *tree_entity_id,
+                        tree.tree_type,
+                        tree_entity.zone,
+                        tree_entity.tile_pos,
+                    ));
+                } else {
+                    tree.update_respawn_ticks(tick, tick_duration, &state.definitions);
+                }
+            }
+        }
+    }
+
+    // the map hands trees out in no set order, and the event log has to be the same
+    // every run
+    respawned_trees.sort_by_key(|(tree_id, ..)| *tree_id);
+    for (tree_id, tree_type, zone, tile) in respawned_trees {
+        info!("Tree {} ({:?}) respawned", tree_id, tree_type);
+        state.resource_history.record_restored(tree_id);
+        state
+            .event_log
+            .record(tick, tree_id, EntityEventKind::TreeRespawned);
+        let msg = ServerMessage::TreeRespawned {
+            tree_entity_id: tree_id,
+        };
+        send_to_viewers(interest_manager, server, tree_id, &msg);
+        state.zone_mut(zone).pathfinder.add_obstacle(tile);
+        send_to_viewers(
+            interest_manager,
+            server,
+            tree_id,
+            &ServerMessage::ObstacleAdded { tile },
+        );
+    }
+
+    // update rock respawn timers
+    let mut respawned_rocks = Vec::new();
+    for (rock_entity_id, rock_entity) in state.entities.iter_mut() {
+        if let Some(ref mut rock) = rock_entity.rock {
+            if rock.is_depleted {
+                rock.respawn_timer += tick_duration;
+
+                let rock_def = RockDefinition::get(rock.rock_type);
+                if rock.respawn_timer >= rock_def.respawn_time {
+                    rock.is_depleted = false;
+                    rock.respawn_timer = 0.0;
+                    respawned_rocks.push((*rock_entity_id, rock.rock_type));
+                }
+            }
+        }
+    }
+
+    respawned_rocks.sort_by_key(|(rock_id, _)| *rock_id);
+    for (rock_id, rock_type) in respawned_rocks {
+        info!("Rock {} ({:?}) respawned", rock_id, rock_type);
+        state.resource_history.record_restored(rock_id);
+        state
+            .event_log
+            .record(tick, rock_id, EntityEventKind::RockRespawned);
+        let msg = ServerMessage::RockRespawned {
+            rock_entity_id: rock_id,
+        };
+        send_to_viewers(interest_manager, server, rock_id, &msg);
+    }
+
+    let expired_items: Vec<u64> = state
+        .entities
+        .iter()
+        .filter(|(_, entity)| entity.despawn_tick.is_some_and(|t| tick >= t))
+        .map(|(entity_id, _)| *entity_id)
+        .collect();
+    for entity_id in expired_items {
+        info!("Ground item {} despawned", entity_id);
+        despawn_entity(entity_id, state, interest_manager, server, commands);
+    }
+
+    for (player_id, player) in state.players.iter() {
+        if player.pending_removal.is_none() {
+            update_interest_for_player(*player_id, state, interest_manager, server);
+        }
+    }
+    for spectator_id in state.spectators.keys() {
+        update_interest_for_player(*spectator_id, state, interest_manager, server);
+    }
+
+    let deltas_sent = send_delta_updates(state, interest_manager, server, tick);
+    send_position_keyframes(state, interest_manager, server, tick);
+    deltas_sent
+}
+
+/// moves an entity through a portal onto a free spawn tile of the zone it leads to.
+/// a player's view starts over so the new zone arrives as freshly entered entities
+fn enter_zone(
+    state: &mut ServerState,
+    server: &mut impl MessageSink,
+    interest_manager: &mut InterestManager,
+    entity_id: u64,
+    portal_id: u32,
+) {
+    let target_zone = match state
+        .zones
+        .values()
+        .flat_map(|zone| &zone.portals)
+        .find(|portal| portal.portal_id == portal_id)
+    {
+        Some(portal) => portal.target_zone,
+        None => {
+            warn!("Entity {} used unknown portal {}", entity_id, portal_id);
+            return;
+        }
+    };
+    let spawn_position = find_spawn_tile(state, target_zone);
+
+    let entity = match state.entities.get_mut(&entity_id) {
+        Some(entity) => entity,
+        None => return,
+    };
+    info!(
+        "Entity {} went through portal {} to zone {:?} at {:?}",
+        entity_id, portal_id, target_zone, spawn_position
+    );
+    entity.zone = target_zone;
+    entity.tile_pos = spawn_position;
+    state.dirty_entities.insert(entity_id);
+
+    let player_id = match entity.player_id {
+        Some(player_id) => player_id,
+        None => return,
+    };
+    interest_manager.reset_view(player_id);
+
+    let msg = ServerMessage::ZoneChanged {
+        zone: target_zone,
+        spawn_position,
+    };
+    send_message(server, player_id, &msg);
+    send_zone_data(state, server, player_id, target_zone);
+}
+
+/// advances the entity's actions by one tick, a move covers up to `steps` tiles of its
+/// path. returns how many tiles the entity walked along a path this tick
+pub fn process_action_queue(
+    queue: &mut ActionQueue,
+    tile_pos: &mut TilePosition,
+    current_time: f64,
+    tick_duration: f64,
+    tool: Option<ItemType>,
+    steps: usize,
+    occupied: &HashSet<TilePosition>,
+) -> usize {
+    if let Some(ref mut action_in_progress) = queue.current_action {
+        let mut blocked = None;
+        let mut moved = 0;
+        if current_time >= action_in_progress.completion_time {
+            if let GameAction::Move { ref path } = action_in_progress.action {
+                while moved < steps {
+                    let next = match path.get(action_in_progress.current_path_index + 1) {
+                        Some(&next) => next,
+                        None => break,
+                    };
+                    if occupied.contains(&next) {
+                        blocked = Some(next);
+                        break;
+                    }
+                    action_in_progress.current_path_index += 1;
+                    *tile_pos = next;
+                    moved += 1;
+                }
+
+                if moved > 0 {
+                    action_in_progress.completion_time = current_time + tick_duration;
+                } else if blocked.is_none() {
+                    queue.current_action = None;
+                }
+            }
+        }
+        if let Some(at) = blocked {
+            queue.block_move(at);
+        }
+        return moved;
+    }
+
+    // a Normal action suspended by a Strong one picks up where it left off
+    if let Some(mut suspended) = queue.suspended_action.take() {
+        info!("  → Resuming suspended {:?}", suspended.action);
+        suspended.completion_time = current_time
+            + suspended
+                .action
+                .duration_seconds_with_tool(tool, tick_duration);
+        queue.current_action = Some(suspended);
+        return 0;
+    }
+
+    if let Some(action) = queue.actions.pop_front() {
+        let action = expand_move(action);
+        let duration = action.duration_seconds_with_tool(tool, tick_duration);
+        let start_index = match &action {
+            GameAction::Move { path } => {
+                if let Some(&first) = path.first() {
+                    if first != *tile_pos && occupied.contains(&first) {
+                        queue.block_move(first);
+                        return 0;
+                    }
+                    *tile_pos = first;
+                }
+                0
+            }
+            _ => 0,
+        };
+
+        queue.current_action = Some(ActionInProgress {
+            action: action.clone(),
+            started_at: current_time,
+            completion_time: current_time + duration,
+            current_path_index: start_index,
+            repeat_count: 0,
+        });
+    }
+    0
+}
+
+#[derive(Debug)]
+pub enum QueueResult {
+    Started,             // action started immediately
+    Queued,              // action queued for later
+    ReplacedSameType,    // replaced in-progress action of same type
+    CancelledAndStarted, // cancelled lower priority action and started
+    Suspended,           // suspended normal action (by strong action)
+    QueueFull,           // queue is full (MAX_QUEUED_ACTIONS waiting)
+}
+
+/// paths may arrive smoothed down to their turn points, moves still go one tile per tick
+fn expand_move(action: GameAction) -> GameAction {
+    match action {
+        GameAction::Move { path } => GameAction::Move {
+            path: Pathfinder::expand_path(&path),
+        },
+        action => action,
+    }
+}
+
+/// the item in the weapon slot, which doubles as the gathering tool
+pub fn equipped_tool(equipment: &Equipment) -> Option<ItemType> {
+    equipment
+        .get(&EquipSlot::Weapon)
+        .map(|stack| stack.item_type)
+}
+
+/// handles adding a new action to the queue with priority-based cancellation
+pub fn queue_action_with_priority(
+    queue: &mut ActionQueue,
+    tile_pos: &mut TilePosition,
+    new_action: GameAction,
+    current_time: f64,
+    tick_duration: f64,
+    tool: Option<ItemType>,
+    occupied: &HashSet<TilePosition>,
+) -> QueueResult {
+    let new_priority = new_action.priority();
+
+    // anything the player asks for themselves replaces a suspended action
+    if new_priority != ActionPriority::Strong {
+        queue.suspended_action = None;
+    }
+
+    if let Some(ref current) = queue.current_action {
+        let current_priority = current.action.priority();
+
+        // Strong actions cancel or suspend whatever is running, higher priorities
+        // cancel lower ones (so any movement cancels gathering)
+        if new_priority == ActionPriority::Strong || new_priority.can_cancel(&current_priority) {
+            let result = if new_priority.should_suspend(&current_priority) {
+                queue.suspended_action = queue.current_action.take();
+                QueueResult::Suspended
+            } else {
+                queue.current_action = None;
+                QueueResult::CancelledAndStarted
+            };
+            queue.actions.clear();
+            start_action(
+                queue,
+                tile_pos,
+                new_action,
+                current_time,
+                tick_duration,
+                tool,
+                occupied,
+            );
+            return result;
+        }
+
+        if new_action.replaces_same_type(&current.action) {
+            queue.current_action = None;
+            queue.actions.clear();
+            start_action(
+                queue,
+                tile_pos,
+                new_action,
+                current_time,
+                tick_duration,
+                tool,
+                occupied,
+            );
+            return QueueResult::ReplacedSameType;
+        }
+
+        if queue.actions.len() < MAX_QUEUED_ACTIONS {
+            queue.actions.push_back(new_action);
+            return QueueResult::Queued;
+        } else {
+            return QueueResult::QueueFull;
+        }
+    }
+
+    // no current action, start immediately
+    start_action(
+        queue,
+        tile_pos,
+        new_action,
+        current_time,
+        tick_duration,
+        tool,
+        occupied,
+    );
+    QueueResult::Started
+}
+
+fn start_action(
+    queue: &mut ActionQueue,
+    tile_pos: &mut TilePosition,
+    action: GameAction,
+    current_time: f64,
+    tick_duration: f64,
+    tool: Option<ItemType>,
+    occupied: &HashSet<TilePosition>,
+) {
+    let action = expand_move(action);
+    let duration = action.duration_seconds_with_tool(tool, tick_duration);
+    let start_index = match &action {
+        GameAction::Move { path } => {
+            // immediately move to first position in path
+            if let Some(&first) = path.first() {
+                if first != *tile_pos && occupied.contains(&first) {
+                    queue.block_move(first);
+                    return;
+                }
+                *tile_pos = first;
+                info!("  → Player moved to {:?} (start of path)", first);
+            }
+            0
+        }
+        _ => 0,
+    };
+
+    queue.current_action = Some(ActionInProgress {
+        action,
+        started_at: current_time,
+        completion_time: current_time + duration,
+        current_path_index: start_index,
+        repeat_count: 0,
+    });
+}
+
+pub fn handle_woodcutting_completion(
+    player_entity_id: u64,
+    tree_entity_id: u64,
+    state: &mut ServerState,
+    rng: &mut GameRng,
+    interest_manager: &InterestManager,
+    server: &mut impl MessageSink,
+) {
+    let tree_def = if let Some(tree_entity) = state.entities.get(&tree_entity_id) {
+        match tree_entity.tree {
+            // validation may have let this through on a rewound view, but someone
+            // else got the tree first
+            Some(ref tree) if tree.is_chopped => {
+                info!(
+                    "Tree {} was chopped before entity {} finished, no logs given",
+                    tree_entity_id, player_entity_id
+                );
+                stop_gathering(state, server, player_entity_id);
+                return;
+            }
+            Some(ref tree) => {
+                let def = state.definitions.tree(tree.tree_type);
+                info!(
+                    "Processing woodcutting completion: tree={:?}, xp={}, logs={:?}",
+                    tree.tree_type, def.experience, def.logs_given
+                );
+                def
+            }
+            None => return,
+        }
+    } else {
+        return;
+    };
+
+    // validation checked the tile the chop would start from, this catches anyone who
+    // wasn't next to the tree by the time it finished
+    if !in_reach(state, player_entity_id, tree_entity_id) {
+        warn!(
+            "Entity {} finished chopping tree {} out of reach, no logs given",
+            player_entity_id, tree_entity_id
+        );
+        stop_gathering(state, server, player_entity_id);
+        return;
+    }
+
+    let depleted = rng.chance(tree_def.depletion_chance);
+    if let Some(tree_entity) = state.entities.get_mut(&tree_entity_id).filter(|_| depleted) {
+        if let Some(ref mut tree) = tree_entity.tree {
+            tree.is_chopped = true;
+            tree.respawn_timer = 0.0;
+            info!(
+                "Tree {} chopped! Will respawn in {}s",
+                tree_entity_id, tree_def.respawn_time
+            );
+            state
+                .resource_history
+                .record_depleted(tree_entity_id, state.server_tick);
+            state.event_log.record(
+                state.server_tick,
+                tree_entity_id,
+                EntityEventKind::TreeChopped,
+            );
+
+            let (zone, tile) = (tree_entity.zone, tree_entity.tile_pos);
+            state.zone_mut(zone).pathfinder.remove_obstacle(tile);
+            send_to_viewers(
+                interest_manager,
+                server,
+                tree_entity_id,
+                &ServerMessage::ObstacleRemoved { tile },
+            );
+        }
+    }
+
+    let player_entity = match state.entities.get_mut(&player_entity_id) {
+        Some(e) => e,
+        None => return,
+    };
+
+    let player_id = match player_entity.player_id {
+        Some(id) => id,
+        None => return,
+    };
+
+    let mut logs_added = 0;
+    if let Some(ref mut inventory) = player_entity.inventory {
+        logs_added = inventory.add_item(tree_def.logs_given, 1, &state.definitions);
+        if logs_added > 0 {
+            let def = state.definitions.item(tree_def.logs_given);
+            info!(
+                "Player {:?} received: {} x{} (total: {})",
+                player_id,
+                def.name,
+                logs_added,
+                inventory.count_item(tree_def.logs_given)
+            );
+
+            let msg = ServerMessage::ItemAdded {
+                item_type: tree_def.logs_given,
+                quantity: logs_added,
+            };
+            send_message(server, player_id, &msg);
+
+            let inv_msg = ServerMessage::InventoryUpdate {
+                inventory: inventory.clone(),
+            };
+            send_message(server, player_id, &inv_msg);
+        } else {
+            warn!(" Player {:?} inventory full! Could not add logs", player_id);
+        }
+
+        // bonus drops only come with logs, a full inventory gets neither
+        let roll = rng.range(0..tree_def.loot.total_weight() as i32) as u32;
+        let bonus = tree_def.loot.pick(roll).filter(|_| logs_added > 0);
+        if let Some(entry) = bonus {
+            let added = inventory.add_item(entry.item_type, entry.quantity, &state.definitions);
+            if added > 0 {
+                info!(
+                    "Player {:?} found a bonus {} x{}",
+                    player_id,
+                    state.definitions.item(entry.item_type).name,
+                    added
+                );
+                let msg = ServerMessage::ItemAdded {
+                    item_type: entry.item_type,
+                    quantity: added,
+                };
+                send_message(server, player_id, &msg);
+
+                let inv_msg = ServerMessage::InventoryUpdate {
+                    inventory: inventory.clone(),
+                };
+                send_message(server, player_id, &inv_msg);
+            }
+        }
+    }
+
+    // experience is only awarded for logs that made it into the inventory
+    let xp_gained = tree_def.experience * logs_added;
+    if let Some(skills) = player_entity.skills.as_mut().filter(|_| xp_gained > 0) {
+        let old_level = skills.get_level(SkillType::Woodcutting);
+        let old_xp = skills.get_experience(SkillType::Woodcutting);
+        let leveled_up = skills.add_experience(SkillType::Woodcutting, xp_gained);
+        let new_xp = skills.get_experience(SkillType::Woodcutting);
+
+        info!(
+            "Player {:?} gained {} Woodcutting XP ({} -> {})",
+            player_id, xp_gained, old_xp, new_xp
+        );
+
+        let xp_msg = ServerMessage::ExperienceGained {
+            skill: SkillType::Woodcutting,
+            amount: xp_gained,
+        };
+        send_message(server, player_id, &xp_msg);
+
+        let skill_data = &skills.skills[&SkillType::Woodcutting];
+        let skill_msg = ServerMessage::SkillUpdate {
+            skill: SkillType::Woodcutting,
+            level: skill_data.level,
+            experience: skill_data.experience,
+            xp_to_next: skills.xp_to_next_level(SkillType::Woodcutting),
+        };
+        send_message(server, player_id, &skill_msg);
+
+        if leveled_up {
+            info!(
+                "LEVEL UP! Player {:?} Woodcutting: {} -> {}",
+                player_id, old_level, skill_data.level
+            );
+            let levelup_msg = ServerMessage::LevelUp {
+                skill: SkillType::Woodcutting,
+                new_level: skill_data.level,
+            };
+            send_message(server, player_id, &levelup_msg);
+        }
+    }
+
+    let first_logs = Some(AchievementId::FirstLogs).filter(|_| logs_added > 0);
+    check_achievements(player_entity, player_id, first_logs, server);
+
+    // a tree still standing is chopped again straight away, unless there was no room
+    // for what it gave
+    let standing = state
+        .entities
+        .get(&tree_entity_id)
+        .and_then(|e| e.tree.as_ref())
+        .is_some_and(|tree| !tree.is_chopped);
+    let player_entity = match state.entities.get_mut(&player_entity_id) {
+        Some(e) => e,
+        None => return,
+    };
+    let queue = &mut player_entity.action_queue;
+    let current_time = state.game_time;
+    let next_chop = queue
+        .current_action
+        .take()
+        .filter(|_| standing && logs_added > 0);
+    if let Some(chop) = next_chop {
+        debug!(
+            "Tree {} still standing, player {:?} keeps chopping",
+            tree_entity_id, player_id
+        );
+        queue.current_action = Some(ActionInProgress {
+            completion_time: current_time + (chop.completion_time - chop.started_at),
+            started_at: current_time,
+            repeat_count: chop.repeat_count + 1,
+            ..chop
+        });
+        state.dirty_entities.insert(player_entity_id);
+        return;
+    }
+
+    let completion_msg = ServerMessage::ActionCompleted {
+        entity_id: player_entity_id,
+    };
+    send_message(server, player_id, &completion_msg);
+    if !depleted {
+        return;
+    }
+
+    // anyone else mid-chop on the fallen tree has nothing left to chop
+    let other_choppers: Vec<u64> = state
+        .entities
+        .iter()
+        .filter(|(entity_id, entity)| {
+            **entity_id != player_entity_id
+                && matches!(
+                    entity.action_queue.current_action,
+                    Some(ActionInProgress {
+                        action: GameAction::ChopTree { tree_entity_id: id },
+                        ..
+                    }) if id == tree_entity_id
+                )
+        })
+        .map(|(entity_id, _)| *entity_id)
+        .collect();
+    for entity_id in other_choppers {
+        debug!(
+            "Tree {} fell, entity {} stops chopping",
+            tree_entity_id, entity_id
+        );
+        stop_gathering(state, server, entity_id);
+    }
+
+    let chopped_msg = ServerMessage::TreeChopped { tree_entity_id };
+    send_to_viewers(interest_manager, server, tree_entity_id, &chopped_msg);
+    info!("Told tree {}'s viewers it was chopped", tree_entity_id);
+}
+
+/// whether the gatherer is still next to its target, in the same zone
+fn in_reach(state: &ServerState, gatherer_entity_id: u64, target_entity_id: u64) -> bool {
+    match (
+        state.entities.get(&gatherer_entity_id),
+        state.entities.get(&target_entity_id),
+    ) {
+        (Some(gatherer), Some(target)) => {
+            gatherer.zone == target.zone
+                && gatherer.tile_pos.distance_chebyshev(&target.tile_pos) <= 1
+        }
+        _ => false,
+    }
+}
+
+/// ends a gathering action that gave nothing, the client still hears it finished
+fn stop_gathering(state: &mut ServerState, server: &mut impl MessageSink, player_entity_id: u64) {
+    let player_entity = match state.entities.get_mut(&player_entity_id) {
+        Some(e) => e,
+        None => return,
+    };
+    player_entity.action_queue.current_action = None;
+    if let Some(player_id) = player_entity.player_id {
+        let msg = ServerMessage::ActionCompleted {
+            entity_id: player_entity_id,
+        };
+        send_message(server, player_id, &msg);
+    }
+}
+
+/// unlocks what the player has newly earned: `gathered` for a first haul, and the
+/// milestone of every skill that has reached its level. each is only sent once
+fn check_achievements(
+    player_entity: &mut ServerEntity,
+    player_id: PlayerId,
+    gathered: Option<AchievementId>,
+    server: &mut impl MessageSink,
+) {
+    let milestones: Vec<AchievementId> = match player_entity.skills {
+        Some(ref skills) => skills
+            .skills
+            .iter()
+            .filter_map(|(skill, data)| AchievementId::skill_milestone(*skill, data.level))
+            .collect(),
+        None => Vec::new(),
+    };
+    let achievements = match player_entity.achievements.as_mut() {
+        Some(achievements) => achievements,
+        None => return,
+    };
+
+    for id in gathered.into_iter().chain(milestones) {
+        if achievements.unlock(id) {
+            info!("Player {:?} unlocked achievement {:?}", player_id, id);
+            let msg = ServerMessage::AchievementUnlocked {
+                id,
+                name: id.name().to_string(),
+            };
+            send_message(server, player_id, &msg);
+        }
+    }
+}
+
+pub fn handle_mining_completion(
+    player_entity_id: u64,
+    rock_entity_id: u64,
+    state: &mut ServerState,
+    interest_manager: &InterestManager,
+    server: &mut impl MessageSink,
+) {
+    let rock_def = match state
+        .entities
+        .get(&rock_entity_id)
+        .and_then(|e| e.rock.as_ref())
+    {
+        Some(rock) if rock.is_depleted => {
+            info!(
+                "Rock {} was mined out before entity {} finished, no ore given",
+                rock_entity_id, player_entity_id
+            );
+            stop_gathering(state, server, player_entity_id);
+            return;
+        }
+        Some(rock) => RockDefinition::get(rock.rock_type),
+        None => return,
+    };
+
+    if !in_reach(state, player_entity_id, rock_entity_id) {
+        warn!(
+            "Entity {} finished mining rock {} out of reach, no ore given",
+            player_entity_id, rock_entity_id
+        );
+        stop_gathering(state, server, player_entity_id);
+        return;
+    }
+
+    let player_entity = match state.entities.get_mut(&player_entity_id) {
+        Some(e) => e,
+        None => return,
+    };
+
+    let player_id = match player_entity.player_id {
+        Some(id) => id,
+        None => return,
+    };
+
+    // rich veins give better ore to higher level miners
+    let mining_level = player_entity
+        .skills
+        .as_ref()
+        .map(|skills| skills.get_level(SkillType::Mining))
+        .unwrap_or(1);
+    let (ore_given, experience) = rock_def.yield_for_level(mining_level);
+    info!(
+        "Processing mining completion: rock={:?}, level={}, xp={}, ore={:?}",
+        rock_def.rock_type, mining_level, experience, ore_given
+    );
+
+    let mut ore_added = 0;
+    if let Some(ref mut inventory) = player_entity.inventory {
+        ore_added = inventory.add_item(ore_given, 1, &state.definitions);
+        if ore_added > 0 {
+            let def = state.definitions.item(ore_given);
+            info!(
+                "Player {:?} received: {} x{} (total: {})",
+                player_id,
+                def.name,
+                ore_added,
+                inventory.count_item(ore_given)
+            );
+
+            let msg = ServerMessage::ItemAdded {
+                item_type: ore_given,
+                quantity: ore_added,
+            };
+            send_message(server, player_id, &msg);
+
+            let inv_msg = ServerMessage::InventoryUpdate {
+                inventory: inventory.clone(),
+            };
+            send_message(server, player_id, &inv_msg);
+        } else {
+            warn!(" Player {:?} inventory full! Could not add ore", player_id);
+        }
+    }
+
+    let xp_gained = experience * ore_added;
+    if let Some(skills) = player_entity.skills.as_mut().filter(|_| xp_gained > 0) {
+        let old_level = skills.get_level(SkillType::Mining);
+        let old_xp = skills.get_experience(SkillType::Mining);
+        let leveled_up = skills.add_experience(SkillType::Mining, xp_gained);
+        let new_xp = skills.get_experience(SkillType::Mining);
+
+        info!(
+            "Player {:?} gained {} Mining XP ({} -> {})",
+            player_id, xp_gained, old_xp, new_xp
+        );
+
+        let xp_msg = ServerMessage::ExperienceGained {
+            skill: SkillType::Mining,
+            amount: xp_gained,
+        };
+        send_message(server, player_id, &xp_msg);
+
+        let skill_data = &skills.skills[&SkillType::Mining];
+        let skill_msg = ServerMessage::SkillUpdate {
+            skill: SkillType::Mining,
+            level: skill_data.level,
+            experience: skill_data.experience,
+            xp_to_next: skills.xp_to_next_level(SkillType::Mining),
+        };
+        send_message(server, player_id, &skill_msg);
+
+        if leveled_up {
+            info!(
+                "LEVEL UP! Player {:?} Mining: {} -> {}",
+                player_id, old_level, skill_data.level
+            );
+            let levelup_msg = ServerMessage::LevelUp {
+                skill: SkillType::Mining,
+                new_level: skill_data.level,
+            };
+            send_message(server, player_id, &levelup_msg);
+        }
+    }
+
+    let first_ore = Some(AchievementId::FirstOre).filter(|_| ore_added > 0);
+    check_achievements(player_entity, player_id, first_ore, server);
+
+    player_entity.action_queue.current_action = None;
+
+    let completion_msg = ServerMessage::ActionCompleted {
+        entity_id: player_entity_id,
+    };
+    send_message(server, player_id, &completion_msg);
+
+    if let Some(rock) = state
+        .entities
+        .get_mut(&rock_entity_id)
+        .and_then(|e| e.rock.as_mut())
+    {
+        rock.is_depleted = true;
+        rock.respawn_timer = 0.0;
+        info!(
+            "Rock {} depleted! Will respawn in {}s",
+            rock_entity_id, rock_def.respawn_time
+        );
+        state
+            .resource_history
+            .record_depleted(rock_entity_id, state.server_tick);
+        state.event_log.record(
+            state.server_tick,
+            rock_entity_id,
+            EntityEventKind::RockDepleted,
+        );
+    }
+
+    let depleted_msg = ServerMessage::RockDepleted { rock_entity_id };
+    send_to_viewers(interest_manager, server, rock_entity_id, &depleted_msg);
+}
+
+pub fn handle_equip_completion(
+    player_entity_id: u64,
+    slot: usize,
+    state: &mut ServerState,
+    server: &mut impl MessageSink,
+) {
+    let player_entity = match state.entities.get_mut(&player_entity_id) {
+        Some(e) => e,
+        None => return,
+    };
+
+    let player_id = match player_entity.player_id {
+        Some(id) => id,
+        None => return,
+    };
+
+    let inventory = match player_entity.inventory {
+        Some(ref mut inventory) => inventory,
+        None => return,
+    };
+
+    let equip_slot = match inventory.slots.get(slot) {
+        Some(Some(stack)) => match state.definitions.item(stack.item_type).equip_slot {
+            Some(equip_slot) => equip_slot,
+            None => {
+                warn!(
+                    "Player {:?} tried to equip {:?}, which can't be equipped",
+                    player_id, stack.item_type
+                );
+                return;
+            }
+        },
+        _ => {
+            warn!(
+                "Player {:?} tried to equip empty or invalid slot {}",
+                player_id, slot
+            );
+            return;
+        }
+    };
+
+    // whatever was wielded before goes back into the slot the new item came from
+    let stack = inventory.slots[slot].take();
+    inventory.slots[slot] = player_entity.equipment.remove(&equip_slot);
+    if let Some(stack) = stack {
+        info!(
+            "Player {:?} equipped {} in {:?}",
+            player_id,
+            state.definitions.item(stack.item_type).name,
+            equip_slot
+        );
+        player_entity.equipment.insert(equip_slot, stack);
+    }
+
+    let inv_msg = ServerMessage::InventoryUpdate {
+        inventory: inventory.clone(),
+    };
+    send_message(server, player_id, &inv_msg);
+
+    let equipment_msg = ServerMessage::EquipmentUpdate {
+        equipment: player_entity.equipment.clone(),
+    };
+    send_message(server, player_id, &equipment_msg);
+}
+
+pub fn send_run_energy(server: &mut impl MessageSink, player_id: PlayerId, run: RunState) {
+    let msg = ServerMessage::RunEnergyUpdate {
+        energy: run.energy,
+        running: run.enabled,
+    };
+    send_message(server, player_id, &msg);
+}
+
+/// consumes one item from the slot and applies its `on_use` effect
+pub fn handle_use_item_completion(
+    player_entity_id: u64,
+    slot: usize,
+    state: &mut ServerState,
+    server: &mut impl MessageSink,
+) {
+    let player_entity = match state.entities.get_mut(&player_entity_id) {
+        Some(e) => e,
+        None => return,
+    };
+
+    let player_id = match player_entity.player_id {
+        Some(id) => id,
+        None => return,
+    };
+
+    let inventory = match player_entity.inventory {
+        Some(ref mut inventory) => inventory,
+        None => return,
+    };
+
+    let (item_type, effect) = match inventory.slots.get(slot) {
+        Some(Some(stack)) => match state.definitions.item(stack.item_type).on_use {
+            Some(effect) => (stack.item_type, effect),
+            None => {
+                warn!(
+                    "Player {:?} tried to use {:?}, which can't be used",
+                    player_id, stack.item_type
+                );
+                return;
+            }
+        },
+        _ => {
+            warn!(
+                "Player {:?} tried to use empty or invalid slot {}",
+                player_id, slot
+            );
+            return;
+        }
+    };
+
+    inventory.take_from_slot(slot, 1);
+    let inv_msg = ServerMessage::InventoryUpdate {
+        inventory: inventory.clone(),
+    };
+    send_message(server, player_id, &inv_msg);
+
+    let removed_msg = ServerMessage::ItemRemoved {
+        item_type,
+        quantity: 1,
+    };
+    send_message(server, player_id, &removed_msg);
+
+    match effect {
+        ItemEffect::Heal(amount) => {
+            let health = player_entity.health.unwrap_or(PLAYER_MAX_HEALTH);
+            let healed = (health + amount).min(PLAYER_MAX_HEALTH);
+            player_entity.health = Some(healed);
+            info!(
+                "Player {:?} ate {}, health {} -> {}",
+                player_id,
+                state.definitions.item(item_type).name,
+                health,
+                healed
+            );
+
+            // everyone nearby draws the health bar, not just the player who ate
+            let zone = player_entity.zone;
+            let health_msg = ServerMessage::HealthUpdate {
+                entity_id: player_entity_id,
+                health: healed,
+            };
+            broadcast_to_zone(state, server, zone, &health_msg);
+        }
+    }
+}
+
+/// takes health off an entity and holds its regeneration off for `REGEN_PAUSE_TICKS`.
+/// everyone nearby is told, like for healing. a player brought to zero dies
+pub fn damage_entity(
+    state: &mut ServerState,
+    interest_manager: &mut InterestManager,
+    server: &mut impl MessageSink,
+    commands: &mut Commands,
+    entity_id: u64,
+    amount: u32,
+) {
+    let tick = state.server_tick;
+    let entity = match state.entities.get_mut(&entity_id) {
+        Some(entity) => entity,
+        None => return,
+    };
+    let health = match entity.health {
+        Some(health) => health,
+        None => return,
+    };
+    let damaged = health.saturating_sub(amount);
+    entity.health = Some(damaged);
+    entity.last_damaged_tick = Some(tick);
+    debug!(
+        "Entity {} hit for {}, health {}",
+        entity_id, amount, damaged
+    );
+
+    let zone = entity.zone;
+    let msg = ServerMessage::HealthUpdate {
+        entity_id,
+        health: damaged,
+    };
+    broadcast_to_zone(state, server, zone, &msg);
+    if damaged == 0 {
+        handle_death(state, interest_manager, server, commands, entity_id);
+    }
+}
+
+/// tells players whose last accepted input has nothing left running that it's done
+fn report_completed_inputs(state: &mut ServerState, server: &mut impl MessageSink) {
+    for entity in state.entities.values_mut() {
+        let queue = &mut entity.action_queue;
+        let idle = queue.current_action.is_none()
+            && queue.actions.is_empty()
+            && queue.input_buffer.is_empty();
+        let (player_id, input_sequence_number) = match (entity.player_id, queue.awaiting_completion)
+        {
+            (Some(player_id), Some(input_sequence_number)) if idle => {
+                (player_id, input_sequence_number)
+            }
+            _ => continue,
+        };
+        queue.awaiting_completion = None;
+        let msg = ServerMessage::ActionResult {
+            input_sequence_number,
+            outcome: ActionOutcome::Completed,
+        };
+        send_message(server, player_id, &msg);
+    }
+}
+
+/// gives a hitpoint back every `REGEN_INTERVAL_TICKS` to anything below full health,
+/// counting from `REGEN_PAUSE_TICKS` after it was last hit. parked players don't heal
+fn regenerate_health(
+    state: &mut ServerState,
+    server: &mut impl MessageSink,
+    tick: u64,
+    parked: &HashSet<u64>,
+) {
+    let mut regenerated = Vec::new();
+    for (entity_id, entity) in state.entities.iter_mut() {
+        if parked.contains(entity_id) {
+            continue;
+        }
+        let health = match entity.health {
+            Some(health) if health < entity.kind.max_health() => health,
+            _ => continue,
+        };
+        let since_hit = tick.saturating_sub(entity.last_damaged_tick.unwrap_or(0));
+        let regen_ticks = match since_hit.checked_sub(REGEN_PAUSE_TICKS) {
+            Some(ticks) if ticks > 0 => ticks,
+            _ => continue,
+        };
+        if regen_ticks.is_multiple_of(REGEN_INTERVAL_TICKS) {
+            entity.health = Some(health + 1);
+            regenerated.push((*entity_id, entity.zone, health + 1));
+        }
+    }
+
+    for (entity_id, zone, health) in regenerated {
+        debug!("Entity {} regenerated to {}", entity_id, health);
+        let msg = ServerMessage::HealthUpdate { entity_id, health };
+        broadcast_to_zone(state, server, zone, &msg);
+    }
+}
+
+pub fn handle_unequip_completion(
+    player_entity_id: u64,
+    slot: EquipSlot,
+    state: &mut ServerState,
+    server: &mut impl MessageSink,
+) {
+    let player_entity = match state.entities.get_mut(&player_entity_id) {
+        Some(e) => e,
+        None => return,
+    };
+
+    let player_id = match player_entity.player_id {
+        Some(id) => id,
+        None => return,
+    };
+
+    let inventory = match player_entity.inventory {
+        Some(ref mut inventory) => inventory,
+        None => return,
+    };
+
+    let stack = match player_entity.equipment.get(&slot) {
+        Some(stack) => stack.clone(),
+        None => {
+            warn!("Player {:?} has nothing equipped in {:?}", player_id, slot);
+            return;
+        }
+    };
+
+    if inventory.add_item(stack.item_type, stack.quantity, &state.definitions) < stack.quantity {
+        warn!("Player {:?} inventory full, can't unequip", player_id);
+        send_message(server, player_id, &ServerMessage::InventoryFull);
+        return;
+    }
+    player_entity.equipment.remove(&slot);
+    info!(
+        "Player {:?} unequipped {} from {:?}",
+        player_id,
+        state.definitions.item(stack.item_type).name,
+        slot
+    );
+
+    let inv_msg = ServerMessage::InventoryUpdate {
+        inventory: inventory.clone(),
+    };
+    send_message(server, player_id, &inv_msg);
+
+    let equipment_msg = ServerMessage::EquipmentUpdate {
+        equipment: player_entity.equipment.clone(),
+    };
+    send_message(server, player_id, &equipment_msg);
+}
+
+/// true if `pos` in `zone` is next to (or on) a bank booth
+pub fn is_near_bank(state: &ServerState, zone: ZoneId, pos: TilePosition) -> bool {
+    state.entities.values().any(|entity| {
+        entity.bank.is_some()
+            && entity.zone == zone
+            && (entity.tile_pos.x - pos.x).abs() <= 1
+            && (entity.tile_pos.y - pos.y).abs() <= 1
+    })
+}
+
+pub fn handle_interact_completion(
+    player_entity_id: u64,
+    target_id: u64,
+    state: &mut ServerState,
+    server: &mut impl MessageSink,
+) {
+    let (player_id, zone, player_pos) = match state.entities.get(&player_entity_id) {
+        Some(ServerEntity {
+            player_id: Some(player_id),
+            zone,
+            tile_pos,
+            ..
+        }) => (*player_id, *zone, *tile_pos),
+        _ => return,
+    };
+
+    let is_bank = state
+        .entities
+        .get(&target_id)
+        .is_some_and(|e| e.bank.is_some());
+    if !is_bank {
+        debug!("Player {:?} interacted with {}", player_id, target_id);
+        return;
+    }
+
+    if !is_near_bank(state, zone, player_pos) {
+        warn!("Player {:?} is too far away to use the bank", player_id);
+        return;
+    }
+
+    if let Some(player) = state.players.get(&player_id) {
+        info!("Player {:?} opened the bank", player_id);
+        let msg = ServerMessage::BankUpdate {
+            bank: player.bank.clone(),
+        };
+        send_message(server, player_id, &msg);
+    }
+}
+
+pub fn handle_deposit_completion(
+    player_entity_id: u64,
+    slot: usize,
+    quantity: u32,
+    state: &mut ServerState,
+    server: &mut impl MessageSink,
+) {
+    let player_entity = match state.entities.get(&player_entity_id) {
+        Some(e) => e,
+        None => return,
+    };
+
+    let player_id = match player_entity.player_id {
+        Some(id) => id,
+        None => return,
+    };
+
+    if !is_near_bank(state, player_entity.zone, player_entity.tile_pos) {
+        warn!("Player {:?} tried to deposit away from a bank", player_id);
+        return;
+    }
+
+    let (player, player_entity) = match (
+        state.players.get_mut(&player_id),
+        state.entities.get_mut(&player_entity_id),
+    ) {
+        (Some(player), Some(entity)) => (player, entity),
+        _ => return,
+    };
+
+    let inventory = match player_entity.inventory {
+        Some(ref mut inventory) => inventory,
+        None => return,
+    };
+
+    // asking for more than the slot holds just deposits the whole stack
+    let (item_type, available) = match inventory.slots.get(slot) {
+        Some(Some(stack)) => (stack.item_type, stack.quantity.min(quantity)),
+        _ => {
+            warn!(
+                "Player {:?} tried to deposit empty or invalid slot {}",
+                player_id, slot
+            );
+            return;
+        }
+    };
+
+    let deposited = player
+        .bank
+        .add_item(item_type, available, &state.definitions);
+    inventory.take_from_slot(slot, deposited);
+    if deposited < available {
+        warn!("Player {:?} bank is full", player_id);
+    }
+    info!(
+        "Player {:?} deposited {} x{}",
+        player_id,
+        state.definitions.item(item_type).name,
+        deposited
+    );
+
+    let inv_msg = ServerMessage::InventoryUpdate {
+        inventory: inventory.clone(),
+    };
+    send_message(server, player_id, &inv_msg);
+
+    let bank_msg = ServerMessage::BankUpdate {
+        bank: player.bank.clone(),
+    };
+    send_message(server, player_id, &bank_msg);
+}
+
+pub fn handle_withdraw_completion(
+    player_entity_id: u64,
+    item_type: ItemType,
+    quantity: u32,
+    state: &mut ServerState,
+    server: &mut impl MessageSink,
+) {
+    let player_entity = match state.entities.get(&player_entity_id) {
+        Some(e) => e,
+        None => return,
+    };
+
+    let player_id = match player_entity.player_id {
+        Some(id) => id,
+        None => return,
+    };
+
+    if !is_near_bank(state, player_entity.zone, player_entity.tile_pos) {
+        warn!("Player {:?} tried to withdraw away from a bank", player_id);
+        return;
+    }
+
+    let (player, player_entity) = match (
+        state.players.get_mut(&player_id),
+        state.entities.get_mut(&player_entity_id),
+    ) {
+        (Some(player), Some(entity)) => (player, entity),
+        _ => return,
+    };
+
+    let inventory = match player_entity.inventory {
+        Some(ref mut inventory) => inventory,
+        None => return,
+    };
+
+    let requested = quantity.min(player.bank.count_item(item_type));
+    if requested == 0 {
+        warn!(
+            "Player {:?} tried to withdraw {:?} they don't have banked",
+            player_id, item_type
+        );
+        return;
+    }
+
+    let withdrawn = inventory.add_item(item_type, requested, &state.definitions);
+    player.bank.take_item(item_type, withdrawn);
+    info!(
+        "Player {:?} withdrew {} x{}",
+        player_id,
+        state.definitions.item(item_type).name,
+        withdrawn
+    );
+
+    if withdrawn < requested {
+        let msg = ServerMessage::WithdrawPartial {
+            item_type,
+            withdrawn,
+            remainder: requested - withdrawn,
+        };
+        send_message(server, player_id, &msg);
+    }
+
+    let inv_msg = ServerMessage::InventoryUpdate {
+        inventory: inventory.clone(),
+    };
+    send_message(server, player_id, &inv_msg);
+
+    let bank_msg = ServerMessage::BankUpdate {
+        bank: player.bank.clone(),
+    };
+    send_message(server, player_id, &bank_msg);
+}
+
+pub fn handle_drop_completion(
+    player_entity_id: u64,
+    slot: usize,
+    state: &mut ServerState,
+    interest_manager: &InterestManager,
+    server: &mut impl MessageSink,
+    commands: &mut Commands,
+) {
+    let player_entity = match state.entities.get_mut(&player_entity_id) {
+        Some(e) => e,
+        None => return,
+    };
+
+    let player_id = match player_entity.player_id {
+        Some(id) => id,
+        None => return,
+    };
+
+    let stack = match player_entity.inventory {
+        Some(ref mut inventory) => match inventory.take_slot(slot) {
+            Some(stack) => {
+                let inv_msg = ServerMessage::InventoryUpdate {
+                    inventory: inventory.clone(),
+                };
+                send_message(server, player_id, &inv_msg);
+                stack
+            }
+            None => {
+                warn!(
+                    "Player {:?} tried to drop empty or invalid slot {}",
+                    player_id, slot
+                );
+                return;
+            }
+        },
+        None => return,
+    };
+
+    let drop_pos = player_entity.tile_pos;
+    let zone = player_entity.zone;
+    info!(
+        "Player {:?} dropped {} x{} at {:?}",
+        player_id,
+        state.definitions.item(stack.item_type).name,
+        stack.quantity,
+        drop_pos
+    );
+
+    let removed_msg = ServerMessage::ItemRemoved {
+        item_type: stack.item_type,
+        quantity: stack.quantity,
+    };
+    send_message(server, player_id, &removed_msg);
+
+    drop_ground_item(
+        zone,
+        drop_pos,
+        stack,
+        state,
+        interest_manager,
+        server,
+        commands,
+    );
+}
+
+/// puts a stack on the ground, merging into a matching stackable pile on the same tile
+pub fn drop_ground_item(
+    zone: ZoneId,
+    pos: TilePosition,
+    stack: ItemStack,
+    state: &mut ServerState,
+    interest_manager: &InterestManager,
+    server: &mut impl MessageSink,
+    commands: &mut Commands,
+) -> u64 {
+    let despawn_tick = state.server_tick + state.ground_item_despawn_ticks;
+
+    if state.definitions.item(stack.item_type).stackable {
+        let existing = state.entities.iter_mut().find(|(_, entity)| {
+            entity.zone == zone
+                && entity.tile_pos == pos
+                && entity
+                    .ground_item
+                    .as_ref()
+                    .is_some_and(|item| item.item_type == stack.item_type)
+        });
+
+        if let Some((entity_id, entity)) = existing {
+            if let Some(ref mut item) = entity.ground_item {
+                item.quantity += stack.quantity;
+                entity.despawn_tick = Some(despawn_tick);
+
+                let msg = ServerMessage::GroundItemChanged {
+                    entity_id: *entity_id,
+                    item: item.clone(),
+                };
+                send_to_viewers(interest_manager, server, *entity_id, &msg);
+            }
+            return *entity_id;
+        }
+    }
+
+    let entity_id = state.next_entity_id;
+    state.next_entity_id += 1;
+
+    let entity = commands
+        .spawn((pos, Transform::from_translation(pos.to_world().extend(0.0))))
+        .id();
+
+    let server_entity = ServerEntity {
+        kind: EntityKind::GroundItem,
+        tile_pos: pos,
+        zone,
+        player_id: None,
+        action_queue: ActionQueue::default(),
+        entity,
+        is_obstacle: false,
+        inventory: None,
+        skills: None,
+        achievements: None,
+        equipment: Equipment::new(),
+        tree: None,
+        rock: None,
+        bank: None,
+        ground_item: Some(stack),
+        despawn_tick: Some(despawn_tick),
+        last_processed_input: None,
+        health: None,
+        last_damaged_tick: None,
+        run: None,
+        npc: None,
+    };
+    state.entities.insert(entity_id, server_entity);
+    state
+        .event_log
+        .record(state.server_tick, entity_id, EntityEventKind::Spawned);
+    entity_id
+}
+
+pub fn handle_pickup_completion(
+    player_entity_id: u64,
+    item_entity_id: u64,
+    state: &mut ServerState,
+    interest_manager: &mut InterestManager,
+    server: &mut impl MessageSink,
+    commands: &mut Commands,
+) {
+    let (item_zone, item_pos, stack) = match state.entities.get(&item_entity_id) {
+        Some(ServerEntity {
+            zone,
+            tile_pos,
+            ground_item: Some(stack),
+            ..
+        }) => (*zone, *tile_pos, stack.clone()),
+        _ => {
+            debug!("Ground item {} is gone, nothing to pick up", item_entity_id);
+            return;
+        }
+    };
+
+    let player_entity = match state.entities.get_mut(&player_entity_id) {
+        Some(e) => e,
+        None => return,
+    };
+
+    let player_id = match player_entity.player_id {
+        Some(id) => id,
+        None => return,
+    };
+
+    let dx = (player_entity.tile_pos.x - item_pos.x).abs();
+    let dy = (player_entity.tile_pos.y - item_pos.y).abs();
+    if player_entity.zone != item_zone || dx > 1 || dy > 1 {
+        warn!(
+            "Player {:?} too far from ground item {} to pick it up",
+            player_id, item_entity_id
+        );
+        return;
+    }
+
+    let mut picked_up = 0;
+    if let Some(ref mut inventory) = player_entity.inventory {
+        picked_up = inventory.add_item(stack.item_type, stack.quantity, &state.definitions);
+        if picked_up == 0 {
+            warn!("Player {:?} inventory full, can't pick up", player_id);
+            send_message(server, player_id, &ServerMessage::InventoryFull);
+            return;
+        }
+
+        let msg = ServerMessage::ItemAdded {
+            item_type: stack.item_type,
+            quantity: picked_up,
+        };
+        send_message(server, player_id, &msg);
+
+        let inv_msg = ServerMessage::InventoryUpdate {
+            inventory: inventory.clone(),
+        };
+        send_message(server, player_id, &inv_msg);
+    }
+
+    info!(
+        "Player {:?} picked up {} x{}",
+        player_id,
+        state.definitions.item(stack.item_type).name,
+        picked_up
+    );
+
+    if picked_up < stack.quantity {
+        // only part of the pile fit, leave the rest on the ground
+        if let Some(ref mut item) = state
+            .entities
+            .get_mut(&item_entity_id)
+            .and_then(|e| e.ground_item.as_mut())
+        {
+            item.quantity -= picked_up;
+            let msg = ServerMessage::GroundItemChanged {
+                entity_id: item_entity_id,
+                item: item.clone(),
+            };
+            send_to_viewers(interest_manager, server, item_entity_id, &msg);
+        }
+        send_message(server, player_id, &ServerMessage::InventoryFull);
+    } else {
+        despawn_entity(item_entity_id, state, interest_manager, server, commands);
+    }
+}
+
+/// removes a non-player entity from the world and tells clients it is gone
+pub fn despawn_entity(
+    entity_id: u64,
+    state: &mut ServerState,
+    interest_manager: &mut InterestManager,
+    server: &mut impl MessageSink,
+    commands: &mut Commands,
+) {
+    if let Some(entity) = state.entities.remove(&entity_id) {
+        commands.entity(entity.entity).despawn();
+        state.last_states.remove(&entity_id);
+        interest_manager.forget_entity(entity_id);
+        state
+            .event_log
+            .record(state.server_tick, entity_id, EntityEventKind::Despawned);
+
+        let msg = ServerMessage::EntitiesLeft {
+            entity_ids: vec![entity_id],
+        };
+        broadcast_message(server, &msg);
+    }
+}
+
+/// everything a client needs to show an entity it has just been told about
+pub fn entity_snapshot(state: &ServerState, entity_id: u64, e: &ServerEntity) -> EntitySnapshot {
+    EntitySnapshot {
+        entity_id,
+        kind: e.kind,
+        tile_position: e.tile_pos,
+        player_id: e.player_id,
+        tree: e.tree.clone(),
+        rock: e.rock.clone(),
+        bank: e.bank.clone(),
+        ground_item: e.ground_item.clone(),
+        last_processed_input: e.last_processed_input,
+        health: e.health,
+        current_action: e
+            .action_queue
+            .current_action
+            .as_ref()
+            .map(|current| current.action.kind()),
+        player_name: e
+            .player_id
+            .and_then(|id| state.players.get(&id))
+            .map(|player| player.name.clone()),
+    }
+}
+
+/// where a client's view is centred: their entity's tile, or the camera of a spectator
+pub fn viewer_position(state: &ServerState, player_id: PlayerId) -> Option<(TilePosition, ZoneId)> {
+    if let Some(spectator) = state.spectators.get(&player_id) {
+        return Some((spectator.center, spectator.zone));
+    }
+    let entity_id = state.players.get(&player_id)?.entity_id;
+    state
+        .entities
+        .get(&entity_id)
+        .map(|entity| (entity.tile_pos, entity.zone))
+}
+
+pub fn update_interest_for_player(
+    player_id: PlayerId,
+    state: &ServerState,
+    interest_manager: &mut InterestManager,
+    server: &mut impl MessageSink,
+) {
+    let (player_pos, zone) = match viewer_position(state, player_id) {
+        Some(viewer) => viewer,
+        None => return,
+    };
+
+    let entity_positions: HashMap<u64, TilePosition> = state
+        .entities
+        .iter()
+        .filter(|(_, e)| e.zone == zone)
+        .map(|(id, e)| (*id, e.tile_pos))
+        .collect();
+
+    let (entered, left) = interest_manager.update_view(player_id, player_pos, &entity_positions);
+
+    if !entered.is_empty() {
+        let snapshots: Vec<EntitySnapshot> = entered
+            .iter()
+            .filter_map(|id| {
+                state
+                    .entities
+                    .get(id)
+                    .map(|e| entity_snapshot(state, *id, e))
+            })
+            .collect();
+
+        let msg = ServerMessage::EntitiesEntered {
+            entities: snapshots,
+        };
+        send_message(server, player_id, &msg);
+    }
+
+    if !left.is_empty() {
+        let msg = ServerMessage::EntitiesLeft { entity_ids: left };
+        send_message(server, player_id, &msg);
+    }
+}
+
+/// sends each player the deltas for the entities they can see, returns how many went out
+pub fn send_delta_updates(
+    state: &mut ServerState,
+    interest_manager: &mut InterestManager,
+    server: &mut impl MessageSink,
+    tick: u64,
+) -> usize {
+    let mut client_deltas: HashMap<PlayerId, Vec<EntityDelta>> = HashMap::new();
+
+    // anything that hasn't moved or started an action has nothing to report, so a
+    // world full of stationary scenery costs nothing here
+    let dirty = std::mem::take(&mut state.dirty_entities);
+    for entity_id in &dirty {
+        let entity = match state.entities.get(entity_id) {
+            Some(entity) => entity,
+            None => continue,
+        };
+        let last_state = state
+            .last_states
+            .entry(*entity_id)
+            .or_insert(EntityLastState {
+                tile_pos: entity.tile_pos,
+                last_sent_tick: 0,
+                action_started_at: None,
+                keyframe_pos: entity.tile_pos,
+                last_keyframe_tick: tick,
+            });
+
+        let changed = last_state.tile_pos != entity.tile_pos || last_state.last_sent_tick == 0;
+        let current_action = entity.action_queue.current_action.as_ref();
+        let started_at = current_action.map(|current| current.started_at);
+        let action_started = started_at.is_some() && started_at != last_state.action_started_at;
+        last_state.action_started_at = started_at;
+        if !changed && !action_started {
+            continue;
+        }
+
+        let viewers: Vec<PlayerId> = interest_manager
+            .client_views
+            .iter()
+            .filter(|(_, view)| view.contains(entity_id))
+            .map(|(player_id, _)| *player_id)
+            .collect();
+
+        if let Some(current) = current_action.filter(|_| action_started) {
+            for player_id in &viewers {
+                client_deltas
+                    .entry(*player_id)
+                    .or_default()
+                    .push(EntityDelta {
+                        entity_id: *entity_id,
+                        delta_type: DeltaType::ActionStarted {
+                            action: current.action.kind(),
+                        },
+                    });
+            }
+        }
+
+        if changed {
+            let is_first_send = last_state.last_sent_tick == 0;
+            let velocity = if is_first_send {
+                None
+            } else {
+                step_between(last_state.tile_pos, entity.tile_pos)
+            };
+            for player_id in viewers {
+                // the first delta a player gets for an entity is always FullState,
+                // even if the entity has been moving since before they could see it
+                let first_for_player = interest_manager.mark_full_state_sent(player_id, *entity_id);
+
+                let delta = EntityDelta {
+                    entity_id: *entity_id,
+                    delta_type: if is_first_send || first_for_player {
+                        DeltaType::FullState {
+                            tile_pos: entity.tile_pos,
+                            player_id: entity.player_id,
+                            last_processed_input: entity.last_processed_input,
+                            velocity,
                         }
-                        QueueResult::QueueFull => {
-                            warn!("  → Queue full (max 1 queued), action rejected");
-                            return;
+                    } else {
+                        DeltaType::PositionOnly {
+                            tile_pos: entity.tile_pos,
+                            last_processed_input: entity.last_processed_input,
+                            velocity,
                         }
-                    }
+                    },
+                };
+
+                client_deltas
+                    .entry(player_id)
+                    .or_insert_with(Vec::new)
+                    .push(delta);
+            }
+
+            last_state.tile_pos = entity.tile_pos;
+            last_state.last_sent_tick = tick;
+        }
+    }
+
+    let mut deltas_sent = 0;
+    for (player_id, deltas) in client_deltas {
+        if !deltas.is_empty() {
+            deltas_sent += deltas.len();
+            debug!("Sending {} deltas to player {:?}", deltas.len(), player_id);
+            // positions go out relative to the player's own tile
+            let center = viewer_position(state, player_id)
+                .map(|(pos, _)| pos)
+                .unwrap_or_default();
+            let msg_bytes = encode_delta_update(tick, center, &deltas);
+            server.send_message(
+                ClientId::from_raw(player_id.0),
+                DefaultChannel::Unreliable,
+                msg_bytes,
+            );
+        }
+    }
+    deltas_sent
+}
+
+/// start time of the entity's current action, which changes whenever a new one starts
+fn action_started_at(entity: &ServerEntity) -> Option<f64> {
+    entity
+        .action_queue
+        .current_action
+        .as_ref()
+        .map(|current| current.started_at)
+}
+
+/// resends, reliably, the position of every entity that moved since its last keyframe
+/// once that keyframe is `KEYFRAME_INTERVAL_TICKS` old. deltas are unreliable, so
+/// without this a lost delta leaves a stopped entity in the wrong place for good
+pub fn send_position_keyframes(
+    state: &mut ServerState,
+    interest_manager: &InterestManager,
+    server: &mut impl MessageSink,
+    tick: u64,
+) {
+    let mut keyframes: HashMap<PlayerId, Vec<(u64, TilePosition)>> = HashMap::new();
+    for (entity_id, last_state) in state.last_states.iter_mut() {
+        if tick < last_state.last_keyframe_tick + KEYFRAME_INTERVAL_TICKS {
+            continue;
+        }
+        let tile_pos = match state.entities.get(entity_id) {
+            Some(entity) => entity.tile_pos,
+            None => continue,
+        };
+        if tile_pos == last_state.keyframe_pos {
+            continue;
+        }
+        last_state.keyframe_pos = tile_pos;
+        last_state.last_keyframe_tick = tick;
+
+        for (player_id, view) in &interest_manager.client_views {
+            if view.contains(entity_id) {
+                keyframes
+                    .entry(*player_id)
+                    .or_default()
+                    .push((*entity_id, tile_pos));
+            }
+        }
+    }
+
+    for (player_id, positions) in keyframes {
+        let msg = ServerMessage::PositionKeyframe { tick, positions };
+        send_message(server, player_id, &msg);
+    }
+}
+
+/// the step from `from` to `to`, or `None` if it is longer than one tick of running,
+/// which means the entity got there some other way than walking
+fn step_between(from: TilePosition, to: TilePosition) -> Option<(i32, i32)> {
+    let (dx, dy) = (to.x - from.x, to.y - from.y);
+    let reach = RUN_STEPS as i32;
+    if dx.abs() <= reach && dy.abs() <= reach && (dx, dy) != (0, 0) {
+        Some((dx, dy))
+    } else {
+        None
+    }
+}
+
+/// the message's bytes, or `None` if it can't be serialized. the message is logged
+/// and skipped so one bad message can't take the server loop down
+fn encode(msg: &ServerMessage, msg_type: &str) -> Option<Vec<u8>> {
+    match bincode::serialize(msg) {
+        Ok(bytes) => Some(bytes),
+        Err(err) => {
+            warn!("Failed to serialize {}, not sending it: {}", msg_type, err);
+            None
+        }
+    }
+}
+
+pub fn send_message(server: &mut impl MessageSink, player_id: PlayerId, msg: &ServerMessage) {
+    let msg_type = match msg {
+        ServerMessage::Welcome { .. } => "Welcome",
+        ServerMessage::SpectatorWelcome { .. } => "SpectatorWelcome",
+        ServerMessage::DeltaUpdate { .. } => "DeltaUpdate",
+        ServerMessage::PositionKeyframe { .. } => "PositionKeyframe",
+        ServerMessage::WorldSnapshot { .. } => "WorldSnapshot",
+        ServerMessage::Kicked { .. } => "Kicked",
+        ServerMessage::ServerShutdown { .. } => "ServerShutdown",
+        ServerMessage::EntitiesEntered { .. } => "EntitiesEntered",
+        ServerMessage::EntitiesLeft { .. } => "EntitiesLeft",
+        ServerMessage::ActionResult { .. } => "ActionResult",
+        ServerMessage::ActionCompleted { .. } => "ActionCompleted",
+        ServerMessage::PathFound { .. } => "PathFound",
+        ServerMessage::PathNotFound { .. } => "PathNotFound",
+        ServerMessage::TileMapData { .. } => "TileMapData",
+        ServerMessage::ZoneChanged { .. } => "ZoneChanged",
+        ServerMessage::ObstacleData { .. } => "ObstacleData",
+        ServerMessage::InventoryUpdate { .. } => "InventoryUpdate",
+        ServerMessage::EquipmentUpdate { .. } => "EquipmentUpdate",
+        ServerMessage::BankUpdate { .. } => "BankUpdate",
+        ServerMessage::WithdrawPartial { .. } => "WithdrawPartial",
+        ServerMessage::ItemAdded { .. } => "ItemAdded",
+        ServerMessage::ItemRemoved { .. } => "ItemRemoved",
+        ServerMessage::SkillUpdate { .. } => "SkillUpdate",
+        ServerMessage::LevelUp { .. } => "LevelUp",
+        ServerMessage::ExperienceGained { .. } => "ExperienceGained",
+        ServerMessage::TreeChopped { .. } => "TreeChopped",
+        ServerMessage::TreeRespawned { .. } => "TreeRespawned",
+        ServerMessage::RockDepleted { .. } => "RockDepleted",
+        ServerMessage::RockRespawned { .. } => "RockRespawned",
+        ServerMessage::ObstacleAdded { .. } => "ObstacleAdded",
+        ServerMessage::ObstacleRemoved { .. } => "ObstacleRemoved",
+        ServerMessage::ActionRejected { .. } => "ActionRejected",
+        ServerMessage::PathBlocked { .. } => "PathBlocked",
+        ServerMessage::Resync { .. } => "Resync",
+        ServerMessage::TickRateChanged { .. } => "TickRateChanged",
+        ServerMessage::GameConfig { .. } => "GameConfig",
+        ServerMessage::GroundItemChanged { .. } => "GroundItemChanged",
+        ServerMessage::HealthUpdate { .. } => "HealthUpdate",
+        ServerMessage::RunEnergyUpdate { .. } => "RunEnergyUpdate",
+        ServerMessage::InventoryFull => "InventoryFull",
+        ServerMessage::ExamineResult { .. } => "ExamineResult",
+        ServerMessage::Definitions { .. } => "Definitions",
+        ServerMessage::AchievementUnlocked { .. } => "AchievementUnlocked",
+    };
+
+    let msg_bytes = match encode(msg, msg_type) {
+        Some(bytes) => bytes,
+        None => return,
+    };
+    debug!(
+        "Sending {} to player {:?} ({} bytes)",
+        msg_type,
+        player_id,
+        msg_bytes.len()
+    );
+    server.send_message(
+        ClientId::from_raw(player_id.0),
+        DefaultChannel::ReliableOrdered,
+        msg_bytes,
+    );
+}
+
+pub fn broadcast_message(server: &mut impl MessageSink, msg: &ServerMessage) {
+    let msg_type = match msg {
+        ServerMessage::TreeChopped { .. } => "TreeChopped",
+        ServerMessage::TreeRespawned { .. } => "TreeRespawned",
+        ServerMessage::RockDepleted { .. } => "RockDepleted",
+        ServerMessage::RockRespawned { .. } => "RockRespawned",
+        ServerMessage::ObstacleAdded { .. } => "ObstacleAdded",
+        ServerMessage::ObstacleRemoved { .. } => "ObstacleRemoved",
+        ServerMessage::EntitiesLeft { .. } => "EntitiesLeft",
+        ServerMessage::TickRateChanged { .. } => "TickRateChanged",
+        ServerMessage::GroundItemChanged { .. } => "GroundItemChanged",
+        ServerMessage::HealthUpdate { .. } => "HealthUpdate",
+        ServerMessage::RunEnergyUpdate { .. } => "RunEnergyUpdate",
+        ServerMessage::ServerShutdown { .. } => "ServerShutdown",
+        _ => "Unknown",
+    };
+
+    let msg_bytes = match encode(msg, msg_type) {
+        Some(bytes) => bytes,
+        None => return,
+    };
+    debug!(
+        "Broadcasting {} to all players ({} bytes)",
+        msg_type,
+        msg_bytes.len()
+    );
+    server.broadcast_message(DefaultChannel::ReliableOrdered, msg_bytes);
+}
+
+/// sends to every connected player whose entity is in `zone`
+pub fn broadcast_to_zone(
+    state: &ServerState,
+    server: &mut impl MessageSink,
+    zone: ZoneId,
+    msg: &ServerMessage,
+) {
+    for (player_id, player) in &state.players {
+        let in_zone = state
+            .entities
+            .get(&player.entity_id)
+            .is_some_and(|entity| entity.zone == zone);
+        if player.pending_removal.is_none() && in_zone {
+            send_message(server, *player_id, msg);
+        }
+    }
+}
+
+/// sends `msg` to every player who can see `entity_id`. anyone else gets the entity's
+/// current state when it comes into view
+pub fn send_to_viewers(
+    interest_manager: &InterestManager,
+    server: &mut impl MessageSink,
+    entity_id: u64,
+    msg: &ServerMessage,
+) {
+    for (player_id, view) in &interest_manager.client_views {
+        if view.contains(&entity_id) {
+            send_message(server, *player_id, msg);
+        }
+    }
+}
+
+/// tells the client why it is being dropped, then disconnects it `KICK_DELAY_TICKS` later
+pub fn kick(
+    state: &mut ServerState,
+    server: &mut impl MessageSink,
+    player_id: PlayerId,
+    reason: &str,
+) {
+    warn!("Kicking {:?}: {}", player_id, reason);
+    let msg = ServerMessage::Kicked {
+        reason: reason.to_string(),
+    };
+    send_message(server, player_id, &msg);
+    state
+        .kicked
+        .entry(player_id)
+        .or_insert(state.server_tick + KICK_DELAY_TICKS);
+}
+
+/// reads the account id each newly connected client put in its connect token
+pub fn record_client_accounts(
+    netcode: Res<NetcodeServerTransport>,
+    server: Res<RenetServer>,
+    mut state: ResMut<ServerState>,
+) {
+    for client_id in server.clients_id() {
+        let player_id = PlayerId(client_id.raw());
+        if state.client_accounts.contains_key(&player_id) {
+            continue;
+        }
+        if let Some(account_id) = netcode
+            .user_data(client_id)
+            .and_then(|data| account_from_user_data(&data))
+        {
+            debug!(
+                "Client {:?} connected as account {:?}",
+                player_id, account_id
+            );
+            state.client_accounts.insert(player_id, account_id);
+        }
+    }
+}
+
+pub fn handle_disconnections(
+    server: &mut impl ServerTransport,
+    state: &mut ServerState,
+    interest_manager: &mut InterestManager,
+) {
+    let tick = state.server_tick;
+    let due: Vec<PlayerId> = state
+        .kicked
+        .iter()
+        .filter(|(_, disconnect_tick)| tick >= **disconnect_tick)
+        .map(|(player_id, _)| *player_id)
+        .collect();
+    for player_id in due {
+        state.kicked.remove(&player_id);
+        server.disconnect(ClientId::from_raw(player_id.0));
+    }
+
+    let connected_clients: HashSet<u64> =
+        server.clients_id().into_iter().map(|id| id.raw()).collect();
+
+    let removal_tick = state.server_tick + state.reconnect_grace_ticks;
+    for (player_id, player) in state.players.iter_mut() {
+        if player.pending_removal.is_some() || connected_clients.contains(&player_id.0) {
+            continue;
+        }
+        info!(
+            "Player {:?} disconnected, keeping entity {} until tick {}",
+            player_id, player.entity_id, removal_tick
+        );
+        player.pending_removal = Some(removal_tick);
+        interest_manager.remove_client(*player_id);
+    }
+    state.spectators.retain(|player_id, _| {
+        let connected = connected_clients.contains(&player_id.0);
+        if !connected {
+            info!("Spectator {:?} disconnected", player_id);
+            interest_manager.remove_client(*player_id);
+        }
+        connected
+    });
+    state
+        .undecodable_messages
+        .retain(|player_id, _| connected_clients.contains(&player_id.0));
+    state
+        .client_accounts
+        .retain(|player_id, _| connected_clients.contains(&player_id.0));
+    state
+        .kicked
+        .retain(|player_id, _| connected_clients.contains(&player_id.0));
+}
 
-                    let msg = ServerMessage::ActionQueued { action };
-                    send_message(server, player_id, &msg);
-                }
-            }
+/// removes players whose reconnect grace period ran out
+fn remove_expired_players(
+    server: &mut impl MessageSink,
+    state: &mut ServerState,
+    interest_manager: &mut InterestManager,
+    commands: &mut Commands,
+) {
+    let expired: Vec<PlayerId> = state
+        .players
+        .iter()
+        .filter(|(_, player)| {
+            player
+                .pending_removal
+                .is_some_and(|t| state.server_tick >= t)
+        })
+        .map(|(player_id, _)| *player_id)
+        .collect();
+
+    for player_id in expired {
+        info!("Player {:?} didn't reconnect in time, removing", player_id);
+        remove_player(server, state, interest_manager, commands, player_id);
+    }
+}
+
+/// drops players who haven't sent anything for `idle_timeout_ticks`, even if the
+/// transport still thinks they're connected
+pub fn disconnect_idle_players(
+    server: &mut impl ServerTransport,
+    state: &mut ServerState,
+    interest_manager: &mut InterestManager,
+    commands: &mut Commands,
+) {
+    let idle_players: Vec<PlayerId> = state
+        .players
+        .iter()
+        .filter(|(_, player)| {
+            player.pending_removal.is_none()
+                && state.server_tick.saturating_sub(player.last_message_tick)
+                    >= state.idle_timeout_ticks
+        })
+        .map(|(player_id, _)| *player_id)
+        .collect();
+
+    for player_id in idle_players {
+        info!(
+            "Player {:?} idle for {} ticks, disconnecting",
+            player_id, state.idle_timeout_ticks
+        );
+        server.disconnect(ClientId::from_raw(player_id.0));
+        remove_player(server, state, interest_manager, commands, player_id);
+    }
+}
+
+/// saves the player's profile, despawns their entity and tells everyone they left
+fn remove_player(
+    server: &mut impl MessageSink,
+    state: &mut ServerState,
+    interest_manager: &mut InterestManager,
+    commands: &mut Commands,
+    player_id: PlayerId,
+) {
+    let player = match state.players.remove(&player_id) {
+        Some(player) => player,
+        None => return,
+    };
+
+    if let Some(entity_data) = state.entities.remove(&player.entity_id) {
+        state.event_log.record(
+            state.server_tick,
+            player.entity_id,
+            EntityEventKind::Despawned,
+        );
+        if let (Some(inventory), Some(skills)) = (entity_data.inventory, entity_data.skills) {
+            let profile = PlayerProfile {
+                inventory,
+                skills,
+                equipment: entity_data.equipment,
+                bank: player.bank.clone(),
+                achievements: entity_data.achievements.unwrap_or_default(),
+            };
+            state.profiles.save(&player.account_id, profile);
+            info!("Saved profile for account '{}'", player.account_id);
         }
-        ClientMessage::QueueActions {
-            actions,
-            input_sequence_number,
-        } => {
-            if let Some(player) = state.players.get(&player_id) {
-                info!(
-                    "Player {:?} '{}' queuing {} actions (input #{})",
-                    player_id,
-                    player.name,
-                    actions.len(),
-                    input_sequence_number
-                );
+        commands.entity(entity_data.entity).despawn();
+    }
+    interest_manager.remove_client(player_id);
+    // everyone hears they left below, so nobody's view should report it again
+    interest_manager.forget_entity(player.entity_id);
+    state.last_states.remove(&player.entity_id);
 
-                let mut all_valid = true;
-                for action in &actions {
-                    if let GameAction::ChopTree { tree_entity_id } = action {
-                        let validation_result = {
-                            let player_entity = state.entities.get(&player.entity_id);
-                            let tree_entity = state.entities.get(tree_entity_id);
-
-                            match (player_entity, tree_entity) {
-                                (Some(p_entity), Some(t_entity)) => validate_woodcutting_action(
-                                    p_entity, t_entity, server, player_id,
-                                ),
-                                _ => {
-                                    warn!("Invalid woodcutting: entity not found (player={}, tree={})",
-                                        player.entity_id, tree_entity_id);
-                                    false
-                                }
-                            }
-                        };
+    let msg = ServerMessage::EntitiesLeft {
+        entity_ids: vec![player.entity_id],
+    };
+    broadcast_message(server, &msg);
+}
 
-                        if !validation_result {
-                            all_valid = false;
-                            break;
-                        }
-                    }
-                }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_log::EntityEvent;
+    use crate::interest_manager::{DistanceMetric, DEFAULT_LEAVE_MARGIN};
+    use crate::lag_compensation::MAX_REWIND_TICKS;
+    use bevy::ecs::world::CommandQueue;
+    use shared::achievements::SKILL_MILESTONE_LEVEL;
+    use shared::actions::GameActionKind;
+    use shared::tile_map::TileType;
+    use shared::trees::TreeDefinition;
 
-                if all_valid && !actions.is_empty() {
-                    if let Some(entity) = state.entities.get_mut(&player.entity_id) {
-                        let first_action = actions[0].clone();
-                        let result = queue_action_with_priority(
-                            &mut entity.action_queue,
-                            &mut entity.tile_pos,
-                            first_action.clone(),
-                            current_time,
-                        );
-                        info!(
-                            "  First action ({:?}): {:?}",
-                            first_action.priority(),
-                            result
-                        );
+    fn tile(x: i32, y: i32) -> TilePosition {
+        TilePosition { x, y }
+    }
 
-                        for action in &actions[1..] {
-                            if entity.action_queue.actions.len() < 1 {
-                                entity.action_queue.actions.push_back(action.clone());
-                                info!("  Queued: {:?}", action);
-                            } else {
-                                warn!("  Queue full, couldn't add: {:?}", action);
-                            }
-                        }
+    /// keeps everything the server sends, decoded again
+    #[derive(Default)]
+    struct RecordingSink {
+        sent: Vec<(ClientId, ServerMessage)>,
+        broadcast: Vec<ServerMessage>,
+    }
 
-                        entity.last_processed_input = Some(input_sequence_number);
-                        info!(
-                            "Action chain processed for player {:?}. Queue size: {}",
-                            player_id,
-                            entity.action_queue.actions.len()
-                        );
-                    }
-                }
+    impl MessageSink for RecordingSink {
+        fn send_message(&mut self, client_id: ClientId, channel: DefaultChannel, message: Vec<u8>) {
+            // deltas go out unreliably, in their own compact encoding
+            let msg = match channel {
+                DefaultChannel::Unreliable => shared::netcode::decode_delta_update(&message)
+                    .ok()
+                    .map(|(tick, deltas)| ServerMessage::DeltaUpdate { tick, deltas }),
+                _ => bincode::deserialize(&message).ok(),
+            };
+            if let Some(msg) = msg {
+                self.sent.push((client_id, msg));
             }
         }
-        ClientMessage::CancelAction => {
-            if let Some(player) = state.players.get(&player_id) {
-                if let Some(entity) = state.entities.get_mut(&player.entity_id) {
-                    let queue_size = entity.action_queue.actions.len();
-                    let has_current = entity.action_queue.current_action.is_some();
-                    entity.action_queue.current_action = None;
-                    entity.action_queue.actions.clear();
-                    info!(
-                        "Player {:?} '{}' cancelled action. Cleared {} queued actions{}",
-                        player_id,
-                        player.name,
-                        queue_size,
-                        if has_current {
-                            " and current action"
-                        } else {
-                            ""
-                        }
-                    );
-                }
+
+        fn broadcast_message(&mut self, _: DefaultChannel, message: Vec<u8>) {
+            if let Ok(msg) = bincode::deserialize(&message) {
+                self.broadcast.push(msg);
             }
         }
-        ClientMessage::RequestPath { start, goal } => {
-            info!(
-                "Player {:?} requesting path from {:?} to {:?}",
-                player_id, start, goal
-            );
+    }
 
-            if let Some(path) = state.pathfinder.find_path_a_star(start, goal) {
-                info!("Path found: {} tiles", path.len());
-                let msg = ServerMessage::PathFound { path: path.clone() };
-                send_message(server, player_id, &msg);
+    /// hands the server whatever each client has queued, the way a socket would, and
+    /// keeps anything sent back
+    #[derive(Resource, Default)]
+    struct InboxTransport {
+        inbox: HashMap<ClientId, VecDeque<Vec<u8>>>,
+        outbox: RecordingSink,
+    }
 
-                if let Some(player) = state.players.get(&player_id) {
-                    if let Some(entity) = state.entities.get_mut(&player.entity_id) {
-                        let move_action = GameAction::Move { path };
-                        entity.action_queue.actions.push_back(move_action);
-                    }
-                }
-            } else {
-                warn!("No path found from {:?} to {:?}", start, goal);
-                let msg = ServerMessage::PathNotFound;
-                send_message(server, player_id, &msg);
-            }
+    impl MessageSink for InboxTransport {
+        fn send_message(&mut self, client_id: ClientId, channel: DefaultChannel, message: Vec<u8>) {
+            self.outbox.send_message(client_id, channel, message);
+        }
+
+        fn broadcast_message(&mut self, channel: DefaultChannel, message: Vec<u8>) {
+            self.outbox.broadcast_message(channel, message);
         }
     }
-}
 
-pub fn validate_woodcutting_action(
-    player_entity: &ServerEntity,
-    tree_entity: &ServerEntity,
-    server: &mut RenetServer,
-    player_id: PlayerId,
-) -> bool {
-    let tree = match &tree_entity.tree {
-        Some(t) if !t.is_chopped => t,
-        Some(t) if t.is_chopped => {
-            warn!("Player {:?} tried to chop already chopped tree", player_id);
-            return false;
+    impl ServerTransport for InboxTransport {
+        fn clients_id(&self) -> Vec<ClientId> {
+            self.inbox.keys().copied().collect()
         }
-        _ => {
-            warn!("Player {:?} tried to chop invalid tree", player_id);
-            return false;
+
+        fn receive_message(&mut self, client_id: ClientId, _: DefaultChannel) -> Option<Vec<u8>> {
+            self.inbox.get_mut(&client_id)?.pop_front()
         }
-    };
 
-    let tree_def = TreeDefinition::get(tree.tree_type);
-    info!(
-        "Validating woodcutting for player {:?}: tree={:?}, required_level={}",
-        player_id, tree.tree_type, tree_def.level_required
-    );
+        fn disconnect(&mut self, client_id: ClientId) {
+            self.inbox.remove(&client_id);
+        }
+    }
 
-    if let Some(ref skills) = player_entity.skills {
-        let wc_level = skills.get_level(SkillType::Woodcutting);
-        if wc_level < tree_def.level_required {
-            warn!(
-                "Player {:?} insufficient level: has {}, needs {}",
-                player_id, wc_level, tree_def.level_required
-            );
-            let msg = ServerMessage::NotEnoughLevel {
+    impl RecordingSink {
+        fn to(&self, player_id: PlayerId) -> impl Iterator<Item = &ServerMessage> {
+            self.sent
+                .iter()
+                .filter(move |(client_id, _)| *client_id == ClientId::from_raw(player_id.0))
+                .map(|(_, msg)| msg)
+        }
+    }
+
+    /// runs `f` with commands against a throwaway world. the tests only look at the
+    /// server state, so the commands never need applying
+    fn with_commands<R>(f: impl FnOnce(&mut Commands) -> R) -> R {
+        let world = World::new();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        f(&mut commands)
+    }
+
+    fn add_player(state: &mut ServerState, tile_pos: TilePosition) -> (PlayerId, u64) {
+        let player_id = PlayerId(state.next_player_id);
+        state.next_player_id += 1;
+        let entity_id = with_commands(|commands| {
+            spawn_player(
+                state,
+                &mut InterestManager::default(),
+                commands,
+                player_id,
+                format!("Tester{}", player_id.0),
+                format!("account-{}", player_id.0),
+            )
+        });
+        if let Some(entity) = state.entities.get_mut(&entity_id) {
+            entity.tile_pos = tile_pos;
+        }
+        (player_id, entity_id)
+    }
+
+    fn add_tree(state: &mut ServerState, tile_pos: TilePosition, tree_type: TreeType) -> u64 {
+        let entity_id = state.next_entity_id;
+        state.next_entity_id += 1;
+        with_commands(|commands| {
+            spawn_tree(
+                state,
+                commands,
+                entity_id,
+                MAIN_ZONE,
+                tile_pos,
+                Tree::new(tree_type),
+            )
+        });
+        entity_id
+    }
+
+    fn add_npc(state: &mut ServerState, home: TilePosition, aggressive: bool) -> u64 {
+        let entity_id = state.next_entity_id;
+        state.next_entity_id += 1;
+        with_commands(|commands| npc::spawn_npc(state, commands, entity_id, home, aggressive));
+        entity_id
+    }
+
+    /// queues `action` for `entity_id` the way server code would, without validation
+    fn queue(state: &mut ServerState, entity_id: u64, action: GameAction) {
+        let current_time = state.game_time;
+        let entity = state.entities.get_mut(&entity_id).unwrap();
+        queue_action_with_priority(
+            &mut entity.action_queue,
+            &mut entity.tile_pos,
+            action,
+            current_time,
+            TICK_RATE as f64,
+            None,
+            &HashSet::new(),
+        );
+    }
+
+    /// starts `entity_id` down `path` the way a queued move would
+    fn walk(state: &mut ServerState, entity_id: u64, path: Vec<TilePosition>) {
+        queue(state, entity_id, GameAction::Move { path });
+    }
+
+    /// starts `entity_id` chopping `tree` from where it stands
+    fn chop(state: &mut ServerState, entity_id: u64, tree: u64) {
+        queue(
+            state,
+            entity_id,
+            GameAction::ChopTree {
+                tree_entity_id: tree,
+            },
+        );
+    }
+
+    /// `msg` arriving from `player_id`
+    fn receive(
+        state: &mut ServerState,
+        interest_manager: &mut InterestManager,
+        sink: &mut RecordingSink,
+        player_id: PlayerId,
+        msg: ClientMessage,
+    ) {
+        with_commands(|commands| {
+            handle_client_message(
+                msg,
+                player_id,
+                state,
+                interest_manager,
+                sink,
+                commands,
+                &ServerTiming::default(),
+            )
+        });
+    }
+
+    /// the outcome of every action the player sent, with its sequence number
+    fn action_results(sink: &RecordingSink, player_id: PlayerId) -> Vec<(u32, ActionOutcome)> {
+        sink.to(player_id)
+            .filter_map(|msg| match msg {
+                ServerMessage::ActionResult {
+                    input_sequence_number,
+                    outcome,
+                } => Some((*input_sequence_number, outcome.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// a server running `server_update_system` over `transport`. time only moves when
+    /// the test advances it
+    fn server_app(state: ServerState, transport: InboxTransport) -> App {
+        let mut app = App::new();
+        app.init_resource::<Time>()
+            .insert_resource(state)
+            .init_resource::<InterestManager>()
+            .init_resource::<BandwidthStats>()
+            .init_resource::<ServerTiming>()
+            .insert_resource(GameRng::new(1))
+            .insert_resource(transport)
+            .add_systems(Update, server_update_system::<InboxTransport>);
+        app
+    }
+
+    /// one server tick, the way `server_update_system` runs it
+    fn run_tick(state: &mut ServerState, rng: &mut GameRng, sink: &mut RecordingSink) {
+        run_viewed_tick(state, rng, sink, &mut InterestManager::default());
+    }
+
+    /// a tick that keeps what everyone can see in `interest_manager`, returning how
+    /// many deltas went out
+    fn run_viewed_tick(
+        state: &mut ServerState,
+        rng: &mut GameRng,
+        sink: &mut RecordingSink,
+        interest_manager: &mut InterestManager,
+    ) -> usize {
+        let timing = ServerTiming::default();
+        state.server_tick += 1;
+        state.game_time += timing.tick_duration as f64;
+        with_commands(|commands| {
+            process_server_tick(state, &timing, rng, sink, interest_manager, commands)
+        })
+    }
+
+    fn experience(state: &ServerState, entity_id: u64, skill: SkillType) -> u32 {
+        state.entities[&entity_id]
+            .skills
+            .as_ref()
+            .map_or(0, |skills| skills.get_experience(skill))
+    }
+
+    #[test]
+    fn bundled_map_road_beats_the_mud_shortcut() {
+        let mut state = ServerState::default();
+        let zone = state.zone_mut(MAIN_ZONE);
+        assert_eq!(zone.tile_map.get(&tile(6, 0)), TileType::Road);
+        assert_eq!(zone.tile_map.get(&tile(7, 0)), TileType::Mud);
+
+        // straight down is four tiles of mud, round by the road is six tiles of road
+        let path = zone.pathfinder.find_path(tile(7, 2), tile(7, -2)).unwrap();
+        assert_eq!(path.len(), 7);
+        assert!(path
+            .iter()
+            .all(|pos| zone.tile_map.get(pos) != TileType::Mud));
+    }
+
+    #[test]
+    fn loaded_oak_experience_replaces_the_built_in_amount() {
+        let mut state = ServerState::default();
+        let mut oak = TreeDefinition::builtin(TreeType::Oak);
+        oak.experience = 100;
+        state.definitions = Definitions {
+            items: Vec::new(),
+            trees: vec![oak],
+        };
+        let (player_id, player) = add_player(&mut state, tile(0, 0));
+        let tree = add_tree(&mut state, tile(1, 0), TreeType::Oak);
+
+        let (im, mut sink) = (InterestManager::default(), RecordingSink::default());
+        handle_woodcutting_completion(
+            player,
+            tree,
+            &mut state,
+            &mut GameRng::new(1),
+            &im,
+            &mut sink,
+        );
+
+        assert_eq!(experience(&state, player, SkillType::Woodcutting), 100);
+        assert!(sink.to(player_id).any(|msg| matches!(
+            msg,
+            ServerMessage::ExperienceGained {
                 skill: SkillType::Woodcutting,
-                required: tree_def.level_required,
-                current: wc_level,
-            };
-            send_message(server, player_id, &msg);
-            return false;
+                amount: 100
+            }
+        )));
+        // anything the file leaves out keeps its built-in definition
+        assert_eq!(
+            state.definitions.tree(TreeType::Normal).experience,
+            TreeDefinition::builtin(TreeType::Normal).experience
+        );
+    }
+
+    #[test]
+    fn saved_trees_come_back_in_their_zone() {
+        let mut state = ServerState::default();
+        let (main_tree, cave_tree) = (state.next_entity_id, state.next_entity_id + 1);
+        state.next_entity_id += 2;
+        with_commands(|commands| {
+            let tree = Tree::new(TreeType::Normal);
+            spawn_tree(
+                &mut state,
+                commands,
+                main_tree,
+                MAIN_ZONE,
+                tile(0, 0),
+                tree.clone(),
+            );
+            spawn_tree(&mut state, commands, cave_tree, CAVE_ZONE, tile(1, 1), tree);
+        });
+        let path = std::env::temp_dir().join(format!("zone-save-{}.save", std::process::id()));
+        state.save_to_path(&path).unwrap();
+
+        let mut loaded = ServerState::default();
+        let result = with_commands(|commands| loaded.load_from_path(&path, commands));
+        let _ = std::fs::remove_file(&path);
+        result.unwrap();
+
+        assert_eq!(loaded.entities[&main_tree].zone, MAIN_ZONE);
+        assert_eq!(loaded.entities[&cave_tree].zone, CAVE_ZONE);
+        assert!(!loaded
+            .zone_mut(CAVE_ZONE)
+            .pathfinder
+            .is_walkable(&tile(1, 1)));
+    }
+
+    #[test]
+    fn merged_ground_pile_is_only_announced_to_its_viewers() {
+        let mut state = ServerState::default();
+        let (near, _) = add_player(&mut state, tile(0, 0));
+        let (far, _) = add_player(&mut state, tile(0, 0));
+        let mut interest_manager = InterestManager::default();
+        let mut sink = RecordingSink::default();
+        let logs = ItemStack {
+            item_type: ItemType::Logs,
+            quantity: 1,
+        };
+
+        let mut drop_logs = |state: &mut ServerState, interest_manager: &InterestManager| {
+            with_commands(|commands| {
+                drop_ground_item(
+                    MAIN_ZONE,
+                    tile(1, 1),
+                    logs.clone(),
+                    state,
+                    interest_manager,
+                    &mut sink,
+                    commands,
+                )
+            })
+        };
+        let pile = drop_logs(&mut state, &interest_manager);
+        interest_manager
+            .client_views
+            .insert(near, HashSet::from([pile]));
+        interest_manager.client_views.insert(far, HashSet::new());
+        assert_eq!(drop_logs(&mut state, &interest_manager), pile);
+
+        let changed = |player_id| {
+            sink.to(player_id)
+                .filter(|msg| matches!(msg, ServerMessage::GroundItemChanged { .. }))
+                .count()
+        };
+        assert_eq!(changed(near), 1);
+        assert_eq!(changed(far), 0);
+        assert!(sink.broadcast.is_empty());
+        assert_eq!(
+            state.entities[&pile]
+                .ground_item
+                .as_ref()
+                .map(|item| item.quantity),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn a_fallen_tree_only_clears_its_tile_for_its_viewers() {
+        let mut state = ServerState::default();
+        let (near, chopper) = add_player(&mut state, tile(0, 0));
+        let (far, _) = add_player(&mut state, tile(12, 0));
+        let tree = add_tree(&mut state, tile(1, 0), TreeType::Normal);
+        let mut interest_manager = InterestManager::default();
+        let mut sink = RecordingSink::default();
+        for player_id in [near, far] {
+            update_interest_for_player(player_id, &state, &mut interest_manager, &mut sink);
+        }
+
+        chop(&mut state, chopper, tree);
+        let mut sink = RecordingSink::default();
+        handle_woodcutting_completion(
+            chopper,
+            tree,
+            &mut state,
+            &mut GameRng::new(1),
+            &interest_manager,
+            &mut sink,
+        );
+
+        let cleared = |player_id| {
+            sink.to(player_id)
+                .filter(|msg| {
+                    matches!(msg, ServerMessage::ObstacleRemoved { tile: t } if *t == tile(1, 0))
+                })
+                .count()
+        };
+        assert_eq!(cleared(near), 1);
+        assert_eq!(cleared(far), 0);
+    }
+
+    #[test]
+    fn a_tree_falling_and_growing_back_is_only_news_to_its_viewers() {
+        let mut state = ServerState::default();
+        let (near, chopper) = add_player(&mut state, tile(0, 0));
+        let (far, _) = add_player(&mut state, tile(12, 0));
+        let tree = add_tree(&mut state, tile(1, 0), TreeType::Normal);
+        let mut interest_manager = InterestManager::default();
+        let mut sink = RecordingSink::default();
+        for player_id in [near, far] {
+            update_interest_for_player(player_id, &state, &mut interest_manager, &mut sink);
+        }
+
+        chop(&mut state, chopper, tree);
+        let mut rng = GameRng::new(1);
+        let mut sink = RecordingSink::default();
+        handle_woodcutting_completion(
+            chopper,
+            tree,
+            &mut state,
+            &mut rng,
+            &interest_manager,
+            &mut sink,
+        );
+        let respawn_time = state.definitions.tree(TreeType::Normal).respawn_time;
+        if let Some(ref mut fallen) = state.entities.get_mut(&tree).unwrap().tree {
+            fallen.respawn_timer = respawn_time;
+        }
+        run_viewed_tick(&mut state, &mut rng, &mut sink, &mut interest_manager);
+
+        let news = |player_id| {
+            sink.to(player_id)
+                .filter(|msg| {
+                    matches!(
+                        msg,
+                        ServerMessage::TreeChopped { .. } | ServerMessage::TreeRespawned { .. }
+                    )
+                })
+                .count()
+        };
+        assert_eq!(news(near), 2);
+        assert_eq!(news(far), 0);
+        assert!(sink.broadcast.is_empty());
+    }
+
+    #[test]
+    fn lower_id_wins_a_tile_two_entities_step_onto() {
+        // every state hashes differently, so a few rounds would catch hash order
+        for _ in 0..10 {
+            let mut state = ServerState::default();
+            let (_, first) = add_player(&mut state, tile(0, 0));
+            let (second_player, second) = add_player(&mut state, tile(2, 0));
+            walk(&mut state, first, vec![tile(0, 0), tile(1, 0)]);
+            walk(&mut state, second, vec![tile(2, 0), tile(1, 0)]);
+
+            let mut sink = RecordingSink::default();
+            run_tick(&mut state, &mut GameRng::new(1), &mut sink);
+            assert_eq!(state.entities[&first].tile_pos, tile(1, 0));
+            assert_eq!(state.entities[&second].tile_pos, tile(2, 0));
+            assert!(sink
+                .to(second_player)
+                .any(|msg| matches!(msg, ServerMessage::PathBlocked { at } if *at == tile(1, 0))));
+        }
+    }
+
+    #[test]
+    fn entities_swapping_tiles_both_stay_put() {
+        let mut state = ServerState::default();
+        let (_, left) = add_player(&mut state, tile(0, 0));
+        let (_, right) = add_player(&mut state, tile(1, 0));
+        walk(&mut state, left, vec![tile(0, 0), tile(1, 0)]);
+        walk(&mut state, right, vec![tile(1, 0), tile(0, 0)]);
+
+        run_tick(
+            &mut state,
+            &mut GameRng::new(1),
+            &mut RecordingSink::default(),
+        );
+        assert_eq!(state.entities[&left].tile_pos, tile(0, 0));
+        assert_eq!(state.entities[&right].tile_pos, tile(1, 0));
+    }
+
+    #[test]
+    fn health_regenerates_after_a_pause_that_each_hit_restarts() {
+        let mut state = ServerState::default();
+        let (_, me) = add_player(&mut state, tile(0, 0));
+        let mut interest_manager = InterestManager::default();
+        let mut sink = RecordingSink::default();
+        let mut rng = GameRng::new(1);
+        let mut hit = |state: &mut ServerState, amount| {
+            with_commands(|commands| {
+                damage_entity(
+                    state,
+                    &mut interest_manager,
+                    &mut sink,
+                    commands,
+                    me,
+                    amount,
+                )
+            })
+        };
+        let health = |state: &ServerState| state.entities[&me].health.unwrap();
+        let first_regen = REGEN_PAUSE_TICKS + REGEN_INTERVAL_TICKS;
+
+        hit(&mut state, 2);
+        assert_eq!(health(&state), PLAYER_MAX_HEALTH - 2);
+        for _ in 1..first_regen {
+            run_tick(&mut state, &mut rng, &mut RecordingSink::default());
+        }
+        assert_eq!(health(&state), PLAYER_MAX_HEALTH - 2);
+        run_tick(&mut state, &mut rng, &mut RecordingSink::default());
+        assert_eq!(health(&state), PLAYER_MAX_HEALTH - 1);
+
+        // a fresh hit holds regeneration off for the whole pause again
+        hit(&mut state, 1);
+        for _ in 1..first_regen {
+            run_tick(&mut state, &mut rng, &mut RecordingSink::default());
         }
-        info!("Level check passed: player has level {}", wc_level);
+        assert_eq!(health(&state), PLAYER_MAX_HEALTH - 2);
+        for _ in 0..REGEN_INTERVAL_TICKS * 4 + 1 {
+            run_tick(&mut state, &mut rng, &mut RecordingSink::default());
+        }
+        assert_eq!(health(&state), PLAYER_MAX_HEALTH);
     }
 
-    if let Some(ref inventory) = player_entity.inventory {
-        if let Some(axe) = inventory.has_any_axe() {
-            info!("Axe check passed: player has {:?}", axe);
-        } else {
-            warn!("Player {:?} has no axe", player_id);
-            let msg = ServerMessage::NoAxeEquipped;
-            send_message(server, player_id, &msg);
-            return false;
+    #[test]
+    fn killing_blow_respawns_the_player_at_full_health() {
+        let mut state = ServerState::default();
+        let (player_id, me) = add_player(&mut state, tile(0, 0));
+        let mut sink = RecordingSink::default();
+        with_commands(|commands| {
+            damage_entity(
+                &mut state,
+                &mut InterestManager::default(),
+                &mut sink,
+                commands,
+                me,
+                PLAYER_MAX_HEALTH + 5,
+            )
+        });
+
+        assert!(sink.to(player_id).any(|msg| matches!(
+            msg,
+            ServerMessage::HealthUpdate { entity_id, health: 0 } if *entity_id == me
+        )));
+        assert_eq!(state.entities[&me].health, Some(PLAYER_MAX_HEALTH));
+        assert_eq!(state.entities[&me].last_damaged_tick, None);
+    }
+
+    /// a player chopping its way round three trees for a few hundred ticks, returning
+    /// what it earned and everything the event log saw
+    fn chop_for_a_while(seed: u64) -> (u32, String, Vec<EntityEvent>) {
+        let mut state = ServerState::default();
+        let mut rng = GameRng::new(seed);
+        let (_, player) = add_player(&mut state, tile(0, 0));
+        let trees = [
+            add_tree(&mut state, tile(1, 0), TreeType::Normal),
+            add_tree(&mut state, tile(0, 1), TreeType::Oak),
+            add_tree(&mut state, tile(-1, 0), TreeType::Willow),
+        ];
+        for _ in 0..300 {
+            let idle = state.entities[&player]
+                .action_queue
+                .current_action
+                .is_none();
+            let standing = trees
+                .iter()
+                .find(|tree| !state.entities[*tree].tree.as_ref().unwrap().is_chopped);
+            if let Some(tree) = standing.filter(|_| idle) {
+                chop(&mut state, player, *tree);
+            }
+            run_tick(&mut state, &mut rng, &mut RecordingSink::default());
         }
+        let inventory = format!("{:?}", state.entities[&player].inventory);
+        let events = state.event_log.events().copied().collect();
+        (
+            experience(&state, player, SkillType::Woodcutting),
+            inventory,
+            events,
+        )
     }
 
-    info!("Woodcutting validation passed for player {:?}", player_id);
-    true
-}
+    #[test]
+    fn same_seed_and_inputs_play_out_the_same() {
+        let (xp, inventory, events) = chop_for_a_while(7);
+        assert!(xp > 0);
+        assert!(events
+            .iter()
+            .any(|event| event.kind == EntityEventKind::TreeChopped));
 
-pub fn process_server_tick(
-    state: &mut ServerState,
-    server: &mut RenetServer,
-    interest_manager: &mut InterestManager,
-) {
-    let tick = state.server_tick;
-    let current_time = tick as f64 * TICK_RATE as f64;
+        assert_eq!(chop_for_a_while(7), (xp, inventory, events));
+    }
 
-    let mut completed_actions = Vec::new();
-    let mut woodcutting_completions = Vec::new();
+    #[test]
+    fn a_flooding_client_only_gets_its_budget_handled_each_tick() {
+        let mut state = ServerState::default();
+        let (flooder, _) = add_player(&mut state, tile(0, 0));
+        let (quiet, _) = add_player(&mut state, tile(3, 0));
+        let message = bincode::serialize(&ClientMessage::SetRunning { enabled: true }).unwrap();
+        let mut transport = InboxTransport::default();
+        for (player_id, count) in [(flooder, 1000), (quiet, 3)] {
+            let queued = std::iter::repeat_n(message.clone(), count).collect();
+            transport
+                .inbox
+                .insert(ClientId::from_raw(player_id.0), queued);
+        }
 
-    for (entity_id, entity) in state.entities.iter_mut() {
-        if let Some(ref current_action) = entity.action_queue.current_action {
-            if let GameAction::ChopTree { tree_entity_id } = current_action.action {
-                if current_time >= current_action.completion_time {
-                    woodcutting_completions.push((*entity_id, tree_entity_id));
-                }
+        let mut app = server_app(state, transport);
+        let frame = |app: &mut App, seconds: f32| {
+            let delta = std::time::Duration::from_secs_f32(seconds);
+            app.world_mut().resource_mut::<Time>().advance_by(delta);
+            app.update();
+            let waiting = |player_id: PlayerId| {
+                app.world().resource::<InboxTransport>().inbox[&ClientId::from_raw(player_id.0)]
+                    .len()
+            };
+            (waiting(flooder), waiting(quiet))
+        };
+        let budget = MAX_MESSAGES_PER_TICK as usize;
+        let tick_duration = ServerTiming::default().tick_duration;
+
+        assert_eq!(frame(&mut app, 0.0), (1000 - budget, 0));
+        // the rest of the tick gets nothing more handled
+        assert_eq!(frame(&mut app, 0.0), (1000 - budget, 0));
+        assert_eq!(frame(&mut app, tick_duration), (1000 - budget, 0));
+        assert_eq!(app.world().resource::<ServerState>().server_tick, 1);
+        // the tick that just ran topped the budget back up
+        assert_eq!(frame(&mut app, 0.0), (1000 - 2 * budget, 0));
+    }
+
+    #[test]
+    fn stationary_scenery_costs_nothing_once_it_has_been_sent() {
+        let mut state = ServerState::default();
+        let (viewer, _) = add_player(&mut state, tile(0, 0));
+        let mut trees = Vec::new();
+        for y in 3..28 {
+            for x in -20..20 {
+                trees.push(add_tree(&mut state, tile(x, y), TreeType::Normal));
             }
         }
+        assert_eq!(trees.len(), 1000);
+        let movers: Vec<u64> = [tile(-4, -4), tile(0, -4), tile(4, -4)]
+            .into_iter()
+            .map(|home| {
+                let entity_id = state.next_entity_id;
+                state.next_entity_id += 1;
+                with_commands(|commands| {
+                    npc::spawn_npc(&mut state, commands, entity_id, home, false)
+                });
+                entity_id
+            })
+            .collect();
+        let mut interest_manager = InterestManager::default();
+        interest_manager.set_view_distance(viewer, MAX_VIEW_DISTANCE);
+        let mut rng = GameRng::new(1);
+        let mut sink = RecordingSink::default();
 
-        process_action_queue(&mut entity.action_queue, &mut entity.tile_pos, current_time);
+        // the trees in view go out once, in full, as they come into view
+        run_viewed_tick(&mut state, &mut rng, &mut sink, &mut interest_manager);
+        let entered: usize = sink
+            .to(viewer)
+            .map(|msg| match msg {
+                ServerMessage::EntitiesEntered { entities } => entities
+                    .iter()
+                    .filter(|entity| trees.contains(&entity.entity_id))
+                    .count(),
+                _ => 0,
+            })
+            .sum();
+        assert!(entered > 400);
 
-        if let Some(ref action_in_progress) = entity.action_queue.current_action {
-            if current_time >= action_in_progress.completion_time {
-                if !matches!(action_in_progress.action, GameAction::ChopTree { .. }) {
-                    completed_actions.push(*entity_id);
-                }
-            }
+        // after that only the movers cost anything: at most a step and a new walk each
+        let mut moved = 0;
+        for _ in 0..100 {
+            let before: Vec<TilePosition> = movers
+                .iter()
+                .map(|mover| state.entities[mover].tile_pos)
+                .collect();
+            let sent = run_viewed_tick(&mut state, &mut rng, &mut sink, &mut interest_manager);
+            assert!(
+                sent <= 2 * movers.len(),
+                "{} deltas for {} movers",
+                sent,
+                movers.len()
+            );
+            moved += movers
+                .iter()
+                .zip(before)
+                .filter(|(mover, before)| state.entities[*mover].tile_pos != *before)
+                .count();
         }
+        assert!(moved > 0);
+        assert!(state.dirty_entities.is_empty());
+        assert!(trees
+            .iter()
+            .all(|tree| !state.last_states.contains_key(tree)));
     }
 
-    if !woodcutting_completions.is_empty() {
-        info!(
-            "Processing {} woodcutting completions",
-            woodcutting_completions.len()
+    #[test]
+    fn rewound_validation_still_sees_a_tree_that_just_fell() {
+        let mut state = ServerState::default();
+        let (player_id, me) = add_player(&mut state, tile(0, 0));
+        let (_, other) = add_player(&mut state, tile(2, 0));
+        let tree = add_tree(&mut state, tile(1, 0), TreeType::Normal);
+        let chop = GameAction::ChopTree {
+            tree_entity_id: tree,
+        };
+
+        // someone else fells it, and our chop arrives the tick after
+        state.server_tick = 10;
+        let (im, mut sink) = (InterestManager::default(), RecordingSink::default());
+        handle_woodcutting_completion(
+            other,
+            tree,
+            &mut state,
+            &mut GameRng::new(1),
+            &im,
+            &mut sink,
         );
-    }
+        assert!(state.entities[&tree].tree.as_ref().unwrap().is_chopped);
+        state.server_tick = 11;
 
-    for (player_entity_id, tree_entity_id) in woodcutting_completions {
-        handle_woodcutting_completion(player_entity_id, tree_entity_id, state, server);
+        let tick_duration = ServerTiming::default().tick_duration;
+        let validate_at = |state: &ServerState, view_tick: u64| {
+            validate_gathering_action(state, me, &chop, tile(0, 0), view_tick, player_id)
+        };
+        assert_eq!(
+            validate_at(&state, state.server_tick),
+            Err(RejectReason::Depleted)
+        );
+        let rewound = state.server_tick - rewind_ticks(tick_duration * 2.0, tick_duration);
+        assert_eq!(validate_at(&state, rewound), Ok(()));
+
+        // however far behind the client claims to be, the rewind stops at the cap
+        state.server_tick = 10 + MAX_REWIND_TICKS + 1;
+        state.resource_history.prune(state.server_tick);
+        let rewound = state.server_tick - rewind_ticks(60.0, tick_duration);
+        assert_eq!(validate_at(&state, rewound), Err(RejectReason::Depleted));
     }
 
-    for entity_id in completed_actions {
-        if let Some(entity) = state.entities.get_mut(&entity_id) {
-            entity.action_queue.current_action = None;
+    #[test]
+    fn a_normal_tree_falls_at_once_and_a_willow_keeps_giving_until_it_falls() {
+        let mut state = ServerState::default();
+        let (_, me) = add_player(&mut state, tile(0, 0));
+        let (other_id, other) = add_player(&mut state, tile(2, 0));
+        let normal = add_tree(&mut state, tile(1, 0), TreeType::Normal);
+        let willow = add_tree(&mut state, tile(0, 1), TreeType::Willow);
+        let mut rng = GameRng::new(1);
+        let (im, mut sink) = (InterestManager::default(), RecordingSink::default());
+        let standing = |state: &ServerState, tree: u64| {
+            !state.entities[&tree].tree.as_ref().unwrap().is_chopped
+        };
+        let repeats = |state: &ServerState, entity_id: u64| {
+            state.entities[&entity_id]
+                .action_queue
+                .current_action
+                .as_ref()
+                .map(|current| current.repeat_count)
+        };
 
-            if let Some(player_id) = entity.player_id {
-                debug!("Action completed for player {:?}", player_id);
-                let msg = ServerMessage::ActionCompleted { entity_id };
-                send_message(server, player_id, &msg);
+        // one chop fells a normal tree, and whoever else was on it stops too
+        chop(&mut state, me, normal);
+        chop(&mut state, other, normal);
+        handle_woodcutting_completion(me, normal, &mut state, &mut rng, &im, &mut sink);
+        assert!(!standing(&state, normal));
+        assert_eq!(repeats(&state, me), None);
+        assert_eq!(repeats(&state, other), None);
+        assert!(sink.to(other_id).any(|msg| matches!(
+            msg,
+            ServerMessage::ActionCompleted { entity_id } if *entity_id == other
+        )));
+
+        // a willow gives logs chop after chop, the chop going again each time
+        chop(&mut state, me, willow);
+        let mut chops = 0;
+        while standing(&state, willow) {
+            handle_woodcutting_completion(me, willow, &mut state, &mut rng, &im, &mut sink);
+            chops += 1;
+            if standing(&state, willow) {
+                assert_eq!(repeats(&state, me), Some(chops));
             }
+            assert!(chops < 200, "willow never fell");
         }
+        assert!(chops > 1);
+        assert_eq!(repeats(&state, me), None);
+        let willow_logs = state.definitions.tree(TreeType::Willow).logs_given;
+        let inventory = state.entities[&me].inventory.as_ref().unwrap();
+        assert_eq!(inventory.count_item(willow_logs), chops);
     }
 
-    // update tree respawn timers
-    let mut respawned_trees = Vec::new();
-    for (tree_entity_id, tree_entity) in state.entities.iter_mut() {
-        if let Some(ref mut tree) = tree_entity.tree {
-            if tree.is_chopped {
-                tree.respawn_timer += TICK_RATE as f64;
-
-                let tree_def = TreeDefinition::get(tree.tree_type);
-                if tree.respawn_timer >= tree_def.respawn_time {
-                    tree.is_chopped = false;
-                    tree.respawn_timer = 0.0;
-                    respawned_trees.push((*tree_entity_id, tree.tree_type));
+    #[test]
+    fn chop_results_answer_the_sequence_number_they_were_sent_with() {
+        let mut state = ServerState::default();
+        let (player_id, _) = add_player(&mut state, tile(0, 0));
+        let far = add_tree(&mut state, tile(4, 0), TreeType::Normal);
+        let near = add_tree(&mut state, tile(0, 1), TreeType::Normal);
+        let mut interest_manager = InterestManager::default();
+        let mut sink = RecordingSink::default();
+        update_interest_for_player(player_id, &state, &mut interest_manager, &mut sink);
 
-                    let msg = ServerMessage::TreeRespawned {
-                        tree_entity_id: *tree_entity_id,
-                    };
-                    broadcast_message(server, &msg);
-                }
-            }
+        for (tree, input_sequence_number) in [(far, 7), (near, 8)] {
+            let msg = ClientMessage::QueueAction {
+                action: GameAction::ChopTree {
+                    tree_entity_id: tree,
+                },
+                input_sequence_number,
+                render_delay: 0.0,
+            };
+            receive(&mut state, &mut interest_manager, &mut sink, player_id, msg);
         }
+
+        assert_eq!(
+            action_results(&sink, player_id),
+            vec![
+                (7, ActionOutcome::Rejected(RejectReason::TooFar)),
+                (8, ActionOutcome::Accepted),
+            ]
+        );
     }
 
-    for (tree_id, tree_type) in respawned_trees {
-        info!("Tree {} ({:?}) respawned", tree_id, tree_type);
+    #[test]
+    fn chopping_needs_the_player_next_to_the_tree() {
+        let mut state = ServerState::default();
+        let (player_id, me) = add_player(&mut state, tile(0, 0));
+        let tree = add_tree(&mut state, tile(5, 5), TreeType::Normal);
+        let validate_from = |from: TilePosition| {
+            validate_woodcutting_action(
+                &state.entities[&me],
+                &state.entities[&tree],
+                from,
+                false,
+                player_id,
+                &state.definitions,
+            )
+        };
+
+        assert_eq!(validate_from(tile(0, 0)), Err(RejectReason::TooFar));
+        assert_eq!(validate_from(tile(3, 5)), Err(RejectReason::TooFar));
+        // where the chop starts from is what counts, wherever the player is now
+        assert_eq!(validate_from(tile(4, 5)), Ok(()));
+        assert_eq!(validate_from(tile(6, 6)), Ok(()));
     }
 
-    for (player_id, _) in state.players.iter() {
-        update_interest_for_player(*player_id, state, interest_manager, server);
+    #[test]
+    fn a_teleport_cuts_a_walk_short_and_lands_on_its_destination() {
+        let mut state = ServerState::default();
+        let (_, me) = add_player(&mut state, tile(0, 0));
+        let mut rng = GameRng::new(1);
+        walk(&mut state, me, (0..=5).map(|x| tile(x, 0)).collect());
+        run_tick(&mut state, &mut rng, &mut RecordingSink::default());
+        assert_eq!(state.entities[&me].tile_pos, tile(1, 0));
+
+        let destination = tile(-3, 2);
+        queue(&mut state, me, GameAction::Teleport { destination });
+        for _ in 0..5 {
+            run_tick(&mut state, &mut rng, &mut RecordingSink::default());
+            assert_eq!(state.entities[&me].tile_pos, destination);
+        }
+        let queue = &state.entities[&me].action_queue;
+        assert!(queue.current_action.is_none());
+        assert!(queue.suspended_action.is_none());
+        assert!(queue.actions.is_empty());
     }
 
-    send_delta_updates(state, interest_manager, server, tick);
-}
+    #[test]
+    fn clients_cannot_teleport_themselves() {
+        let mut state = ServerState::default();
+        let (player_id, me) = add_player(&mut state, tile(0, 0));
+        let mut sink = RecordingSink::default();
+        let teleport = GameAction::Teleport {
+            destination: tile(1, 1),
+        };
+        receive(
+            &mut state,
+            &mut InterestManager::default(),
+            &mut sink,
+            player_id,
+            ClientMessage::QueueAction {
+                action: teleport.clone(),
+                input_sequence_number: 1,
+                render_delay: 0.0,
+            },
+        );
+        receive(
+            &mut state,
+            &mut InterestManager::default(),
+            &mut sink,
+            player_id,
+            ClientMessage::QueueActions {
+                actions: vec![teleport],
+                input_sequence_number: 2,
+                render_delay: 0.0,
+            },
+        );
 
-pub fn process_action_queue(
-    queue: &mut ActionQueue,
-    tile_pos: &mut TilePosition,
-    current_time: f64,
-) {
-    if let Some(ref mut action_in_progress) = queue.current_action {
-        if current_time >= action_in_progress.completion_time {
-            if let GameAction::Move { ref path } = action_in_progress.action {
-                action_in_progress.current_path_index += 1;
+        assert_eq!(
+            action_results(&sink, player_id),
+            vec![
+                (1, ActionOutcome::Rejected(RejectReason::ServerOnly)),
+                (2, ActionOutcome::Rejected(RejectReason::ServerOnly)),
+            ]
+        );
+        assert_eq!(state.entities[&me].tile_pos, tile(0, 0));
+    }
 
-                if action_in_progress.current_path_index < path.len() {
-                    *tile_pos = path[action_in_progress.current_path_index];
-                    action_in_progress.completion_time = current_time + TICK_RATE as f64;
-                } else {
-                    queue.current_action = None;
-                }
-            }
+    #[test]
+    fn a_batch_is_checked_from_where_the_walk_in_progress_ends() {
+        let mut state = ServerState::default();
+        let (player_id, me) = add_player(&mut state, tile(0, 0));
+        let tree = add_tree(&mut state, tile(4, 0), TreeType::Normal);
+        let mut interest_manager = InterestManager::default();
+        let mut sink = RecordingSink::default();
+        update_interest_for_player(player_id, &state, &mut interest_manager, &mut sink);
+        walk(&mut state, me, (0..=3).map(|x| tile(x, 0)).collect());
+
+        // too far from here, but right next to it once the walk is over
+        let chop = GameAction::ChopTree {
+            tree_entity_id: tree,
+        };
+        for (input_sequence_number, msg) in [
+            ClientMessage::QueueAction {
+                action: chop.clone(),
+                input_sequence_number: 1,
+                render_delay: 0.0,
+            },
+            ClientMessage::QueueActions {
+                actions: vec![chop],
+                input_sequence_number: 2,
+                render_delay: 0.0,
+            },
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            receive(&mut state, &mut interest_manager, &mut sink, player_id, msg);
+            assert_eq!(
+                action_results(&sink, player_id)[input_sequence_number],
+                (input_sequence_number as u32 + 1, ActionOutcome::Accepted)
+            );
         }
-        return;
     }
 
-    if let Some(action) = queue.actions.pop_front() {
-        let duration = action.duration_seconds();
-        let start_index = match &action {
-            GameAction::Move { path } => {
-                if !path.is_empty() {
-                    *tile_pos = path[0];
+    #[test]
+    fn banks_and_ground_items_are_out_of_reach_from_another_zone() {
+        let mut state = ServerState::default();
+        let (player_id, me) = add_player(&mut state, tile(10, 11));
+        let bank = state.next_entity_id;
+        let mut sink = RecordingSink::default();
+        // the pile is despawned once it's picked up, so it has to be in the same world
+        let reached = with_commands(|commands| {
+            spawn_bank(&mut state, commands, tile(10, 10));
+            let logs = ItemStack {
+                item_type: ItemType::Logs,
+                quantity: 1,
+            };
+            let pile = drop_ground_item(
+                MAIN_ZONE,
+                tile(10, 12),
+                logs,
+                &mut state,
+                &InterestManager::default(),
+                &mut RecordingSink::default(),
+                commands,
+            );
+            let mut reach_both = |state: &mut ServerState, zone: ZoneId| {
+                state.entities.get_mut(&me).unwrap().zone = zone;
+                handle_interact_completion(me, bank, state, &mut sink);
+                handle_pickup_completion(
+                    me,
+                    pile,
+                    state,
+                    &mut InterestManager::default(),
+                    &mut sink,
+                    commands,
+                );
+                let opened = sink
+                    .to(player_id)
+                    .any(|msg| matches!(msg, ServerMessage::BankUpdate { .. }));
+                let logs = state.entities[&me]
+                    .inventory
+                    .as_ref()
+                    .unwrap()
+                    .count_item(ItemType::Logs);
+                (opened, logs)
+            };
+            // the same coordinates in the cave are nowhere near them
+            [
+                reach_both(&mut state, CAVE_ZONE),
+                reach_both(&mut state, MAIN_ZONE),
+            ]
+        });
+        assert_eq!(reached, [(false, 0), (true, 1)]);
+    }
+
+    #[test]
+    fn going_through_a_portal_stops_deltas_from_the_old_zone() {
+        let mut state = ServerState::default();
+        let (traveller, me) = add_player(&mut state, tile(4, 3));
+        let (_, walker) = add_player(&mut state, tile(6, 0));
+        let mut interest_manager = InterestManager::default();
+        let mut rng = GameRng::new(1);
+        let mut sink = RecordingSink::default();
+        let hears_of_walker = |sink: &RecordingSink| {
+            sink.to(traveller).any(|msg| match msg {
+                ServerMessage::DeltaUpdate { deltas, .. } => {
+                    deltas.iter().any(|delta| delta.entity_id == walker)
                 }
-                0
+                _ => false,
+            })
+        };
+        let mut pace = |state: &mut ServerState, sink: &mut RecordingSink| {
+            let from = state.entities[&walker].tile_pos.y;
+            let to = if from == 0 { 6 } else { 0 };
+            let step = if to > from { 1 } else { -1 };
+            let path = (0..=6).map(|i| tile(6, from + i * step)).collect();
+            walk(state, walker, path);
+            for _ in 0..3 {
+                run_viewed_tick(state, &mut rng, sink, &mut interest_manager);
             }
-            _ => 0,
         };
 
-        queue.current_action = Some(ActionInProgress {
-            action: action.clone(),
-            started_at: current_time,
-            completion_time: current_time + duration,
-            current_path_index: start_index,
-            repeat_count: 0,
-        });
-    }
-}
-
-#[derive(Debug)]
-pub enum QueueResult {
-    Started,             // action started immediately
-    Queued,              // action queued for later
-    ReplacedSameType,    // replaced in-progress action of same type
-    CancelledAndStarted, // cancelled lower priority action and started
-    Suspended,           // suspended normal action (by strong action)
-    QueueFull,           // queue is full (max 1 queued action)
-}
+        pace(&mut state, &mut sink);
+        assert!(hears_of_walker(&sink));
 
-/// handles adding a new action to the queue with priority-based cancellation
-pub fn queue_action_with_priority(
-    queue: &mut ActionQueue,
-    tile_pos: &mut TilePosition,
-    new_action: GameAction,
-    current_time: f64,
-) -> QueueResult {
-    let new_priority = new_action.priority();
+        walk(&mut state, me, vec![tile(4, 3), tile(4, 4)]);
+        pace(&mut state, &mut RecordingSink::default());
+        queue(&mut state, me, GameAction::EnterZone { portal_id: 1 });
+        pace(&mut state, &mut RecordingSink::default());
+        assert_eq!(state.entities[&me].zone, CAVE_ZONE);
 
-    if let Some(ref current) = queue.current_action {
-        let current_priority = current.action.priority();
+        let mut sink = RecordingSink::default();
+        pace(&mut state, &mut sink);
+        pace(&mut state, &mut sink);
+        assert!(!hears_of_walker(&sink));
+    }
 
-        // Strong actions cancel/suspend current action
-        if new_priority == shared::actions::ActionPriority::Strong {
-            if current_priority == shared::actions::ActionPriority::Normal {
-                // Suspend normal action
-                queue.suspended_action = queue.current_action.take();
-                queue.actions.clear();
-            } else {
-                // Cancel weak action
-                queue.current_action = None;
-                queue.actions.clear();
-            }
-            start_action(queue, tile_pos, new_action, current_time);
-            return QueueResult::Started;
+    #[test]
+    fn a_silent_client_is_disconnected_and_leaves_once() {
+        let mut state = ServerState {
+            idle_timeout_ticks: 5,
+            ..Default::default()
+        };
+        let (chatty, chatty_entity) = add_player(&mut state, tile(0, 0));
+        let (silent, silent_entity) = add_player(&mut state, tile(1, 0));
+        let mut transport = InboxTransport::default();
+        for player_id in [chatty, silent] {
+            transport
+                .inbox
+                .insert(ClientId::from_raw(player_id.0), VecDeque::new());
         }
-
-        // Normal actions cancel Weak actions
-        if new_priority == shared::actions::ActionPriority::Normal
-            && current_priority == shared::actions::ActionPriority::Weak
-        {
-            queue.current_action = None;
-            queue.actions.clear();
-            start_action(queue, tile_pos, new_action, current_time);
-            return QueueResult::CancelledAndStarted;
+        let mut app = server_app(state, transport);
+        // removing a player despawns its entity, so it has to be in the app's world
+        for entity_id in [chatty_entity, silent_entity] {
+            let entity = app.world_mut().spawn_empty().id();
+            let mut state = app.world_mut().resource_mut::<ServerState>();
+            state.entities.get_mut(&entity_id).unwrap().entity = entity;
         }
+        let tick = std::time::Duration::from_secs_f32(ServerTiming::default().tick_duration);
+        let message = bincode::serialize(&ClientMessage::SetRunning { enabled: false }).unwrap();
 
-        if new_action.replaces_same_type(&current.action) {
-            queue.current_action = None;
-            queue.actions.clear();
-            start_action(queue, tile_pos, new_action, current_time);
-            return QueueResult::ReplacedSameType;
+        let mut left = 0;
+        for _ in 0..10 {
+            let mut transport = app.world_mut().resource_mut::<InboxTransport>();
+            if let Some(inbox) = transport.inbox.get_mut(&ClientId::from_raw(chatty.0)) {
+                inbox.push_back(message.clone());
+            }
+            app.world_mut().resource_mut::<Time>().advance_by(tick);
+            app.update();
+            let mut transport = app.world_mut().resource_mut::<InboxTransport>();
+            let outbox = std::mem::take(&mut transport.outbox);
+            left += outbox
+                .sent
+                .iter()
+                .map(|(client_id, msg)| (*client_id, msg))
+                .chain(
+                    outbox
+                        .broadcast
+                        .iter()
+                        .map(|msg| (ClientId::from_raw(chatty.0), msg)),
+                )
+                .filter(|(client_id, msg)| {
+                    *client_id == ClientId::from_raw(chatty.0)
+                        && matches!(
+                            msg,
+                            ServerMessage::EntitiesLeft { entity_ids }
+                                if entity_ids.contains(&silent_entity)
+                        )
+                })
+                .count();
         }
 
-        if queue.actions.is_empty() {
-            queue.actions.push_back(new_action);
-            return QueueResult::Queued;
-        } else {
-            return QueueResult::QueueFull;
-        }
+        let transport = app.world().resource::<InboxTransport>();
+        assert!(!transport.inbox.contains_key(&ClientId::from_raw(silent.0)));
+        assert!(transport.inbox.contains_key(&ClientId::from_raw(chatty.0)));
+        let state = app.world().resource::<ServerState>();
+        assert!(!state.players.contains_key(&silent));
+        assert!(state.players.contains_key(&chatty));
+        assert_eq!(left, 1);
+        let interest_manager = app.world().resource::<InterestManager>();
+        assert!(!interest_manager.can_see(chatty, silent_entity));
     }
 
-    // no current action, start immediately
-    start_action(queue, tile_pos, new_action, current_time);
-    QueueResult::Started
-}
+    #[test]
+    fn a_chop_at_a_tree_out_of_view_is_rejected() {
+        let mut state = ServerState::default();
+        let (player_id, _) = add_player(&mut state, tile(0, 0));
+        let hidden = add_tree(&mut state, tile(VIEW_DISTANCE + 3, 0), TreeType::Normal);
+        let mut interest_manager = InterestManager::default();
+        let mut sink = RecordingSink::default();
+        update_interest_for_player(player_id, &state, &mut interest_manager, &mut sink);
 
-fn start_action(
-    queue: &mut ActionQueue,
-    tile_pos: &mut TilePosition,
-    action: GameAction,
-    current_time: f64,
-) {
-    let duration = action.duration_seconds();
-    let start_index = match &action {
-        GameAction::Move { path } => {
-            // immediately move to first position in path
-            if !path.is_empty() {
-                *tile_pos = path[0];
-                info!("  → Player moved to {:?} (start of path)", path[0]);
-            }
-            0
+        let msg = ClientMessage::QueueAction {
+            action: GameAction::ChopTree {
+                tree_entity_id: hidden,
+            },
+            input_sequence_number: 3,
+            render_delay: 0.0,
+        };
+        receive(&mut state, &mut interest_manager, &mut sink, player_id, msg);
+        assert_eq!(
+            action_results(&sink, player_id),
+            vec![(3, ActionOutcome::Rejected(RejectReason::NotInView))]
+        );
+    }
+
+    #[test]
+    fn a_late_viewer_first_hears_of_a_moving_entity_in_full() {
+        let mut state = ServerState::default();
+        let (_, walker) = add_player(&mut state, tile(0, 0));
+        let mut interest_manager = InterestManager::default();
+        let mut rng = GameRng::new(1);
+        let mut sink = RecordingSink::default();
+        walk(&mut state, walker, (0..8).map(|x| tile(x, 0)).collect());
+        for _ in 0..3 {
+            run_viewed_tick(&mut state, &mut rng, &mut sink, &mut interest_manager);
         }
-        _ => 0,
-    };
 
-    queue.current_action = Some(ActionInProgress {
-        action,
-        started_at: current_time,
-        completion_time: current_time + duration,
-        current_path_index: start_index,
-        repeat_count: 0,
-    });
-}
+        // the walker has long had its full state sent, just not to this player
+        let (late, _) = add_player(&mut state, tile(3, 2));
+        let mut sink = RecordingSink::default();
+        run_viewed_tick(&mut state, &mut rng, &mut sink, &mut interest_manager);
 
-pub fn handle_woodcutting_completion(
-    player_entity_id: u64,
-    tree_entity_id: u64,
-    state: &mut ServerState,
-    server: &mut RenetServer,
-) {
-    let tree_def = if let Some(tree_entity) = state.entities.get(&tree_entity_id) {
-        if let Some(ref tree) = tree_entity.tree {
-            let def = TreeDefinition::get(tree.tree_type);
-            info!(
-                "Processing woodcutting completion: tree={:?}, xp={}, logs={:?}",
-                tree.tree_type, def.experience, def.logs_given
+        let first = sink.to(late).find_map(|msg| match msg {
+            ServerMessage::DeltaUpdate { deltas, .. } => deltas
+                .iter()
+                .find(|delta| delta.entity_id == walker)
+                .map(|delta| delta.delta_type.clone()),
+            _ => None,
+        });
+        assert!(matches!(first, Some(DeltaType::FullState { .. })));
+    }
+
+    #[test]
+    fn an_account_that_comes_back_gets_its_logs_and_levels_back() {
+        let mut state = ServerState::default();
+        let mut interest_manager = InterestManager::default();
+        let mut sink = RecordingSink::default();
+        let timing = ServerTiming::default();
+        let join = || ClientMessage::Join {
+            name: "Returner".to_string(),
+            account_id: "returner".to_string(),
+        };
+        let (first, second) = (PlayerId(1), PlayerId(2));
+
+        // one world throughout, leaving despawns the entity joining spawned
+        with_commands(|commands| {
+            handle_client_message(
+                join(),
+                first,
+                &mut state,
+                &mut interest_manager,
+                &mut sink,
+                commands,
+                &timing,
             );
-            def
-        } else {
-            return;
-        }
-    } else {
-        return;
-    };
+            let entity_id = state.players[&first].entity_id;
+            let entity = state.entities.get_mut(&entity_id).unwrap();
+            let inventory = entity.inventory.as_mut().unwrap();
+            assert_eq!(
+                inventory.add_item(ItemType::Logs, 50, &state.definitions),
+                50
+            );
+            let skills = entity.skills.as_mut().unwrap();
+            skills.add_experience(SkillType::Woodcutting, Skills::xp_for_level(5));
 
-    if let Some(tree_entity) = state.entities.get_mut(&tree_entity_id) {
-        if let Some(ref mut tree) = tree_entity.tree {
-            tree.is_chopped = true;
-            tree.respawn_timer = 0.0;
-            info!(
-                "Tree {} chopped! Will respawn in {}s",
-                tree_entity_id, tree_def.respawn_time
+            // the grace period runs out without them reconnecting
+            state.players.get_mut(&first).unwrap().pending_removal = Some(state.server_tick);
+            remove_expired_players(&mut sink, &mut state, &mut interest_manager, commands);
+            assert!(!state.entities.contains_key(&entity_id));
+
+            handle_client_message(
+                join(),
+                second,
+                &mut state,
+                &mut interest_manager,
+                &mut sink,
+                commands,
+                &timing,
             );
-        }
+        });
+
+        let entity = &state.entities[&state.players[&second].entity_id];
+        assert_eq!(
+            entity
+                .inventory
+                .as_ref()
+                .unwrap()
+                .count_item(ItemType::Logs),
+            50
+        );
+        assert_eq!(
+            entity
+                .skills
+                .as_ref()
+                .unwrap()
+                .get_level(SkillType::Woodcutting),
+            5
+        );
     }
 
-    let player_entity = match state.entities.get_mut(&player_entity_id) {
-        Some(e) => e,
-        None => return,
-    };
+    #[test]
+    fn a_steel_axe_chops_faster_than_a_bronze_one() {
+        let mut state = ServerState::default();
+        let (player_id, me) = add_player(&mut state, tile(0, 0));
+        let tree = add_tree(&mut state, tile(1, 0), TreeType::Normal);
+        let mut interest_manager = InterestManager::default();
+        let mut sink = RecordingSink::default();
+        update_interest_for_player(player_id, &state, &mut interest_manager, &mut sink);
 
-    let player_id = match player_entity.player_id {
-        Some(id) => id,
-        None => return,
-    };
+        // how long a chop sent the way the client sends it takes with what is wielded
+        let mut chop_time = |state: &mut ServerState| {
+            let chop = ClientMessage::QueueAction {
+                action: GameAction::ChopTree {
+                    tree_entity_id: tree,
+                },
+                input_sequence_number: 1,
+                render_delay: 0.0,
+            };
+            receive(state, &mut interest_manager, &mut sink, player_id, chop);
+            let queue = &mut state.entities.get_mut(&me).unwrap().action_queue;
+            let current = queue.current_action.take().expect("chop started");
+            current.completion_time - current.started_at
+        };
+        assert_eq!(
+            equipped_tool(&state.entities[&me].equipment),
+            Some(ItemType::BronzeAxe)
+        );
+        let bronze = chop_time(&mut state);
 
-    if let Some(ref mut inventory) = player_entity.inventory {
-        if inventory.add_item(tree_def.logs_given, 1) {
-            let def = ItemDefinition::get(tree_def.logs_given);
-            info!(
-                "Player {:?} received: {} x1 (total: {})",
-                player_id,
-                def.name,
-                inventory.count_item(tree_def.logs_given)
+        let inventory = state
+            .entities
+            .get_mut(&me)
+            .unwrap()
+            .inventory
+            .as_mut()
+            .unwrap();
+        let slot = inventory.slots.iter().position(Option::is_none).unwrap();
+        inventory.slots[slot] = Some(ItemStack {
+            item_type: ItemType::SteelAxe,
+            quantity: 1,
+        });
+        handle_equip_completion(me, slot, &mut state, &mut RecordingSink::default());
+        assert_eq!(
+            equipped_tool(&state.entities[&me].equipment),
+            Some(ItemType::SteelAxe)
+        );
+        let steel = chop_time(&mut state);
+
+        assert!(steel < bronze, "steel {}s, bronze {}s", steel, bronze);
+    }
+
+    #[test]
+    fn a_100ms_tick_still_takes_one_step_a_tick() {
+        let mut state = ServerState::default();
+        let (player_id, me) = add_player(&mut state, tile(0, 0));
+        let walk_east = ClientMessage::QueueAction {
+            action: GameAction::Move {
+                path: (1..=5).map(|x| tile(x, 0)).collect(),
+            },
+            input_sequence_number: 1,
+            render_delay: 0.0,
+        };
+        let mut transport = InboxTransport::default();
+        transport.inbox.insert(
+            ClientId::from_raw(player_id.0),
+            VecDeque::from([bincode::serialize(&walk_east).unwrap()]),
+        );
+        let mut app = server_app(state, transport);
+        let timing = ServerTiming::new(0.1);
+        app.insert_resource(timing);
+
+        let mut steps = Vec::new();
+        for _ in 0..6 {
+            app.world_mut()
+                .resource_mut::<Time>()
+                .advance_by(std::time::Duration::from_millis(100));
+            app.update();
+            steps.push(
+                app.world().resource::<ServerState>().entities[&me]
+                    .tile_pos
+                    .x,
             );
+        }
+
+        // the walk takes its first tile as it starts, then one more each 100ms tick.
+        // at the default 600ms only the last of these updates would have ticked
+        assert_eq!(steps, vec![2, 3, 4, 5, 5, 5]);
+    }
 
-            let msg = ServerMessage::ItemAdded {
-                item_type: tree_def.logs_given,
-                quantity: 1,
+    #[test]
+    fn a_square_view_sees_the_corners_a_diamond_misses() {
+        let entities = HashMap::from([
+            (1, tile(VIEW_DISTANCE, 0)),
+            (2, tile(2, 3)),
+            (3, tile(3, 3)),
+            (4, tile(VIEW_DISTANCE, VIEW_DISTANCE)),
+            (5, tile(-VIEW_DISTANCE, -VIEW_DISTANCE)),
+            (6, tile(VIEW_DISTANCE + 1, 0)),
+        ]);
+        let visible = |metric| {
+            let mut interest_manager = InterestManager {
+                metric,
+                ..Default::default()
             };
-            send_message(server, player_id, &msg);
+            let (mut entered, _) = interest_manager.update_view(PlayerId(1), tile(0, 0), &entities);
+            entered.sort();
+            entered
+        };
 
-            let inv_msg = ServerMessage::InventoryUpdate {
-                inventory: inventory.clone(),
+        assert_eq!(visible(DistanceMetric::Chebyshev), vec![1, 2, 3, 4, 5]);
+        assert_eq!(visible(DistanceMetric::Manhattan), vec![1, 2]);
+    }
+
+    #[test]
+    fn three_players_joining_at_once_land_on_three_open_tiles() {
+        let mut state = ServerState::default();
+        let mut interest_manager = InterestManager::default();
+        let mut sink = RecordingSink::default();
+        for n in 1..=3 {
+            let join = ClientMessage::Join {
+                name: format!("Joiner{}", n),
+                account_id: format!("joiner-{}", n),
             };
-            send_message(server, player_id, &inv_msg);
-        } else {
-            warn!(" Player {:?} inventory full! Could not add logs", player_id);
+            receive(
+                &mut state,
+                &mut interest_manager,
+                &mut sink,
+                PlayerId(n),
+                join,
+            );
+        }
+
+        let tiles: HashSet<TilePosition> = state
+            .players
+            .values()
+            .map(|player| state.entities[&player.entity_id].tile_pos)
+            .collect();
+        assert_eq!(tiles.len(), 3);
+        for pos in &tiles {
+            assert!(
+                state.zone(MAIN_ZONE).pathfinder.is_walkable(pos),
+                "{:?}",
+                pos
+            );
         }
     }
 
-    if let Some(ref mut skills) = player_entity.skills {
-        let old_level = skills.get_level(SkillType::Woodcutting);
-        let old_xp = skills.get_experience(SkillType::Woodcutting);
-        let leveled_up = skills.add_experience(SkillType::Woodcutting, tree_def.experience);
-        let new_xp = skills.get_experience(SkillType::Woodcutting);
+    #[test]
+    fn twenty_queued_actions_stop_at_the_cap() {
+        let mut state = ServerState::default();
+        let (_, me) = add_player(&mut state, tile(0, 0));
+        walk(&mut state, me, (0..30).map(|x| tile(x, 0)).collect());
 
-        info!(
-            "Player {:?} gained {} Woodcutting XP ({} -> {})",
-            player_id, tree_def.experience, old_xp, new_xp
+        // chops don't cancel or replace a walk, so each one waits behind it
+        let entity = state.entities.get_mut(&me).unwrap();
+        let results: Vec<QueueResult> = (0..20)
+            .map(|tree_entity_id| {
+                queue_action_with_priority(
+                    &mut entity.action_queue,
+                    &mut entity.tile_pos,
+                    GameAction::ChopTree { tree_entity_id },
+                    0.0,
+                    TICK_RATE as f64,
+                    None,
+                    &HashSet::new(),
+                )
+            })
+            .collect();
+
+        let queued = results
+            .iter()
+            .filter(|result| matches!(result, QueueResult::Queued))
+            .count();
+        let full = results
+            .iter()
+            .filter(|result| matches!(result, QueueResult::QueueFull))
+            .count();
+        assert_eq!(
+            (queued, full),
+            (MAX_QUEUED_ACTIONS, 20 - MAX_QUEUED_ACTIONS)
         );
+        assert_eq!(entity.action_queue.actions.len(), MAX_QUEUED_ACTIONS);
+        assert!(matches!(
+            entity.action_queue.current_action,
+            Some(ActionInProgress {
+                action: GameAction::Move { .. },
+                ..
+            })
+        ));
 
-        let xp_msg = ServerMessage::ExperienceGained {
-            skill: SkillType::Woodcutting,
-            amount: tree_def.experience,
-        };
-        send_message(server, player_id, &xp_msg);
+        // a new walk still replaces the one in progress rather than waiting
+        let result = queue_action_with_priority(
+            &mut entity.action_queue,
+            &mut entity.tile_pos,
+            GameAction::Move {
+                path: vec![tile(0, 1)],
+            },
+            0.0,
+            TICK_RATE as f64,
+            None,
+            &HashSet::new(),
+        );
+        assert!(matches!(result, QueueResult::ReplacedSameType));
+        assert!(entity.action_queue.actions.is_empty());
+    }
 
-        let skill_data = &skills.skills[&SkillType::Woodcutting];
-        let skill_msg = ServerMessage::SkillUpdate {
-            skill: SkillType::Woodcutting,
-            level: skill_data.level,
-            experience: skill_data.experience,
+    #[test]
+    fn each_priority_cancels_suspends_or_waits_as_it_should() {
+        fn push(state: &mut ServerState, entity_id: u64, action: GameAction) -> QueueResult {
+            let current_time = state.game_time;
+            let entity = state.entities.get_mut(&entity_id).unwrap();
+            queue_action_with_priority(
+                &mut entity.action_queue,
+                &mut entity.tile_pos,
+                action,
+                current_time,
+                TICK_RATE as f64,
+                None,
+                &HashSet::new(),
+            )
+        }
+        fn current(state: &ServerState, entity_id: u64) -> Option<GameActionKind> {
+            let queue = &state.entities[&entity_id].action_queue;
+            queue.current_action.as_ref().map(|now| now.action.kind())
+        }
+        let mut state = ServerState::default();
+        let (_, me) = add_player(&mut state, tile(0, 0));
+        let mut rng = GameRng::new(1);
+        let east = |to: i32| GameAction::Move {
+            path: (0..=to).map(|x| tile(x, 0)).collect(),
         };
-        send_message(server, player_id, &skill_msg);
+        let chop = GameAction::ChopTree { tree_entity_id: 99 };
+        let interact = GameAction::Interact { entity_id: 99 };
 
-        if leveled_up {
-            info!(
-                "LEVEL UP! Player {:?} Woodcutting: {} -> {}",
-                player_id, old_level, skill_data.level
-            );
-            let levelup_msg = ServerMessage::LevelUp {
-                skill: SkillType::Woodcutting,
-                new_level: skill_data.level,
-            };
-            send_message(server, player_id, &levelup_msg);
+        // weak and other normal actions wait behind a walk, another walk replaces it
+        walk(&mut state, me, (0..=9).map(|x| tile(x, 0)).collect());
+        assert!(matches!(
+            push(&mut state, me, chop.clone()),
+            QueueResult::Queued
+        ));
+        let equip = GameAction::Equip { slot: 0 };
+        assert!(matches!(push(&mut state, me, equip), QueueResult::Queued));
+        assert!(matches!(
+            push(&mut state, me, east(9)),
+            QueueResult::ReplacedSameType
+        ));
+        assert!(state.entities[&me].action_queue.actions.is_empty());
+
+        // a strong action suspends the walk, which carries on once it is done
+        assert!(matches!(
+            push(&mut state, me, interact.clone()),
+            QueueResult::Suspended
+        ));
+        assert_eq!(current(&state, me), Some(GameActionKind::Interact));
+        let before = state.entities[&me].tile_pos;
+        for _ in 0..4 {
+            run_tick(&mut state, &mut rng, &mut RecordingSink::default());
         }
+        assert_eq!(current(&state, me), Some(GameActionKind::Move));
+        assert!(state.entities[&me].tile_pos.x > before.x);
+
+        // movement cancels a chop outright
+        state.entities.get_mut(&me).unwrap().action_queue = ActionQueue::default();
+        push(&mut state, me, chop.clone());
+        assert!(matches!(
+            push(&mut state, me, east(3)),
+            QueueResult::CancelledAndStarted
+        ));
+        assert_eq!(current(&state, me), Some(GameActionKind::Move));
+
+        // and so does a strong action, leaving nothing to resume
+        state.entities.get_mut(&me).unwrap().action_queue = ActionQueue::default();
+        push(&mut state, me, chop);
+        assert!(matches!(
+            push(&mut state, me, interact),
+            QueueResult::CancelledAndStarted
+        ));
+        assert!(state.entities[&me].action_queue.suspended_action.is_none());
     }
 
-    player_entity.action_queue.current_action = None;
+    #[test]
+    fn reconnecting_within_the_grace_period_keeps_the_same_entity_in_place() {
+        let (first, second) = (ClientId::from_raw(1), ClientId::from_raw(2));
+        let join = bincode::serialize(&ClientMessage::Join {
+            name: "Flaky".to_string(),
+            account_id: "flaky".to_string(),
+        })
+        .unwrap();
+        let mut transport = InboxTransport::default();
+        transport
+            .inbox
+            .insert(first, VecDeque::from([join.clone()]));
+        let mut app = server_app(ServerState::default(), transport);
+        let tick = std::time::Duration::from_secs_f32(ServerTiming::default().tick_duration);
+        let run_ticks = |app: &mut App, ticks: u32| {
+            for _ in 0..ticks {
+                app.world_mut().resource_mut::<Time>().advance_by(tick);
+                app.update();
+            }
+        };
+        run_ticks(&mut app, 1);
 
-    let completion_msg = ServerMessage::ActionCompleted {
-        entity_id: player_entity_id,
-    };
-    send_message(server, player_id, &completion_msg);
+        let state = app.world().resource::<ServerState>();
+        let entity_id = state.players[&PlayerId(1)].entity_id;
+        let grace = state.reconnect_grace_ticks;
+        assert!(grace > 2);
+        let spot = tile(3, -2);
+        app.world_mut()
+            .resource_mut::<ServerState>()
+            .entities
+            .get_mut(&entity_id)
+            .unwrap()
+            .tile_pos = spot;
 
-    let chopped_msg = ServerMessage::TreeChopped { tree_entity_id };
-    broadcast_message(server, &chopped_msg);
-    info!("Broadcasted tree {} chopped to all players", tree_entity_id);
-}
+        // the connection drops, and comes back a little later as a new client
+        app.world_mut()
+            .resource_mut::<InboxTransport>()
+            .inbox
+            .remove(&first);
+        run_ticks(&mut app, 2);
+        let state = app.world().resource::<ServerState>();
+        assert!(state.players[&PlayerId(1)].pending_removal.is_some());
+        assert!(state.entities.contains_key(&entity_id));
 
-pub fn update_interest_for_player(
-    player_id: PlayerId,
-    state: &ServerState,
-    interest_manager: &mut InterestManager,
-    server: &mut RenetServer,
-) {
-    let player_entity_id = match state.players.get(&player_id) {
-        Some(p) => p.entity_id,
-        None => return,
-    };
+        app.world_mut()
+            .resource_mut::<InboxTransport>()
+            .inbox
+            .insert(second, VecDeque::from([join]));
+        run_ticks(&mut app, grace as u32);
 
-    let player_pos = match state.entities.get(&player_entity_id) {
-        Some(e) => e.tile_pos,
-        None => return,
-    };
+        let state = app.world().resource::<ServerState>();
+        assert!(!state.players.contains_key(&PlayerId(1)));
+        let player = &state.players[&PlayerId(2)];
+        assert_eq!(player.pending_removal, None);
+        assert_eq!(player.entity_id, entity_id);
+        assert_eq!(state.entities[&entity_id].tile_pos, spot);
+        assert_eq!(state.entities[&entity_id].player_id, Some(PlayerId(2)));
+    }
 
-    let entity_positions: HashMap<u64, TilePosition> = state
-        .entities
-        .iter()
-        .map(|(id, e)| (*id, e.tile_pos))
-        .collect();
+    #[test]
+    fn an_oak_drops_a_birds_nest_about_once_in_a_hundred_chops() {
+        let mut state = ServerState::default();
+        let (player_id, me) = add_player(&mut state, tile(0, 0));
+        let oak = add_tree(&mut state, tile(1, 0), TreeType::Oak);
+        let interest_manager = InterestManager::default();
+        let mut rng = GameRng::new(7);
+        let mut sink = RecordingSink::default();
+        let chops = 10_000;
 
-    let (entered, left) = interest_manager.update_view(player_id, player_pos, &entity_positions);
+        for _ in 0..chops {
+            // keep the oak standing and the inventory roomy, only the roll should matter
+            let entity = state.entities.get_mut(&me).unwrap();
+            entity.inventory = Some(Inventory::new(28));
+            if let Some(ref mut tree) = state.entities.get_mut(&oak).unwrap().tree {
+                tree.is_chopped = false;
+            }
+            handle_woodcutting_completion(
+                me,
+                oak,
+                &mut state,
+                &mut rng,
+                &interest_manager,
+                &mut sink,
+            );
+        }
 
-    if !entered.is_empty() {
-        let snapshots: Vec<EntitySnapshot> = entered
-            .iter()
-            .filter_map(|id| {
-                state.entities.get(id).map(|e| EntitySnapshot {
-                    entity_id: *id,
-                    tile_position: e.tile_pos,
-                    player_id: e.player_id,
-                    tree: e.tree.clone(),
-                    last_processed_input: e.last_processed_input,
-                })
+        let nests: u32 = sink
+            .to(player_id)
+            .filter_map(|msg| match msg {
+                ServerMessage::ItemAdded {
+                    item_type: ItemType::BirdsNest,
+                    quantity,
+                } => Some(*quantity),
+                _ => None,
             })
-            .collect();
+            .sum();
+        let loot = state.definitions.tree(TreeType::Oak).loot;
+        let expected = chops * loot.entries[0].weight / loot.total_weight();
+        assert!(
+            nests.abs_diff(expected) <= expected / 4,
+            "{} nests in {} chops, expected about {}",
+            nests,
+            chops,
+            expected
+        );
+    }
 
-        let msg = ServerMessage::EntitiesEntered {
-            entities: snapshots,
+    #[test]
+    fn empty_overlong_and_emoji_names_fall_back_to_a_default() {
+        let player_id = PlayerId(7);
+        assert_eq!(sanitize_name("", player_id), "Player7");
+        assert_eq!(sanitize_name("   ", player_id), "Player7");
+        assert_eq!(sanitize_name(&"a".repeat(200), player_id), "Player7");
+        assert_eq!(sanitize_name("Lumber🪓Jack", player_id), "Player7");
+        assert_eq!(sanitize_name("Tab\tName", player_id), "Player7");
+        assert_eq!(sanitize_name("  Lumber Jack  ", player_id), "Lumber Jack");
+        let longest = "b".repeat(MAX_NAME_LENGTH);
+        assert_eq!(sanitize_name(&longest, player_id), longest);
+
+        // the client is told the name it ended up with
+        let mut state = ServerState::default();
+        let mut sink = RecordingSink::default();
+        let join = ClientMessage::Join {
+            name: "🌲".repeat(3),
+            account_id: "emoji".to_string(),
         };
-        send_message(server, player_id, &msg);
+        receive(
+            &mut state,
+            &mut InterestManager::default(),
+            &mut sink,
+            player_id,
+            join,
+        );
+        let welcomed = sink.to(player_id).find_map(|msg| match msg {
+            ServerMessage::Welcome { name, .. } => Some(name.clone()),
+            _ => None,
+        });
+        assert_eq!(welcomed.as_deref(), Some("Player7"));
+        assert_eq!(state.players[&player_id].name, "Player7");
     }
 
-    if !left.is_empty() {
-        let msg = ServerMessage::EntitiesLeft { entity_ids: left };
-        send_message(server, player_id, &msg);
+    #[test]
+    fn pacing_along_the_view_edge_enters_once_instead_of_every_tick() {
+        // an entity stepping back and forth across the edge of view each tick
+        let changes = |leave_margin| {
+            let mut interest_manager = InterestManager {
+                leave_margin,
+                ..Default::default()
+            };
+            let mut changes = 0;
+            for step in 0..20 {
+                let x = VIEW_DISTANCE + step % 2;
+                let entities = HashMap::from([(1, tile(x, 0))]);
+                let (entered, left) =
+                    interest_manager.update_view(PlayerId(1), tile(0, 0), &entities);
+                changes += entered.len() + left.len();
+            }
+            changes
+        };
+
+        assert_eq!(changes(0), 20);
+        assert_eq!(changes(DEFAULT_LEAVE_MARGIN), 1);
+
+        // past the margin it does leave
+        let mut interest_manager = InterestManager::default();
+        let near = HashMap::from([(1, tile(VIEW_DISTANCE, 0))]);
+        interest_manager.update_view(PlayerId(1), tile(0, 0), &near);
+        let gone = HashMap::from([(1, tile(VIEW_DISTANCE + DEFAULT_LEAVE_MARGIN + 1, 0))]);
+        let (_, left) = interest_manager.update_view(PlayerId(1), tile(0, 0), &gone);
+        assert_eq!(left, vec![1]);
     }
-}
 
-pub fn send_delta_updates(
-    state: &mut ServerState,
-    interest_manager: &InterestManager,
-    server: &mut RenetServer,
-    tick: u64,
-) {
-    let mut client_deltas: HashMap<PlayerId, Vec<EntityDelta>> = HashMap::new();
+    #[test]
+    fn players_asking_for_different_view_distances_see_different_things() {
+        let mut state = ServerState::default();
+        let (short_sighted, _) = add_player(&mut state, tile(0, 0));
+        let (far_sighted, _) = add_player(&mut state, tile(0, 0));
+        let trees: Vec<u64> = [2, 8, MAX_VIEW_DISTANCE, MAX_VIEW_DISTANCE + 1]
+            .into_iter()
+            .map(|y| add_tree(&mut state, tile(0, y), TreeType::Normal))
+            .collect();
+        let mut interest_manager = InterestManager::default();
+        let mut sink = RecordingSink::default();
+        for (player_id, tiles) in [(short_sighted, 3), (far_sighted, 100)] {
+            let msg = ClientMessage::SetViewDistance { tiles };
+            receive(&mut state, &mut interest_manager, &mut sink, player_id, msg);
+        }
 
-    for (entity_id, entity) in state.entities.iter() {
-        let last_state = state
-            .last_states
-            .entry(*entity_id)
-            .or_insert(EntityLastState {
-                tile_pos: entity.tile_pos,
-                last_sent_tick: 0,
-            });
+        // asking for more than the server allows gets the most it allows
+        let granted = |player_id| {
+            sink.to(player_id).find_map(|msg| match msg {
+                ServerMessage::GameConfig { view_distance, .. } => Some(*view_distance),
+                _ => None,
+            })
+        };
+        assert_eq!(granted(short_sighted), Some(3));
+        assert_eq!(granted(far_sighted), Some(MAX_VIEW_DISTANCE));
 
-        let changed = last_state.tile_pos != entity.tile_pos || last_state.last_sent_tick == 0;
+        let seen = |player_id| -> Vec<u64> {
+            trees
+                .iter()
+                .copied()
+                .filter(|tree| interest_manager.can_see(player_id, *tree))
+                .collect()
+        };
+        assert_eq!(seen(short_sighted), vec![trees[0]]);
+        assert_eq!(seen(far_sighted), trees[..3].to_vec());
+    }
 
-        if changed {
-            let delta = EntityDelta {
-                entity_id: *entity_id,
-                delta_type: if last_state.last_sent_tick == 0 {
-                    DeltaType::FullState {
-                        tile_pos: entity.tile_pos,
-                        player_id: entity.player_id,
-                        last_processed_input: entity.last_processed_input,
-                    }
-                } else {
-                    DeltaType::PositionOnly {
-                        tile_pos: entity.tile_pos,
-                        last_processed_input: entity.last_processed_input,
-                    }
-                },
+    #[test]
+    fn a_second_login_to_an_active_account_is_kicked() {
+        let mut state = ServerState::default();
+        let mut interest_manager = InterestManager::default();
+        let mut sink = RecordingSink::default();
+        let join = || ClientMessage::Join {
+            name: "Twin".to_string(),
+            account_id: "twin".to_string(),
+        };
+        let (first, second) = (PlayerId(1), PlayerId(2));
+        receive(&mut state, &mut interest_manager, &mut sink, first, join());
+        receive(&mut state, &mut interest_manager, &mut sink, second, join());
+
+        assert_eq!(state.players.len(), 1);
+        assert!(state.players.contains_key(&first));
+        assert!(state.kicked.contains_key(&second));
+        assert!(sink.to(second).any(|msg| matches!(
+            msg,
+            ServerMessage::Kicked { reason } if reason == "this account is already logged in"
+        )));
+        assert!(!sink
+            .to(first)
+            .any(|msg| matches!(msg, ServerMessage::Kicked { .. })));
+    }
+
+    #[test]
+    fn using_an_empty_slot_is_rejected_and_eating_food_heals() {
+        let mut state = ServerState::default();
+        let (player_id, me) = add_player(&mut state, tile(0, 0));
+        let entity = state.entities.get_mut(&me).unwrap();
+        entity.health = Some(PLAYER_MAX_HEALTH - 5);
+        let inventory = entity.inventory.as_mut().unwrap();
+        inventory.add_item(ItemType::Shrimp, 1, &state.definitions);
+        inventory.add_item(ItemType::Logs, 1, &state.definitions);
+        let slot_of = |item_type: ItemType| {
+            inventory
+                .slots
+                .iter()
+                .position(|stack| stack.as_ref().is_some_and(|s| s.item_type == item_type))
+                .unwrap()
+        };
+        let (shrimp, logs) = (slot_of(ItemType::Shrimp), slot_of(ItemType::Logs));
+        let empty = inventory.slots.iter().position(Option::is_none).unwrap();
+        let mut interest_manager = InterestManager::default();
+        let mut sink = RecordingSink::default();
+
+        // nothing at all, logs nobody can eat, then the shrimp
+        for (slot, input_sequence_number) in [(empty, 1), (logs, 2), (shrimp, 3)] {
+            let msg = ClientMessage::QueueAction {
+                action: GameAction::UseItem { slot },
+                input_sequence_number,
+                render_delay: 0.0,
             };
+            receive(&mut state, &mut interest_manager, &mut sink, player_id, msg);
+        }
+        assert_eq!(
+            action_results(&sink, player_id),
+            vec![
+                (1, ActionOutcome::Rejected(RejectReason::NothingUsable)),
+                (2, ActionOutcome::Rejected(RejectReason::NothingUsable)),
+                (3, ActionOutcome::Accepted),
+            ]
+        );
 
-            for (player_id, view) in interest_manager.client_views.iter() {
-                if view.contains(entity_id) {
-                    client_deltas
-                        .entry(*player_id)
-                        .or_insert_with(Vec::new)
-                        .push(delta.clone());
-                }
-            }
+        let mut rng = GameRng::new(1);
+        for _ in 0..5 {
+            run_tick(&mut state, &mut rng, &mut sink);
+        }
+        let entity = &state.entities[&me];
+        let inventory = entity.inventory.as_ref().unwrap();
+        assert_eq!(inventory.count_item(ItemType::Shrimp), 0);
+        assert_eq!(inventory.count_item(ItemType::Logs), 1);
+        assert_eq!(entity.health, Some(PLAYER_MAX_HEALTH - 2));
+    }
 
-            last_state.tile_pos = entity.tile_pos;
-            last_state.last_sent_tick = tick;
+    #[test]
+    fn running_until_empty_drops_to_a_walk_and_standing_still_refills() {
+        let mut state = ServerState::default();
+        let (_, me) = add_player(&mut state, tile(0, 0));
+        state.entities.get_mut(&me).unwrap().run = Some(RunState {
+            enabled: true,
+            energy: MAX_RUN_ENERGY,
+        });
+        walk(&mut state, me, (1..=60).map(|x| tile(x, 0)).collect());
+        let mut rng = GameRng::new(1);
+        let mut sink = RecordingSink::default();
+        let run = |state: &ServerState| state.entities[&me].run.unwrap();
+
+        let running_ticks = (MAX_RUN_ENERGY / RUN_ENERGY_DRAIN) as usize;
+        for _ in 0..running_ticks {
+            assert!(run(&state).is_running());
+            run_tick(&mut state, &mut rng, &mut sink);
+        }
+        assert_eq!(run(&state).energy, 0.0);
+        assert!(!run(&state).enabled);
+
+        // out of breath, the rest of the walk goes a tile a tick
+        let before = state.entities[&me].tile_pos;
+        run_tick(&mut state, &mut rng, &mut sink);
+        assert_eq!(state.entities[&me].tile_pos.x, before.x + 1);
+        assert_eq!(run(&state).energy, 0.0);
+
+        while state.entities[&me].action_queue.current_action.is_some() {
+            run_tick(&mut state, &mut rng, &mut sink);
         }
+        let rested = run(&state).energy;
+        for _ in 0..3 {
+            run_tick(&mut state, &mut rng, &mut sink);
+        }
+        assert_eq!(run(&state).energy, rested + 3.0 * RUN_ENERGY_REGEN);
+        assert!(!run(&state).enabled);
     }
 
-    for (player_id, deltas) in client_deltas {
-        if !deltas.is_empty() {
-            debug!("Sending {} deltas to player {:?}", deltas.len(), player_id);
-            let msg = ServerMessage::DeltaUpdate { tick, deltas };
-            let msg_bytes = bincode::serialize(&msg).unwrap();
-            server.send_message(
-                ClientId::from_raw(player_id.0),
-                DefaultChannel::Unreliable,
-                msg_bytes,
+    #[test]
+    fn paths_that_skip_tiles_or_cross_a_tree_are_rejected() {
+        let mut state = ServerState::default();
+        let (player_id, me) = add_player(&mut state, tile(0, 0));
+        add_tree(&mut state, tile(2, 0), TreeType::Normal);
+        let mut interest_manager = InterestManager::default();
+        let mut sink = RecordingSink::default();
+        let paths = [
+            vec![tile(1, 0), tile(3, 2)],
+            vec![tile(1, 0), tile(2, 0), tile(3, 0)],
+            vec![tile(0, 1), tile(1, 1), tile(2, 1), tile(3, 1)],
+        ];
+
+        for (input_sequence_number, path) in (1..).zip(paths) {
+            let msg = ClientMessage::QueueAction {
+                action: GameAction::Move { path },
+                input_sequence_number,
+                render_delay: 0.0,
+            };
+            receive(&mut state, &mut interest_manager, &mut sink, player_id, msg);
+            if input_sequence_number < 3 {
+                assert_eq!(state.entities[&me].tile_pos, tile(0, 0));
+            }
+        }
+
+        assert_eq!(
+            action_results(&sink, player_id),
+            vec![
+                (1, ActionOutcome::Rejected(RejectReason::InvalidPath)),
+                (2, ActionOutcome::Rejected(RejectReason::InvalidPath)),
+                (3, ActionOutcome::Accepted),
+            ]
+        );
+    }
+
+    #[test]
+    fn reaching_woodcutting_ten_unlocks_lumberjack_once() {
+        let mut state = ServerState::default();
+        let (player_id, me) = add_player(&mut state, tile(0, 0));
+        let tree = add_tree(&mut state, tile(1, 0), TreeType::Normal);
+        let skills = state
+            .entities
+            .get_mut(&me)
+            .unwrap()
+            .skills
+            .as_mut()
+            .unwrap();
+        skills.add_experience(
+            SkillType::Woodcutting,
+            Skills::xp_for_level(SKILL_MILESTONE_LEVEL) - 1,
+        );
+        assert_eq!(
+            skills.get_level(SkillType::Woodcutting),
+            SKILL_MILESTONE_LEVEL - 1
+        );
+        let interest_manager = InterestManager::default();
+        let mut rng = GameRng::new(1);
+        let mut sink = RecordingSink::default();
+
+        for _ in 0..3 {
+            if let Some(ref mut tree) = state.entities.get_mut(&tree).unwrap().tree {
+                tree.is_chopped = false;
+            }
+            handle_woodcutting_completion(
+                me,
+                tree,
+                &mut state,
+                &mut rng,
+                &interest_manager,
+                &mut sink,
             );
         }
+
+        let unlocked: Vec<AchievementId> = sink
+            .to(player_id)
+            .filter_map(|msg| match msg {
+                ServerMessage::AchievementUnlocked { id, .. } => Some(*id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            unlocked,
+            vec![AchievementId::FirstLogs, AchievementId::Woodcutting10]
+        );
+        let achievements = state.entities[&me].achievements.as_ref().unwrap();
+        assert!(achievements
+            .unlocked
+            .contains(&AchievementId::Woodcutting10));
     }
-}
 
-pub fn send_message(server: &mut RenetServer, player_id: PlayerId, msg: &ServerMessage) {
-    let msg_type = match msg {
-        ServerMessage::Welcome { .. } => "Welcome",
-        ServerMessage::DeltaUpdate { .. } => "DeltaUpdate",
-        ServerMessage::EntitiesEntered { .. } => "EntitiesEntered",
-        ServerMessage::EntitiesLeft { .. } => "EntitiesLeft",
-        ServerMessage::ActionQueued { .. } => "ActionQueued",
-        ServerMessage::ActionCompleted { .. } => "ActionCompleted",
-        ServerMessage::PathFound { .. } => "PathFound",
-        ServerMessage::PathNotFound => "PathNotFound",
-        ServerMessage::ObstacleData { .. } => "ObstacleData",
-        ServerMessage::InventoryUpdate { .. } => "InventoryUpdate",
-        ServerMessage::ItemAdded { .. } => "ItemAdded",
-        ServerMessage::ItemRemoved { .. } => "ItemRemoved",
-        ServerMessage::SkillUpdate { .. } => "SkillUpdate",
-        ServerMessage::LevelUp { .. } => "LevelUp",
-        ServerMessage::ExperienceGained { .. } => "ExperienceGained",
-        ServerMessage::TreeChopped { .. } => "TreeChopped",
-        ServerMessage::TreeRespawned { .. } => "TreeRespawned",
-        ServerMessage::NotEnoughLevel { .. } => "NotEnoughLevel",
-        ServerMessage::NoAxeEquipped => "NoAxeEquipped",
-    };
+    #[test]
+    fn a_spectator_sees_what_it_pans_to_but_cannot_act() {
+        let mut state = ServerState::default();
+        let mut interest_manager = InterestManager::default();
+        let mut sink = RecordingSink::default();
+        let watcher = PlayerId(1);
+        receive(
+            &mut state,
+            &mut interest_manager,
+            &mut sink,
+            watcher,
+            ClientMessage::JoinAsSpectator,
+        );
+        let center = state.spectators[&watcher].center;
+        let far = VIEW_DISTANCE * 3;
+        let tree = add_tree(&mut state, tile(center.x + far, center.y), TreeType::Normal);
+        let entities_before = state.entities.len();
+        let entered = |sink: &RecordingSink| {
+            sink.to(watcher).any(|msg| match msg {
+                ServerMessage::EntitiesEntered { entities } => {
+                    entities.iter().any(|entity| entity.entity_id == tree)
+                }
+                _ => false,
+            })
+        };
+        assert!(!entered(&sink));
 
-    let msg_bytes = bincode::serialize(msg).unwrap();
-    debug!(
-        "Sending {} to player {:?} ({} bytes)",
-        msg_type,
-        player_id,
-        msg_bytes.len()
-    );
-    server.send_message(
-        ClientId::from_raw(player_id.0),
-        DefaultChannel::ReliableOrdered,
-        msg_bytes,
-    );
-}
+        let pan = ClientMessage::MoveSpectatorCamera {
+            center: tile(center.x + far - 1, center.y),
+        };
+        receive(&mut state, &mut interest_manager, &mut sink, watcher, pan);
+        assert!(entered(&sink));
 
-pub fn broadcast_message(server: &mut RenetServer, msg: &ServerMessage) {
-    let msg_type = match msg {
-        ServerMessage::TreeChopped { .. } => "TreeChopped",
-        ServerMessage::TreeRespawned { .. } => "TreeRespawned",
-        ServerMessage::EntitiesLeft { .. } => "EntitiesLeft",
-        _ => "Unknown",
-    };
+        // it has no body to move, so the walk goes nowhere and nobody answers it
+        let msg = ClientMessage::QueueAction {
+            action: GameAction::Move {
+                path: vec![tile(center.x + 1, center.y)],
+            },
+            input_sequence_number: 1,
+            render_delay: 0.0,
+        };
+        receive(&mut state, &mut interest_manager, &mut sink, watcher, msg);
+        assert!(action_results(&sink, watcher).is_empty());
+        assert!(!state.players.contains_key(&watcher));
+        assert_eq!(state.entities.len(), entities_before);
+        assert!(state
+            .entities
+            .values()
+            .all(|entity| entity.player_id != Some(watcher)));
+    }
 
-    let msg_bytes = bincode::serialize(msg).unwrap();
-    debug!(
-        "Broadcasting {} to all players ({} bytes)",
-        msg_type,
-        msg_bytes.len()
-    );
-    server.broadcast_message(DefaultChannel::ReliableOrdered, msg_bytes);
-}
+    #[test]
+    fn joining_a_full_server_is_kicked_with_a_reason() {
+        let mut state = ServerState {
+            max_clients: 2,
+            ..Default::default()
+        };
+        let mut interest_manager = InterestManager::default();
+        let mut sink = RecordingSink::default();
+        for n in 1..=3 {
+            let join = ClientMessage::Join {
+                name: format!("Joiner{}", n),
+                account_id: format!("joiner-{}", n),
+            };
+            receive(
+                &mut state,
+                &mut interest_manager,
+                &mut sink,
+                PlayerId(n),
+                join,
+            );
+        }
+        receive(
+            &mut state,
+            &mut interest_manager,
+            &mut sink,
+            PlayerId(4),
+            ClientMessage::JoinAsSpectator,
+        );
 
-pub fn handle_disconnections(
-    server: &mut RenetServer,
-    state: &mut ServerState,
-    interest_manager: &mut InterestManager,
-    commands: &mut Commands,
-) {
-    let connected_clients: HashSet<u64> =
-        server.clients_id().into_iter().map(|id| id.raw()).collect();
+        assert_eq!(state.players.len(), 2);
+        assert!(state.spectators.is_empty());
+        let kicked_full = |player_id: PlayerId| {
+            sink.to(player_id).any(|msg| {
+                matches!(msg, ServerMessage::Kicked { reason } if reason == SERVER_FULL_REASON)
+            })
+        };
+        for turned_away in [PlayerId(3), PlayerId(4)] {
+            assert!(kicked_full(turned_away), "{:?}", turned_away);
+            assert!(state.kicked.contains_key(&turned_away));
+        }
+        assert!(!kicked_full(PlayerId(1)) && !kicked_full(PlayerId(2)));
+    }
 
-    let disconnected_players: Vec<PlayerId> = state
-        .players
-        .keys()
-        .filter(|player_id| !connected_clients.contains(&player_id.0))
-        .copied()
-        .collect();
+    #[test]
+    fn a_wandering_npc_moves_about_on_walkable_tiles() {
+        let mut state = ServerState::default();
+        let home = tile(0, 0);
+        let wanderer = add_npc(&mut state, home, false);
+        assert_eq!(state.entities[&wanderer].kind, EntityKind::Npc);
+        assert_eq!(state.entities[&wanderer].player_id, None);
+        let mut rng = GameRng::new(3);
+        let mut sink = RecordingSink::default();
 
-    for player_id in disconnected_players {
-        if let Some(player) = state.players.remove(&player_id) {
-            info!("Player {:?} disconnected", player_id);
-            if let Some(entity_data) = state.entities.remove(&player.entity_id) {
-                commands.entity(entity_data.entity).despawn();
-            }
-            interest_manager.client_views.remove(&player_id);
-            state.last_states.remove(&player.entity_id);
+        let mut visited = HashSet::from([home]);
+        for _ in 0..60 {
+            run_tick(&mut state, &mut rng, &mut sink);
+            let pos = state.entities[&wanderer].tile_pos;
+            assert!(
+                state.zone(MAIN_ZONE).pathfinder.is_walkable(&pos),
+                "{:?}",
+                pos
+            );
+            assert!(pos.distance_chebyshev(&home) <= npc::NPC_WANDER_RADIUS);
+            visited.insert(pos);
+        }
+        assert!(visited.len() > 1);
+    }
 
-            let msg = ServerMessage::EntitiesLeft {
-                entity_ids: vec![player.entity_id],
-            };
-            broadcast_message(server, &msg);
+    #[test]
+    fn an_aggressive_npc_hurts_a_player_nearby_until_they_run_off() {
+        let mut state = ServerState::default();
+        let (_, me) = add_player(&mut state, tile(2, 0));
+        let brute = add_npc(&mut state, tile(0, 0), true);
+        let mut rng = GameRng::new(3);
+        let mut sink = RecordingSink::default();
+        let health = |state: &ServerState| state.entities[&me].health.unwrap();
+        let target = |state: &ServerState| {
+            state.entities[&brute]
+                .npc
+                .as_ref()
+                .and_then(|npc| npc.target.as_ref())
+                .map(|target| target.entity_id)
+        };
+
+        let mut ticks = 0;
+        while health(&state) == PLAYER_MAX_HEALTH {
+            run_tick(&mut state, &mut rng, &mut sink);
+            ticks += 1;
+            assert!(ticks < 30, "the npc never landed a hit");
+        }
+        assert_eq!(target(&state), Some(me));
+
+        // running covers two tiles a tick to the npc's one, so the gap soon opens up
+        state.entities.get_mut(&me).unwrap().run = Some(RunState {
+            enabled: true,
+            energy: MAX_RUN_ENERGY,
+        });
+        let from = state.entities[&me].tile_pos;
+        walk(
+            &mut state,
+            me,
+            (1..=30).map(|x| tile(from.x + x, from.y)).collect(),
+        );
+        for _ in 0..20 {
+            run_tick(&mut state, &mut rng, &mut sink);
+        }
+        let gap = state.entities[&me]
+            .tile_pos
+            .distance_chebyshev(&state.entities[&brute].tile_pos);
+        assert!(gap > npc::NPC_DETECTION_RADIUS, "{}", gap);
+        assert_eq!(target(&state), None);
+        let fled_with = health(&state);
+        for _ in 0..10 {
+            run_tick(&mut state, &mut rng, &mut sink);
         }
+        assert!(health(&state) >= fled_with);
     }
 }
@@ -1,9 +1,17 @@
 use bevy::log::tracing_subscriber;
 use bevy::prelude::*;
+use bevy_renet::renet::RenetServer;
 use bevy_renet::transport::NetcodeServerPlugin;
 use bevy_renet::*;
+use server::admin::{admin_console_system, AdminConsole};
+use server::event_log::dump_event_log_on_exit;
 use server::interest_manager::InterestManager;
-use server::{server_update_system, setup_server, ServerState};
+use server::metrics::BandwidthStats;
+use server::persistence::save_world_on_exit;
+use server::rng::GameRng;
+use server::shutdown::{handle_shutdown, ShutdownSignal};
+use server::timing::ServerTiming;
+use server::{record_client_accounts, server_update_system, setup_server, ServerState};
 
 fn main() {
     tracing_subscriber::fmt()
@@ -19,7 +27,22 @@ fn main() {
         .add_plugins(NetcodeServerPlugin)
         .init_resource::<ServerState>()
         .init_resource::<InterestManager>()
+        .init_resource::<BandwidthStats>()
+        .insert_resource(ServerTiming::from_args_or_env())
+        .insert_resource(GameRng::from_args_or_env())
+        .insert_resource(AdminConsole::from_stdin())
+        .insert_resource(ShutdownSignal::install())
         .add_systems(Startup, setup_server)
-        .add_systems(Update, server_update_system)
+        .add_systems(
+            Update,
+            (
+                admin_console_system::<RenetServer>,
+                record_client_accounts,
+                server_update_system::<RenetServer>,
+                handle_shutdown::<RenetServer>,
+            )
+                .chain(),
+        )
+        .add_systems(Last, (save_world_on_exit, dump_event_log_on_exit))
         .run();
 }
@@ -0,0 +1,104 @@
+use bevy::prelude::*;
+use bevy::utils::tracing::info;
+use bevy_renet::renet::{ClientId, DefaultChannel};
+use shared::transport::{MessageSink, ServerTransport};
+use std::collections::HashMap;
+
+/// how often the server health and per-player bandwidth summaries are logged
+pub const METRICS_LOG_INTERVAL_TICKS: u64 = 50; // 30s at the default tick rate
+
+/// bytes sent to each client and deltas sent overall, summed over a logging window
+/// and then reset
+#[derive(Resource, Default)]
+pub struct BandwidthStats {
+    pub window_bytes: HashMap<u64, u64>,
+    pub window_messages: HashMap<u64, u64>,
+    pub window_deltas: u64,
+    pub window_ticks: u64,
+}
+
+impl BandwidthStats {
+    pub fn record(&mut self, client_id: ClientId, bytes: usize) {
+        let total = self.window_bytes.entry(client_id.raw()).or_default();
+        *total = total.saturating_add(bytes as u64);
+        let count = self.window_messages.entry(client_id.raw()).or_default();
+        *count = count.saturating_add(1);
+    }
+
+    pub fn record_deltas(&mut self, count: usize) {
+        self.window_deltas = self.window_deltas.saturating_add(count as u64);
+    }
+
+    /// called once per server tick, logs and resets the window when it fills up
+    pub fn end_tick(&mut self, tick: u64, connected_players: usize, entities: usize) {
+        self.window_ticks += 1;
+        if self.window_ticks < METRICS_LOG_INTERVAL_TICKS {
+            return;
+        }
+
+        let total_bytes: u64 = self.window_bytes.values().sum();
+        let bytes_per_player = match self.window_bytes.len() as u64 {
+            0 => 0,
+            players => total_bytes / players / self.window_ticks,
+        };
+        info!(
+            "Server tick #{}: {} players, {} entities, {:.1} deltas/tick, {} bytes/tick per player",
+            tick,
+            connected_players,
+            entities,
+            self.window_deltas as f64 / self.window_ticks as f64,
+            bytes_per_player
+        );
+
+        let mut clients: Vec<_> = self.window_bytes.iter().collect();
+        clients.sort();
+        for (client_id, bytes) in clients {
+            info!(
+                "Bandwidth PlayerId({}): {} bytes/tick, {} msgs over {} ticks",
+                client_id,
+                bytes / self.window_ticks,
+                self.window_messages.get(client_id).copied().unwrap_or(0),
+                self.window_ticks
+            );
+        }
+
+        self.window_bytes.clear();
+        self.window_messages.clear();
+        self.window_deltas = 0;
+        self.window_ticks = 0;
+    }
+}
+
+/// wraps a transport and counts every byte sent through it
+pub struct MeteredTransport<'a, T: ServerTransport> {
+    pub inner: &'a mut T,
+    pub stats: &'a mut BandwidthStats,
+}
+
+impl<T: ServerTransport> MessageSink for MeteredTransport<'_, T> {
+    fn send_message(&mut self, client_id: ClientId, channel: DefaultChannel, message: Vec<u8>) {
+        self.stats.record(client_id, message.len());
+        self.inner.send_message(client_id, channel, message);
+    }
+
+    fn broadcast_message(&mut self, channel: DefaultChannel, message: Vec<u8>) {
+        for client_id in self.inner.clients_id() {
+            self.stats.record(client_id, message.len());
+        }
+        self.inner.broadcast_message(channel, message);
+    }
+}
+
+impl<T: ServerTransport> ServerTransport for MeteredTransport<'_, T> {
+    fn clients_id(&self) -> Vec<ClientId> {
+        self.inner.clients_id()
+    }
+
+    fn receive_message(&mut self, client_id: ClientId, channel: DefaultChannel) -> Option<Vec<u8>> {
+        self.inner.receive_message(client_id, channel)
+    }
+
+    fn disconnect(&mut self, client_id: ClientId) {
+        self.inner.disconnect(client_id);
+    }
+}
@@ -0,0 +1,350 @@
+use bevy::prelude::*;
+use bevy::utils::tracing::{debug, info, warn};
+use shared::actions::GameAction;
+use shared::items::Equipment;
+use shared::tile_system::TilePosition;
+use shared::{EntityKind, PlayerId, ZoneId, NPC_MAX_HEALTH};
+
+use crate::event_log::EntityEventKind;
+use crate::rng::GameRng;
+use crate::{
+    occupied_tiles, queue_action_with_priority, ActionQueue, ServerEntity, ServerState, MAIN_ZONE,
+};
+
+pub struct NpcSpawn {
+    pub home: TilePosition,
+    pub aggressive: bool,
+}
+
+/// where the main zone's npcs start out, each wanders around its own spot
+pub const NPC_SPAWNS: [NpcSpawn; 3] = [
+    NpcSpawn {
+        home: TilePosition { x: -2, y: 4 },
+        aggressive: false,
+    },
+    NpcSpawn {
+        home: TilePosition { x: 5, y: 3 },
+        aggressive: false,
+    },
+    // well clear of the spawn points, so nobody is set upon the moment they join
+    NpcSpawn {
+        home: TilePosition { x: 11, y: -2 },
+        aggressive: true,
+    },
+];
+
+/// furthest an npc strays from home, in tiles along each axis
+pub const NPC_WANDER_RADIUS: i32 = 4;
+
+/// ticks an npc stands around between walks, picked at random in this range
+pub const NPC_IDLE_TICKS: std::ops::Range<i32> = 4..10;
+
+/// how close a player has to come, in tiles either way, before an aggressive npc
+/// goes after them
+pub const NPC_DETECTION_RADIUS: i32 = 5;
+
+/// ticks a target can spend outside the detection radius before the npc gives up
+/// and goes back to wandering
+pub const NPC_GIVE_UP_TICKS: u64 = 5;
+
+/// a server-driven wanderer. it has no player, it just walks somewhere near home
+/// every so often
+#[derive(Clone, Debug)]
+pub struct Npc {
+    pub home: TilePosition,
+    /// tick it sets off for somewhere new, if it isn't still walking
+    pub next_wander_tick: u64,
+    pub aggressive: bool,
+    /// the player an aggressive npc is after, while it is after one
+    pub target: Option<NpcTarget>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NpcTarget {
+    pub player_id: PlayerId,
+    pub entity_id: u64,
+    /// where the target stood when the npc last set off after them
+    pub chasing_to: Option<TilePosition>,
+    pub out_of_range_ticks: u64,
+}
+
+pub fn spawn_npcs(state: &mut ServerState, commands: &mut Commands) {
+    for spawn in NPC_SPAWNS {
+        if !state.zone(MAIN_ZONE).pathfinder.is_walkable(&spawn.home) {
+            warn!("Npc home {:?} is blocked, not spawning there", spawn.home);
+            continue;
+        }
+        let entity_id = state.next_entity_id;
+        state.next_entity_id += 1;
+        spawn_npc(state, commands, entity_id, spawn.home, spawn.aggressive);
+    }
+}
+
+pub fn spawn_npc(
+    state: &mut ServerState,
+    commands: &mut Commands,
+    entity_id: u64,
+    home: TilePosition,
+    aggressive: bool,
+) {
+    let entity = commands
+        .spawn((
+            home,
+            Transform::from_translation(home.to_world().extend(0.0)),
+        ))
+        .id();
+
+    let server_entity = ServerEntity {
+        kind: EntityKind::Npc,
+        tile_pos: home,
+        zone: MAIN_ZONE,
+        player_id: None,
+        action_queue: ActionQueue::default(),
+        entity,
+        is_obstacle: false,
+        inventory: None,
+        skills: None,
+        achievements: None,
+        equipment: Equipment::new(),
+        tree: None,
+        rock: None,
+        bank: None,
+        ground_item: None,
+        despawn_tick: None,
+        last_processed_input: None,
+        health: Some(NPC_MAX_HEALTH),
+        last_damaged_tick: None,
+        run: None,
+        npc: Some(Npc {
+            home,
+            next_wander_tick: state.server_tick,
+            aggressive,
+            target: None,
+        }),
+    };
+
+    state.entities.insert(entity_id, server_entity);
+    state.dirty_entities.insert(entity_id);
+    state
+        .event_log
+        .record(state.server_tick, entity_id, EntityEventKind::Spawned);
+    info!(
+        "Spawned {} npc {} at {:?}",
+        if aggressive { "aggressive" } else { "passive" },
+        entity_id,
+        home
+    );
+}
+
+/// sends every idle npc whose wait is over towards a random walkable tile near its
+/// home. the walk is an ordinary move, so it goes out in the usual position deltas
+pub fn wander_npcs(state: &mut ServerState, rng: &mut GameRng, tick_duration: f64) {
+    let tick = state.server_tick;
+    let due: Vec<u64> = state
+        .entities
+        .iter()
+        .filter(|(_, entity)| {
+            entity.action_queue.current_action.is_none()
+                && entity
+                    .npc
+                    .as_ref()
+                    .is_some_and(|npc| npc.target.is_none() && tick >= npc.next_wander_tick)
+        })
+        .map(|(entity_id, _)| *entity_id)
+        .collect();
+
+    for entity_id in due {
+        let (from, zone, home) = match state.entities.get(&entity_id) {
+            Some(ServerEntity {
+                tile_pos,
+                zone,
+                npc: Some(npc),
+                ..
+            }) => (*tile_pos, *zone, npc.home),
+            _ => continue,
+        };
+        let target = TilePosition {
+            x: home.x + rng.range(-NPC_WANDER_RADIUS..NPC_WANDER_RADIUS + 1),
+            y: home.y + rng.range(-NPC_WANDER_RADIUS..NPC_WANDER_RADIUS + 1),
+        };
+        let idle_ticks = rng.range(NPC_IDLE_TICKS) as u64;
+        // an unwalkable or unreachable target just means standing around a bit longer
+        let path = state
+            .zone_mut(zone)
+            .pathfinder
+            .find_path(from, target)
+            .filter(|path| path.len() > 1);
+
+        let occupied = occupied_tiles(state, entity_id);
+        let current_time = state.game_time;
+        let entity = match state.entities.get_mut(&entity_id) {
+            Some(entity) => entity,
+            None => continue,
+        };
+        if let Some(npc) = entity.npc.as_mut() {
+            npc.next_wander_tick = tick + idle_ticks;
+        }
+        if let Some(path) = path {
+            debug!(
+                "Npc {} wandering from {:?} to {:?}",
+                entity_id, from, target
+            );
+            queue_action_with_priority(
+                &mut entity.action_queue,
+                &mut entity.tile_pos,
+                GameAction::Move { path },
+                current_time,
+                tick_duration,
+                None,
+                &occupied,
+            );
+            state.dirty_entities.insert(entity_id);
+        }
+    }
+}
+
+/// the closest player within the detection radius of `tile`, ignoring anyone parked
+/// after a disconnect
+fn nearest_player_in_range(
+    state: &ServerState,
+    zone: ZoneId,
+    tile: TilePosition,
+) -> Option<NpcTarget> {
+    state
+        .players
+        .iter()
+        .filter(|(_, player)| player.pending_removal.is_none())
+        .filter_map(|(player_id, player)| {
+            let entity = state.entities.get(&player.entity_id)?;
+            let distance = entity.tile_pos.distance_chebyshev(&tile);
+            (entity.zone == zone && distance <= NPC_DETECTION_RADIUS).then_some((
+                distance,
+                *player_id,
+                player.entity_id,
+            ))
+        })
+        .min_by_key(|(distance, _, entity_id)| (*distance, *entity_id))
+        .map(|(_, player_id, entity_id)| NpcTarget {
+            player_id,
+            entity_id,
+            chasing_to: None,
+            out_of_range_ticks: 0,
+        })
+}
+
+/// aggressive npcs pick up the nearest player that comes within the detection
+/// radius, walk up to them and attack. once the target has been out of range for
+/// `NPC_GIVE_UP_TICKS` the npc drops what it was doing and wanders again
+pub fn chase_players(state: &mut ServerState, tick_duration: f64) {
+    let aggressive: Vec<u64> = state
+        .entities
+        .iter()
+        .filter(|(_, entity)| entity.npc.as_ref().is_some_and(|npc| npc.aggressive))
+        .map(|(entity_id, _)| *entity_id)
+        .collect();
+
+    for entity_id in aggressive {
+        let (from, zone, target) = match state.entities.get(&entity_id) {
+            Some(ServerEntity {
+                tile_pos,
+                zone,
+                npc: Some(npc),
+                ..
+            }) => (*tile_pos, *zone, npc.target.clone()),
+            _ => continue,
+        };
+        let mut target = match target.or_else(|| nearest_player_in_range(state, zone, from)) {
+            Some(target) => target,
+            None => continue,
+        };
+        let target_tile = state
+            .entities
+            .get(&target.entity_id)
+            .filter(|entity| entity.zone == zone)
+            .map(|entity| entity.tile_pos);
+        match target_tile {
+            Some(tile) if tile.distance_chebyshev(&from) <= NPC_DETECTION_RADIUS => {
+                target.out_of_range_ticks = 0;
+            }
+            Some(_) => target.out_of_range_ticks += 1,
+            // gone, or off to another zone
+            None => target.out_of_range_ticks = NPC_GIVE_UP_TICKS,
+        }
+
+        let current_time = state.game_time;
+        let tick = state.server_tick;
+        let target_tile = match target_tile {
+            Some(tile) if target.out_of_range_ticks < NPC_GIVE_UP_TICKS => tile,
+            _ => {
+                info!("Npc {} lost interest in {:?}", entity_id, target.player_id);
+                if let Some(entity) = state.entities.get_mut(&entity_id) {
+                    clear_actions(&mut entity.action_queue);
+                    if let Some(npc) = entity.npc.as_mut() {
+                        npc.target = None;
+                        npc.next_wander_tick = tick;
+                    }
+                }
+                continue;
+            }
+        };
+
+        let current = state
+            .entities
+            .get(&entity_id)
+            .and_then(|entity| entity.action_queue.current_action.as_ref())
+            .map(|current| current.action.clone());
+        let adjacent = from.distance_chebyshev(&target_tile) <= 1;
+        let next = match current {
+            // an attack in progress is left to finish
+            Some(GameAction::Attack { .. }) if adjacent => None,
+            _ if adjacent => Some(GameAction::Attack {
+                target_entity_id: target.entity_id,
+            }),
+            Some(GameAction::Move { .. }) if target.chasing_to == Some(target_tile) => None,
+            _ => {
+                // the target's own tile is taken, so the walk stops one short of it
+                let path = state
+                    .zone_mut(zone)
+                    .pathfinder
+                    .find_path(from, target_tile)
+                    .map(|mut path| {
+                        path.pop();
+                        path
+                    })
+                    .filter(|path| path.len() > 1);
+                target.chasing_to = Some(target_tile);
+                path.map(|path| GameAction::Move { path })
+            }
+        };
+
+        let occupied = occupied_tiles(state, entity_id);
+        let entity = match state.entities.get_mut(&entity_id) {
+            Some(entity) => entity,
+            None => continue,
+        };
+        if let Some(action) = next {
+            if matches!(action, GameAction::Attack { .. }) {
+                debug!("Npc {} attacking {:?}", entity_id, target.player_id);
+            }
+            clear_actions(&mut entity.action_queue);
+            queue_action_with_priority(
+                &mut entity.action_queue,
+                &mut entity.tile_pos,
+                action,
+                current_time,
+                tick_duration,
+                None,
+                &occupied,
+            );
+            state.dirty_entities.insert(entity_id);
+        }
+        if let Some(npc) = entity.npc.as_mut() {
+            npc.target = Some(target);
+        }
+    }
+}
+
+fn clear_actions(queue: &mut ActionQueue) {
+    queue.current_action = None;
+    queue.actions.clear();
+}
@@ -0,0 +1,229 @@
+use bevy::prelude::*;
+use bevy::utils::tracing::{info, warn};
+use serde::{Deserialize, Serialize};
+use shared::definitions::Definitions;
+use shared::mining::Rock;
+use shared::tile_map::TileMap;
+use shared::tile_system::TilePosition;
+use shared::trees::Tree;
+use shared::ZoneId;
+use std::fs;
+use std::path::Path;
+
+use crate::profiles::{PlayerProfile, PlayerProfileStore};
+use crate::{spawn_rock, spawn_tree, ServerState, MAIN_ZONE};
+
+pub const WORLD_SAVE_PATH: &str = "world.save";
+pub const MAP_PATH: &str = "world.map";
+pub const DEFINITIONS_PATH: &str = "definitions.ron";
+pub const SAVE_INTERVAL_TICKS: u64 = 100; // 60s at the default tick rate
+
+#[derive(Serialize, Deserialize)]
+struct SavedEntity {
+    entity_id: u64,
+    zone: ZoneId,
+    tile_pos: TilePosition,
+    tree: Option<Tree>,
+    rock: Option<Rock>,
+}
+
+/// everything that survives a server restart. players are not saved as entities,
+/// their progress lives in the profile store instead
+#[derive(Serialize, Deserialize)]
+struct WorldSave {
+    next_entity_id: u64,
+    entities: Vec<SavedEntity>,
+    profiles: PlayerProfileStore,
+}
+
+impl ServerState {
+    pub fn save_to_path(&self, path: &Path) -> bincode::Result<()> {
+        let entities = self
+            .entities
+            .iter()
+            .filter(|(_, entity)| entity.player_id.is_none())
+            .map(|(entity_id, entity)| SavedEntity {
+                entity_id: *entity_id,
+                zone: entity.zone,
+                tile_pos: entity.tile_pos,
+                tree: entity.tree.clone(),
+                rock: entity.rock.clone(),
+            })
+            .collect();
+
+        // include the current progress of everyone still online
+        let mut profiles = PlayerProfileStore {
+            profiles: self.profiles.profiles.clone(),
+        };
+        for player in self.players.values() {
+            if let Some(entity) = self.entities.get(&player.entity_id) {
+                if let (Some(inventory), Some(skills)) = (&entity.inventory, &entity.skills) {
+                    profiles.save(
+                        &player.account_id,
+                        PlayerProfile {
+                            inventory: inventory.clone(),
+                            skills: skills.clone(),
+                            equipment: entity.equipment.clone(),
+                            bank: player.bank.clone(),
+                            achievements: entity.achievements.clone().unwrap_or_default(),
+                        },
+                    );
+                }
+            }
+        }
+
+        let save = WorldSave {
+            next_entity_id: self.next_entity_id,
+            entities,
+            profiles,
+        };
+
+        // write then rename so a crash mid-save never leaves a partial file behind
+        let bytes = bincode::serialize(&save)?;
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, bytes)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// loads a save written by `save_to_path`. the state is left untouched on error
+    pub fn load_from_path(&mut self, path: &Path, commands: &mut Commands) -> bincode::Result<()> {
+        let bytes = fs::read(path)?;
+        let save: WorldSave = bincode::deserialize(&bytes)?;
+
+        for saved in save.entities {
+            // a zone that no longer exists would have nowhere to put it
+            if !self.zones.contains_key(&saved.zone) {
+                warn!(
+                    "Saved entity {} is in unknown zone {:?}, skipping it",
+                    saved.entity_id, saved.zone
+                );
+                continue;
+            }
+            if let Some(tree) = saved.tree {
+                spawn_tree(
+                    self,
+                    commands,
+                    saved.entity_id,
+                    saved.zone,
+                    saved.tile_pos,
+                    tree,
+                );
+            } else if let Some(rock) = saved.rock {
+                spawn_rock(
+                    self,
+                    commands,
+                    saved.entity_id,
+                    saved.zone,
+                    saved.tile_pos,
+                    rock,
+                );
+            }
+        }
+        self.next_entity_id = self.next_entity_id.max(save.next_entity_id);
+        self.profiles = save.profiles;
+        Ok(())
+    }
+}
+
+pub fn save_world(state: &ServerState) {
+    match state.save_to_path(Path::new(WORLD_SAVE_PATH)) {
+        Ok(()) => info!(
+            "World saved to {} (tick {})",
+            WORLD_SAVE_PATH, state.server_tick
+        ),
+        Err(err) => warn!("Failed to save world to {}: {}", WORLD_SAVE_PATH, err),
+    }
+}
+
+/// replaces the bundled map with `world.map` from the working directory, if there is one
+pub fn load_tile_map(state: &mut ServerState) {
+    let path = Path::new(MAP_PATH);
+    if !path.exists() {
+        info!("No {} found, using the bundled map", MAP_PATH);
+        return;
+    }
+
+    match fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|text| TileMap::parse(&text))
+    {
+        Ok(tile_map) => {
+            info!(
+                "Loaded map from {} ({} tiles)",
+                MAP_PATH,
+                tile_map.tiles.len()
+            );
+            state.set_tile_map(MAIN_ZONE, tile_map);
+        }
+        Err(err) => warn!(
+            "Map file {} is unreadable ({}), using the bundled map",
+            MAP_PATH, err
+        ),
+    }
+}
+
+/// item and tree definitions from `definitions.ron` in the working directory, if
+/// there is one. each entry replaces the built-in definition of its type
+pub fn load_definitions(state: &mut ServerState) {
+    let path = Path::new(DEFINITIONS_PATH);
+    if !path.exists() {
+        info!(
+            "No {} found, using the built-in definitions",
+            DEFINITIONS_PATH
+        );
+        return;
+    }
+
+    match fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|text| ron::from_str::<Definitions>(&text).map_err(|err| err.to_string()))
+    {
+        Ok(definitions) => {
+            info!(
+                "Loaded {} item and {} tree definitions from {}",
+                definitions.items.len(),
+                definitions.trees.len(),
+                DEFINITIONS_PATH
+            );
+            state.definitions = definitions;
+        }
+        Err(err) => warn!(
+            "Definitions file {} is unreadable ({}), using the built-in definitions",
+            DEFINITIONS_PATH, err
+        ),
+    }
+}
+
+/// loads the saved world if there is one, otherwise (or if it is unreadable) returns false
+pub fn load_world(state: &mut ServerState, commands: &mut Commands) -> bool {
+    let path = Path::new(WORLD_SAVE_PATH);
+    if !path.exists() {
+        return false;
+    }
+
+    match state.load_from_path(path, commands) {
+        Ok(()) => {
+            info!(
+                "Loaded world from {} ({} entities, {} profiles)",
+                WORLD_SAVE_PATH,
+                state.entities.len(),
+                state.profiles.profiles.len()
+            );
+            true
+        }
+        Err(err) => {
+            warn!(
+                "Save file {} is unreadable ({}), starting a fresh world",
+                WORLD_SAVE_PATH, err
+            );
+            false
+        }
+    }
+}
+
+pub fn save_world_on_exit(mut exit_events: EventReader<AppExit>, state: Res<ServerState>) {
+    if exit_events.read().next().is_some() {
+        save_world(&state);
+    }
+}
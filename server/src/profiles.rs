@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+use shared::achievements::Achievements;
+use shared::inventory::Inventory;
+use shared::items::Equipment;
+use shared::skills::Skills;
+use std::collections::HashMap;
+
+/// progress kept for a player between sessions
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PlayerProfile {
+    pub inventory: Inventory,
+    pub skills: Skills,
+    pub equipment: Equipment,
+    pub bank: Inventory,
+    pub achievements: Achievements,
+}
+
+/// player profiles keyed by the persistent account id sent in `Join`
+#[derive(Serialize, Deserialize, Default)]
+pub struct PlayerProfileStore {
+    pub profiles: HashMap<String, PlayerProfile>,
+}
+
+impl PlayerProfileStore {
+    pub fn get(&self, account_id: &str) -> Option<&PlayerProfile> {
+        self.profiles.get(account_id)
+    }
+
+    pub fn save(&mut self, account_id: &str, profile: PlayerProfile) {
+        self.profiles.insert(account_id.to_string(), profile);
+    }
+}
@@ -0,0 +1,97 @@
+use bevy::prelude::*;
+use bevy::utils::tracing::{info, warn};
+use std::ops::Range;
+use std::time::SystemTime;
+
+/// environment variable with the gameplay rng seed
+pub const SEED_ENV: &str = "SERVER_SEED";
+/// command line flag with the gameplay rng seed, takes priority over the env var
+pub const SEED_ARG: &str = "--seed";
+
+/// the only source of randomness for gameplay. a small xorshift64* generator, so two
+/// servers started with the same seed and fed the same inputs play out identically
+#[derive(Resource, Clone, Debug)]
+pub struct GameRng {
+    seed: u64,
+    state: u64,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        // splitmix64 spreads small seeds over the whole state and never yields 0,
+        // which would lock xorshift at 0 forever
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Self {
+            seed,
+            state: z.max(1),
+        }
+    }
+
+    /// reads `--seed <n>` or `SERVER_SEED`, falling back to a seed from the clock.
+    /// the seed is logged either way so a run can be replayed
+    pub fn from_args_or_env() -> Self {
+        let args: Vec<String> = std::env::args().collect();
+        let from_args = args
+            .iter()
+            .position(|arg| arg == SEED_ARG)
+            .and_then(|i| args.get(i + 1).cloned());
+        let value = from_args.or_else(|| std::env::var(SEED_ENV).ok());
+
+        let seed = match value.map(|value| (value.parse::<u64>(), value)) {
+            Some((Ok(seed), _)) => seed,
+            Some((Err(_), value)) => {
+                warn!("Invalid seed '{}', seeding from the clock", value);
+                clock_seed()
+            }
+            None => clock_seed(),
+        };
+        info!("Gameplay rng seed: {}", seed);
+        Self::new(seed)
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// uniform in `0.0..1.0`
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// true with probability `p`, so 0.0 never and 1.0 always
+    pub fn chance(&mut self, p: f32) -> bool {
+        self.next_f32() < p
+    }
+
+    /// uniform in `range`, or its start if the range is empty
+    pub fn range(&mut self, range: Range<i32>) -> i32 {
+        if range.is_empty() {
+            return range.start;
+        }
+        let span = (range.end as i64 - range.start as i64) as u64;
+        (range.start as i64 + (self.next_u64() % span) as i64) as i32
+    }
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+fn clock_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,73 @@
+use bevy::prelude::*;
+use bevy::utils::tracing::{info, warn};
+use bevy_renet::renet::ClientId;
+use shared::messages::ServerMessage;
+use shared::transport::ServerTransport;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::broadcast_message;
+
+/// reason clients are given when the server is stopped with Ctrl-C or SIGTERM
+pub const SHUTDOWN_REASON: &str = "the server is shutting down";
+
+/// set from the signal handler, the app notices it on its next frame
+#[derive(Resource, Clone, Default)]
+pub struct ShutdownSignal {
+    requested: Arc<AtomicBool>,
+    /// clients were told during an earlier frame, so the message has been sent
+    notified: bool,
+}
+
+impl ShutdownSignal {
+    /// a signal tripped by Ctrl-C and SIGTERM
+    pub fn install() -> Self {
+        let signal = Self::default();
+        let requested = signal.requested.clone();
+        if let Err(err) = ctrlc::set_handler(move || requested.store(true, Ordering::SeqCst)) {
+            warn!("Could not install the shutdown handler: {}", err);
+        }
+        signal
+    }
+
+    /// asks for a shutdown the way Ctrl-C does
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+}
+
+/// once a shutdown is requested, tells every client why and, a frame later when the
+/// message has gone out, disconnects them and exits. the world is saved on exit
+pub fn handle_shutdown<T: ServerTransport + Resource>(
+    mut signal: ResMut<ShutdownSignal>,
+    mut transport: ResMut<T>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if !signal.is_requested() {
+        return;
+    }
+
+    if !signal.notified {
+        info!(
+            "Shutting down, notifying {} clients",
+            transport.clients_id().len()
+        );
+        let msg = ServerMessage::ServerShutdown {
+            reason: SHUTDOWN_REASON.to_string(),
+        };
+        broadcast_message(&mut *transport, &msg);
+        signal.notified = true;
+        return;
+    }
+
+    let clients: Vec<ClientId> = transport.clients_id();
+    for client_id in clients {
+        transport.disconnect(client_id);
+    }
+    info!("Shutdown complete");
+    exit.send(AppExit::Success);
+}
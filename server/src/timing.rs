@@ -0,0 +1,51 @@
+use bevy::prelude::*;
+use bevy::utils::tracing::warn;
+use shared::TICK_RATE;
+
+/// environment variable with the tick length in milliseconds
+pub const TICK_MS_ENV: &str = "SERVER_TICK_MS";
+/// command line flag with the tick length in milliseconds, takes priority over the env var
+pub const TICK_MS_ARG: &str = "--tick-ms";
+
+/// how much real time one server tick takes. can be shortened to exercise the netcode
+/// at higher update rates
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct ServerTiming {
+    /// seconds per tick
+    pub tick_duration: f32,
+}
+
+impl Default for ServerTiming {
+    fn default() -> Self {
+        Self {
+            tick_duration: TICK_RATE,
+        }
+    }
+}
+
+impl ServerTiming {
+    pub fn new(tick_duration: f32) -> Self {
+        Self { tick_duration }
+    }
+
+    /// reads `--tick-ms <ms>` or `SERVER_TICK_MS`, falling back to the default tick rate
+    pub fn from_args_or_env() -> Self {
+        let args: Vec<String> = std::env::args().collect();
+        let from_args = args
+            .iter()
+            .position(|arg| arg == TICK_MS_ARG)
+            .and_then(|i| args.get(i + 1).cloned());
+        let value = from_args.or_else(|| std::env::var(TICK_MS_ENV).ok());
+
+        match value {
+            Some(value) => match value.parse::<u32>() {
+                Ok(ms) if ms > 0 => Self::new(ms as f32 / 1000.0),
+                _ => {
+                    warn!("Invalid tick length '{}', using the default", value);
+                    Self::default()
+                }
+            },
+            None => Self::default(),
+        }
+    }
+}
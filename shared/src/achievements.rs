@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::skills::SkillType;
+
+/// level a skill has to reach for its milestone achievement
+pub const SKILL_MILESTONE_LEVEL: u32 = 10;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AchievementId {
+    FirstLogs,
+    FirstOre,
+    Woodcutting10,
+    Mining10,
+}
+
+impl AchievementId {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AchievementId::FirstLogs => "Timber!",
+            AchievementId::FirstOre => "Rock bottom",
+            AchievementId::Woodcutting10 => "Lumberjack",
+            AchievementId::Mining10 => "Prospector",
+        }
+    }
+
+    /// the achievement for reaching `SKILL_MILESTONE_LEVEL` in `skill`, if it has one
+    pub fn skill_milestone(skill: SkillType, level: u32) -> Option<Self> {
+        if level < SKILL_MILESTONE_LEVEL {
+            return None;
+        }
+        match skill {
+            SkillType::Woodcutting => Some(AchievementId::Woodcutting10),
+            SkillType::Mining => Some(AchievementId::Mining10),
+            _ => None,
+        }
+    }
+}
+
+/// achievements a player has unlocked, kept in their profile
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Achievements {
+    pub unlocked: HashSet<AchievementId>,
+}
+
+impl Achievements {
+    /// true if the achievement is new, false if it was already unlocked
+    pub fn unlock(&mut self, id: AchievementId) -> bool {
+        self.unlocked.insert(id)
+    }
+}
@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
 
-use crate::{tile_system::TilePosition, PlayerId, TICK_RATE};
+use crate::{
+    items::{EquipSlot, ItemType},
+    tile_system::TilePosition,
+};
 
 /// Action priority levels
 /// Strong > Normal > Weak
@@ -26,14 +29,117 @@ impl ActionPriority {
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum GameAction {
-    Move { path: Vec<TilePosition> },
-    Attack { target: PlayerId },
-    UseItem { item_id: u32 },
-    Interact { entity_id: u64 },
-    ChopTree { tree_entity_id: u64 },
+    Move {
+        path: Vec<TilePosition>,
+    },
+    Attack {
+        target_entity_id: u64,
+    },
+    UseItem {
+        slot: usize,
+    },
+    Interact {
+        entity_id: u64,
+    },
+    ChopTree {
+        tree_entity_id: u64,
+    },
+    Mine {
+        rock_entity_id: u64,
+    },
+    DropItem {
+        slot: usize,
+    },
+    PickUp {
+        entity_id: u64,
+    },
+    Equip {
+        slot: usize,
+    },
+    Unequip {
+        slot: EquipSlot,
+    },
+    Deposit {
+        slot: usize,
+        quantity: u32,
+    },
+    Withdraw {
+        item_type: ItemType,
+        quantity: u32,
+    },
+    Teleport {
+        destination: TilePosition,
+    },
+    EnterZone {
+        portal_id: u32,
+    },
+    /// chops the nearest standing tree in view, then the next nearest, until cancelled.
+    /// the server turns it into a walk and a `ChopTree` each time
+    ChopNearest,
+}
+
+/// which kind of action an entity is doing, without the action's data
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GameActionKind {
+    Move,
+    Attack,
+    UseItem,
+    Interact,
+    ChopTree,
+    Mine,
+    DropItem,
+    PickUp,
+    Equip,
+    Unequip,
+    Deposit,
+    Withdraw,
+    Teleport,
+    EnterZone,
+    ChopNearest,
+}
+
+impl GameActionKind {
+    /// every kind, in declaration order so `ALL[kind as usize] == kind`
+    pub const ALL: [GameActionKind; 15] = [
+        GameActionKind::Move,
+        GameActionKind::Attack,
+        GameActionKind::UseItem,
+        GameActionKind::Interact,
+        GameActionKind::ChopTree,
+        GameActionKind::Mine,
+        GameActionKind::DropItem,
+        GameActionKind::PickUp,
+        GameActionKind::Equip,
+        GameActionKind::Unequip,
+        GameActionKind::Deposit,
+        GameActionKind::Withdraw,
+        GameActionKind::Teleport,
+        GameActionKind::EnterZone,
+        GameActionKind::ChopNearest,
+    ];
 }
 
 impl GameAction {
+    pub fn kind(&self) -> GameActionKind {
+        match self {
+            GameAction::Move { .. } => GameActionKind::Move,
+            GameAction::Attack { .. } => GameActionKind::Attack,
+            GameAction::UseItem { .. } => GameActionKind::UseItem,
+            GameAction::Interact { .. } => GameActionKind::Interact,
+            GameAction::ChopTree { .. } => GameActionKind::ChopTree,
+            GameAction::Mine { .. } => GameActionKind::Mine,
+            GameAction::DropItem { .. } => GameActionKind::DropItem,
+            GameAction::PickUp { .. } => GameActionKind::PickUp,
+            GameAction::Equip { .. } => GameActionKind::Equip,
+            GameAction::Unequip { .. } => GameActionKind::Unequip,
+            GameAction::Deposit { .. } => GameActionKind::Deposit,
+            GameAction::Withdraw { .. } => GameActionKind::Withdraw,
+            GameAction::Teleport { .. } => GameActionKind::Teleport,
+            GameAction::EnterZone { .. } => GameActionKind::EnterZone,
+            GameAction::ChopNearest => GameActionKind::ChopNearest,
+        }
+    }
+
     pub fn priority(&self) -> ActionPriority {
         match self {
             GameAction::Move { .. } => ActionPriority::Normal,
@@ -41,35 +147,85 @@ impl GameAction {
             GameAction::UseItem { .. } => ActionPriority::Normal,
             GameAction::Interact { .. } => ActionPriority::Strong,
             GameAction::ChopTree { .. } => ActionPriority::Weak,
+            GameAction::Mine { .. } => ActionPriority::Weak,
+            GameAction::DropItem { .. } => ActionPriority::Normal,
+            GameAction::PickUp { .. } => ActionPriority::Normal,
+            GameAction::Equip { .. } => ActionPriority::Normal,
+            GameAction::Unequip { .. } => ActionPriority::Normal,
+            GameAction::Deposit { .. } => ActionPriority::Normal,
+            GameAction::Withdraw { .. } => ActionPriority::Normal,
+            GameAction::Teleport { .. } => ActionPriority::Strong,
+            GameAction::EnterZone { .. } => ActionPriority::Strong,
+            GameAction::ChopNearest => ActionPriority::Weak,
         }
     }
 
     /// get the base tick delay for this action in ticks
     pub fn tick_delay(&self) -> u32 {
         match self {
-            GameAction::Move { .. } => 1,     // 1 tick per tile (0.6s)
-            GameAction::Attack { .. } => 4,   // 4 ticks (2.4s) - typical weapon speed
-            GameAction::UseItem { .. } => 1,  // 1 tick (0.6s) - eat/drink
-            GameAction::Interact { .. } => 2, // 2 ticks (1.2s) - interact delay
-            GameAction::ChopTree { .. } => 4, // 4 ticks (2.4s) - chop attempt
+            GameAction::Move { .. } => 1,      // 1 tick per tile (0.6s)
+            GameAction::Attack { .. } => 4,    // 4 ticks (2.4s) - typical weapon speed
+            GameAction::UseItem { .. } => 1,   // 1 tick (0.6s) - eat/drink
+            GameAction::Interact { .. } => 2,  // 2 ticks (1.2s) - interact delay
+            GameAction::ChopTree { .. } => 4,  // 4 ticks (2.4s) - chop attempt
+            GameAction::Mine { .. } => 5,      // 5 ticks (3.0s) - mine attempt
+            GameAction::DropItem { .. } => 1,  // 1 tick (0.6s) - drop
+            GameAction::PickUp { .. } => 1,    // 1 tick (0.6s) - pick up
+            GameAction::Equip { .. } => 1,     // 1 tick (0.6s) - wield
+            GameAction::Unequip { .. } => 1,   // 1 tick (0.6s) - remove
+            GameAction::Deposit { .. } => 1,   // 1 tick (0.6s) - bank deposit
+            GameAction::Withdraw { .. } => 1,  // 1 tick (0.6s) - bank withdraw
+            GameAction::Teleport { .. } => 1,  // applied on the next tick
+            GameAction::EnterZone { .. } => 1, // applied on the next tick
+            GameAction::ChopNearest => 1,      // replaced by a walk and a chop
         }
     }
 
-    pub fn duration_seconds(&self) -> f64 {
-        self.tick_delay() as f64 * TICK_RATE as f64
+    /// how long the action takes. gathering speed lives here alone, the tree and rock
+    /// definitions only say what is gathered
+    pub fn duration_seconds(&self, tick_duration: f64) -> f64 {
+        self.tick_delay() as f64 * tick_duration
+    }
+
+    /// tick delay after the speed bonus of the equipped tool, if it suits the action
+    pub fn tick_delay_with_tool(&self, tool: Option<ItemType>) -> u32 {
+        let bonus = match (self, tool) {
+            (GameAction::ChopTree { .. }, Some(tool)) if tool.is_axe() => tool.tool_speed_bonus(),
+            (GameAction::Mine { .. }, Some(tool)) if tool.is_pickaxe() => tool.tool_speed_bonus(),
+            _ => 0,
+        };
+        self.tick_delay().saturating_sub(bonus).max(1)
+    }
+
+    pub fn duration_seconds_with_tool(&self, tool: Option<ItemType>, tick_duration: f64) -> f64 {
+        self.tick_delay_with_tool(tool) as f64 * tick_duration
     }
 
     pub fn replaces_same_type(&self, other: &GameAction) -> bool {
         match (self, other) {
             (GameAction::Move { .. }, GameAction::Move { .. }) => true,
             (GameAction::ChopTree { .. }, GameAction::ChopTree { .. }) => true,
+            (GameAction::Mine { .. }, GameAction::Mine { .. }) => true,
             (GameAction::Attack { .. }, GameAction::Attack { .. }) => true,
             _ => false,
         }
     }
 
+    /// the entity this action is aimed at, if any
+    pub fn target_entity(&self) -> Option<u64> {
+        match self {
+            GameAction::Interact { entity_id } | GameAction::PickUp { entity_id } => {
+                Some(*entity_id)
+            }
+            GameAction::ChopTree { tree_entity_id } => Some(*tree_entity_id),
+            GameAction::Mine { rock_entity_id } => Some(*rock_entity_id),
+            GameAction::Attack { target_entity_id } => Some(*target_entity_id),
+            _ => None,
+        }
+    }
+
     /// repeating actions loop until cancelled or resource depleted
     pub fn is_repeating(&self) -> bool {
-        matches!(self, GameAction::ChopTree { .. })
+        matches!(self, GameAction::ChopTree { .. } | GameAction::Mine { .. })
     }
 }
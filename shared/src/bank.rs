@@ -0,0 +1,9 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// slots in each player's bank
+pub const BANK_SLOTS: usize = 200;
+
+/// a bank booth. players standing next to one can deposit and withdraw items
+#[derive(Component, Serialize, Deserialize, Clone, Debug)]
+pub struct Bank;
@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+use crate::items::{ItemDefinition, ItemType};
+use crate::trees::{TreeDefinition, TreeType};
+
+/// item and tree definitions that replace the built-in ones. the server loads them
+/// from a data file and sends them to clients on join, anything left out keeps its
+/// built-in definition. the server keeps them in its state and the client in its
+/// own, so a reconnect never sees what another server loaded
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Definitions {
+    #[serde(default)]
+    pub items: Vec<ItemDefinition>,
+    #[serde(default)]
+    pub trees: Vec<TreeDefinition>,
+}
+
+impl Definitions {
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty() && self.trees.is_empty()
+    }
+
+    /// the item definition in use, the loaded one if there is one and the built-in
+    /// default otherwise
+    pub fn item(&self, item_type: ItemType) -> ItemDefinition {
+        self.items
+            .iter()
+            .find(|def| def.item_type == item_type)
+            .cloned()
+            .unwrap_or_else(|| ItemDefinition::builtin(item_type))
+    }
+
+    /// the tree definition in use, the loaded one if there is one and the built-in
+    /// default otherwise
+    pub fn tree(&self, tree_type: TreeType) -> TreeDefinition {
+        self.trees
+            .iter()
+            .find(|def| def.tree_type == tree_type)
+            .cloned()
+            .unwrap_or_else(|| TreeDefinition::builtin(tree_type))
+    }
+}
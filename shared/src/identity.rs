@@ -0,0 +1,26 @@
+use bevy_renet::renet::transport::NETCODE_USER_DATA_BYTES;
+
+/// longest account id that fits in the connect token next to its length byte
+pub const MAX_ACCOUNT_ID_BYTES: usize = NETCODE_USER_DATA_BYTES - 1;
+
+/// packs the account id into netcode user data, which the server reads during the
+/// connection handshake. `None` if the id is empty or too long to fit
+pub fn account_to_user_data(account_id: &str) -> Option<[u8; NETCODE_USER_DATA_BYTES]> {
+    let bytes = account_id.as_bytes();
+    if bytes.is_empty() || bytes.len() > MAX_ACCOUNT_ID_BYTES {
+        return None;
+    }
+    let mut data = [0; NETCODE_USER_DATA_BYTES];
+    data[0] = bytes.len() as u8;
+    data[1..=bytes.len()].copy_from_slice(bytes);
+    Some(data)
+}
+
+/// the account id packed by `account_to_user_data`, if there is a valid one
+pub fn account_from_user_data(data: &[u8; NETCODE_USER_DATA_BYTES]) -> Option<String> {
+    let len = data[0] as usize;
+    if len == 0 {
+        return None;
+    }
+    String::from_utf8(data[1..=len].to_vec()).ok()
+}
@@ -1,7 +1,8 @@
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::items::{ItemDefinition, ItemStack, ItemType};
+use crate::definitions::Definitions;
+use crate::items::{ItemStack, ItemType};
 
 #[derive(Serialize, Deserialize, Clone, Debug, Component)]
 pub struct Inventory {
@@ -17,31 +18,45 @@ impl Inventory {
         }
     }
 
-    pub fn add_item(&mut self, item_type: ItemType, quantity: u32) -> bool {
-        let def = ItemDefinition::get(item_type);
+    /// adds as many of the items as fit, topping up existing stacks before
+    /// spilling into empty slots. returns how many were actually added
+    pub fn add_item(
+        &mut self,
+        item_type: ItemType,
+        quantity: u32,
+        definitions: &Definitions,
+    ) -> u32 {
+        let def = definitions.item(item_type);
+        let mut remaining = quantity;
 
         if def.stackable {
-            for slot in &mut self.slots {
-                if let Some(stack) = slot {
-                    if stack.item_type == item_type {
-                        stack.quantity += quantity;
-                        return true;
-                    }
+            for stack in self.slots.iter_mut().flatten() {
+                if remaining == 0 {
+                    break;
+                }
+                if stack.item_type == item_type && stack.quantity < def.max_stack {
+                    let added = remaining.min(def.max_stack - stack.quantity);
+                    stack.quantity += added;
+                    remaining -= added;
                 }
             }
         }
 
         for slot in &mut self.slots {
+            if remaining == 0 {
+                break;
+            }
             if slot.is_none() {
+                let added = remaining.min(def.max_stack);
                 *slot = Some(ItemStack {
                     item_type,
-                    quantity,
+                    quantity: added,
                 });
-                return true;
+                remaining -= added;
             }
         }
 
-        false
+        quantity - remaining
     }
 
     pub fn remove_item(&mut self, item_type: ItemType, quantity: u32) -> bool {
@@ -59,6 +74,91 @@ impl Inventory {
         false
     }
 
+    /// removes up to `quantity` of an item across all stacks, returns how many were removed
+    pub fn take_item(&mut self, item_type: ItemType, quantity: u32) -> u32 {
+        let mut remaining = quantity;
+        for slot in &mut self.slots {
+            if remaining == 0 {
+                break;
+            }
+            if let Some(stack) = slot {
+                if stack.item_type == item_type {
+                    let taken = remaining.min(stack.quantity);
+                    stack.quantity -= taken;
+                    remaining -= taken;
+                    if stack.quantity == 0 {
+                        *slot = None;
+                    }
+                }
+            }
+        }
+        quantity - remaining
+    }
+
+    /// removes up to `quantity` from a single slot, returns how many were removed
+    pub fn take_from_slot(&mut self, slot: usize, quantity: u32) -> u32 {
+        match self.slots.get_mut(slot) {
+            Some(entry) => match entry {
+                Some(stack) => {
+                    let taken = quantity.min(stack.quantity);
+                    stack.quantity -= taken;
+                    if stack.quantity == 0 {
+                        *entry = None;
+                    }
+                    taken
+                }
+                None => 0,
+            },
+            None => 0,
+        }
+    }
+
+    pub fn swap_slots(&mut self, a: usize, b: usize) -> bool {
+        if a >= self.max_slots || b >= self.max_slots {
+            return false;
+        }
+        self.slots.swap(a, b);
+        true
+    }
+
+    /// moves the item in `from` to `to`. stacks of the same stackable type are merged
+    /// up to the stack cap, anything else already in `to` is swapped back into `from`
+    pub fn move_item(&mut self, from: usize, to: usize, definitions: &Definitions) -> bool {
+        if from >= self.max_slots || to >= self.max_slots || self.slots[from].is_none() {
+            return false;
+        }
+        if from == to {
+            return true;
+        }
+
+        if let (Some(source), Some(target)) = (&self.slots[from], &self.slots[to]) {
+            let def = definitions.item(source.item_type);
+            if source.item_type == target.item_type
+                && def.stackable
+                && target.quantity < def.max_stack
+            {
+                let moved = source.quantity.min(def.max_stack - target.quantity);
+                if let Some(target) = self.slots[to].as_mut() {
+                    target.quantity += moved;
+                }
+                if let Some(source) = self.slots[from].as_mut() {
+                    source.quantity -= moved;
+                    if source.quantity == 0 {
+                        self.slots[from] = None;
+                    }
+                }
+                return true;
+            }
+        }
+
+        self.swap_slots(from, to)
+    }
+
+    /// empties a slot and returns what was in it
+    pub fn take_slot(&mut self, slot: usize) -> Option<ItemStack> {
+        self.slots.get_mut(slot).and_then(|s| s.take())
+    }
+
     pub fn has_item(&self, item_type: ItemType, quantity: u32) -> bool {
         self.count_item(item_type) >= quantity
     }
@@ -81,4 +181,104 @@ impl Inventory {
         }
         None
     }
+
+    pub fn has_any_pickaxe(&self) -> Option<ItemType> {
+        let pickaxes = [
+            ItemType::SteelPickaxe,
+            ItemType::IronPickaxe,
+            ItemType::BronzePickaxe,
+        ];
+        pickaxes
+            .into_iter()
+            .find(|&pickaxe| self.has_item(pickaxe, 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::items::MAX_STACK;
+
+    fn stack(item_type: ItemType, quantity: u32) -> Option<ItemStack> {
+        Some(ItemStack {
+            item_type,
+            quantity,
+        })
+    }
+
+    /// what sits in `slot`, in a form that can be compared
+    fn held(inventory: &Inventory, slot: usize) -> Option<(ItemType, u32)> {
+        inventory.slots[slot]
+            .as_ref()
+            .map(|stack| (stack.item_type, stack.quantity))
+    }
+
+    #[test]
+    fn swapping_two_filled_slots_trades_their_items() {
+        let mut inventory = Inventory::new(4);
+        inventory.slots[0] = stack(ItemType::BronzeAxe, 1);
+        inventory.slots[1] = stack(ItemType::Logs, 5);
+
+        assert!(inventory.swap_slots(0, 1));
+        assert_eq!(held(&inventory, 0), Some((ItemType::Logs, 5)));
+        assert_eq!(held(&inventory, 1), Some((ItemType::BronzeAxe, 1)));
+        assert!(!inventory.swap_slots(0, 4));
+    }
+
+    #[test]
+    fn moving_onto_an_empty_slot_leaves_the_old_one_empty() {
+        let definitions = Definitions::default();
+        let mut inventory = Inventory::new(4);
+        inventory.slots[0] = stack(ItemType::BronzeAxe, 1);
+
+        assert!(inventory.move_item(0, 3, &definitions));
+        assert_eq!(held(&inventory, 0), None);
+        assert_eq!(held(&inventory, 3), Some((ItemType::BronzeAxe, 1)));
+        // nothing left to move, and no slot past the end
+        assert!(!inventory.move_item(0, 1, &definitions));
+        assert!(!inventory.move_item(3, 4, &definitions));
+    }
+
+    #[test]
+    fn moving_logs_onto_logs_merges_the_stacks() {
+        let definitions = Definitions::default();
+        let mut inventory = Inventory::new(4);
+        inventory.slots[0] = stack(ItemType::Logs, 3);
+        inventory.slots[2] = stack(ItemType::Logs, 4);
+
+        assert!(inventory.move_item(0, 2, &definitions));
+        assert_eq!(held(&inventory, 0), None);
+        assert_eq!(held(&inventory, 2), Some((ItemType::Logs, 7)));
+    }
+
+    #[test]
+    fn logs_past_the_stack_cap_spill_into_the_next_free_slot() {
+        let definitions = Definitions::default();
+        let mut inventory = Inventory::new(3);
+        inventory.slots[0] = stack(ItemType::Logs, MAX_STACK - 10);
+        inventory.slots[1] = stack(ItemType::BronzeAxe, 1);
+
+        assert_eq!(inventory.add_item(ItemType::Logs, 25, &definitions), 25);
+        assert_eq!(held(&inventory, 0), Some((ItemType::Logs, MAX_STACK)));
+        assert_eq!(held(&inventory, 1), Some((ItemType::BronzeAxe, 1)));
+        assert_eq!(held(&inventory, 2), Some((ItemType::Logs, 15)));
+    }
+
+    #[test]
+    fn whatever_does_not_fit_is_left_out_of_the_count() {
+        let definitions = Definitions::default();
+        let mut inventory = Inventory::new(2);
+
+        // two full stacks fit, the rest stays behind
+        let added = inventory.add_item(ItemType::Logs, 2 * MAX_STACK + 500, &definitions);
+        assert_eq!(added, 2 * MAX_STACK);
+        assert_eq!(held(&inventory, 0), Some((ItemType::Logs, MAX_STACK)));
+        assert_eq!(held(&inventory, 1), Some((ItemType::Logs, MAX_STACK)));
+
+        // axes never stack, so each one needs a slot of its own
+        let mut inventory = Inventory::new(2);
+        assert_eq!(inventory.add_item(ItemType::BronzeAxe, 3, &definitions), 2);
+        assert_eq!(held(&inventory, 0), Some((ItemType::BronzeAxe, 1)));
+        assert_eq!(held(&inventory, 1), Some((ItemType::BronzeAxe, 1)));
+    }
 }
@@ -1,75 +1,218 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum ItemType {
     BronzeAxe,
     IronAxe,
     SteelAxe,
+    BronzePickaxe,
+    IronPickaxe,
+    SteelPickaxe,
     Logs,
     OakLogs,
     WillowLogs,
+    CopperOre,
+    TinOre,
+    IronOre,
     Shrimp,
     Salmon,
+    BirdsNest,
 }
 
+impl ItemType {
+    pub fn is_axe(&self) -> bool {
+        matches!(
+            self,
+            ItemType::BronzeAxe | ItemType::IronAxe | ItemType::SteelAxe
+        )
+    }
+
+    pub fn is_pickaxe(&self) -> bool {
+        matches!(
+            self,
+            ItemType::BronzePickaxe | ItemType::IronPickaxe | ItemType::SteelPickaxe
+        )
+    }
+
+    /// ticks shaved off a gathering action when this is the equipped tool
+    pub fn tool_speed_bonus(&self) -> u32 {
+        match self {
+            ItemType::IronAxe | ItemType::IronPickaxe => 1,
+            ItemType::SteelAxe | ItemType::SteelPickaxe => 2,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EquipSlot {
+    Weapon,
+}
+
+/// items currently worn or wielded, separate from the backpack
+pub type Equipment = HashMap<EquipSlot, ItemStack>;
+
+/// what a usable item does when it is consumed
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ItemEffect {
+    /// restores this much health, up to the maximum
+    Heal(u32),
+}
+
+/// the most of one stackable item a single inventory slot can hold
+pub const MAX_STACK: u32 = 1000;
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ItemDefinition {
     pub item_type: ItemType,
-    pub name: &'static str,
+    pub name: String,
     pub stackable: bool,
-    pub description: &'static str,
+    pub max_stack: u32,
+    /// where the item is worn, `None` if it can't be equipped
+    pub equip_slot: Option<EquipSlot>,
+    /// what using the item from the inventory does, `None` if it can't be used
+    pub on_use: Option<ItemEffect>,
+    pub description: String,
 }
 
 impl ItemDefinition {
-    pub fn get(item_type: ItemType) -> Self {
+    pub fn builtin(item_type: ItemType) -> Self {
         match item_type {
             ItemType::BronzeAxe => ItemDefinition {
                 item_type,
-                name: "Bronze axe",
+                name: "Bronze axe".into(),
                 stackable: false,
-                description: "A woodcutter's axe made of bronze.",
+                max_stack: 1,
+                equip_slot: Some(EquipSlot::Weapon),
+                on_use: None,
+                description: "A woodcutter's axe made of bronze.".into(),
             },
             ItemType::IronAxe => ItemDefinition {
                 item_type,
-                name: "Iron axe",
+                name: "Iron axe".into(),
                 stackable: false,
-                description: "A woodcutter's axe made of iron.",
+                max_stack: 1,
+                equip_slot: Some(EquipSlot::Weapon),
+                on_use: None,
+                description: "A woodcutter's axe made of iron.".into(),
             },
             ItemType::SteelAxe => ItemDefinition {
                 item_type,
-                name: "Steel axe",
+                name: "Steel axe".into(),
+                stackable: false,
+                max_stack: 1,
+                equip_slot: Some(EquipSlot::Weapon),
+                on_use: None,
+                description: "A woodcutter's axe made of steel.".into(),
+            },
+            ItemType::BronzePickaxe => ItemDefinition {
+                item_type,
+                name: "Bronze pickaxe".into(),
                 stackable: false,
-                description: "A woodcutter's axe made of steel.",
+                max_stack: 1,
+                equip_slot: Some(EquipSlot::Weapon),
+                on_use: None,
+                description: "A miner's pickaxe made of bronze.".into(),
+            },
+            ItemType::IronPickaxe => ItemDefinition {
+                item_type,
+                name: "Iron pickaxe".into(),
+                stackable: false,
+                max_stack: 1,
+                equip_slot: Some(EquipSlot::Weapon),
+                on_use: None,
+                description: "A miner's pickaxe made of iron.".into(),
+            },
+            ItemType::SteelPickaxe => ItemDefinition {
+                item_type,
+                name: "Steel pickaxe".into(),
+                stackable: false,
+                max_stack: 1,
+                equip_slot: Some(EquipSlot::Weapon),
+                on_use: None,
+                description: "A miner's pickaxe made of steel.".into(),
             },
             ItemType::Logs => ItemDefinition {
                 item_type,
-                name: "Logs",
+                name: "Logs".into(),
                 stackable: true,
-                description: "Logs cut from a tree.",
+                max_stack: MAX_STACK,
+                equip_slot: None,
+                on_use: None,
+                description: "Logs cut from a tree.".into(),
             },
             ItemType::OakLogs => ItemDefinition {
                 item_type,
-                name: "Oak logs",
+                name: "Oak logs".into(),
                 stackable: true,
-                description: "Logs cut from an oak tree.",
+                max_stack: MAX_STACK,
+                equip_slot: None,
+                on_use: None,
+                description: "Logs cut from an oak tree.".into(),
             },
             ItemType::WillowLogs => ItemDefinition {
                 item_type,
-                name: "Willow logs",
+                name: "Willow logs".into(),
+                stackable: true,
+                max_stack: MAX_STACK,
+                equip_slot: None,
+                on_use: None,
+                description: "Logs cut from a willow tree.".into(),
+            },
+            ItemType::CopperOre => ItemDefinition {
+                item_type,
+                name: "Copper ore".into(),
                 stackable: true,
-                description: "Logs cut from a willow tree.",
+                max_stack: MAX_STACK,
+                equip_slot: None,
+                on_use: None,
+                description: "Ore mined from a copper rock.".into(),
+            },
+            ItemType::TinOre => ItemDefinition {
+                item_type,
+                name: "Tin ore".into(),
+                stackable: true,
+                max_stack: MAX_STACK,
+                equip_slot: None,
+                on_use: None,
+                description: "Ore mined from a tin rock.".into(),
+            },
+            ItemType::IronOre => ItemDefinition {
+                item_type,
+                name: "Iron ore".into(),
+                stackable: true,
+                max_stack: MAX_STACK,
+                equip_slot: None,
+                on_use: None,
+                description: "Ore mined from an iron rock.".into(),
             },
             ItemType::Shrimp => ItemDefinition {
                 item_type,
-                name: "Shrimp",
+                name: "Shrimp".into(),
                 stackable: true,
-                description: "Some nicely cooked shrimp.",
+                max_stack: MAX_STACK,
+                equip_slot: None,
+                on_use: Some(ItemEffect::Heal(3)),
+                description: "Some nicely cooked shrimp.".into(),
             },
             ItemType::Salmon => ItemDefinition {
                 item_type,
-                name: "Salmon",
+                name: "Salmon".into(),
                 stackable: true,
-                description: "Some nicely cooked salmon.",
+                max_stack: MAX_STACK,
+                equip_slot: None,
+                on_use: Some(ItemEffect::Heal(5)),
+                description: "Some nicely cooked salmon.".into(),
+            },
+            ItemType::BirdsNest => ItemDefinition {
+                item_type,
+                name: "Bird's nest".into(),
+                stackable: false,
+                max_stack: 1,
+                equip_slot: None,
+                on_use: None,
+                description: "It fell out of a tree.".into(),
             },
         }
     }
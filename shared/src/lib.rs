@@ -1,20 +1,63 @@
 use serde::{Deserialize, Serialize};
 
+pub mod achievements;
 pub mod actions;
+pub mod bank;
+pub mod definitions;
+pub mod identity;
 pub mod inventory;
 pub mod items;
+pub mod loot;
 pub mod messages;
+pub mod mining;
+pub mod netcode;
 pub mod pathfinding;
 pub mod skills;
+pub mod tile_map;
 pub mod tile_system;
+pub mod transport;
 pub mod trees;
 
 pub const TILE_SIZE: f32 = 32.0;
-pub const PROTOCOL_ID: u64 = 7;
+pub const PROTOCOL_ID: u64 = 10;
 pub const SERVER_PORT: u16 = 5000;
-pub const TICK_RATE: f32 = 0.6; // 600ms per tick
-pub const VIEW_DISTANCE: i32 = 5;
+pub const TICK_RATE: f32 = 0.6; // default 600ms per tick, the server can override it
+pub const VIEW_DISTANCE: i32 = 5; // default, each player can ask for their own
+pub const MIN_VIEW_DISTANCE: i32 = 1;
+pub const MAX_VIEW_DISTANCE: i32 = 15;
 pub const INTERPOLATION_DELAY: f32 = 0.1;
+pub const PLAYER_MAX_HEALTH: u32 = 10;
+pub const NPC_MAX_HEALTH: u32 = 5;
+pub const MAX_RUN_ENERGY: f32 = 100.0;
+pub const RUN_ENERGY_DRAIN: f32 = 5.0; // per tick spent running
+pub const RUN_ENERGY_REGEN: f32 = 2.0; // per tick spent standing still
+pub const RUN_STEPS: usize = 2; // tiles covered per tick while running
 
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct PlayerId(pub u64);
+
+/// a separate map with its own entities. players only see what is in their zone
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub struct ZoneId(pub u32);
+
+/// what an entity is, set when it is spawned
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EntityKind {
+    Player,
+    Tree,
+    Rock,
+    Bank,
+    FishingSpot,
+    GroundItem,
+    Npc,
+}
+
+impl EntityKind {
+    /// health when unhurt, for the kinds that can be hit
+    pub fn max_health(&self) -> u32 {
+        match self {
+            EntityKind::Npc => NPC_MAX_HEALTH,
+            _ => PLAYER_MAX_HEALTH,
+        }
+    }
+}
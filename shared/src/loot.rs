@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+use crate::items::ItemType;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LootEntry {
+    pub item_type: ItemType,
+    pub quantity: u32,
+    pub weight: u32,
+}
+
+/// weighted bonus drops, at most one entry is picked per roll. `nothing_weight` is the
+/// share of rolls that give nothing, so a 1 weight entry next to 99 is a 1 in 100 drop
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct LootTable {
+    pub entries: Vec<LootEntry>,
+    pub nothing_weight: u32,
+}
+
+impl LootTable {
+    pub fn total_weight(&self) -> u32 {
+        self.nothing_weight + self.entries.iter().map(|entry| entry.weight).sum::<u32>()
+    }
+
+    /// the entry `roll` lands on, where `roll` is uniform in `0..total_weight()`
+    pub fn pick(&self, roll: u32) -> Option<&LootEntry> {
+        let mut remaining = roll;
+        for entry in &self.entries {
+            if remaining < entry.weight {
+                return Some(entry);
+            }
+            remaining -= entry.weight;
+        }
+        None
+    }
+}
@@ -1,28 +1,88 @@
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    actions::GameAction, inventory::Inventory, items::ItemType, skills::SkillType,
-    tile_system::TilePosition, trees::Tree, PlayerId,
+    achievements::AchievementId,
+    actions::{GameAction, GameActionKind},
+    bank::Bank,
+    definitions::Definitions,
+    inventory::Inventory,
+    items::{Equipment, ItemStack, ItemType},
+    mining::Rock,
+    skills::SkillType,
+    tile_map::TileMap,
+    tile_system::TilePosition,
+    trees::Tree,
+    EntityKind, PlayerId, ZoneId,
 };
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum ClientMessage {
     Join {
         name: String,
+        /// persistent identity used to restore the player's profile
+        account_id: String,
     },
     QueueAction {
         action: GameAction,
         input_sequence_number: u32,
+        /// seconds behind the server the client draws the world, the server validates
+        /// the action against the world as it was then
+        render_delay: f32,
     },
     QueueActions {
         actions: Vec<GameAction>,
         input_sequence_number: u32,
+        render_delay: f32,
+    },
+    /// a WASD step of one tile in direction `dx`, `dy`. the server walks it from
+    /// wherever the player is by the time it gets to it, at most one step a tick
+    StepInput {
+        dx: i32,
+        dy: i32,
+        input_sequence_number: u32,
+        /// client clock when the key went down, buffered steps are taken in this order
+        pressed_at: f64,
     },
     CancelAction,
     RequestPath {
         start: TilePosition,
         goal: TilePosition,
     },
+    /// path from the player's tile through each waypoint in order
+    RequestMultiPath {
+        waypoints: Vec<TilePosition>,
+    },
+    MoveItem {
+        from: usize,
+        to: usize,
+    },
+    /// every entity in the player's zone, admins only
+    RequestWorldSnapshot,
+    /// how far the player wants to see, the server clamps it and replies with `GameConfig`
+    SetViewDistance {
+        tiles: i32,
+    },
+    /// turns running on or off, the server replies with `RunEnergyUpdate`
+    SetRunning {
+        enabled: bool,
+    },
+    /// asks what an entity in view is, the server replies with `ExamineResult`
+    Examine {
+        entity_id: u64,
+    },
+    /// asks what the item in an inventory slot is, the server replies with `ExamineResult`
+    ExamineItem {
+        slot: usize,
+    },
+    /// our position has drifted from the server's, asks for a reliable `FullState` of
+    /// our entity and our stats
+    RequestResync,
+    /// watch the world without an entity, the server replies with `SpectatorWelcome`
+    JoinAsSpectator,
+    /// moves a spectator's view, ignored for players
+    MoveSpectatorCamera {
+        center: TilePosition,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -30,19 +90,46 @@ pub enum ServerMessage {
     Welcome {
         player_id: PlayerId,
         spawn_position: TilePosition,
+        /// the name others see, which may differ from the one sent in `Join`
+        name: String,
+    },
+    /// the reply to `JoinAsSpectator`, the view starts at `center`
+    SpectatorWelcome {
+        player_id: PlayerId,
+        center: TilePosition,
     },
     DeltaUpdate {
         tick: u64,
         deltas: Vec<EntityDelta>,
     },
+    /// sent just before the server disconnects the client
+    Kicked {
+        reason: String,
+    },
+    /// the server is going away, it disconnects everyone right after
+    ServerShutdown {
+        reason: String,
+    },
+    /// every entity in the requester's zone as of `tick`, ignoring view distance
+    WorldSnapshot {
+        tick: u64,
+        entities: Vec<EntitySnapshot>,
+    },
+    /// entity positions as of `tick`, sent reliably to repair lost deltas
+    PositionKeyframe {
+        tick: u64,
+        positions: Vec<(u64, TilePosition)>,
+    },
     EntitiesEntered {
         entities: Vec<EntitySnapshot>,
     },
     EntitiesLeft {
         entity_ids: Vec<u64>,
     },
-    ActionQueued {
-        action: GameAction,
+    /// what became of the action (or chain of actions) sent as `input_sequence_number`
+    ActionResult {
+        input_sequence_number: u32,
+        outcome: ActionOutcome,
     },
     ActionCompleted {
         entity_id: u64,
@@ -50,13 +137,46 @@ pub enum ServerMessage {
     PathFound {
         path: Vec<TilePosition>,
     },
-    PathNotFound,
+    /// `waypoint_index` is the goal that couldn't be reached, 0 for a plain `RequestPath`
+    PathNotFound {
+        waypoint_index: usize,
+    },
+    /// terrain of the player's zone, sent on join and on every zone change
+    TileMapData {
+        tile_map: TileMap,
+        portals: Vec<TilePosition>,
+    },
+    /// the player went through a portal, everything from the old zone is gone
+    ZoneChanged {
+        zone: ZoneId,
+        spawn_position: TilePosition,
+    },
     ObstacleData {
         obstacles: Vec<TilePosition>,
     },
+    /// a tile stopped being walkable after join, e.g. a tree grew back
+    ObstacleAdded {
+        tile: TilePosition,
+    },
+    /// a tile became walkable after join, e.g. a tree was chopped down
+    ObstacleRemoved {
+        tile: TilePosition,
+    },
     InventoryUpdate {
         inventory: Inventory,
     },
+    EquipmentUpdate {
+        equipment: Equipment,
+    },
+    BankUpdate {
+        bank: Inventory,
+    },
+    /// the backpack filled up part way through a withdrawal
+    WithdrawPartial {
+        item_type: ItemType,
+        withdrawn: u32,
+        remainder: u32,
+    },
     ItemAdded {
         item_type: ItemType,
         quantity: u32,
@@ -69,6 +189,7 @@ pub enum ServerMessage {
         skill: SkillType,
         level: u32,
         experience: u32,
+        xp_to_next: u32,
     },
     LevelUp {
         skill: SkillType,
@@ -84,21 +205,121 @@ pub enum ServerMessage {
     TreeRespawned {
         tree_entity_id: u64,
     },
+    RockDepleted {
+        rock_entity_id: u64,
+    },
+    RockRespawned {
+        rock_entity_id: u64,
+    },
+    /// the server refused a request that isn't a queued action, like a world snapshot.
+    /// actions get an `ActionResult` instead
+    ActionRejected {
+        reason: String,
+    },
+    /// a move was stopped because the next tile is taken by another entity
+    PathBlocked {
+        at: TilePosition,
+    },
+    /// answers `RequestResync` with where the server has our entity. nothing the
+    /// client was predicting still applies after it
+    Resync {
+        tick: u64,
+        entity_id: u64,
+        tile_pos: TilePosition,
+        last_processed_input: Option<u32>,
+    },
+    TickRateChanged {
+        tick_rate: f32,
+    },
+    /// server settings the client needs to match, sent on join
+    GameConfig {
+        tick_rate: f32,
+        view_distance: i32,
+    },
+    GroundItemChanged {
+        entity_id: u64,
+        item: ItemStack,
+    },
+    HealthUpdate {
+        entity_id: u64,
+        health: u32,
+    },
+    /// the player's run energy and whether they are running, sent when either changes
+    RunEnergyUpdate {
+        energy: f32,
+        running: bool,
+    },
+    InventoryFull,
+    /// the description asked for with `Examine` or `ExamineItem`
+    ExamineResult {
+        text: String,
+    },
+    /// definitions the server loaded over the built-in ones, sent on join
+    Definitions {
+        definitions: Definitions,
+    },
+    AchievementUnlocked {
+        id: AchievementId,
+        name: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum ActionOutcome {
+    /// queued or started
+    Accepted,
+    Rejected(RejectReason),
+    /// everything the input queued has finished
+    Completed,
+}
+
+/// why the server turned an action down
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum RejectReason {
+    /// the target isn't one of the entities the player can see
+    NotInView,
+    /// the target isn't next to where the player is, or will be once they've walked
+    TooFar,
+    InvalidTarget,
+    /// the tree or rock is already used up
+    Depleted,
+    InvalidPath,
+    QueueFull,
     NotEnoughLevel {
         skill: SkillType,
         required: u32,
         current: u32,
     },
     NoAxeEquipped,
+    NoPickaxeEquipped,
+    NotOnPortal,
+    DestinationBlocked,
+    /// the first step of the walk is onto a tile someone else is standing on
+    PathBlocked,
+    NothingUsable,
+    NoTreesInView,
+    /// something only the server queues, like a teleport
+    ServerOnly,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct EntitySnapshot {
     pub entity_id: u64,
+    pub kind: EntityKind,
     pub tile_position: TilePosition,
     pub player_id: Option<PlayerId>,
     pub tree: Option<Tree>,
+    pub rock: Option<Rock>,
+    pub bank: Option<Bank>,
+    pub ground_item: Option<ItemStack>,
     pub last_processed_input: Option<u32>,
+    pub health: Option<u32>,
+    /// what the entity is busy with right now, so a player who comes into view
+    /// mid-action shows it straight away
+    pub current_action: Option<GameActionKind>,
+    /// display name, players only. names aren't unique, use `entity_id` to tell
+    /// players apart
+    pub player_name: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -113,13 +334,18 @@ pub enum DeltaType {
         tile_pos: TilePosition,
         player_id: Option<PlayerId>,
         last_processed_input: Option<u32>,
+        velocity: Option<(i32, i32)>,
     },
     PositionOnly {
         tile_pos: TilePosition,
         last_processed_input: Option<u32>,
+        /// the step taken since the previous tick, in tiles per tick. none when the
+        /// entity didn't walk there, e.g. first sight or a teleport
+        velocity: Option<(i32, i32)>,
     },
+    /// only the kind, a walk's whole path is no use to someone watching it
     ActionStarted {
-        action: GameAction,
+        action: GameActionKind,
     },
     Removed,
 }
@@ -0,0 +1,108 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::items::ItemType;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RockType {
+    Copper,
+    Tin,
+    Iron,
+    RichVein,
+}
+
+/// a better ore a rock gives once the miner is high enough level
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RichOre {
+    pub level_required: u32,
+    pub ore_given: ItemType,
+    pub experience: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RockDefinition {
+    pub rock_type: RockType,
+    pub name: &'static str,
+    pub description: &'static str,
+    pub level_required: u32,
+    pub ore_given: ItemType,
+    pub experience: u32,
+    pub respawn_time: f64,
+    pub rich_ore: Option<RichOre>,
+}
+
+impl RockDefinition {
+    pub fn get(rock_type: RockType) -> Self {
+        match rock_type {
+            RockType::Copper => RockDefinition {
+                rock_type,
+                name: "Copper rock",
+                description: "A rock with copper ore in it.",
+                level_required: 1,
+                ore_given: ItemType::CopperOre,
+                experience: 17,
+                respawn_time: 3.0,
+                rich_ore: None,
+            },
+            RockType::Tin => RockDefinition {
+                rock_type,
+                name: "Tin rock",
+                description: "A rock with tin ore in it.",
+                level_required: 1,
+                ore_given: ItemType::TinOre,
+                experience: 17,
+                respawn_time: 3.0,
+                rich_ore: None,
+            },
+            RockType::Iron => RockDefinition {
+                rock_type,
+                name: "Iron rock",
+                description: "A rock with iron ore in it.",
+                level_required: 15,
+                ore_given: ItemType::IronOre,
+                experience: 35,
+                respawn_time: 6.0,
+                rich_ore: None,
+            },
+            RockType::RichVein => RockDefinition {
+                rock_type,
+                name: "Rich vein",
+                description: "Copper ore, with iron deeper down for skilled miners.",
+                level_required: 1,
+                ore_given: ItemType::CopperOre,
+                experience: 17,
+                respawn_time: 8.0,
+                rich_ore: Some(RichOre {
+                    level_required: 15,
+                    ore_given: ItemType::IronOre,
+                    experience: 35,
+                }),
+            },
+        }
+    }
+
+    /// ore and experience a miner of the given level gets from this rock
+    pub fn yield_for_level(&self, level: u32) -> (ItemType, u32) {
+        match &self.rich_ore {
+            Some(rich) if level >= rich.level_required => (rich.ore_given, rich.experience),
+            _ => (self.ore_given, self.experience),
+        }
+    }
+}
+
+#[derive(Component, Serialize, Deserialize, Clone, Debug)]
+pub struct Rock {
+    pub rock_type: RockType,
+    pub is_depleted: bool,
+    pub respawn_timer: f64,
+}
+
+impl Rock {
+    pub fn new(rock_type: RockType) -> Self {
+        Self {
+            rock_type,
+            is_depleted: false,
+            respawn_timer: 0.0,
+        }
+    }
+}
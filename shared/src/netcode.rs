@@ -0,0 +1,413 @@
+use crate::actions::GameActionKind;
+use crate::messages::{DeltaType, EntityDelta};
+use crate::tile_system::TilePosition;
+use crate::PlayerId;
+
+/// first byte of every compact `DeltaUpdate`, bumped whenever the layout changes
+pub const DELTA_FORMAT_VERSION: u8 = 2;
+
+const KIND_FULL_STATE: u8 = 0;
+const KIND_POSITION_ONLY: u8 = 1;
+const KIND_ACTION_STARTED: u8 = 2;
+const KIND_REMOVED: u8 = 3;
+
+const FLAG_FAR: u8 = 1;
+const FLAG_PLAYER_ID: u8 = 1 << 1;
+const FLAG_LAST_INPUT: u8 = 1 << 2;
+const FLAG_VELOCITY: u8 = 1 << 3;
+
+/// packs one tick of deltas for a player. positions are stored relative to `center`,
+/// the player's own tile, as one byte per axis, and only fall back to a full i32 per
+/// axis for entities too far away for that. ids and counts are varints
+pub fn encode_delta_update(tick: u64, center: TilePosition, deltas: &[EntityDelta]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(16 + deltas.len() * 6);
+    buf.push(DELTA_FORMAT_VERSION);
+    write_varint(&mut buf, tick);
+    write_varint(&mut buf, zigzag(center.x));
+    write_varint(&mut buf, zigzag(center.y));
+    write_varint(&mut buf, deltas.len() as u64);
+
+    for delta in deltas {
+        write_varint(&mut buf, delta.entity_id);
+        match &delta.delta_type {
+            DeltaType::FullState {
+                tile_pos,
+                player_id,
+                last_processed_input,
+                velocity,
+            } => {
+                buf.push(KIND_FULL_STATE);
+                let fields = PositionFields {
+                    tile_pos: *tile_pos,
+                    player_id: player_id.map(|id| id.0),
+                    last_processed_input: *last_processed_input,
+                    velocity: *velocity,
+                };
+                write_position(&mut buf, center, &fields);
+            }
+            DeltaType::PositionOnly {
+                tile_pos,
+                last_processed_input,
+                velocity,
+            } => {
+                buf.push(KIND_POSITION_ONLY);
+                let fields = PositionFields {
+                    tile_pos: *tile_pos,
+                    player_id: None,
+                    last_processed_input: *last_processed_input,
+                    velocity: *velocity,
+                };
+                write_position(&mut buf, center, &fields);
+            }
+            DeltaType::ActionStarted { action } => {
+                buf.push(KIND_ACTION_STARTED);
+                buf.push(*action as u8);
+            }
+            DeltaType::Removed => buf.push(KIND_REMOVED),
+        }
+    }
+    buf
+}
+
+/// the tick and deltas packed by `encode_delta_update`
+pub fn decode_delta_update(bytes: &[u8]) -> Result<(u64, Vec<EntityDelta>), String> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let version = reader.u8()?;
+    if version != DELTA_FORMAT_VERSION {
+        return Err(format!(
+            "delta format version {}, expected {}",
+            version, DELTA_FORMAT_VERSION
+        ));
+    }
+    let tick = reader.varint()?;
+    let center = TilePosition {
+        x: unzigzag(reader.varint()?),
+        y: unzigzag(reader.varint()?),
+    };
+    let count = reader.varint()? as usize;
+
+    // every delta takes at least two bytes, so a bogus count can't allocate much
+    let mut deltas = Vec::with_capacity(count.min(bytes.len() / 2));
+    for _ in 0..count {
+        let entity_id = reader.varint()?;
+        let delta_type = match reader.u8()? {
+            KIND_FULL_STATE => {
+                let fields = read_position(&mut reader, center)?;
+                DeltaType::FullState {
+                    tile_pos: fields.tile_pos,
+                    player_id: fields.player_id.map(PlayerId),
+                    last_processed_input: fields.last_processed_input,
+                    velocity: fields.velocity,
+                }
+            }
+            KIND_POSITION_ONLY => {
+                let fields = read_position(&mut reader, center)?;
+                DeltaType::PositionOnly {
+                    tile_pos: fields.tile_pos,
+                    last_processed_input: fields.last_processed_input,
+                    velocity: fields.velocity,
+                }
+            }
+            KIND_ACTION_STARTED => {
+                let index = reader.u8()?;
+                let action = match GameActionKind::ALL.get(index as usize) {
+                    Some(action) => *action,
+                    None => return Err(format!("unknown action kind {}", index)),
+                };
+                DeltaType::ActionStarted { action }
+            }
+            KIND_REMOVED => DeltaType::Removed,
+            kind => return Err(format!("unknown delta kind {}", kind)),
+        };
+        deltas.push(EntityDelta {
+            entity_id,
+            delta_type,
+        });
+    }
+    if reader.pos != bytes.len() {
+        return Err(format!("{} trailing bytes", bytes.len() - reader.pos));
+    }
+    Ok((tick, deltas))
+}
+
+/// what `FullState` and `PositionOnly` have in common, `player_id` is only in full state
+struct PositionFields {
+    tile_pos: TilePosition,
+    player_id: Option<u64>,
+    last_processed_input: Option<u32>,
+    velocity: Option<(i32, i32)>,
+}
+
+fn write_position(buf: &mut Vec<u8>, center: TilePosition, fields: &PositionFields) {
+    let tile_pos = fields.tile_pos;
+    let dx = i8::try_from(tile_pos.x as i64 - center.x as i64);
+    let dy = i8::try_from(tile_pos.y as i64 - center.y as i64);
+    let near = match (dx, dy) {
+        (Ok(dx), Ok(dy)) => Some((dx, dy)),
+        _ => None,
+    };
+
+    let mut flags = 0;
+    if near.is_none() {
+        flags |= FLAG_FAR;
+    }
+    if fields.player_id.is_some() {
+        flags |= FLAG_PLAYER_ID;
+    }
+    if fields.last_processed_input.is_some() {
+        flags |= FLAG_LAST_INPUT;
+    }
+    if fields.velocity.is_some() {
+        flags |= FLAG_VELOCITY;
+    }
+    buf.push(flags);
+
+    match near {
+        Some((dx, dy)) => buf.extend_from_slice(&[dx as u8, dy as u8]),
+        None => {
+            buf.extend_from_slice(&tile_pos.x.to_le_bytes());
+            buf.extend_from_slice(&tile_pos.y.to_le_bytes());
+        }
+    }
+    if let Some(player_id) = fields.player_id {
+        write_varint(buf, player_id);
+    }
+    if let Some(input) = fields.last_processed_input {
+        write_varint(buf, input as u64);
+    }
+    if let Some((vx, vy)) = fields.velocity {
+        write_varint(buf, zigzag(vx));
+        write_varint(buf, zigzag(vy));
+    }
+}
+
+fn read_position(reader: &mut Reader, center: TilePosition) -> Result<PositionFields, String> {
+    let flags = reader.u8()?;
+    let tile_pos = if flags & FLAG_FAR != 0 {
+        TilePosition {
+            x: reader.i32()?,
+            y: reader.i32()?,
+        }
+    } else {
+        let dx = reader.u8()? as i8;
+        let dy = reader.u8()? as i8;
+        TilePosition {
+            x: center.x.wrapping_add(dx as i32),
+            y: center.y.wrapping_add(dy as i32),
+        }
+    };
+    let player_id = match flags & FLAG_PLAYER_ID {
+        0 => None,
+        _ => Some(reader.varint()?),
+    };
+    let last_processed_input = match flags & FLAG_LAST_INPUT {
+        0 => None,
+        _ => Some(reader.varint()? as u32),
+    };
+    let velocity = match flags & FLAG_VELOCITY {
+        0 => None,
+        _ => Some((unzigzag(reader.varint()?), unzigzag(reader.varint()?))),
+    };
+    Ok(PositionFields {
+        tile_pos,
+        player_id,
+        last_processed_input,
+        velocity,
+    })
+}
+
+/// maps small negative numbers to small varints: 0, -1, 1, -2 become 0, 1, 2, 3
+fn zigzag(value: i32) -> u64 {
+    ((value << 1) ^ (value >> 31)) as u32 as u64
+}
+
+fn unzigzag(value: u64) -> i32 {
+    let value = value as u32;
+    ((value >> 1) as i32) ^ -((value & 1) as i32)
+}
+
+/// little endian base 128, seven bits per byte with the top bit set on all but the last
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| "message ends early".to_string())?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        let mut le = [0; 4];
+        le.copy_from_slice(self.take(4)?);
+        Ok(i32::from_le_bytes(le))
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("varint longer than 64 bits".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::ServerMessage;
+
+    fn tile(x: i32, y: i32) -> TilePosition {
+        TilePosition { x, y }
+    }
+
+    fn moved(entity_id: u64, tile_pos: TilePosition) -> EntityDelta {
+        EntityDelta {
+            entity_id,
+            delta_type: DeltaType::PositionOnly {
+                tile_pos,
+                last_processed_input: None,
+                velocity: Some((1, -1)),
+            },
+        }
+    }
+
+    /// encodes and decodes `deltas`, checking nothing changed on the way
+    fn round_trip(center: TilePosition, deltas: Vec<EntityDelta>) -> Vec<u8> {
+        let bytes = encode_delta_update(42, center, &deltas);
+        let (tick, decoded) = decode_delta_update(&bytes).unwrap();
+        assert_eq!(tick, 42);
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", deltas));
+        bytes
+    }
+
+    #[test]
+    fn every_delta_kind_survives_the_round_trip() {
+        let deltas = vec![
+            EntityDelta {
+                entity_id: 1,
+                delta_type: DeltaType::FullState {
+                    tile_pos: tile(-3, -7),
+                    player_id: Some(PlayerId(u64::MAX)),
+                    last_processed_input: Some(u32::MAX),
+                    velocity: Some((-1, 0)),
+                },
+            },
+            EntityDelta {
+                entity_id: 300,
+                delta_type: DeltaType::FullState {
+                    tile_pos: tile(0, 0),
+                    player_id: None,
+                    last_processed_input: None,
+                    velocity: None,
+                },
+            },
+            EntityDelta {
+                entity_id: 2,
+                delta_type: DeltaType::PositionOnly {
+                    tile_pos: tile(-10, -20),
+                    last_processed_input: Some(7),
+                    velocity: Some((i32::MIN, i32::MAX)),
+                },
+            },
+            EntityDelta {
+                entity_id: 3,
+                delta_type: DeltaType::ActionStarted {
+                    action: GameActionKind::ALL[GameActionKind::ALL.len() - 1],
+                },
+            },
+            EntityDelta {
+                entity_id: u64::MAX,
+                delta_type: DeltaType::Removed,
+            },
+        ];
+        round_trip(tile(-5, -5), deltas);
+        round_trip(tile(0, 0), Vec::new());
+    }
+
+    #[test]
+    fn positions_out_of_byte_range_fall_back_to_full_coordinates() {
+        let center = tile(-50, 50);
+        // one step either side of the edge of what fits in an i8
+        let edges = [
+            tile(-50 + 127, 50 - 128),
+            tile(-50 + 128, 50),
+            tile(-50, 50 - 129),
+        ];
+        let sizes: Vec<usize> = edges
+            .iter()
+            .map(|pos| round_trip(center, vec![moved(1, *pos)]).len())
+            .collect();
+        assert_eq!(sizes[1], sizes[0] + 6);
+        assert_eq!(sizes[2], sizes[0] + 6);
+
+        // teleports across the map, and centers at the very ends of the range
+        round_trip(tile(0, 0), vec![moved(1, tile(1_000_000, -1_000_000))]);
+        round_trip(
+            tile(i32::MAX, i32::MIN),
+            vec![moved(1, tile(i32::MIN, i32::MAX))],
+        );
+        round_trip(
+            tile(i32::MAX, i32::MIN),
+            vec![moved(1, tile(i32::MAX - 3, i32::MIN + 3))],
+        );
+    }
+
+    #[test]
+    fn nearby_movers_take_a_fraction_of_the_bincode_size() {
+        let center = tile(-1000, 2000);
+        let deltas: Vec<EntityDelta> = (0..50)
+            .map(|i| {
+                moved(
+                    100 + i,
+                    tile(center.x + (i as i32 % 20) - 10, center.y - 15),
+                )
+            })
+            .collect();
+        let compact = round_trip(center, deltas.clone()).len();
+        let bincode = bincode::serialize(&ServerMessage::DeltaUpdate { tick: 42, deltas })
+            .unwrap()
+            .len();
+        assert!(
+            compact * 4 < bincode,
+            "compact {} bytes, bincode {}",
+            compact,
+            bincode
+        );
+    }
+
+    #[test]
+    fn truncated_or_mislabelled_updates_are_rejected() {
+        let bytes = encode_delta_update(42, tile(0, 0), &[moved(1, tile(2, 2))]);
+        assert!(decode_delta_update(&bytes[..bytes.len() - 1]).is_err());
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(decode_delta_update(&extra).is_err());
+        let mut old = bytes;
+        old[0] = DELTA_FORMAT_VERSION - 1;
+        assert!(decode_delta_update(&old).is_err());
+    }
+}
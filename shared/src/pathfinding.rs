@@ -1,8 +1,10 @@
 use std::cmp::Ordering;
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, VecDeque};
 
+use bevy::utils::tracing::warn;
 use bevy::utils::{HashMap, HashSet};
 
+use crate::tile_map::BASE_MOVEMENT_COST;
 use crate::tile_system::TilePosition;
 
 #[derive(Clone, Eq, PartialEq)]
@@ -28,36 +30,380 @@ impl PartialOrd for PathNode {
     }
 }
 
+/// how many recent (start, goal) results are kept
+pub const PATH_CACHE_SIZE: usize = 64;
+
+/// nodes a search may expand before giving up, far more than any path on the bundled
+/// maps needs
+pub const DEFAULT_MAX_SEARCH_NODES: usize = 20_000;
+
+/// which search `Pathfinder::find_path` runs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PathMode {
+    #[default]
+    AStar,
+    /// jump point search. it moves diagonally, so only grids that allow that use it
+    JumpPoint,
+}
+
+impl PathMode {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text.trim() {
+            "astar" => Ok(PathMode::AStar),
+            "jps" => Ok(PathMode::JumpPoint),
+            other => Err(format!(
+                "unknown path mode '{}', expected astar or jps",
+                other
+            )),
+        }
+    }
+}
+
+type PathKey = (PathMode, TilePosition, TilePosition);
+
+/// least recently used paths, only valid for the obstacle generation they were found in
+#[derive(Default)]
+struct PathCache {
+    generation: u64,
+    paths: HashMap<PathKey, Option<Vec<TilePosition>>>,
+    order: VecDeque<PathKey>,
+}
+
+impl PathCache {
+    fn get(&mut self, key: PathKey, generation: u64) -> Option<Option<Vec<TilePosition>>> {
+        if self.generation != generation {
+            self.paths.clear();
+            self.order.clear();
+            self.generation = generation;
+            return None;
+        }
+
+        let path = self.paths.get(&key)?.clone();
+        if let Some(index) = self.order.iter().position(|k| *k == key) {
+            self.order.remove(index);
+        }
+        self.order.push_back(key);
+        Some(path)
+    }
+
+    fn insert(&mut self, key: PathKey, path: Option<Vec<TilePosition>>) {
+        if self.paths.insert(key, path).is_none() {
+            self.order.push_back(key);
+        }
+        while self.order.len() > PATH_CACHE_SIZE {
+            if let Some(oldest) = self.order.pop_front() {
+                self.paths.remove(&oldest);
+            }
+        }
+    }
+}
+
 pub struct Pathfinder {
-    pub obstacles: HashSet<TilePosition>,
+    obstacles: HashSet<TilePosition>,
+    /// cost of stepping onto tiles that aren't plain grass, see `TileType::movement_cost`
+    terrain_costs: HashMap<TilePosition, i32>,
+    /// cheapest tile anywhere, the heuristic assumes every step could cost this little
+    min_terrain_cost: i32,
     pub allow_diagonal: bool,
+    /// set through `set_mode`, which keeps jump point search off four-connected grids
+    mode: PathMode,
+    /// nodes A* may expand before it gives up. open ground is unbounded, so without a
+    /// limit an unreachable goal would be searched for forever
+    pub max_nodes: usize,
+    /// bumped whenever the obstacle set changes, which invalidates cached paths
+    generation: u64,
+    cache: PathCache,
+    /// nodes the last search that wasn't answered from the cache expanded
+    last_expanded: usize,
 }
 
 impl Pathfinder {
     pub fn new(allow_diagonal: bool) -> Self {
         Self {
             obstacles: HashSet::new(),
+            terrain_costs: HashMap::new(),
+            min_terrain_cost: BASE_MOVEMENT_COST,
             allow_diagonal,
+            mode: PathMode::default(),
+            max_nodes: DEFAULT_MAX_SEARCH_NODES,
+            generation: 0,
+            cache: PathCache::default(),
+            last_expanded: 0,
         }
     }
 
+    /// how much work the last uncached search did, for comparing search modes
+    pub fn last_expanded(&self) -> usize {
+        self.last_expanded
+    }
+
+    pub fn mode(&self) -> PathMode {
+        self.mode
+    }
+
+    /// picks the search `find_path` runs. jump point search is refused without
+    /// diagonal movement, its paths would take steps nobody may walk
+    pub fn set_mode(&mut self, mode: PathMode) -> Result<(), String> {
+        if mode == PathMode::JumpPoint && !self.allow_diagonal {
+            return Err("jump point search needs diagonal movement".to_string());
+        }
+        self.mode = mode;
+        Ok(())
+    }
+
+    pub fn obstacles(&self) -> &HashSet<TilePosition> {
+        &self.obstacles
+    }
+
     pub fn add_obstacle(&mut self, pos: TilePosition) {
-        self.obstacles.insert(pos);
+        if self.obstacles.insert(pos) {
+            self.generation += 1;
+        }
     }
 
     pub fn remove_obstacle(&mut self, pos: TilePosition) {
-        self.obstacles.remove(&pos);
+        if self.obstacles.remove(&pos) {
+            self.generation += 1;
+        }
+    }
+
+    /// replaces the whole obstacle set, e.g. with the map sent by the server
+    pub fn set_obstacles(&mut self, obstacles: impl IntoIterator<Item = TilePosition>) {
+        self.obstacles = obstacles.into_iter().collect();
+        self.generation += 1;
+    }
+
+    /// replaces the per-tile movement costs, e.g. with `TileMap::terrain_costs`.
+    /// tiles left out cost `BASE_MOVEMENT_COST`
+    pub fn set_terrain_costs(&mut self, costs: impl IntoIterator<Item = (TilePosition, i32)>) {
+        self.terrain_costs = costs.into_iter().collect();
+        self.min_terrain_cost = self
+            .terrain_costs
+            .values()
+            .copied()
+            .fold(BASE_MOVEMENT_COST, i32::min)
+            .max(1);
+        self.generation += 1;
+    }
+
+    /// what stepping onto `pos` costs relative to `BASE_MOVEMENT_COST`
+    pub fn terrain_cost(&self, pos: &TilePosition) -> i32 {
+        self.terrain_costs
+            .get(pos)
+            .copied()
+            .unwrap_or(BASE_MOVEMENT_COST)
     }
 
     pub fn is_walkable(&self, pos: &TilePosition) -> bool {
         !self.obstacles.contains(pos)
     }
 
-    pub fn find_path_a_star(
+    /// checks a walk a client sent: it has to start on or next to `from` and go one
+    /// walkable tile at a time, diagonally only if diagonal movement is allowed
+    pub fn check_walk(&self, from: TilePosition, path: &[TilePosition]) -> Result<(), String> {
+        let first = match path.first() {
+            Some(first) => *first,
+            None => return Err("empty path".to_string()),
+        };
+        if first != from && !self.is_single_step(from, first) {
+            return Err(format!(
+                "path starts at {:?}, not next to {:?}",
+                first, from
+            ));
+        }
+        for step in path.windows(2) {
+            if !self.is_single_step(step[0], step[1]) {
+                return Err(format!(
+                    "{:?} to {:?} is not a single step",
+                    step[0], step[1]
+                ));
+            }
+        }
+        match path.iter().find(|tile| !self.is_walkable(tile)) {
+            Some(blocked) => Err(format!("path crosses blocked tile {:?}", blocked)),
+            None => Ok(()),
+        }
+    }
+
+    fn is_single_step(&self, from: TilePosition, to: TilePosition) -> bool {
+        match ((from.x - to.x).abs(), (from.y - to.y).abs()) {
+            (0, 1) | (1, 0) => true,
+            (1, 1) => self.allow_diagonal,
+            _ => false,
+        }
+    }
+
+    /// a path with whichever search `mode` picks. jump point search assumes every tile
+    /// costs the same, so on a map with terrain costs A* is used instead
+    pub fn find_path(
+        &mut self,
+        start: TilePosition,
+        goal: TilePosition,
+    ) -> Option<Vec<TilePosition>> {
+        match self.mode {
+            PathMode::JumpPoint if self.terrain_costs.is_empty() => self.find_path_jps(start, goal),
+            _ => self.find_path_a_star(start, goal),
+        }
+    }
+
+    /// like `find_path`, but a blocked goal such as a tree or rock is walked up to: the
+    /// path ends on whichever free tile next to it is quickest to reach. that is a
+    /// single A* search towards all of them at once, so it gets one node budget rather
+    /// than one per side. from a tile already next to the goal the path is just the start
+    pub fn find_path_or_adjacent(
+        &mut self,
+        start: TilePosition,
+        goal: TilePosition,
+    ) -> Option<Vec<TilePosition>> {
+        if self.is_walkable(&goal) {
+            return self.find_path(start, goal);
+        }
+        if (start.x - goal.x).abs() <= 1 && (start.y - goal.y).abs() <= 1 {
+            return Some(vec![start]);
+        }
+
+        let approaches: Vec<TilePosition> = goal
+            .neighbors_diagonal()
+            .into_iter()
+            .filter(|tile| self.is_walkable(tile))
+            .collect();
+        if approaches.is_empty() {
+            return None;
+        }
+        self.search_any(start, &approaches)
+    }
+
+    /// the walkable tile closest to `target`, searching outwards one ring of tiles at a
+    /// time up to `max_radius` away. ties go to the tile nearer `from`, so a click on
+    /// a wall ends up on our side of it
+    pub fn nearest_walkable(
         &self,
+        target: TilePosition,
+        from: TilePosition,
+        max_radius: i32,
+    ) -> Option<TilePosition> {
+        if self.is_walkable(&target) {
+            return Some(target);
+        }
+        for radius in 1..=max_radius {
+            let ring = (-radius..=radius)
+                .flat_map(|dx| (-radius..=radius).map(move |dy| (dx, dy)))
+                .filter(|(dx, dy)| dx.abs() == radius || dy.abs() == radius)
+                .map(|(dx, dy)| TilePosition {
+                    x: target.x + dx,
+                    y: target.y + dy,
+                });
+            let nearest = ring
+                .filter(|tile| self.is_walkable(tile))
+                .min_by_key(|tile| (tile.distance_to(&target), tile.distance_to(&from)));
+            if nearest.is_some() {
+                return nearest;
+            }
+        }
+        None
+    }
+
+    /// A* search weighted by terrain cost, answered from the cache when the same path
+    /// was asked for since the obstacles or terrain last changed
+    pub fn find_path_a_star(
+        &mut self,
         start: TilePosition,
         goal: TilePosition,
     ) -> Option<Vec<TilePosition>> {
+        let key = (PathMode::AStar, start, goal);
+        if let Some(path) = self.cache.get(key, self.generation) {
+            return path;
+        }
+        let path = self.search(start, goal);
+        self.cache.insert(key, path.clone());
+        path
+    }
+
+    /// jump point search, cached like `find_path_a_star`. it only expands the tiles where
+    /// a shortest path can turn, so crossing open ground costs a handful of nodes
+    /// instead of every tile on the way. the path costs as much as a diagonal A* one and
+    /// comes back tile by tile. without diagonal movement this is just A*
+    pub fn find_path_jps(
+        &mut self,
+        start: TilePosition,
+        goal: TilePosition,
+    ) -> Option<Vec<TilePosition>> {
+        if !self.allow_diagonal {
+            return self.find_path_a_star(start, goal);
+        }
+        let key = (PathMode::JumpPoint, start, goal);
+        if let Some(path) = self.cache.get(key, self.generation) {
+            return path;
+        }
+        let path = self.search_jps(start, goal);
+        self.cache.insert(key, path.clone());
+        path
+    }
+
+    /// chains A* through each waypoint in order. the tile joining two legs only appears
+    /// once. on failure returns the index of the waypoint that couldn't be reached
+    pub fn find_path_multi(
+        &mut self,
+        start: TilePosition,
+        waypoints: &[TilePosition],
+    ) -> Result<Vec<TilePosition>, usize> {
+        let mut path: Vec<TilePosition> = Vec::new();
+        let mut from = start;
+
+        for (index, waypoint) in waypoints.iter().enumerate() {
+            let leg = self.find_path(from, *waypoint).ok_or(index)?;
+            let skip = if path.last() == leg.first() { 1 } else { 0 };
+            path.extend(leg.into_iter().skip(skip));
+            from = *waypoint;
+        }
+
+        Ok(path)
+    }
+
+    /// drops tiles on straight runs, keeping only the points where the path turns. two
+    /// kept points are always joined by a clear line, see `expand_path`
+    pub fn smooth_path(&self, path: &[TilePosition]) -> Vec<TilePosition> {
+        if path.len() <= 2 {
+            return path.to_vec();
+        }
+
+        let mut smoothed = vec![path[0]];
+        let mut anchor = 0;
+        while anchor < path.len() - 1 {
+            let mut next = anchor + 1;
+            for candidate in anchor + 2..path.len() {
+                if !self.line_of_sight(path[anchor], path[candidate]) {
+                    break;
+                }
+                next = candidate;
+            }
+            smoothed.push(path[next]);
+            anchor = next;
+        }
+        smoothed
+    }
+
+    /// fills the gaps in a smoothed path back in so it can be walked one tile at a time.
+    /// a path that is already tile-by-tile comes back unchanged
+    pub fn expand_path(path: &[TilePosition]) -> Vec<TilePosition> {
+        let mut expanded: Vec<TilePosition> = Vec::new();
+        for (index, point) in path.iter().enumerate() {
+            if index == 0 {
+                expanded.push(*point);
+                continue;
+            }
+            expanded.extend(line_tiles(path[index - 1], *point).into_iter().skip(1));
+        }
+        expanded
+    }
+
+    fn line_of_sight(&self, from: TilePosition, to: TilePosition) -> bool {
+        line_tiles(from, to)
+            .iter()
+            .all(|tile| self.is_walkable(tile))
+    }
+
+    fn search(&mut self, start: TilePosition, goal: TilePosition) -> Option<Vec<TilePosition>> {
         if start == goal {
             return Some(vec![goal]);
         }
@@ -66,6 +412,24 @@ impl Pathfinder {
             return None;
         }
 
+        self.search_any(start, &[goal])
+    }
+
+    /// A* to whichever of `goals` is cheapest to reach, all in one search sharing one
+    /// node budget. the goals have to be walkable
+    fn search_any(
+        &mut self,
+        start: TilePosition,
+        goals: &[TilePosition],
+    ) -> Option<Vec<TilePosition>> {
+        let heuristic = |pathfinder: &Self, pos: &TilePosition| {
+            goals
+                .iter()
+                .map(|goal| pathfinder.heuristic(pos, goal))
+                .min()
+                .unwrap_or(0)
+        };
+
         let mut open_set = BinaryHeap::new();
         let mut came_from: HashMap<TilePosition, TilePosition> = HashMap::new();
         let mut g_score: HashMap<TilePosition, i32> = HashMap::new();
@@ -74,17 +438,29 @@ impl Pathfinder {
         open_set.push(PathNode {
             position: start,
             g_cost: 0,
-            h_cost: Self::heuristic(&start, &goal),
-            f_cost: Self::heuristic(&start, &goal),
+            h_cost: heuristic(self, &start),
+            f_cost: heuristic(self, &start),
         });
 
+        let mut expanded = 0;
         while let Some(current_node) = open_set.pop() {
             let current = current_node.position;
 
-            if current == goal {
+            if goals.contains(&current) {
+                self.last_expanded = expanded;
                 return Some(self.reconstruct_path(&came_from, current));
             }
 
+            expanded += 1;
+            if expanded > self.max_nodes {
+                warn!(
+                    "Path search from {:?} to {:?} gave up after {} nodes",
+                    start, goals, self.max_nodes
+                );
+                self.last_expanded = expanded;
+                return None;
+            }
+
             let neighbors = if self.allow_diagonal {
                 current.neighbors_diagonal()
             } else {
@@ -98,7 +474,8 @@ impl Pathfinder {
 
                 let is_diagonal =
                     (current.x - neighbor.x).abs() + (current.y - neighbor.y).abs() == 2;
-                let move_cost = if is_diagonal { 14 } else { 10 };
+                let step = if is_diagonal { 14 } else { 10 };
+                let move_cost = step * self.terrain_cost(&neighbor) / BASE_MOVEMENT_COST;
 
                 let tentative_g_score = g_score.get(&current).unwrap_or(&i32::MAX) + move_cost;
 
@@ -106,7 +483,7 @@ impl Pathfinder {
                     came_from.insert(neighbor, current);
                     g_score.insert(neighbor, tentative_g_score);
 
-                    let h_cost = Self::heuristic(&neighbor, &goal);
+                    let h_cost = heuristic(self, &neighbor);
                     let f_cost = tentative_g_score + h_cost;
 
                     open_set.push(PathNode {
@@ -119,11 +496,220 @@ impl Pathfinder {
             }
         }
 
+        self.last_expanded = expanded;
         None
     }
 
-    fn heuristic(a: &TilePosition, b: &TilePosition) -> i32 {
-        ((a.x - b.x).abs() + (a.y - b.y).abs()) * 10
+    /// never more than the real cost, which keeps A* paths cheapest. distance is
+    /// scaled down to the cheapest terrain, since every step could be on it
+    fn heuristic(&self, a: &TilePosition, b: &TilePosition) -> i32 {
+        let distance = if self.allow_diagonal {
+            octile_distance(a, b)
+        } else {
+            ((a.x - b.x).abs() + (a.y - b.y).abs()) * 10
+        };
+        distance * self.min_terrain_cost / BASE_MOVEMENT_COST
+    }
+
+    fn search_jps(&mut self, start: TilePosition, goal: TilePosition) -> Option<Vec<TilePosition>> {
+        if start == goal {
+            return Some(vec![goal]);
+        }
+
+        if !self.is_walkable(&goal) {
+            return None;
+        }
+
+        let bounds = self.search_bounds(start, goal);
+        let mut open_set = BinaryHeap::new();
+        let mut came_from: HashMap<TilePosition, TilePosition> = HashMap::new();
+        let mut g_score: HashMap<TilePosition, i32> = HashMap::new();
+
+        g_score.insert(start, 0);
+        open_set.push(PathNode {
+            position: start,
+            g_cost: 0,
+            h_cost: octile_distance(&start, &goal),
+            f_cost: octile_distance(&start, &goal),
+        });
+
+        let mut expanded = 0;
+        while let Some(current_node) = open_set.pop() {
+            let current = current_node.position;
+
+            if current == goal {
+                self.last_expanded = expanded;
+                let jump_points = self.reconstruct_path(&came_from, current);
+                return Some(fill_jumps(&jump_points));
+            }
+
+            expanded += 1;
+            if expanded > self.max_nodes {
+                warn!(
+                    "Jump point search from {:?} to {:?} gave up after {} nodes",
+                    start, goal, self.max_nodes
+                );
+                self.last_expanded = expanded;
+                return None;
+            }
+
+            let parent = came_from.get(&current).copied();
+            for (dx, dy) in self.jps_directions(current, parent, &bounds) {
+                let jump_point = match self.jump(current, dx, dy, goal, &bounds) {
+                    Some(jump_point) => jump_point,
+                    None => continue,
+                };
+
+                let tentative_g_score = g_score.get(&current).unwrap_or(&i32::MAX)
+                    + octile_distance(&current, &jump_point);
+
+                if tentative_g_score < *g_score.get(&jump_point).unwrap_or(&i32::MAX) {
+                    came_from.insert(jump_point, current);
+                    g_score.insert(jump_point, tentative_g_score);
+
+                    let h_cost = octile_distance(&jump_point, &goal);
+                    open_set.push(PathNode {
+                        position: jump_point,
+                        g_cost: tentative_g_score,
+                        h_cost,
+                        f_cost: tentative_g_score + h_cost,
+                    });
+                }
+            }
+        }
+
+        self.last_expanded = expanded;
+        None
+    }
+
+    /// the obstacles' bounding box around start and goal, grown by a tile. the ring
+    /// just outside the obstacles is always clear, so some shortest path stays inside
+    /// and the rest of the unbounded grid can be treated as wall
+    fn search_bounds(&self, start: TilePosition, goal: TilePosition) -> SearchBounds {
+        let mut bounds = SearchBounds {
+            min: start,
+            max: start,
+        };
+        for pos in self.obstacles.iter().chain([&goal]) {
+            bounds.min.x = bounds.min.x.min(pos.x);
+            bounds.min.y = bounds.min.y.min(pos.y);
+            bounds.max.x = bounds.max.x.max(pos.x);
+            bounds.max.y = bounds.max.y.max(pos.y);
+        }
+        bounds.min.x -= 1;
+        bounds.min.y -= 1;
+        bounds.max.x += 1;
+        bounds.max.y += 1;
+        bounds
+    }
+
+    fn is_open(&self, x: i32, y: i32, bounds: &SearchBounds) -> bool {
+        let pos = TilePosition { x, y };
+        bounds.contains(&pos) && self.is_walkable(&pos)
+    }
+
+    /// directions worth searching from `current` given where the search came from:
+    /// the way it was already going plus any forced by an obstacle alongside
+    fn jps_directions(
+        &self,
+        current: TilePosition,
+        parent: Option<TilePosition>,
+        bounds: &SearchBounds,
+    ) -> Vec<(i32, i32)> {
+        let (x, y) = (current.x, current.y);
+        let parent = match parent {
+            Some(parent) => parent,
+            None => {
+                return vec![
+                    (1, 0),
+                    (-1, 0),
+                    (0, 1),
+                    (0, -1),
+                    (1, 1),
+                    (1, -1),
+                    (-1, 1),
+                    (-1, -1),
+                ];
+            }
+        };
+        let dx = (x - parent.x).signum();
+        let dy = (y - parent.y).signum();
+
+        let mut directions = Vec::new();
+        if dx != 0 && dy != 0 {
+            directions.extend([(dx, 0), (0, dy), (dx, dy)]);
+            if !self.is_open(x - dx, y, bounds) {
+                directions.push((-dx, dy));
+            }
+            if !self.is_open(x, y - dy, bounds) {
+                directions.push((dx, -dy));
+            }
+        } else if dx != 0 {
+            directions.push((dx, 0));
+            if !self.is_open(x, y + 1, bounds) {
+                directions.push((dx, 1));
+            }
+            if !self.is_open(x, y - 1, bounds) {
+                directions.push((dx, -1));
+            }
+        } else {
+            directions.push((0, dy));
+            if !self.is_open(x + 1, y, bounds) {
+                directions.push((1, dy));
+            }
+            if !self.is_open(x - 1, y, bounds) {
+                directions.push((-1, dy));
+            }
+        }
+        directions
+    }
+
+    /// walks from `from` in one direction until the goal, a tile with a forced
+    /// neighbour, or for diagonals a tile a straight jump finds something from
+    fn jump(
+        &self,
+        from: TilePosition,
+        dx: i32,
+        dy: i32,
+        goal: TilePosition,
+        bounds: &SearchBounds,
+    ) -> Option<TilePosition> {
+        let mut current = from;
+        loop {
+            current = TilePosition {
+                x: current.x + dx,
+                y: current.y + dy,
+            };
+            let (x, y) = (current.x, current.y);
+            if !self.is_open(x, y, bounds) {
+                return None;
+            }
+            if current == goal {
+                return Some(current);
+            }
+
+            if dx != 0 && dy != 0 {
+                let forced = (!self.is_open(x - dx, y, bounds)
+                    && self.is_open(x - dx, y + dy, bounds))
+                    || (!self.is_open(x, y - dy, bounds) && self.is_open(x + dx, y - dy, bounds));
+                if forced
+                    || self.jump(current, dx, 0, goal, bounds).is_some()
+                    || self.jump(current, 0, dy, goal, bounds).is_some()
+                {
+                    return Some(current);
+                }
+            } else if dx != 0 {
+                if (!self.is_open(x, y + 1, bounds) && self.is_open(x + dx, y + 1, bounds))
+                    || (!self.is_open(x, y - 1, bounds) && self.is_open(x + dx, y - 1, bounds))
+                {
+                    return Some(current);
+                }
+            } else if (!self.is_open(x + 1, y, bounds) && self.is_open(x + 1, y + dy, bounds))
+                || (!self.is_open(x - 1, y, bounds) && self.is_open(x - 1, y + dy, bounds))
+            {
+                return Some(current);
+            }
+        }
     }
 
     fn reconstruct_path(
@@ -140,3 +726,278 @@ impl Pathfinder {
         path
     }
 }
+
+/// inclusive rectangle a jump point search stays inside
+struct SearchBounds {
+    min: TilePosition,
+    max: TilePosition,
+}
+
+impl SearchBounds {
+    fn contains(&self, pos: &TilePosition) -> bool {
+        (self.min.x..=self.max.x).contains(&pos.x) && (self.min.y..=self.max.y).contains(&pos.y)
+    }
+}
+
+/// cost of the cheapest route on an open grid with diagonal moves
+fn octile_distance(a: &TilePosition, b: &TilePosition) -> i32 {
+    let dx = (a.x - b.x).abs();
+    let dy = (a.y - b.y).abs();
+    10 * dx.max(dy) + 4 * dx.min(dy)
+}
+
+/// jump points joined back up into single steps. consecutive jump points always lie
+/// on one straight or diagonal line
+fn fill_jumps(jump_points: &[TilePosition]) -> Vec<TilePosition> {
+    let mut path = jump_points[..1].to_vec();
+    for target in &jump_points[1..] {
+        let mut current = path[path.len() - 1];
+        let dx = (target.x - current.x).signum();
+        let dy = (target.y - current.y).signum();
+        while current != *target {
+            current.x += dx;
+            current.y += dy;
+            path.push(current);
+        }
+    }
+    path
+}
+
+/// tiles on a four-connected bresenham line, both ends included. the line only takes
+/// straight steps, so it can be walked without diagonal movement
+fn line_tiles(from: TilePosition, to: TilePosition) -> Vec<TilePosition> {
+    let dx = (to.x - from.x).abs();
+    let dy = -(to.y - from.y).abs();
+    let sx = (to.x - from.x).signum();
+    let sy = (to.y - from.y).signum();
+    let mut err = dx + dy;
+    let mut current = from;
+    let mut tiles = vec![current];
+
+    while current != to {
+        let e2 = 2 * err;
+        if e2 - dy > dx - e2 {
+            err += dy;
+            current.x += sx;
+        } else {
+            err += dx;
+            current.y += sy;
+        }
+        tiles.push(current);
+    }
+    tiles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile(x: i32, y: i32) -> TilePosition {
+        TilePosition { x, y }
+    }
+
+    /// what walking `path` costs, in the units the searches use
+    fn path_cost(path: &[TilePosition]) -> i32 {
+        path.windows(2)
+            .map(|step| {
+                let diagonal = step[0].x != step[1].x && step[0].y != step[1].y;
+                if diagonal {
+                    14
+                } else {
+                    10
+                }
+            })
+            .sum()
+    }
+
+    fn assert_walkable_steps(pathfinder: &Pathfinder, path: &[TilePosition]) {
+        assert!(pathfinder.check_walk(path[0], path).is_ok(), "{:?}", path);
+    }
+
+    #[test]
+    fn search_gives_up_once_the_node_budget_is_spent() {
+        let mut pathfinder = Pathfinder::new(false);
+        pathfinder.max_nodes = 100;
+        // an open plane with the goal boxed in, so nothing ever reaches it
+        let goal = tile(50, 50);
+        pathfinder.set_obstacles(goal.neighbors());
+
+        assert_eq!(pathfinder.find_path_a_star(tile(0, 0), goal), None);
+        assert_eq!(pathfinder.last_expanded(), 101);
+
+        // a budget that covers the search still finds the way
+        pathfinder.max_nodes = DEFAULT_MAX_SEARCH_NODES;
+        assert!(pathfinder
+            .find_path_a_star(tile(0, 0), tile(8, 8))
+            .is_some());
+        assert!(pathfinder.last_expanded() < 100);
+    }
+
+    #[test]
+    fn jump_point_paths_cost_the_same_as_a_star() {
+        let wall: Vec<TilePosition> = (-5..=5).filter(|y| *y != 3).map(|y| tile(4, y)).collect();
+        let pillars: Vec<TilePosition> = (0..6)
+            .flat_map(|i| [tile(2 * i, 2), tile(2 * i + 1, -3)])
+            .collect();
+        let pocket = vec![tile(2, 1), tile(2, 2), tile(2, 3), tile(3, 3), tile(4, 3)];
+        let layouts = [Vec::new(), wall, pillars, pocket];
+
+        for obstacles in layouts {
+            for goal in [tile(8, 0), tile(9, 5), tile(3, 2), tile(-6, -4)] {
+                let mut pathfinder = Pathfinder::new(true);
+                pathfinder.set_obstacles(obstacles.clone());
+                if !pathfinder.is_walkable(&goal) {
+                    continue;
+                }
+                let a_star = pathfinder.find_path_a_star(tile(0, 0), goal);
+                let jps = pathfinder.find_path_jps(tile(0, 0), goal);
+                match (a_star, jps) {
+                    (Some(a_star), Some(jps)) => {
+                        assert_eq!(path_cost(&a_star), path_cost(&jps), "to {:?}", goal);
+                        assert_eq!(jps.last(), Some(&goal));
+                        assert_walkable_steps(&pathfinder, &jps);
+                    }
+                    (None, None) => {}
+                    (a_star, jps) => panic!("to {:?}: A* {:?}, JPS {:?}", goal, a_star, jps),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn jump_point_search_expands_fewer_nodes_in_open_space() {
+        let mut pathfinder = Pathfinder::new(true);
+        // a few obstacles far off, so the search has room to wander
+        pathfinder.set_obstacles([tile(-20, -20), tile(40, 30)]);
+
+        pathfinder
+            .find_path_a_star(tile(0, 0), tile(30, 20))
+            .unwrap();
+        let a_star_nodes = pathfinder.last_expanded();
+        pathfinder.find_path_jps(tile(0, 0), tile(30, 20)).unwrap();
+        let jps_nodes = pathfinder.last_expanded();
+
+        assert!(
+            jps_nodes < a_star_nodes,
+            "JPS expanded {} nodes, A* {}",
+            jps_nodes,
+            a_star_nodes
+        );
+    }
+
+    #[test]
+    fn jump_point_search_is_refused_without_diagonals() {
+        let mut pathfinder = Pathfinder::new(false);
+        assert!(pathfinder.set_mode(PathMode::JumpPoint).is_err());
+        assert_eq!(pathfinder.mode(), PathMode::AStar);
+
+        // asked for directly it falls back to A*, so every step is a straight one
+        let path = pathfinder.find_path_jps(tile(0, 0), tile(4, 3)).unwrap();
+        assert_eq!(path_cost(&path), 70);
+        assert_walkable_steps(&pathfinder, &path);
+
+        let mut diagonal = Pathfinder::new(true);
+        assert!(diagonal.set_mode(PathMode::JumpPoint).is_ok());
+        assert_eq!(PathMode::parse("jps"), Ok(PathMode::JumpPoint));
+        assert!(PathMode::parse("dijkstra").is_err());
+    }
+
+    #[test]
+    fn path_to_an_obstacle_ends_next_to_it() {
+        let mut pathfinder = Pathfinder::new(false);
+        pathfinder.add_obstacle(tile(5, 0));
+
+        assert_eq!(pathfinder.find_path(tile(0, 0), tile(5, 0)), None);
+        let path = pathfinder
+            .find_path_or_adjacent(tile(0, 0), tile(5, 0))
+            .unwrap();
+        assert_eq!(path.first(), Some(&tile(0, 0)));
+        assert_eq!(path.last(), Some(&tile(4, 0)));
+        assert_eq!(path.len(), 5);
+
+        assert_eq!(
+            pathfinder.find_path_or_adjacent(tile(4, 1), tile(5, 0)),
+            Some(vec![tile(4, 1)])
+        );
+    }
+
+    #[test]
+    fn walled_in_target_uses_one_node_budget() {
+        let mut pathfinder = Pathfinder::new(false);
+        pathfinder.max_nodes = 200;
+        // the tiles around the target are free, but a ring of wall shuts them in
+        let ring = (-2..=2)
+            .flat_map(|dx: i32| (-2..=2).map(move |dy: i32| (dx, dy)))
+            .filter(|(dx, dy)| dx.abs().max(dy.abs()) == 2)
+            .map(|(dx, dy)| tile(10 + dx, dy));
+        pathfinder.set_obstacles(ring.chain([tile(10, 0)]));
+
+        assert_eq!(
+            pathfinder.find_path_or_adjacent(tile(0, 0), tile(10, 0)),
+            None
+        );
+        assert!(pathfinder.last_expanded() <= pathfinder.max_nodes + 1);
+    }
+
+    #[test]
+    fn asking_for_the_same_path_again_searches_nothing() {
+        let mut pathfinder = Pathfinder::new(false);
+        pathfinder.set_obstacles([tile(3, 0), tile(3, 1), tile(3, -1)]);
+
+        let first = pathfinder.find_path_a_star(tile(0, 0), tile(6, 0));
+        assert!(first.is_some());
+        let expanded = pathfinder.last_expanded();
+        // a different search in between, so the counter has moved on
+        assert!(pathfinder
+            .find_path_a_star(tile(0, 0), tile(1, 0))
+            .is_some());
+        let short = pathfinder.last_expanded();
+        assert_ne!(short, expanded);
+
+        assert_eq!(pathfinder.find_path_a_star(tile(0, 0), tile(6, 0)), first);
+        assert_eq!(pathfinder.last_expanded(), short);
+
+        // any change to the obstacles means searching again
+        pathfinder.add_obstacle(tile(10, 10));
+        assert_eq!(pathfinder.find_path_a_star(tile(0, 0), tile(6, 0)), first);
+        assert_eq!(pathfinder.last_expanded(), expanded);
+    }
+
+    #[test]
+    fn waypoints_round_an_l_chain_into_one_walk() {
+        let mut pathfinder = Pathfinder::new(false);
+        // one rock on each arm of the L, so two of the legs have to step round
+        pathfinder.set_obstacles([tile(0, 2), tile(1, 4)]);
+        let waypoints = [tile(0, 4), tile(2, 4), tile(4, 4)];
+
+        let path = pathfinder.find_path_multi(tile(0, 0), &waypoints).unwrap();
+        assert_eq!(path.first(), Some(&tile(0, 0)));
+        assert_eq!(path.last(), Some(&tile(4, 4)));
+        assert_walkable_steps(&pathfinder, &path);
+        let mut visited = waypoints
+            .iter()
+            .map(|waypoint| path.iter().position(|step| step == waypoint).unwrap());
+        let (first, second, third) = (visited.next(), visited.next(), visited.next());
+        assert!(first < second && second < third, "{:?}", path);
+        // six steps round the first rock, four round the second and two more, with
+        // each junction only walked once
+        assert_eq!(path.len(), 1 + 6 + 4 + 2);
+
+        // a waypoint nobody can reach says which one it was
+        pathfinder.set_obstacles(tile(2, 4).neighbors());
+        assert_eq!(pathfinder.find_path_multi(tile(0, 0), &waypoints), Err(1));
+    }
+
+    #[test]
+    fn a_straight_corridor_smooths_to_its_ends_and_expands_back() {
+        let mut pathfinder = Pathfinder::new(false);
+        // walls either side of a ten tile corridor
+        let walls = (-1..=10).flat_map(|x| [tile(x, 1), tile(x, -1)]);
+        pathfinder.set_obstacles(walls);
+        let corridor: Vec<TilePosition> = (0..10).map(|x| tile(x, 0)).collect();
+
+        let smoothed = pathfinder.smooth_path(&corridor);
+        assert_eq!(smoothed, vec![tile(0, 0), tile(9, 0)]);
+        assert_eq!(Pathfinder::expand_path(&smoothed), corridor);
+    }
+}
@@ -1,6 +1,8 @@
 use bevy::{prelude::*, utils::HashMap};
 use serde::{Deserialize, Serialize};
 
+pub const MAX_LEVEL: u32 = 99;
+
 #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum SkillType {
     Woodcutting,
@@ -56,7 +58,7 @@ impl Skills {
 
     pub fn add_experience(&mut self, skill: SkillType, xp: u32) -> bool {
         if let Some(skill_data) = self.skills.get_mut(&skill) {
-            skill_data.experience += xp;
+            skill_data.experience = skill_data.experience.saturating_add(xp);
             let new_level = Self::calculate_level(skill_data.experience);
             if new_level > skill_data.level {
                 skill_data.level = new_level;
@@ -74,18 +76,127 @@ impl Skills {
         self.skills.get(&skill).map(|s| s.experience).unwrap_or(0)
     }
 
-    fn calculate_level(xp: u32) -> u32 {
-        let mut level: u32 = 1 as u32;
-        let mut xp_needed = 0;
+    /// total experience needed to reach `level` (83 for level 2, 13,034,431 for 99)
+    pub fn xp_for_level(level: u32) -> u32 {
+        let points: f64 = (1..level.min(MAX_LEVEL)).map(Self::level_points).sum();
+        (points / 4.0).floor() as u32
+    }
 
-        while xp_needed <= xp {
-            level += 1;
-            xp_needed += (level as f32 + 300.0 * 2_f32.powf(level as f32 / 7.0)).floor() as u32 / 4;
-            if level >= 99 {
+    /// experience still needed for the next level, 0 once the skill is maxed
+    pub fn xp_to_next_level(&self, skill: SkillType) -> u32 {
+        let level = self.get_level(skill);
+        if level >= MAX_LEVEL {
+            return 0;
+        }
+        Self::xp_for_level(level + 1).saturating_sub(self.get_experience(skill))
+    }
+
+    /// highest level whose threshold `xp` has reached, never lower for more xp
+    pub fn calculate_level(xp: u32) -> u32 {
+        let mut points = 0.0;
+        let mut level = 1;
+        while level < MAX_LEVEL {
+            points += Self::level_points(level);
+            if ((points / 4.0).floor() as u32) > xp {
                 break;
             }
+            level += 1;
+        }
+        level
+    }
+
+    fn level_points(level: u32) -> f64 {
+        (level as f64 + 300.0 * 2_f64.powf(level as f64 / 7.0)).floor()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// total xp at which each level is reached
+    const THRESHOLDS: [(u32, u32); 4] = [(2, 83), (10, 1_154), (50, 101_333), (99, 13_034_431)];
+
+    #[test]
+    fn levels_start_exactly_at_their_thresholds() {
+        for (level, xp) in THRESHOLDS {
+            assert_eq!(Skills::xp_for_level(level), xp, "level {}", level);
+            assert_eq!(Skills::calculate_level(xp), level, "{} xp", xp);
+            assert_eq!(Skills::calculate_level(xp - 1), level - 1, "{} xp", xp - 1);
+        }
+        assert_eq!(Skills::calculate_level(0), 1);
+        assert_eq!(Skills::calculate_level(u32::MAX), MAX_LEVEL);
+    }
+
+    #[test]
+    fn more_xp_never_means_a_lower_level() {
+        for level in 2..=MAX_LEVEL {
+            assert!(Skills::xp_for_level(level) > Skills::xp_for_level(level - 1));
+        }
+        let mut last = 1;
+        for xp in (0..=14_000_000).step_by(997) {
+            let level = Skills::calculate_level(xp);
+            assert!(level >= last, "{} xp dropped to level {}", xp, level);
+            last = level;
+        }
+    }
+
+    #[test]
+    fn leveled_up_matches_the_level_reached() {
+        let mut skills = Skills::new();
+        assert!(!skills.add_experience(SkillType::Woodcutting, 82));
+        assert_eq!(skills.get_level(SkillType::Woodcutting), 1);
+        assert_eq!(skills.xp_to_next_level(SkillType::Woodcutting), 1);
+
+        assert!(skills.add_experience(SkillType::Woodcutting, 1));
+        assert_eq!(skills.get_level(SkillType::Woodcutting), 2);
+        assert!(!skills.add_experience(SkillType::Woodcutting, 0));
+
+        // one big jump straight past several levels still reports a single level up
+        assert!(skills.add_experience(SkillType::Woodcutting, 1_154 - 83));
+        assert_eq!(skills.get_level(SkillType::Woodcutting), 10);
+
+        assert!(skills.add_experience(SkillType::Woodcutting, u32::MAX));
+        assert_eq!(skills.get_level(SkillType::Woodcutting), MAX_LEVEL);
+        assert_eq!(skills.xp_to_next_level(SkillType::Woodcutting), 0);
+    }
+
+    #[test]
+    fn xp_to_next_level_counts_down_to_each_threshold() {
+        assert_eq!(Skills::xp_for_level(1), 0);
+        // asking past the cap doesn't climb beyond level 99
+        assert_eq!(
+            Skills::xp_for_level(MAX_LEVEL + 1),
+            Skills::xp_for_level(MAX_LEVEL)
+        );
+
+        for level in [1, 2, 10, 50, MAX_LEVEL - 1] {
+            let next = Skills::xp_for_level(level + 1);
+            let mut skills = Skills::new();
+            skills.add_experience(SkillType::Woodcutting, Skills::xp_for_level(level));
+            assert_eq!(skills.get_level(SkillType::Woodcutting), level);
+            assert_eq!(
+                skills.xp_to_next_level(SkillType::Woodcutting),
+                next - Skills::xp_for_level(level),
+                "level {}",
+                level
+            );
+
+            // one short of the next level leaves exactly one to go
+            skills.add_experience(
+                SkillType::Woodcutting,
+                next - Skills::xp_for_level(level) - 1,
+            );
+            assert_eq!(
+                skills.xp_to_next_level(SkillType::Woodcutting),
+                1,
+                "level {}",
+                level
+            );
         }
 
-        level.saturating_sub(1).max(1)
+        let mut maxed = Skills::new();
+        maxed.add_experience(SkillType::Woodcutting, Skills::xp_for_level(MAX_LEVEL));
+        assert_eq!(maxed.xp_to_next_level(SkillType::Woodcutting), 0);
     }
 }
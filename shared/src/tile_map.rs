@@ -0,0 +1,135 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::tile_system::TilePosition;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum TileType {
+    #[default]
+    Grass,
+    Water,
+    Wall,
+    Road,
+    Mud,
+}
+
+/// what crossing a tile of grass costs, see `TileType::movement_cost`
+pub const BASE_MOVEMENT_COST: i32 = 10;
+
+impl TileType {
+    pub fn is_walkable(&self) -> bool {
+        matches!(self, TileType::Grass | TileType::Road | TileType::Mud)
+    }
+
+    /// what the pathfinder pays to step onto this tile, relative to
+    /// `BASE_MOVEMENT_COST` for grass. only means anything for walkable tiles
+    pub fn movement_cost(&self) -> i32 {
+        match self {
+            TileType::Road => 5,
+            TileType::Mud => 25,
+            _ => BASE_MOVEMENT_COST,
+        }
+    }
+
+    fn from_char(c: char) -> Option<Self> {
+        match c {
+            '.' => Some(TileType::Grass),
+            '~' => Some(TileType::Water),
+            '#' => Some(TileType::Wall),
+            '=' => Some(TileType::Road),
+            ',' => Some(TileType::Mud),
+            _ => None,
+        }
+    }
+}
+
+/// terrain of the world. tiles that aren't in the map are grass
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TileMap {
+    pub tiles: HashMap<TilePosition, TileType>,
+}
+
+impl TileMap {
+    /// parses a text map, one character per tile: `.` grass, `~` water, `#` wall,
+    /// `=` road, `,` mud.
+    /// an optional first line `origin <x> <y>` gives the tile of the top-left
+    /// character, otherwise it is 0,0. each following line is one row further down
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().peekable();
+        let mut origin = TilePosition { x: 0, y: 0 };
+        if let Some(header) = lines.peek().and_then(|line| line.strip_prefix("origin")) {
+            let coords: Vec<i32> = header
+                .split_whitespace()
+                .map(|part| part.parse::<i32>())
+                .collect::<Result<_, _>>()
+                .map_err(|err| format!("bad origin line: {}", err))?;
+            match coords[..] {
+                [x, y] => origin = TilePosition { x, y },
+                _ => return Err("origin line needs exactly two numbers".to_string()),
+            }
+            lines.next();
+        }
+
+        let mut tiles = HashMap::new();
+        for (row, line) in lines.enumerate() {
+            for (column, c) in line.trim_end().chars().enumerate() {
+                let tile_type = match TileType::from_char(c) {
+                    Some(tile_type) => tile_type,
+                    None => {
+                        return Err(format!(
+                            "unknown tile '{}' at row {} column {}",
+                            c, row, column
+                        ))
+                    }
+                };
+                let pos = TilePosition {
+                    x: origin.x + column as i32,
+                    y: origin.y - row as i32,
+                };
+                tiles.insert(pos, tile_type);
+            }
+        }
+        Ok(Self { tiles })
+    }
+
+    pub fn get(&self, pos: &TilePosition) -> TileType {
+        self.tiles.get(pos).copied().unwrap_or_default()
+    }
+
+    /// tiles nothing can walk on, these become pathfinder obstacles
+    pub fn blocked_tiles(&self) -> impl Iterator<Item = TilePosition> + '_ {
+        self.tiles
+            .iter()
+            .filter(|(_, tile_type)| !tile_type.is_walkable())
+            .map(|(pos, _)| *pos)
+    }
+
+    /// walkable tiles that cost something other than grass to cross, these become
+    /// pathfinder terrain costs
+    pub fn terrain_costs(&self) -> impl Iterator<Item = (TilePosition, i32)> + '_ {
+        self.tiles
+            .iter()
+            .filter(|(_, tile_type)| tile_type.is_walkable())
+            .map(|(pos, tile_type)| (*pos, tile_type.movement_cost()))
+            .filter(|(_, cost)| *cost != BASE_MOVEMENT_COST)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn water_and_walls_become_obstacles() {
+        let map = TileMap::parse("origin -1 1\n.~.\n#=,\n").unwrap();
+        let blocked: HashSet<TilePosition> = map.blocked_tiles().collect();
+        assert_eq!(
+            blocked,
+            HashSet::from([TilePosition { x: 0, y: 1 }, TilePosition { x: -1, y: 0 }])
+        );
+        assert_eq!(map.get(&TilePosition { x: 5, y: 5 }), TileType::Grass);
+        assert!(TileMap::parse("x").is_err());
+    }
+}
@@ -25,6 +25,11 @@ impl TilePosition {
         (self.x - other.x).abs() + (self.y - other.y).abs()
     }
 
+    /// distance where diagonal steps count as one, so equal distances form a square
+    pub fn distance_chebyshev(&self, other: &TilePosition) -> i32 {
+        (self.x - other.x).abs().max((self.y - other.y).abs())
+    }
+
     pub fn neighbors(&self) -> Vec<TilePosition> {
         vec![
             TilePosition {
@@ -0,0 +1,170 @@
+use bevy_renet::renet::{ClientId, DefaultChannel, RenetClient, RenetServer};
+
+/// anything the server can push serialized messages into
+pub trait MessageSink {
+    fn send_message(&mut self, client_id: ClientId, channel: DefaultChannel, message: Vec<u8>);
+    fn broadcast_message(&mut self, channel: DefaultChannel, message: Vec<u8>);
+}
+
+/// the full surface the server needs from its network layer
+pub trait ServerTransport: MessageSink {
+    fn clients_id(&self) -> Vec<ClientId>;
+    fn receive_message(&mut self, client_id: ClientId, channel: DefaultChannel) -> Option<Vec<u8>>;
+    /// drops a client from the server side
+    fn disconnect(&mut self, client_id: ClientId);
+}
+
+/// the surface the client needs from its network layer
+pub trait ClientTransport {
+    fn is_connected(&self) -> bool;
+    fn send_message(&mut self, channel: DefaultChannel, message: Vec<u8>);
+    fn receive_message(&mut self, channel: DefaultChannel) -> Option<Vec<u8>>;
+}
+
+impl MessageSink for RenetServer {
+    fn send_message(&mut self, client_id: ClientId, channel: DefaultChannel, message: Vec<u8>) {
+        RenetServer::send_message(self, client_id, channel, message);
+    }
+
+    fn broadcast_message(&mut self, channel: DefaultChannel, message: Vec<u8>) {
+        RenetServer::broadcast_message(self, channel, message);
+    }
+}
+
+impl ServerTransport for RenetServer {
+    fn clients_id(&self) -> Vec<ClientId> {
+        RenetServer::clients_id(self)
+    }
+
+    fn receive_message(&mut self, client_id: ClientId, channel: DefaultChannel) -> Option<Vec<u8>> {
+        RenetServer::receive_message(self, client_id, channel).map(|bytes| bytes.to_vec())
+    }
+
+    fn disconnect(&mut self, client_id: ClientId) {
+        RenetServer::disconnect(self, client_id);
+    }
+}
+
+impl ClientTransport for RenetClient {
+    fn is_connected(&self) -> bool {
+        RenetClient::is_connected(self)
+    }
+
+    fn send_message(&mut self, channel: DefaultChannel, message: Vec<u8>) {
+        RenetClient::send_message(self, channel, message);
+    }
+
+    fn receive_message(&mut self, channel: DefaultChannel) -> Option<Vec<u8>> {
+        RenetClient::receive_message(self, channel).map(|bytes| bytes.to_vec())
+    }
+}
+
+/// in-process transport for running a server and clients in one app without sockets
+#[cfg(feature = "memory-transport")]
+pub mod memory {
+    use std::collections::{HashMap, VecDeque};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use bevy::prelude::*;
+    use bevy_renet::renet::{ClientId, DefaultChannel};
+
+    use super::{ClientTransport, MessageSink, ServerTransport};
+
+    type Queue = Arc<Mutex<VecDeque<(u8, Vec<u8>)>>>;
+
+    #[derive(Clone, Default)]
+    struct MemoryLink {
+        to_client: Queue,
+        to_server: Queue,
+        connected: Arc<AtomicBool>,
+    }
+
+    fn push(queue: &Queue, channel: DefaultChannel, message: Vec<u8>) {
+        let channel_id: u8 = channel.into();
+        queue.lock().unwrap().push_back((channel_id, message));
+    }
+
+    fn pop(queue: &Queue, channel: DefaultChannel) -> Option<Vec<u8>> {
+        let channel_id: u8 = channel.into();
+        let mut queue = queue.lock().unwrap();
+        let index = queue.iter().position(|(id, _)| *id == channel_id)?;
+        queue.remove(index).map(|(_, message)| message)
+    }
+
+    #[derive(Resource, Default)]
+    pub struct MemoryServerTransport {
+        links: HashMap<ClientId, MemoryLink>,
+    }
+
+    impl MemoryServerTransport {
+        /// connects a new client and returns its end of the link
+        pub fn connect(&mut self, client_id: ClientId) -> MemoryClientTransport {
+            let link = MemoryLink::default();
+            link.connected.store(true, Ordering::SeqCst);
+            self.links.insert(client_id, link.clone());
+            MemoryClientTransport { link }
+        }
+    }
+
+    impl MessageSink for MemoryServerTransport {
+        fn send_message(&mut self, client_id: ClientId, channel: DefaultChannel, message: Vec<u8>) {
+            if let Some(link) = self.links.get(&client_id) {
+                push(&link.to_client, channel, message);
+            }
+        }
+
+        fn broadcast_message(&mut self, channel: DefaultChannel, message: Vec<u8>) {
+            let channel_id: u8 = channel.into();
+            for link in self.links.values() {
+                link.to_client
+                    .lock()
+                    .unwrap()
+                    .push_back((channel_id, message.clone()));
+            }
+        }
+    }
+
+    impl ServerTransport for MemoryServerTransport {
+        fn clients_id(&self) -> Vec<ClientId> {
+            self.links.keys().copied().collect()
+        }
+
+        fn receive_message(
+            &mut self,
+            client_id: ClientId,
+            channel: DefaultChannel,
+        ) -> Option<Vec<u8>> {
+            self.links
+                .get(&client_id)
+                .and_then(|link| pop(&link.to_server, channel))
+        }
+
+        fn disconnect(&mut self, client_id: ClientId) {
+            if let Some(link) = self.links.remove(&client_id) {
+                link.connected.store(false, Ordering::SeqCst);
+            }
+        }
+    }
+
+    #[derive(Resource)]
+    pub struct MemoryClientTransport {
+        link: MemoryLink,
+    }
+
+    impl ClientTransport for MemoryClientTransport {
+        fn is_connected(&self) -> bool {
+            self.link.connected.load(Ordering::SeqCst)
+        }
+
+        fn send_message(&mut self, channel: DefaultChannel, message: Vec<u8>) {
+            if self.is_connected() {
+                push(&self.link.to_server, channel, message);
+            }
+        }
+
+        fn receive_message(&mut self, channel: DefaultChannel) -> Option<Vec<u8>> {
+            pop(&self.link.to_client, channel)
+        }
+    }
+}
@@ -1,9 +1,11 @@
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use crate::definitions::Definitions;
 use crate::items::ItemType;
+use crate::loot::{LootEntry, LootTable};
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum TreeType {
     Normal,
     Oak,
@@ -13,53 +15,95 @@ pub enum TreeType {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct TreeDefinition {
     pub tree_type: TreeType,
-    pub name: &'static str,
+    pub name: String,
+    pub description: String,
     pub level_required: u32,
-    pub chop_time: f64,
     pub logs_given: ItemType,
     pub experience: u32,
     pub respawn_time: f64,
+    /// rolled on every successful chop, on top of the logs
+    pub loot: LootTable,
+    /// chance each chop fells the tree. until then it keeps giving logs
+    #[serde(default = "always_depletes")]
+    pub depletion_chance: f32,
+}
+
+fn always_depletes() -> f32 {
+    1.0
 }
 
 impl TreeDefinition {
-    pub fn get(tree_type: TreeType) -> Self {
+    pub fn builtin(tree_type: TreeType) -> Self {
         match tree_type {
             TreeType::Normal => TreeDefinition {
                 tree_type,
-                name: "Tree",
+                name: "Tree".into(),
+                description: "A commonly found tree.".into(),
                 level_required: 1,
-                chop_time: 3.0,
                 logs_given: ItemType::Logs,
                 experience: 25,
                 respawn_time: 5.0,
+                loot: LootTable::default(),
+                depletion_chance: 1.0,
             },
             TreeType::Oak => TreeDefinition {
                 tree_type,
-                name: "Oak",
+                name: "Oak".into(),
+                description: "A sturdy oak, slow to fell.".into(),
                 level_required: 15,
-                chop_time: 5.0,
                 logs_given: ItemType::OakLogs,
                 experience: 37,
                 respawn_time: 8.0,
+                loot: LootTable {
+                    entries: vec![LootEntry {
+                        item_type: ItemType::BirdsNest,
+                        quantity: 1,
+                        weight: 1,
+                    }],
+                    nothing_weight: 99,
+                },
+                depletion_chance: 0.125,
             },
             TreeType::Willow => TreeDefinition {
                 tree_type,
-                name: "Willow",
+                name: "Willow".into(),
+                description: "A willow with long drooping branches.".into(),
                 level_required: 30,
-                chop_time: 4.0,
                 logs_given: ItemType::WillowLogs,
                 experience: 67,
                 respawn_time: 10.0,
+                loot: LootTable {
+                    entries: vec![LootEntry {
+                        item_type: ItemType::BirdsNest,
+                        quantity: 1,
+                        weight: 1,
+                    }],
+                    nothing_weight: 79,
+                },
+                depletion_chance: 0.125,
             },
         }
     }
 }
 
+/// how far along a felled tree is, for drawing it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TreeStage {
+    Standing,
+    Chopped,
+    /// the second half of the respawn wait
+    Regrowing,
+}
+
 #[derive(Component, Serialize, Deserialize, Clone, Debug)]
 pub struct Tree {
     pub tree_type: TreeType,
     pub is_chopped: bool,
     pub respawn_timer: f64,
+    /// server tick the tree was felled on, while it is chopped
+    pub chopped_at_tick: Option<u64>,
+    /// server tick the tree is due back on, while it is chopped
+    pub respawn_at_tick: Option<u64>,
 }
 
 impl Tree {
@@ -68,6 +112,52 @@ impl Tree {
             tree_type,
             is_chopped: false,
             respawn_timer: 0.0,
+            chopped_at_tick: None,
+            respawn_at_tick: None,
+        }
+    }
+
+    /// works out the chop and respawn ticks from `respawn_timer` as of `tick`, so a
+    /// snapshot taken mid-respawn tells the client how long is left
+    pub fn update_respawn_ticks(
+        &mut self,
+        tick: u64,
+        tick_duration: f64,
+        definitions: &Definitions,
+    ) {
+        if !self.is_chopped || tick_duration <= 0.0 {
+            self.chopped_at_tick = None;
+            self.respawn_at_tick = None;
+            return;
+        }
+        let respawn_time = definitions.tree(self.tree_type).respawn_time;
+        let elapsed = (self.respawn_timer / tick_duration).round() as u64;
+        let total = (respawn_time / tick_duration).ceil() as u64;
+        let chopped_at = tick.saturating_sub(elapsed);
+        self.chopped_at_tick = Some(chopped_at);
+        self.respawn_at_tick = Some(chopped_at + total);
+    }
+
+    /// how much of the respawn wait has passed at `tick`, 0 when just felled and 1
+    /// once due back. standing trees are always 1
+    pub fn regrowth(&self, tick: f64) -> f32 {
+        match (self.chopped_at_tick, self.respawn_at_tick) {
+            (Some(chopped_at), Some(respawn_at)) if respawn_at > chopped_at => {
+                let progress = (tick - chopped_at as f64) / (respawn_at - chopped_at) as f64;
+                progress.clamp(0.0, 1.0) as f32
+            }
+            _ if self.is_chopped => 0.0,
+            _ => 1.0,
+        }
+    }
+
+    pub fn stage(&self, tick: f64) -> TreeStage {
+        if !self.is_chopped {
+            TreeStage::Standing
+        } else if self.regrowth(tick) < 0.5 {
+            TreeStage::Chopped
+        } else {
+            TreeStage::Regrowing
         }
     }
 }